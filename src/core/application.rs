@@ -2,97 +2,154 @@ extern crate glfw;
 extern crate gl;
 
 use glfw::{ Context, Key, Action };
-use std::collections::HashMap;
+use std::path::Path;
 use std::sync::mpsc::Receiver;
-use std::ffi::CStr;
-use cgmath::{ perspective, vec3, Deg, Matrix4, Point3 };
+use std::time::{ SystemTime, UNIX_EPOCH };
+use cgmath::{ vec3, Point3 };
+use rand::Rng;
 use crate::graphics::camera::{ Camera, CameraMovement };
+use crate::graphics::capture::{ CaptureFormat, CaptureSource, FrameCapture };
+use crate::graphics::material::{ BlendMode, Material };
 use crate::graphics::model::Model;
-use crate::graphics::shader::{Shader, ShaderType};
+use crate::graphics::render_graph::LoadOp;
+use crate::graphics::renderer::Renderer;
+use crate::graphics::resource::{ self, GlThreadGuard };
+use crate::graphics::shader::ShaderType;
+use crate::physics::world::PhysicsWorld;
+use crate::world::asteroids::AsteroidBelt;
 use crate::world::entity::Entity;
+use crate::world::light::{ PointLight, SpotLight };
 use crate::world::scene::Scene;
 use crate::world::transform::Transform;
 
 // settings
 const SCR_WIDTH: u32 = 800;
 const SCR_HEIGHT: u32 = 600;
+const RECORDING_FPS: f32 = 60.0;
+const ASTEROID_COUNT: usize = 10_000;
 
+// Fields drop in order: what owns GL objects goes first, while the context is still current, the window
+// and glfw last.
 pub struct Application {
-    glfw: glfw::Glfw,
-    window: glfw::Window,
-    events: Receiver<(f64, glfw::WindowEvent)>,
+    renderer: Renderer,
+    scene: Scene,
+    // the orbits of the rocks when running Demo::ASTEROIDS
+    asteroids: Option<AsteroidBelt>,
+    // moves the entities given a RigidBody
+    physics: PhysicsWorld,
+    capture: FrameCapture,
+    _gl_thread: GlThreadGuard,
     camera: Camera,
     first_mouse: bool,
     last_x: f32,
     last_y: f32,
     delta_time: f32,
     last_frame: f32,
-    shaders: HashMap<ShaderType, Shader>,
-    scene: Scene
+    events: Receiver<(f64, glfw::WindowEvent)>,
+    window: glfw::Window,
+    glfw: glfw::Glfw
+}
+
+// the scene the application opens with
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum Demo {
+    // the nanosuit among foliage, windows and lights
+    SHOWCASE,
+    // a planet circled by ASTEROID_COUNT instanced rocks
+    ASTEROIDS
 }
 
 impl Application {
     pub fn new() -> Self {
+        Application::with_demo(Demo::SHOWCASE)
+    }
+
+    pub fn with_demo(demo: Demo) -> Self {
         // glfw initialize and configure
         let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
-        glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
         glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
         #[cfg(target_os="macos")]
         glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
 
-        let (mut window, events) = glfw.create_window(
-            SCR_WIDTH,
-            SCR_HEIGHT,
-            "Argus Engine",
-            glfw::WindowMode::Windowed
-        ).expect("Failed to create GLFW window");
+        // 4.3 brings compute and tessellation, drivers without it get the 3.3 baseline. The first attempt
+        // may fail, which must not abort.
+        glfw.set_error_callback::<()>(None);
+        glfw.window_hint(glfw::WindowHint::ContextVersion(4, 3));
+        let window = glfw.create_window(SCR_WIDTH, SCR_HEIGHT, "Argus Engine", glfw::WindowMode::Windowed);
+        glfw.set_error_callback(glfw::FAIL_ON_ERRORS);
+        let (mut window, events) = window.unwrap_or_else(|| {
+            glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+            glfw.create_window(SCR_WIDTH, SCR_HEIGHT, "Argus Engine", glfw::WindowMode::Windowed)
+                .expect("Failed to create GLFW window")
+        });
 
         window.make_current();
         window.set_framebuffer_size_polling(true);
         window.set_cursor_pos_polling(true);
         window.set_scroll_polling(true);
+        window.set_key_polling(true);
+        window.set_mouse_button_polling(true);
 
         // tell GLFW to capture our mouse
         window.set_cursor_mode(glfw::CursorMode::Disabled);
 
         // gl: load all OpenGL function pointers
         gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+        let gl_thread = resource::register_gl_thread();
 
-        // load all shaders
-        let mut shaders: HashMap<ShaderType, Shader> = HashMap::new();
-        unsafe {
-            gl::Enable(gl::DEPTH_TEST);
+        let (width, height) = window.get_framebuffer_size();
+        let mut renderer = Renderer::new(width, height);
 
-            shaders.insert(ShaderType::MODEL, Shader::new("src/graphics/shaders/model.vs", "src/graphics/shaders/model.fs"));
-            shaders.insert(ShaderType::SKYBOX, Shader::new("src/graphics/shaders/skybox.vs", "src/graphics/shaders/skybox.fs"));
-        }
+        // a custom pass drawing a crosshair on top of the final image
+        renderer.add_pass(|graph, resources| {
+            let backbuffer = resources.backbuffer;
+            graph.add_pass("crosshair", |pass| {
+                pass.color_attachment(backbuffer, LoadOp::LOAD);
+            }, |frame, _| unsafe {
+                let (width, height) = frame.renderer.size();
+                let color = [1.0f32, 1.0, 1.0, 1.0];
+                gl::Enable(gl::SCISSOR_TEST);
+                for &(w, h) in &[(12, 2), (2, 12)] {
+                    gl::Scissor((width - w) / 2, (height - h) / 2, w, h);
+                    gl::ClearBufferfv(gl::COLOR, 0, color.as_ptr());
+                }
+                gl::Disable(gl::SCISSOR_TEST);
+            });
+        });
 
-        let mut scene = Scene::new(shaders.get(&ShaderType::SKYBOX).expect("ShaderType is not initialized"));
-        let model = Model::new("resources/objects/nanosuit/nanosuit.obj");
-        scene.entities.push(Entity::new(
-            Some(model),
-            Transform::new(
-                vec3(0.0, -1.75, 0.0),
-                vec3(0.0, 0.0, 0.0),
-                vec3(0.2, 0.2, 0.2)
-            )
-        ));
+        let mut scene = Scene::new(renderer.shader(ShaderType::SKYBOX));
+        let mut camera = Camera {
+            position: Point3 { x: 0.0, y: 0.0, z: 3.0 },
+            ..Camera::default()
+        };
+        let mut asteroids = None;
+        match demo {
+            Demo::SHOWCASE => populate_showcase(&mut scene),
+            Demo::ASTEROIDS => {
+                asteroids = Some(AsteroidBelt::new(&mut scene, ASTEROID_COUNT));
+                camera = Camera::new(Point3::new(0.0, 12.0, AsteroidBelt::RADIUS * 1.6), -90.0, -8.0);
+                camera.far = AsteroidBelt::RADIUS * 4.0;
+                camera.movement_speed = 20.0;
+            }
+        }
 
         Application {
             glfw,
             window,
             events,
-            camera: Camera {
-                position: Point3 { x: 0.0, y: 0.0, z: 3.0 },
-                ..Camera::default()
-            },
+            camera,
             first_mouse: true,
             last_x: SCR_WIDTH as f32 / 2.0,
             last_y: SCR_HEIGHT as f32 / 2.0,
             delta_time: 0.0,
             last_frame: 0.0,
-            shaders,
-            scene
+            renderer,
+            scene,
+            asteroids,
+            physics: PhysicsWorld::new(),
+            capture: FrameCapture::new("screenshots"),
+            _gl_thread: gl_thread
         }
     }
 
@@ -103,14 +160,19 @@ impl Application {
             self.process_event();
             self.process_inputs();
             self.render();
+            // the back buffer has to be read before it is swapped
+            let (width, height) = self.renderer.size();
+            unsafe { self.capture.end_frame(CaptureSource::default_framebuffer(width, height)) };
             self.window.swap_buffers();
             self.glfw.poll_events();
         }
+        unsafe { self.capture.finish() };
     }
 
     fn update_delta_time(&mut self) {
         let current_frame = self.glfw.get_time() as f32;
-        self.delta_time = current_frame - self.last_frame;
+        // a recording advances by a fixed step so the frames play back at the right speed
+        self.delta_time = self.capture.frame_time(current_frame - self.last_frame);
         self.last_frame = current_frame;
     }
 
@@ -119,6 +181,91 @@ impl Application {
             match event {
                 glfw::WindowEvent::FramebufferSize(width, height) => {
                     unsafe { gl::Viewport(0, 0, width, height) }
+                    self.renderer.resize(width, height);
+                }
+                glfw::WindowEvent::Key(Key::F1, _, Action::Press, _) => {
+                    // colour every shadow cascade
+                    self.renderer.shadow_map.toggle_debug();
+                }
+                glfw::WindowEvent::Key(Key::F2, _, Action::Press, _) => {
+                    self.camera.hdr.tonemapper = self.camera.hdr.tonemapper.next();
+                    println!("Tonemapper: {:?}", self.camera.hdr.tonemapper);
+                }
+                glfw::WindowEvent::Key(Key::F3, _, Action::Press, _) => {
+                    self.camera.hdr.bloom.enabled = !self.camera.hdr.bloom.enabled;
+                }
+                glfw::WindowEvent::Key(Key::F4, _, Action::Press, _) => {
+                    self.camera.hdr.enabled = !self.camera.hdr.enabled;
+                }
+                glfw::WindowEvent::Key(key @ (Key::F5 | Key::F6 | Key::F7 | Key::F8 | Key::F9), _, Action::Press, _) => {
+                    let effect = match key {
+                        Key::F5 => "fxaa",
+                        Key::F6 => "sharpen",
+                        Key::F7 => "chromatic_aberration",
+                        Key::F8 => "film_grain",
+                        _ => "vignette"
+                    };
+                    let enabled = self.renderer.post_process.toggle(effect);
+                    println!("Post effect {}: {}", effect, if enabled { "on" } else { "off" });
+                }
+                glfw::WindowEvent::Key(Key::F10, _, Action::Press, _) => {
+                    self.renderer.ssao.toggle();
+                }
+                glfw::WindowEvent::Key(Key::F11, _, Action::Press, _) => {
+                    self.renderer.toggle_render_path();
+                    println!("Render path: {:?}", self.renderer.config.path);
+                }
+                glfw::WindowEvent::Key(Key::F12, _, Action::Press, _) => {
+                    self.renderer.toggle_transparency_mode();
+                    println!("Transparency: {:?}", self.renderer.config.transparency);
+                }
+                glfw::WindowEvent::Key(Key::I, _, Action::Press, _) => {
+                    let enabled = self.renderer.profiler.toggle();
+                    println!("Profiler: {}", if enabled { "on" } else { "off" });
+                }
+                glfw::WindowEvent::Key(Key::C, _, Action::Press, _) => {
+                    self.renderer.toggle_frustum_culling();
+                    println!("Frustum culling: {}", if self.renderer.config.frustum_culling { "on" } else { "off" });
+                }
+                glfw::WindowEvent::Key(Key::F, _, Action::Press, _) => {
+                    // frame the whole scene
+                    if let Some(bounds) = self.scene.bounds() {
+                        self.camera.focus(&bounds.sphere, self.renderer.aspect_ratio());
+                    }
+                }
+                glfw::WindowEvent::MouseButton(glfw::MouseButtonLeft, Action::Press, _) => {
+                    // the cursor is captured, so pick what is under the crosshair in the middle of the window
+                    let (width, height) = self.window.get_size();
+                    let (width, height) = (width as f32, height as f32);
+                    let ray = self.camera.screen_ray(width / 2.0, height / 2.0, width, height);
+                    match ray.and_then(|ray| self.scene.raycast(&ray)) {
+                        Some(hit) => println!("Picked entity {} mesh {} triangle {} at {:.2} units", hit.entity, hit.mesh, hit.triangle, hit.distance),
+                        None => println!("Picked nothing")
+                    }
+                }
+                glfw::WindowEvent::Key(Key::P, _, Action::Press, modifiers) => {
+                    // shift saves the HDR scene colour before tonemapping
+                    let path = unsafe {
+                        if modifiers.contains(glfw::Modifiers::Shift) {
+                            let source = CaptureSource::framebuffer(&self.renderer.hdr.scene_target, 0);
+                            self.capture.screenshot(source, CaptureFormat::EXR)
+                        } else {
+                            let (width, height) = self.renderer.size();
+                            self.capture.screenshot(CaptureSource::default_framebuffer(width, height), CaptureFormat::PNG)
+                        }
+                    };
+                    println!("Screenshot: {}", path.display());
+                }
+                glfw::WindowEvent::Key(Key::R, _, Action::Press, _) => {
+                    if self.capture.is_recording() {
+                        let frames = unsafe { self.capture.stop_recording() };
+                        println!("Recording stopped after {} frames", frames);
+                    } else {
+                        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+                        let directory = format!("recordings/{}", seconds);
+                        self.capture.start_recording(Path::new(&directory), RECORDING_FPS, CaptureFormat::PNG);
+                        println!("Recording to {}", directory);
+                    }
                 }
                 glfw::WindowEvent::CursorPos(xpos, ypos) => {
                     let (xpos, ypos) = (xpos as f32, ypos as f32);
@@ -164,42 +311,64 @@ impl Application {
     }
 
     fn render(&mut self) {
+        // pick up the shaders edited since the last frame
+        self.renderer.reload_changed_shaders();
+
+        // update the scene
+        self.scene.update();
+        if let Some(asteroids) = &mut self.asteroids {
+            asteroids.update(&mut self.scene, self.delta_time);
+        }
+        self.physics.update(&mut self.scene, self.delta_time);
+
+        // render the scene
         unsafe {
-            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
-            self.shaders.get(&ShaderType::MODEL)
-                .expect("ShaderType is not initialized")
-                .use_program();
-
-            // view / projection transformations
-            let projection: Matrix4<f32> = perspective(
-                Deg(self.camera.zoom),
-                SCR_WIDTH as f32 / SCR_HEIGHT as f32,
-                0.1,
-                100.0
-            );
-            let view = self.camera.get_view_matrix();
-            self.shaders.get(&ShaderType::MODEL)
-                .expect("ShaderType is not initialized")
-                .set_mat4(c_str!("projection"), &projection);
-
-            self.shaders.get(&ShaderType::MODEL)
-                .expect("ShaderType is not initialized")
-                .set_mat4(c_str!("view"), &view);
-
-            // update the scene
-            self.scene.update();
-
-            // render the scene
-            self.scene.render(
-                &self.shaders.get(&ShaderType::MODEL).expect("ShaderType is not initialized")
-            );
-            self.scene.skybox.draw(
-                projection, 
-                &self.camera,
-                &self.shaders.get(&ShaderType::SKYBOX).expect("ShaderType is not initialized")
-            );
+            self.renderer.render(&mut self.scene, &self.camera, self.delta_time);
         }
     }
-}
\ No newline at end of file
+}
+
+// the nanosuit with cutout foliage and blended windows in front of it, lit by many small lights
+fn populate_showcase(scene: &mut Scene) {
+    let model = Model::new("resources/objects/nanosuit/nanosuit.obj");
+    scene.entities.push(Entity::new(
+        Some(model),
+        Transform::new(
+            vec3(0.0, -1.75, 0.0),
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.2, 0.2, 0.2)
+        )
+    ));
+
+    // grass (alpha tested) and windows (blended) in front of the model
+    for &(x, z) in &[(-1.5, 0.5), (1.2, 0.8), (-0.4, 1.4)] {
+        let grass = Model::textured_quad("resources/textures/grass.png", Material {
+            blend_mode: BlendMode::CUTOUT,
+            ..Material::default()
+        });
+        scene.entities.push(Entity::new(Some(grass), Transform::new(vec3(x, -1.25, z), vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0))));
+    }
+    for &(x, z) in &[(-0.8, 1.8), (0.6, 2.2), (0.0, 1.0)] {
+        let window = Model::textured_quad("resources/textures/window.png", Material {
+            blend_mode: BlendMode::ALPHA,
+            ..Material::default()
+        });
+        scene.entities.push(Entity::new(Some(window), Transform::new(vec3(x, -0.5, z), vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0))));
+    }
+
+    // a few coloured lights around the model
+    scene.point_lights.push(PointLight::new(vec3(1.5, 0.5, 1.0), vec3(1.0, 0.3, 0.2), 4.0, 5.0));
+    scene.point_lights.push(PointLight::new(vec3(-1.5, 0.5, 1.0), vec3(0.2, 0.4, 1.0), 4.0, 5.0));
+    scene.point_lights.push(PointLight::new(vec3(0.0, 2.0, -1.5), vec3(0.3, 1.0, 0.4), 4.0, 5.0));
+
+    // a field of small lights to exercise the clustered light culling
+    let mut rng = rand::thread_rng();
+    for x in -10..10 {
+        for z in -10..10 {
+            let color = vec3(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+            scene.point_lights.push(PointLight::new(vec3(x as f32, -1.6, z as f32), color, 1.5, 1.2));
+        }
+    }
+    scene.spot_lights.push(SpotLight::new(vec3(0.0, 3.0, 2.0), vec3(0.0, -1.0, -0.8), vec3(1.0, 0.9, 0.7), 8.0, 8.0));
+    scene.spot_lights.push(SpotLight::new(vec3(-3.0, 2.0, 0.0), vec3(1.0, -0.6, 0.0), vec3(0.6, 0.6, 1.0), 6.0, 8.0));
+}
@@ -1,9 +1,12 @@
 use cgmath;
 use cgmath::vec3;
 use cgmath::prelude::*;
-use cgmath::Matrix4;
+use cgmath::{ perspective, Deg, Matrix4 };
 use cgmath::Point3;
-use cgmath::Vector3;
+use cgmath::{ Vector3, Vector4 };
+
+use crate::graphics::hdr::HdrSettings;
+use crate::world::bounds::{ BoundingSphere, Ray };
 
 // Defines several possible options for camera movement. Used as abstraction to stay away from window-system specific input methods
 #[derive(PartialEq, Clone, Copy)]
@@ -20,6 +23,8 @@ const PITCH: f32 = 0.0;
 const SPEED: f32 = 2.5;
 const SENSITIVITY: f32 = 0.1;
 const ZOOM: f32 = 45.0;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
 
 pub struct Camera {
     // Camera attributes
@@ -36,7 +41,14 @@ pub struct Camera {
     // Camera options
     pub movement_speed: f32,
     pub mouse_sensitivity: f32,
-    pub zoom: f32
+    pub zoom: f32,
+
+    // Projection options
+    pub near: f32,
+    pub far: f32,
+
+    // Post-processing options
+    pub hdr: HdrSettings
 }
 
 impl Default for Camera {
@@ -51,7 +63,10 @@ impl Default for Camera {
             pitch: PITCH,
             movement_speed: SPEED,
             mouse_sensitivity: SENSITIVITY,
-            zoom: ZOOM
+            zoom: ZOOM,
+            near: NEAR,
+            far: FAR,
+            hdr: HdrSettings::default()
         };
         camera.update_camera_vectors();
         camera
@@ -59,11 +74,53 @@ impl Default for Camera {
 }
 
 impl Camera {
+    // a camera at `position` looking along the direction given by the Euler angles, in degrees
+    pub fn new(position: Point3<f32>, yaw: f32, pitch: f32) -> Camera {
+        let mut camera = Camera {
+            position,
+            yaw,
+            pitch,
+            ..Camera::default()
+        };
+        camera.update_camera_vectors();
+        camera
+    }
+
     // Returns the view matrix calculated using Eular Angles and the LookAt Matrix
     pub fn get_view_matrix(&self) -> Matrix4<f32> {
         Matrix4::look_at(self.position, self.position + self.front, self.up)
     }
 
+    // Returns the perspective projection matrix for the given viewport aspect ratio
+    pub fn get_projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        perspective(Deg(self.zoom), aspect, self.near, self.far)
+    }
+
+    // Move back along the view direction until `sphere` fits the narrower of the two fields of view
+    pub fn focus(&mut self, sphere: &BoundingSphere, aspect: f32) {
+        let vertical = (self.zoom * 0.5).to_radians();
+        let horizontal = (vertical.tan() * aspect).atan();
+        let distance = sphere.radius / vertical.min(horizontal).sin();
+        self.position = Point3::from_vec(sphere.center - self.front * distance);
+    }
+
+    // The ray from the near plane through the window point (x, y), in pixels from the top left of a
+    // `width` by `height` window, e.g. the mouse cursor. None for an empty window, e.g. a minimized one.
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Option<Ray> {
+        if width <= 0.0 || height <= 0.0 {
+            return None;
+        }
+        let view_projection = self.get_projection_matrix(width / height) * self.get_view_matrix();
+        let inverse = view_projection.invert()?;
+        let (ndc_x, ndc_y) = (2.0 * x / width - 1.0, 1.0 - 2.0 * y / height);
+        let unproject = |depth: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            point.truncate() / point.w
+        };
+        let near = unproject(-1.0);
+        Some(Ray::new(near, unproject(1.0) - near))
+    }
+
     // Processes input received from any keyboard-like input system. Accepts input parameter in the form of camera defined ENUM (to abstract it from windowing systems)
     pub fn process_keyboard(&mut self, direction: CameraMovement, delta_time: f32) {
         let velocity = self.movement_speed * delta_time;
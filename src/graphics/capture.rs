@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::fs::{ self, File };
+use std::io::{ self, BufWriter, Write };
+use std::path::{ Path, PathBuf };
+use std::ptr;
+use std::sync::mpsc::{ self, Sender };
+use std::thread::{ self, JoinHandle };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use gl;
+use gl::types::*;
+use image;
+
+use crate::graphics::device::GlDevice;
+use crate::graphics::framebuffer::Framebuffer;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+
+// readbacks in flight before a new capture waits for the oldest one
+const MAX_PENDING: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureFormat {
+    // 8 bit RGBA, what ends on screen
+    PNG,
+    // 32 bit float RGBA, keeps the range of HDR targets
+    EXR
+}
+
+impl CaptureFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CaptureFormat::PNG => "png",
+            CaptureFormat::EXR => "exr"
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<CaptureFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "png" => Some(CaptureFormat::PNG),
+            "exr" => Some(CaptureFormat::EXR),
+            _ => None
+        }
+    }
+}
+
+// The framebuffer and color attachment a capture reads from
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureSource {
+    pub fbo: u32,
+    pub attachment: u32,
+    pub width: i32,
+    pub height: i32
+}
+
+impl CaptureSource {
+    // the back buffer of the window, must be captured before the buffers are swapped
+    pub fn default_framebuffer(width: i32, height: i32) -> CaptureSource {
+        CaptureSource { fbo: 0, attachment: 0, width, height }
+    }
+
+    pub fn framebuffer(framebuffer: &Framebuffer, attachment: u32) -> CaptureSource {
+        CaptureSource { fbo: framebuffer.fbo(), attachment, width: framebuffer.width, height: framebuffer.height }
+    }
+}
+
+pub enum CapturePixels {
+    LDR(Vec<u8>),
+    HDR(Vec<f32>)
+}
+
+// RGBA pixels as read back from GL, rows go from bottom to top
+pub struct CapturedImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: CapturePixels
+}
+
+impl CapturedImage {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        }
+        match &self.pixels {
+            CapturePixels::LDR(pixels) => {
+                let rows = flip_rows(pixels, self.width * 4);
+                image::save_buffer(path, &rows, self.width as u32, self.height as u32, image::ColorType::RGBA(8))
+                    .map_err(|e| e.to_string())
+            }
+            CapturePixels::HDR(pixels) => {
+                let rows = flip_rows(pixels, self.width * 4);
+                let mut file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+                write_exr(&mut file, self.width, self.height, &rows).and_then(|_| file.flush()).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+fn flip_rows<T: Copy>(pixels: &[T], row_length: usize) -> Vec<T> {
+    pixels.chunks(row_length).rev().flatten().copied().collect()
+}
+
+// Write an uncompressed scanline OpenEXR image with float R, G, B and A channels. `rgba` is interleaved
+// and its rows go from top to bottom.
+pub fn write_exr<W: Write>(writer: &mut W, width: usize, height: usize, rgba: &[f32]) -> io::Result<()> {
+    fn attribute(header: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]) {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(type_name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+
+    // channels are stored in alphabetical order
+    const CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+    let mut channels = Vec::new();
+    for (name, _) in CHANNELS.iter() {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+        channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+        channels.extend_from_slice(&1i32.to_le_bytes()); // x and y sampling
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let mut window = Vec::new();
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // one scanline per chunk, each made of its y, its size and the channels one after the other
+    let line_size = width * CHANNELS.len() * 4;
+    let table_end = header.len() + height * 8;
+    for y in 0..height {
+        let offset = (table_end + y * (8 + line_size)) as u64;
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+    let mut line = Vec::with_capacity(8 + line_size);
+    for y in 0..height {
+        line.clear();
+        line.extend_from_slice(&(y as i32).to_le_bytes());
+        line.extend_from_slice(&(line_size as i32).to_le_bytes());
+        let row = &rgba[y * width * 4..(y + 1) * width * 4];
+        for &(_, channel) in CHANNELS.iter() {
+            for pixel in row.chunks(4) {
+                line.extend_from_slice(&pixel[channel].to_le_bytes());
+            }
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+// Numbered frames written at a fixed simulated timestep, whatever the time it took to render them
+pub struct Recorder {
+    pub directory: PathBuf,
+    pub format: CaptureFormat,
+    pub frame_time: f32,
+    pub frame: u32
+}
+
+impl Recorder {
+    pub fn new(directory: &Path, frames_per_second: f32, format: CaptureFormat) -> Recorder {
+        Recorder {
+            directory: directory.to_path_buf(),
+            format,
+            frame_time: 1.0 / frames_per_second,
+            frame: 0
+        }
+    }
+
+    // path of the next frame
+    pub fn next_path(&mut self) -> PathBuf {
+        let path = self.directory.join(format!("frame_{:05}.{}", self.frame, self.format.extension()));
+        self.frame += 1;
+        path
+    }
+}
+
+struct PendingReadback {
+    buffer: GpuHandle,
+    fence: GLsync,
+    width: usize,
+    height: usize,
+    format: CaptureFormat,
+    path: PathBuf
+}
+
+struct SaveJob {
+    image: CapturedImage,
+    path: PathBuf
+}
+
+// Screenshots and recordings. glReadPixels goes into a pixel pack buffer followed by a fence, so a capture
+// never waits on the GPU: the buffer is mapped once the fence signaled, a frame or two later, and the
+// image is encoded on a worker thread.
+pub struct FrameCapture {
+    pub screenshot_directory: PathBuf,
+    recorder: Option<Recorder>,
+    free_buffers: Vec<GpuHandle>,
+    pending: VecDeque<PendingReadback>,
+    jobs: Option<Sender<SaveJob>>,
+    worker: Option<JoinHandle<()>>,
+    screenshot_count: u32
+}
+
+impl FrameCapture {
+    pub fn new(screenshot_directory: &str) -> FrameCapture {
+        let (jobs, receiver) = mpsc::channel::<SaveJob>();
+        let worker = thread::spawn(move || {
+            for job in receiver {
+                if let Err(error) = job.image.save(&job.path) {
+                    println!("ERROR::CAPTURE:: failed to save {}: {}", job.path.display(), error);
+                }
+            }
+        });
+        FrameCapture {
+            screenshot_directory: PathBuf::from(screenshot_directory),
+            recorder: None,
+            free_buffers: Vec::new(),
+            pending: VecDeque::new(),
+            jobs: Some(jobs),
+            worker: Some(worker),
+            screenshot_count: 0
+        }
+    }
+
+    // queue a readback of `source`, the format follows the extension of `path` and defaults to PNG
+    pub unsafe fn capture(&mut self, source: CaptureSource, path: &Path) {
+        let format = CaptureFormat::from_path(path).unwrap_or(CaptureFormat::PNG);
+        if self.pending.len() >= MAX_PENDING {
+            self.resolve_oldest(true);
+        }
+
+        let (width, height) = (source.width.max(1) as usize, source.height.max(1) as usize);
+        let (type_, texel_size) = match format {
+            CaptureFormat::PNG => (gl::UNSIGNED_BYTE, 4),
+            CaptureFormat::EXR => (gl::FLOAT, 16)
+        };
+        let buffer = self.free_buffers.pop().unwrap_or_else(|| {
+            let mut buffer = 0;
+            gl::GenBuffers(1, &mut buffer);
+            GpuHandle::new(&GlDevice::current(), ResourceKind::BUFFER, buffer, "capture readback")
+        });
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer.id());
+        gl::BufferData(gl::PIXEL_PACK_BUFFER, (width * height * texel_size) as GLsizeiptr, ptr::null(), gl::STREAM_READ);
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, source.fbo);
+        gl::ReadBuffer(if source.fbo == 0 { gl::BACK } else { gl::COLOR_ATTACHMENT0 + source.attachment });
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, type_, ptr::null_mut());
+        let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+
+        self.pending.push_back(PendingReadback { buffer, fence, width, height, format, path: path.to_path_buf() });
+    }
+
+    // capture `source` into the screenshot directory with a unique name, returns the path it will be saved to
+    pub unsafe fn screenshot(&mut self, source: CaptureSource, format: CaptureFormat) -> PathBuf {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let name = format!("screenshot_{}_{:03}.{}", seconds, self.screenshot_count, format.extension());
+        self.screenshot_count += 1;
+        let path = self.screenshot_directory.join(name);
+        self.capture(source, &path);
+        path
+    }
+
+    pub fn start_recording(&mut self, directory: &Path, frames_per_second: f32, format: CaptureFormat) {
+        self.recorder = Some(Recorder::new(directory, frames_per_second, format));
+    }
+
+    // stop recording and return the number of frames written
+    pub unsafe fn stop_recording(&mut self) -> u32 {
+        self.finish_pending();
+        self.recorder.take().map_or(0, |recorder| recorder.frame)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // the timestep to simulate the frame with: fixed while recording, the measured one otherwise
+    pub fn frame_time(&self, delta_time: f32) -> f32 {
+        self.recorder.as_ref().map_or(delta_time, |recorder| recorder.frame_time)
+    }
+
+    // call once the frame is rendered, before the buffers are swapped: captures the frame when recording
+    // and hands the readbacks that completed to the worker
+    pub unsafe fn end_frame(&mut self, source: CaptureSource) {
+        if let Some(path) = self.recorder.as_mut().map(|recorder| recorder.next_path()) {
+            self.capture(source, &path);
+        }
+        while self.pending.front().is_some_and(|readback| is_signaled(readback.fence)) {
+            self.resolve_oldest(false);
+        }
+    }
+
+    // wait for every readback and every file to be written, then release the buffers
+    pub unsafe fn finish(&mut self) {
+        self.finish_pending();
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+        self.free_buffers.clear();
+    }
+
+    unsafe fn finish_pending(&mut self) {
+        while !self.pending.is_empty() {
+            self.resolve_oldest(true);
+        }
+    }
+
+    unsafe fn resolve_oldest(&mut self, wait: bool) {
+        let readback = match self.pending.pop_front() {
+            Some(readback) => readback,
+            None => return
+        };
+        if wait {
+            gl::ClientWaitSync(readback.fence, gl::SYNC_FLUSH_COMMANDS_BIT, u64::MAX);
+        }
+        gl::DeleteSync(readback.fence);
+
+        let count = readback.width * readback.height * 4;
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, readback.buffer.id());
+        let pixels = match readback.format {
+            CaptureFormat::PNG => {
+                let data = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, count as GLsizeiptr, gl::MAP_READ_BIT) as *const u8;
+                CapturePixels::LDR(if data.is_null() { vec![0; count] } else { std::slice::from_raw_parts(data, count).to_vec() })
+            }
+            CaptureFormat::EXR => {
+                let data = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, (count * 4) as GLsizeiptr, gl::MAP_READ_BIT) as *const f32;
+                CapturePixels::HDR(if data.is_null() { vec![0.0; count] } else { std::slice::from_raw_parts(data, count).to_vec() })
+            }
+        };
+        gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        self.free_buffers.push(readback.buffer);
+
+        let image = CapturedImage { width: readback.width, height: readback.height, pixels };
+        if let Some(jobs) = &self.jobs {
+            jobs.send(SaveJob { image, path: readback.path }).ok();
+        }
+    }
+}
+
+pub(crate) unsafe fn is_signaled(fence: GLsync) -> bool {
+    let status = gl::ClientWaitSync(fence, 0, 0);
+    status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED
+}
@@ -0,0 +1,369 @@
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::os::raw::c_void;
+
+use cgmath::{ vec4, Matrix4, Vector3, Vector4 };
+use cgmath::prelude::*;
+use gl;
+use gl::types::*;
+
+use crate::graphics::device::GlDevice;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::shader::Shader;
+use crate::world::scene::Scene;
+
+// texture units of the light buffers in the model pass, after the shadow map and SSAO units
+pub const LIGHT_DATA_UNIT: u32 = 12;
+pub const CLUSTER_GRID_UNIT: u32 = 13;
+pub const LIGHT_INDEX_UNIT: u32 = 14;
+
+// must match the light fetch in model.fs
+const TEXELS_PER_LIGHT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ClusterConfig {
+    // number of screen-space tiles along x and y
+    pub tiles_x: usize,
+    pub tiles_y: usize,
+    // number of exponentially distributed slices between the near and far planes
+    pub depth_slices: usize,
+    // lights past this count are dropped from a cluster
+    pub max_lights_per_cluster: usize
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        ClusterConfig {
+            tiles_x: 16,
+            tiles_y: 9,
+            depth_slices: 24,
+            max_lights_per_cluster: 128
+        }
+    }
+}
+
+// View-space axis aligned box enclosing one cluster
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ClusterBounds {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>
+}
+
+impl ClusterBounds {
+    fn from_points(points: &[Vector3<f32>]) -> ClusterBounds {
+        let mut bounds = ClusterBounds { min: points[0], max: points[0] };
+        for point in &points[1..] {
+            bounds.min = vec3_min(bounds.min, *point);
+            bounds.max = vec3_max(bounds.max, *point);
+        }
+        bounds
+    }
+
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        let closest = vec3_max(self.min, vec3_min(center, self.max));
+        (closest - center).magnitude2() <= radius * radius
+    }
+}
+
+// View-space bounding sphere of a light, the input of the light assignment
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LightSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32
+}
+
+// Light lists of every cluster. `clusters` holds an (offset, count) range into `indices` per cluster.
+pub struct LightAssignment {
+    pub clusters: Vec<[u32; 2]>,
+    pub indices: Vec<u32>,
+    // number of light references dropped because a cluster was full
+    pub dropped: usize
+}
+
+impl LightAssignment {
+    pub fn lights_in(&self, cluster: usize) -> &[u32] {
+        let [offset, count] = self.clusters[cluster];
+        &self.indices[offset as usize..(offset + count) as usize]
+    }
+}
+
+// Splits the view frustum into tiles_x * tiles_y * depth_slices clusters. Clusters are indexed x first,
+// then y (from the bottom of the screen), then depth.
+pub struct ClusterGrid {
+    pub config: ClusterConfig,
+    projection: Matrix4<f32>,
+    near: f32,
+    far: f32,
+    bounds: Vec<ClusterBounds>
+}
+
+impl ClusterGrid {
+    // `projection` must be a perspective projection with the given near and far planes
+    pub fn new(config: ClusterConfig, projection: &Matrix4<f32>, near: f32, far: f32) -> ClusterGrid {
+        let mut grid = ClusterGrid {
+            config,
+            projection: *projection,
+            near,
+            far,
+            bounds: Vec::new()
+        };
+        grid.bounds = grid.compute_bounds();
+        grid
+    }
+
+    // whether the cluster bounds are still valid for this configuration and projection
+    pub fn matches(&self, config: &ClusterConfig, projection: &Matrix4<f32>, near: f32, far: f32) -> bool {
+        self.config == *config && self.projection == *projection && self.near == near && self.far == far
+    }
+
+    pub fn cluster_count(&self) -> usize {
+        self.config.tiles_x * self.config.tiles_y * self.config.depth_slices
+    }
+
+    pub fn cluster_index(&self, x: usize, y: usize, slice: usize) -> usize {
+        x + self.config.tiles_x * (y + self.config.tiles_y * slice)
+    }
+
+    pub fn bounds(&self, cluster: usize) -> &ClusterBounds {
+        &self.bounds[cluster]
+    }
+
+    // view-space distance at which `slice` starts, `depth_slices` gives the far plane
+    pub fn slice_depth(&self, slice: usize) -> f32 {
+        self.near * (self.far / self.near).powf(slice as f32 / self.config.depth_slices as f32)
+    }
+
+    // slice containing a positive view-space distance, clamped to the grid
+    pub fn depth_slice(&self, depth: f32) -> usize {
+        let (scale, bias) = self.slice_scale_bias();
+        let slice = (depth.max(self.near).ln() * scale + bias).floor();
+        (slice.max(0.0) as usize).min(self.config.depth_slices - 1)
+    }
+
+    // slice = floor(ln(depth) * scale + bias), evaluated per fragment in the shader
+    pub fn slice_scale_bias(&self) -> (f32, f32) {
+        let scale = self.config.depth_slices as f32 / (self.far / self.near).ln();
+        (scale, -self.near.ln() * scale)
+    }
+
+    fn compute_bounds(&self) -> Vec<ClusterBounds> {
+        let inverse_projection = self.projection.invert().expect("Projection matrix is not invertible");
+        // direction through a point of the near plane, scaled so it reaches a view-space distance of 1
+        let ray = |ndc_x: f32, ndc_y: f32| {
+            let point: Vector4<f32> = inverse_projection * vec4(ndc_x, ndc_y, -1.0, 1.0);
+            let point = point.truncate() / point.w;
+            point / -point.z
+        };
+
+        let config = &self.config;
+        let mut bounds = vec![ClusterBounds { min: Vector3::zero(), max: Vector3::zero() }; self.cluster_count()];
+        for y in 0..config.tiles_y {
+            let ndc_y0 = -1.0 + 2.0 * y as f32 / config.tiles_y as f32;
+            let ndc_y1 = -1.0 + 2.0 * (y + 1) as f32 / config.tiles_y as f32;
+            for x in 0..config.tiles_x {
+                let ndc_x0 = -1.0 + 2.0 * x as f32 / config.tiles_x as f32;
+                let ndc_x1 = -1.0 + 2.0 * (x + 1) as f32 / config.tiles_x as f32;
+                let rays = [ray(ndc_x0, ndc_y0), ray(ndc_x1, ndc_y0), ray(ndc_x0, ndc_y1), ray(ndc_x1, ndc_y1)];
+
+                for slice in 0..config.depth_slices {
+                    let (depth_near, depth_far) = (self.slice_depth(slice), self.slice_depth(slice + 1));
+                    let mut corners = [Vector3::zero(); 8];
+                    for (i, ray) in rays.iter().enumerate() {
+                        corners[i] = ray * depth_near;
+                        corners[i + 4] = ray * depth_far;
+                    }
+                    bounds[self.cluster_index(x, y, slice)] = ClusterBounds::from_points(&corners);
+                }
+            }
+        }
+        bounds
+    }
+
+    // Builds the light list of every cluster. Lights keep their order inside a list, so a full cluster
+    // drops the lights that come last in `lights`.
+    pub fn assign(&self, lights: &[LightSphere]) -> LightAssignment {
+        let config = &self.config;
+        let mut lists: Vec<Vec<u32>> = vec![Vec::new(); self.cluster_count()];
+        let mut dropped = 0;
+
+        for (light_index, light) in lights.iter().enumerate() {
+            // the camera looks down -Z
+            let depth = -light.center.z;
+            if depth + light.radius < self.near || depth - light.radius > self.far {
+                continue;
+            }
+
+            // only the slices the sphere overlaps need testing
+            let first_slice = self.depth_slice(depth - light.radius);
+            let last_slice = self.depth_slice(depth + light.radius);
+            for slice in first_slice..=last_slice {
+                for y in 0..config.tiles_y {
+                    for x in 0..config.tiles_x {
+                        let cluster = self.cluster_index(x, y, slice);
+                        if !self.bounds[cluster].intersects_sphere(light.center, light.radius) {
+                            continue;
+                        }
+                        if lists[cluster].len() < config.max_lights_per_cluster {
+                            lists[cluster].push(light_index as u32);
+                        } else {
+                            dropped += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut clusters = Vec::with_capacity(lists.len());
+        let mut indices = Vec::new();
+        for list in lists {
+            clusters.push([indices.len() as u32, list.len() as u32]);
+            indices.extend(list);
+        }
+        LightAssignment { clusters, indices, dropped }
+    }
+}
+
+fn vec3_min(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn vec3_max(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+// A buffer object read in shaders through a buffer texture
+struct TextureBuffer {
+    buffer: GpuHandle,
+    texture: GpuHandle,
+    format: GLenum
+}
+
+impl TextureBuffer {
+    unsafe fn new(format: GLenum) -> TextureBuffer {
+        let (mut buffer, mut texture) = (0, 0);
+        gl::GenBuffers(1, &mut buffer);
+        gl::GenTextures(1, &mut texture);
+        let device = GlDevice::current();
+        TextureBuffer {
+            buffer: GpuHandle::new(&device, ResourceKind::BUFFER, buffer, "light buffer"),
+            texture: GpuHandle::new(&device, ResourceKind::TEXTURE, texture, "light buffer texture"),
+            format
+        }
+    }
+
+    // replace the whole content, the old storage is orphaned so the upload doesn't stall on the previous frame
+    unsafe fn upload<T>(&self, data: &[T]) {
+        gl::BindBuffer(gl::TEXTURE_BUFFER, self.buffer.id());
+        gl::BufferData(
+            gl::TEXTURE_BUFFER,
+            (data.len().max(1) * size_of::<T>()) as isize,
+            if data.is_empty() { std::ptr::null() } else { data.as_ptr() as *const c_void },
+            gl::STREAM_DRAW
+        );
+        gl::BindTexture(gl::TEXTURE_BUFFER, self.texture.id());
+        gl::TexBuffer(gl::TEXTURE_BUFFER, self.format, self.buffer.id());
+        gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+    }
+
+    unsafe fn bind(&self, unit: u32) {
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        gl::BindTexture(gl::TEXTURE_BUFFER, self.texture.id());
+    }
+}
+
+// Clustered forward shading: every frame the point and spot lights of the scene are assigned to the
+// clusters they reach, and the model shader only loops over the lights of the fragment's cluster.
+//
+// Light data, three RGBA32F texels per light:
+//  0: world-space position, radius
+//  1: color * intensity, cosine of the inner cone angle
+//  2: direction, cosine of the outer cone angle
+// Point lights use an outer cosine of -2 and an inner cosine of -1, so their cone factor is always 1.
+pub struct ClusteredLighting {
+    pub config: ClusterConfig,
+    // built on the first update and whenever the projection or the configuration changes
+    grid: Option<ClusterGrid>,
+    light_data: TextureBuffer,
+    cluster_grid: TextureBuffer,
+    light_indices: TextureBuffer,
+    // lights uploaded last frame and references dropped because of full clusters
+    pub light_count: usize,
+    pub dropped: usize
+}
+
+impl ClusteredLighting {
+    pub fn new(config: ClusterConfig) -> ClusteredLighting {
+        unsafe {
+            ClusteredLighting {
+                config,
+                grid: None,
+                light_data: TextureBuffer::new(gl::RGBA32F),
+                cluster_grid: TextureBuffer::new(gl::RG32UI),
+                light_indices: TextureBuffer::new(gl::R32UI),
+                light_count: 0,
+                dropped: 0
+            }
+        }
+    }
+
+    // assign the scene lights to clusters and upload the result
+    pub unsafe fn update(&mut self, scene: &Scene, view: &Matrix4<f32>, projection: &Matrix4<f32>, near: f32, far: f32) {
+        let config = self.config;
+        if !self.grid.as_ref().is_some_and(|grid| grid.matches(&config, projection, near, far)) {
+            self.grid = Some(ClusterGrid::new(config, projection, near, far));
+        }
+        let grid = self.grid.as_ref().unwrap();
+
+        let light_count = scene.point_lights.len() + scene.spot_lights.len();
+        let mut spheres = Vec::with_capacity(light_count);
+        let mut data: Vec<[f32; 4]> = Vec::with_capacity(light_count * TEXELS_PER_LIGHT);
+        let to_view = |position: Vector3<f32>| (view * position.extend(1.0)).truncate();
+
+        for light in &scene.point_lights {
+            spheres.push(LightSphere { center: to_view(light.position), radius: light.radius });
+            let color = light.color * light.intensity;
+            data.push([light.position.x, light.position.y, light.position.z, light.radius]);
+            data.push([color.x, color.y, color.z, -1.0]);
+            data.push([0.0, 0.0, 0.0, -2.0]);
+        }
+        for light in &scene.spot_lights {
+            let (center, radius) = light.bounding_sphere();
+            spheres.push(LightSphere { center: to_view(center), radius });
+            let color = light.color * light.intensity;
+            let direction = light.direction.normalize();
+            data.push([light.position.x, light.position.y, light.position.z, light.radius]);
+            data.push([color.x, color.y, color.z, light.inner_angle.to_radians().cos()]);
+            data.push([direction.x, direction.y, direction.z, light.outer_angle.to_radians().cos()]);
+        }
+
+        let assignment = grid.assign(&spheres);
+        self.light_data.upload(&data);
+        self.cluster_grid.upload(&assignment.clusters);
+        self.light_indices.upload(&assignment.indices);
+        self.light_count = light_count;
+        self.dropped = assignment.dropped;
+    }
+
+    // bind the light buffers and grid parameters on a shader shading with the clustered lights, after `update`
+    pub unsafe fn bind(&self, shader: &Shader, width: i32, height: i32) {
+        let grid = self.grid.as_ref().expect("Clustered lights are bound before their first update");
+        shader.use_program();
+        self.light_data.bind(LIGHT_DATA_UNIT);
+        self.cluster_grid.bind(CLUSTER_GRID_UNIT);
+        self.light_indices.bind(LIGHT_INDEX_UNIT);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        let config = &grid.config;
+        let (scale, bias) = grid.slice_scale_bias();
+        shader.set_int(c_str!("lightData"), LIGHT_DATA_UNIT as i32);
+        shader.set_int(c_str!("clusterGrid"), CLUSTER_GRID_UNIT as i32);
+        shader.set_int(c_str!("lightIndices"), LIGHT_INDEX_UNIT as i32);
+        shader.set_ivec3(c_str!("clusterDims"), config.tiles_x as i32, config.tiles_y as i32, config.depth_slices as i32);
+        shader.set_vec2(c_str!("clusterDepthScaleBias"), scale, bias);
+        shader.set_vec2(
+            c_str!("clusterTileScale"),
+            config.tiles_x as f32 / width.max(1) as f32,
+            config.tiles_y as f32 / height.max(1) as f32
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::path::Path;
+
+use crate::graphics::device::{ GlDevice, ImageAccess, ImageFormat, RenderDevice, ShaderStage, UniformValue };
+use crate::graphics::preprocessor::ShaderPreprocessor;
+use crate::graphics::shader::Shader;
+
+// A compute program and the size of its work groups. Compute needs an OpenGL 4.3 context, on 3.3 the
+// constructor fails without touching GL so the caller can keep a CPU path for the same work.
+pub struct ComputeShader {
+    pub shader: Shader,
+    // local_size_x, _y and _z declared by the source, 1 when left out
+    pub local_size: [u32; 3]
+}
+
+impl ComputeShader {
+    pub fn new(path: &str, keywords: &[&str]) -> Result<ComputeShader, String> {
+        ComputeShader::with_device(unsafe { &mut GlDevice::current() }, path, keywords)
+    }
+
+    pub fn with_device<D: RenderDevice>(device: &mut D, path: &str, keywords: &[&str]) -> Result<ComputeShader, String> {
+        if !ComputeShader::is_supported(device) {
+            let (major, minor) = device.features().version;
+            return Err(format!("ERROR::SHADER:: compute shaders need OpenGL 4.3, the context is {}.{}", major, minor));
+        }
+        let shader = Shader::builder().stage(ShaderStage::COMPUTE, path).keywords(keywords).build_with(device)?;
+        let source = ShaderPreprocessor::default().process_file(Path::new(path), &shader.keywords)?;
+        Ok(ComputeShader { shader, local_size: local_size(&source.code) })
+    }
+
+    pub fn is_supported<D: RenderDevice>(device: &mut D) -> bool {
+        let features = device.features();
+        features.compute && features.storage_buffers
+    }
+
+    // work groups covering `work_items` invocations in each dimension
+    pub fn group_count(&self, work_items: [u32; 3]) -> [u32; 3] {
+        let mut groups = [1; 3];
+        for i in 0..3 {
+            groups[i] = work_items[i].div_ceil(self.local_size[i]).max(1);
+        }
+        groups
+    }
+
+    pub fn set_uniform<D: RenderDevice>(&self, device: &mut D, name: &CStr, value: UniformValue) {
+        device.use_program(self.shader.id);
+        self.shader.set_with(device, name, value);
+    }
+
+    // `buffer` was created with BufferKind::STORAGE, `binding` matches the block's layout(binding = N)
+    pub fn bind_storage_buffer<D: RenderDevice>(&self, device: &mut D, binding: u32, buffer: u32) {
+        device.bind_storage_buffer(binding, buffer);
+    }
+
+    // `unit` matches the image's layout(binding = N), `format` its layout format qualifier
+    pub fn bind_image<D: RenderDevice>(&self, device: &mut D, unit: u32, texture: u32, access: ImageAccess, format: ImageFormat) {
+        device.bind_image(unit, texture, access, format);
+    }
+
+    pub fn dispatch<D: RenderDevice>(&self, device: &mut D, groups: [u32; 3]) {
+        device.use_program(self.shader.id);
+        device.dispatch_compute(groups);
+    }
+
+    // run one invocation per work item and make the results visible to what comes next
+    pub fn run<D: RenderDevice>(&self, device: &mut D, work_items: [u32; 3]) {
+        self.dispatch(device, self.group_count(work_items));
+        device.memory_barrier();
+    }
+}
+
+// the work group size of `layout (local_size_x = 8, local_size_y = 8) in;`, sizes may be #defines
+fn local_size(code: &str) -> [u32; 3] {
+    let mut defines = HashMap::new();
+    let mut size = [1; 3];
+    for line in code.lines().map(str::trim) {
+        let tokens: Vec<&str> = line.split(|c: char| c.is_whitespace() || "(),=;".contains(c)).filter(|t| !t.is_empty()).collect();
+        match tokens.as_slice() {
+            ["#define", name, value, ..] => {
+                if let Ok(value) = value.parse::<u32>() {
+                    defines.insert(name.to_string(), value);
+                }
+            }
+            ["layout", qualifiers @ .., "in"] => {
+                for pair in qualifiers.windows(2) {
+                    let axis = match pair[0] {
+                        "local_size_x" => 0,
+                        "local_size_y" => 1,
+                        "local_size_z" => 2,
+                        _ => continue
+                    };
+                    if let Some(value) = pair[1].parse().ok().or_else(|| defines.get(pair[1]).copied()) {
+                        size[axis] = value;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    size
+}
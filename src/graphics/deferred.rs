@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+
+use cgmath::prelude::*;
+use gl;
+
+use crate::graphics::device::GlDevice;
+use crate::graphics::framebuffer::{ Framebuffer, FullscreenTriangle, TextureFormat };
+use crate::graphics::instancing::InstanceBuffer;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::shader::{ Shader, ShaderType };
+use crate::graphics::shadow::CascadedShadowMap;
+use crate::graphics::ssao::SsaoPass;
+use crate::graphics::state_tracker::{ StateStats, StateTracker };
+use crate::world::scene::{ Culling, Scene };
+
+const SPHERE_SEGMENTS: u32 = 16;
+const SPHERE_RINGS: u32 = 12;
+
+// Builds a UV sphere of radius 1, returns the positions (xyz) and triangle indices
+pub fn unit_sphere(segments: u32, rings: u32) -> (Vec<f32>, Vec<u32>) {
+    let mut positions = Vec::new();
+    for ring in 0..=rings {
+        let phi = PI * ring as f32 / rings as f32;
+        for segment in 0..=segments {
+            let theta = 2.0 * PI * segment as f32 / segments as f32;
+            positions.push(phi.sin() * theta.cos());
+            positions.push(phi.cos());
+            positions.push(phi.sin() * theta.sin());
+        }
+    }
+
+    let mut indices = Vec::new();
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let current = ring * (segments + 1) + segment;
+            let next = current + segments + 1;
+            indices.extend_from_slice(&[current, next, current + 1]);
+            indices.extend_from_slice(&[current + 1, next, next + 1]);
+        }
+    }
+    (positions, indices)
+}
+
+// Scale making the faceted unit_sphere circumscribe the unit sphere, its vertices are on the sphere so
+// its faces cut inside it. The faces on the equator come closest, their corners are half a ring and
+// half a segment away from their center.
+pub fn circumscribing_scale(segments: u32, rings: u32) -> f32 {
+    1.0 / ((PI / (2 * rings) as f32).cos() * (PI / segments as f32).cos())
+}
+
+// A sphere mesh drawn around every point and spot light so only the pixels it can reach are shaded
+struct LightVolume {
+    vao: GpuHandle,
+    _vbo: GpuHandle,
+    _ebo: GpuHandle,
+    index_count: i32,
+    scale: f32
+}
+
+impl LightVolume {
+    unsafe fn new() -> LightVolume {
+        let (positions, indices) = unit_sphere(SPHERE_SEGMENTS, SPHERE_RINGS);
+
+        let (mut vao, mut vbo, mut ebo) = (0, 0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::GenBuffers(1, &mut ebo);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (positions.len() * size_of::<f32>()) as isize,
+            positions.as_ptr() as *const c_void,
+            gl::STATIC_DRAW
+        );
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+        gl::BufferData(
+            gl::ELEMENT_ARRAY_BUFFER,
+            (indices.len() * size_of::<u32>()) as isize,
+            indices.as_ptr() as *const c_void,
+            gl::STATIC_DRAW
+        );
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * size_of::<f32>() as i32, ptr::null());
+        gl::BindVertexArray(0);
+
+        let scale = circumscribing_scale(SPHERE_SEGMENTS, SPHERE_RINGS);
+        let device = GlDevice::current();
+        LightVolume {
+            vao: GpuHandle::new(&device, ResourceKind::VERTEX_ARRAY, vao, "light volume vertex array"),
+            _vbo: GpuHandle::new(&device, ResourceKind::BUFFER, vbo, "light volume vertex buffer"),
+            _ebo: GpuHandle::new(&device, ResourceKind::BUFFER, ebo, "light volume index buffer"),
+            index_count: indices.len() as i32,
+            scale
+        }
+    }
+
+    unsafe fn draw(&self) {
+        gl::BindVertexArray(self.vao.id());
+        gl::DrawElements(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, ptr::null());
+        gl::BindVertexArray(0);
+    }
+}
+
+// G-buffer layout:
+//  0: albedo (RGB)
+//  1: world-space normal
+//  2: material parameters (specular, shininess / MAX_SHININESS)
+//  depth
+pub struct DeferredPipeline {
+    pub gbuffer: Framebuffer,
+    light_volume: LightVolume,
+    fullscreen: FullscreenTriangle
+}
+
+impl DeferredPipeline {
+    pub fn new(width: i32, height: i32) -> DeferredPipeline {
+        DeferredPipeline {
+            gbuffer: create_gbuffer(width, height),
+            light_volume: unsafe { LightVolume::new() },
+            fullscreen: FullscreenTriangle::new()
+        }
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if self.gbuffer.width == width && self.gbuffer.height == height {
+            return;
+        }
+        self.gbuffer = create_gbuffer(width, height);
+    }
+
+    // write the opaque geometry into the G-buffer
+    pub unsafe fn geometry_pass(&self, scene: &mut Scene, shader: &Shader, instances: &InstanceBuffer, culling: &Culling) -> StateStats {
+        self.gbuffer.bind();
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+        let mut device = GlDevice::current();
+        let mut tracker = StateTracker::new(&mut device);
+        scene.render_visible_with(&mut tracker, shader, instances, culling, |mesh| !mesh.material.is_transparent());
+        tracker.stats()
+    }
+
+    // accumulate the lighting of the G-buffer into `target`, which also receives the G-buffer depth
+    // so the skybox and the forward passes can depth test against the opaque geometry
+    pub unsafe fn lighting_pass(
+        &self,
+        scene: &Scene,
+        target: &Framebuffer,
+        shaders: &HashMap<ShaderType, Shader>,
+        shadow_map: &CascadedShadowMap,
+        ssao: &SsaoPass
+    ) {
+        let (width, height) = (self.gbuffer.width, self.gbuffer.height);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.gbuffer.fbo());
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.fbo());
+        gl::BlitFramebuffer(0, 0, width, height, 0, 0, target.width, target.height, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+
+        target.bind();
+        gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::Disable(gl::DEPTH_TEST);
+
+        // 1. ambient and directional light over the whole screen
+        let light_shader = get_shader(shaders, ShaderType::DEFERRED_LIGHT);
+        light_shader.use_program();
+        self.bind_gbuffer(light_shader);
+        let light = &scene.directional_light;
+        light_shader.set_vector3(c_str!("light.direction"), &light.direction);
+        light_shader.set_vector3(c_str!("light.color"), &light.color);
+        light_shader.set_float(c_str!("light.intensity"), light.intensity);
+        light_shader.set_float(c_str!("light.ambient"), light.ambient);
+        shadow_map.bind(light_shader);
+        ssao.bind(light_shader, width, height);
+        self.fullscreen.draw();
+
+        // 2. point and spot lights, additively blended light volumes. Only back faces are drawn so the volume
+        // still covers the screen when the camera is inside of it.
+        let point_shader = get_shader(shaders, ShaderType::DEFERRED_POINT);
+        point_shader.use_program();
+        self.bind_gbuffer(point_shader);
+        point_shader.set_vec2(c_str!("screenSize"), width as f32, height as f32);
+        point_shader.set_float(c_str!("volumeScale"), self.light_volume.scale);
+
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        gl::Enable(gl::CULL_FACE);
+        gl::CullFace(gl::FRONT);
+        for point_light in &scene.point_lights {
+            point_shader.set_vector3(c_str!("volumeCenter"), &point_light.position);
+            point_shader.set_float(c_str!("volumeRadius"), point_light.radius);
+            point_shader.set_vector3(c_str!("light.position"), &point_light.position);
+            point_shader.set_vector3(c_str!("light.color"), &point_light.color);
+            point_shader.set_float(c_str!("light.intensity"), point_light.intensity);
+            point_shader.set_float(c_str!("light.radius"), point_light.radius);
+            point_shader.set_vec3(c_str!("light.direction"), 0.0, 0.0, 0.0);
+            point_shader.set_float(c_str!("light.cosInner"), -1.0);
+            point_shader.set_float(c_str!("light.cosOuter"), -2.0);
+            self.light_volume.draw();
+        }
+        for spot_light in &scene.spot_lights {
+            let (center, radius) = spot_light.bounding_sphere();
+            point_shader.set_vector3(c_str!("volumeCenter"), &center);
+            point_shader.set_float(c_str!("volumeRadius"), radius);
+            point_shader.set_vector3(c_str!("light.position"), &spot_light.position);
+            point_shader.set_vector3(c_str!("light.color"), &spot_light.color);
+            point_shader.set_float(c_str!("light.intensity"), spot_light.intensity);
+            point_shader.set_float(c_str!("light.radius"), spot_light.radius);
+            point_shader.set_vector3(c_str!("light.direction"), &spot_light.direction.normalize());
+            point_shader.set_float(c_str!("light.cosInner"), spot_light.inner_angle.to_radians().cos());
+            point_shader.set_float(c_str!("light.cosOuter"), spot_light.outer_angle.to_radians().cos());
+            self.light_volume.draw();
+        }
+        gl::CullFace(gl::BACK);
+        gl::Disable(gl::CULL_FACE);
+        gl::Disable(gl::BLEND);
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    unsafe fn bind_gbuffer(&self, shader: &Shader) {
+        let depth = self.gbuffer.depth_texture().expect("G-buffer has no depth");
+        let textures = [
+            self.gbuffer.color_texture(0),
+            self.gbuffer.color_texture(1),
+            self.gbuffer.color_texture(2),
+            depth
+        ];
+        for (i, texture) in textures.iter().enumerate() {
+            gl::ActiveTexture(gl::TEXTURE0 + i as u32);
+            gl::BindTexture(gl::TEXTURE_2D, *texture);
+        }
+        shader.set_int(c_str!("gAlbedo"), 0);
+        shader.set_int(c_str!("gNormal"), 1);
+        shader.set_int(c_str!("gMaterial"), 2);
+        shader.set_int(c_str!("gDepth"), 3);
+    }
+}
+
+fn create_gbuffer(width: i32, height: i32) -> Framebuffer {
+    Framebuffer::new(
+        width,
+        height,
+        &[TextureFormat::RGBA8, TextureFormat::RGB16F, TextureFormat::RGBA8],
+        true
+    )
+}
+
+fn get_shader(shaders: &HashMap<ShaderType, Shader>, shader_type: ShaderType) -> &Shader {
+    shaders.get(&shader_type).expect("ShaderType is not initialized")
+}
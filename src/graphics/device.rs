@@ -0,0 +1,1000 @@
+use core::str;
+use std::collections::HashMap;
+use std::ffi::{ CStr, CString };
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{ AtomicU32, Ordering };
+
+use gl;
+use gl::types::*;
+
+use crate::graphics::material::{ BlendMode, RenderQueue };
+use crate::graphics::reflection::{ AttributeInfo, ProgramReflection, UniformBlockInfo, UniformType };
+
+// Objects are referred to by the name the backend gave them. For the GL backend these are the GL object
+// names, so handles can still be mixed with the code calling GL directly. 0 is never a valid handle.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum BufferKind {
+    VERTEX,
+    INDEX,
+    UNIFORM,
+    // shader storage buffer, read and written by compute shaders
+    STORAGE
+}
+
+// How often the content of a buffer is expected to change
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum BufferUsage {
+    STATIC,
+    DYNAMIC,
+    STREAM
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum PixelFormat {
+    R8,
+    RG8,
+    RGB8,
+    RGBA8,
+    // color textures authored in sRGB, converted to linear when sampled
+    SRGB8,
+    SRGB8_ALPHA8
+}
+
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::R8 => 1,
+            PixelFormat::RG8 => 2,
+            PixelFormat::RGB8 | PixelFormat::SRGB8 => 3,
+            PixelFormat::RGBA8 | PixelFormat::SRGB8_ALPHA8 => 4
+        }
+    }
+
+    // (internal format, format) pair passed to glTexImage2D
+    fn gl_formats(self) -> (GLenum, GLenum) {
+        match self {
+            PixelFormat::R8 => (gl::R8, gl::RED),
+            PixelFormat::RG8 => (gl::RG8, gl::RG),
+            PixelFormat::RGB8 => (gl::RGB8, gl::RGB),
+            PixelFormat::RGBA8 => (gl::RGBA8, gl::RGBA),
+            PixelFormat::SRGB8 => (gl::SRGB8, gl::RGB),
+            PixelFormat::SRGB8_ALPHA8 => (gl::SRGB8_ALPHA8, gl::RGBA)
+        }
+    }
+}
+
+// 8 bit per channel pixels, rows bottom to top
+#[derive(Clone, Copy, Debug)]
+pub struct TextureData<'a> {
+    pub width: i32,
+    pub height: i32,
+    pub format: PixelFormat,
+    pub pixels: &'a [u8]
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum WrapMode {
+    REPEAT,
+    CLAMP_TO_EDGE
+}
+
+// Filtering is always linear, trilinear when the texture has mipmaps
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sampler {
+    pub wrap: WrapMode,
+    pub mipmaps: bool
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum TextureTarget {
+    TEXTURE_2D,
+    CUBE_MAP
+}
+
+// A float vertex attribute read from the vertex buffer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub components: i32,
+    pub offset: usize
+}
+
+// in pipeline order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[allow(non_camel_case_types)]
+pub enum ShaderStage {
+    VERTEX,
+    TESS_CONTROL,
+    TESS_EVALUATION,
+    GEOMETRY,
+    FRAGMENT,
+    COMPUTE
+}
+
+// Internal format of a texture bound as an image for load and store
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum ImageFormat {
+    R32F,
+    RGBA8,
+    RGBA16F,
+    RGBA32F
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum ImageAccess {
+    READ_ONLY,
+    WRITE_ONLY,
+    READ_WRITE
+}
+
+// Tells apart the devices objects were created by, so a handle is only ever deleted by its own device.
+// Every default constructed id is a new one.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct DeviceId(u32);
+
+impl DeviceId {
+    // the GL context, there is only ever one
+    pub const GL: DeviceId = DeviceId(0);
+}
+
+impl Default for DeviceId {
+    fn default() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(1);
+        DeviceId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// What the context can do beyond the OpenGL 3.3 baseline the engine requires
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DeviceFeatures {
+    pub version: (u32, u32),
+    pub geometry_shaders: bool,
+    pub tessellation: bool,
+    pub compute: bool,
+    pub storage_buffers: bool,
+    pub image_load_store: bool
+}
+
+impl DeviceFeatures {
+    // what the core profile of an OpenGL version provides
+    pub fn for_version(major: u32, minor: u32) -> DeviceFeatures {
+        let at_least = |m, n| (major, minor) >= (m, n);
+        DeviceFeatures {
+            version: (major, minor),
+            geometry_shaders: at_least(3, 2),
+            tessellation: at_least(4, 0),
+            compute: at_least(4, 3),
+            storage_buffers: at_least(4, 3),
+            image_load_store: at_least(4, 2)
+        }
+    }
+
+    pub fn supports(&self, stage: ShaderStage) -> bool {
+        match stage {
+            ShaderStage::VERTEX | ShaderStage::FRAGMENT => true,
+            ShaderStage::GEOMETRY => self.geometry_shaders,
+            ShaderStage::TESS_CONTROL | ShaderStage::TESS_EVALUATION => self.tessellation,
+            ShaderStage::COMPUTE => self.compute
+        }
+    }
+}
+
+impl Default for DeviceFeatures {
+    fn default() -> Self {
+        DeviceFeatures::for_version(3, 3)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum UniformValue {
+    INT(i32),
+    FLOAT(f32),
+    VEC2([f32; 2]),
+    VEC3([f32; 3]),
+    IVEC3([i32; 3]),
+    // column major
+    MAT4([[f32; 4]; 4])
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum DepthFunc {
+    LESS,
+    LEQUAL,
+    ALWAYS
+}
+
+// Fixed-function state used by a draw
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PipelineState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_func: DepthFunc,
+    // OPAQUE and CUTOUT disable blending
+    pub blend: BlendMode,
+    pub cull_back_faces: bool
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        PipelineState {
+            depth_test: true,
+            depth_write: true,
+            depth_func: DepthFunc::LESS,
+            blend: BlendMode::OPAQUE,
+            cull_back_faces: false
+        }
+    }
+}
+
+// A linked program and the state it is drawn with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pipeline {
+    pub program: u32,
+    pub state: PipelineState
+}
+
+// Everything the engine asks from the graphics API: resource creation, state and draw submission
+pub trait RenderDevice {
+    fn device_id(&self) -> DeviceId;
+
+    // the OpenGL 3.3 baseline unless the backend knows better
+    fn features(&mut self) -> DeviceFeatures {
+        DeviceFeatures::default()
+    }
+
+    fn create_buffer(&mut self, kind: BufferKind, usage: BufferUsage, data: &[u8]) -> u32;
+    fn update_buffer(&mut self, buffer: u32, kind: BufferKind, offset: usize, data: &[u8]);
+    fn delete_buffer(&mut self, buffer: u32);
+
+    fn create_texture(&mut self, data: &TextureData, sampler: Sampler) -> u32;
+    // faces in the +X, -X, +Y, -Y, +Z, -Z order
+    fn create_cubemap(&mut self, faces: &[TextureData]) -> u32;
+    fn delete_texture(&mut self, texture: u32);
+
+    fn create_vertex_array(&mut self, vertex_buffer: u32, index_buffer: Option<u32>, stride: i32, attributes: &[VertexAttribute]) -> u32;
+    fn delete_vertex_array(&mut self, vertex_array: u32);
+    // framebuffers are still created by the GL renderers themselves, only their deletion goes through the device
+    fn delete_framebuffer(&mut self, framebuffer: u32);
+    // point per-instance attributes of `vertex_array` at `buffer` from `offset`, they advance once per instance
+    fn set_instance_buffer(&mut self, vertex_array: u32, buffer: u32, offset: usize, stride: i32, attributes: &[VertexAttribute]);
+
+    // the error is the compiler or linker log
+    fn create_shader(&mut self, stage: ShaderStage, source: &str) -> Result<u32, String>;
+    fn delete_shader(&mut self, shader: u32);
+    fn create_program(&mut self, shaders: &[u32]) -> Result<u32, String>;
+    fn delete_program(&mut self, program: u32);
+
+    // active uniforms, attributes and uniform blocks of a linked program, None when the backend can't tell
+    fn reflect_program(&mut self, _program: u32) -> Option<ProgramReflection> {
+        None
+    }
+
+    fn use_program(&mut self, program: u32);
+    fn set_state(&mut self, state: &PipelineState);
+    // the program must be in use
+    fn set_uniform(&mut self, program: u32, name: &CStr, value: UniformValue);
+    // the program must be in use, `location` comes from its reflection
+    fn set_uniform_location(&mut self, program: u32, location: i32, value: UniformValue);
+    fn bind_texture(&mut self, unit: u32, target: TextureTarget, texture: u32);
+    // `size` bytes of a uniform buffer from `offset` feed the blocks assigned to `binding`
+    fn bind_uniform_buffer(&mut self, binding: u32, buffer: u32, offset: usize, size: usize);
+    // `block` is the index of the block in the program's reflection
+    fn bind_uniform_block(&mut self, program: u32, block: u32, binding: u32);
+    // offsets given to bind_uniform_buffer must be a multiple of this
+    fn uniform_buffer_alignment(&mut self) -> usize {
+        256
+    }
+
+    fn draw_arrays(&mut self, vertex_array: u32, first: i32, count: i32);
+    // indices are u32
+    fn draw_elements(&mut self, vertex_array: u32, count: i32);
+    fn draw_elements_instanced(&mut self, vertex_array: u32, count: i32, instances: i32);
+    // indexed patches of `patch_vertices` control points for a program with tessellation stages
+    fn draw_patches(&mut self, vertex_array: u32, count: i32, patch_vertices: i32);
+
+    // compute, only when the features say so
+    fn bind_storage_buffer(&mut self, binding: u32, buffer: u32);
+    fn bind_image(&mut self, unit: u32, texture: u32, access: ImageAccess, format: ImageFormat);
+    // the program must be in use
+    fn dispatch_compute(&mut self, groups: [u32; 3]);
+    // make the writes of the previous dispatches visible to whatever reads them next
+    fn memory_barrier(&mut self);
+
+    fn bind_pipeline(&mut self, pipeline: &Pipeline) {
+        self.use_program(pipeline.program);
+        self.set_state(&pipeline.state);
+    }
+}
+
+// View a slice of plain data as bytes to upload it
+pub unsafe fn as_bytes<T>(data: &[T]) -> &[u8] {
+    slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+}
+
+// The OpenGL 3.3 backend. It has no state of its own, every call goes straight to the current context.
+#[derive(Clone, Copy)]
+pub struct GlDevice {
+    _private: ()
+}
+
+impl GlDevice {
+    // the GL functions must be loaded and a context current on this thread while the device is used
+    pub unsafe fn current() -> GlDevice {
+        GlDevice { _private: () }
+    }
+
+    fn buffer_target(kind: BufferKind) -> GLenum {
+        match kind {
+            BufferKind::VERTEX => gl::ARRAY_BUFFER,
+            BufferKind::INDEX => gl::ELEMENT_ARRAY_BUFFER,
+            BufferKind::UNIFORM => gl::UNIFORM_BUFFER,
+            BufferKind::STORAGE => gl::SHADER_STORAGE_BUFFER
+        }
+    }
+
+    fn texture_target(target: TextureTarget) -> GLenum {
+        match target {
+            TextureTarget::TEXTURE_2D => gl::TEXTURE_2D,
+            TextureTarget::CUBE_MAP => gl::TEXTURE_CUBE_MAP
+        }
+    }
+
+    unsafe fn info_log(object: u32, is_program: bool) -> String {
+        let mut length = 0;
+        if is_program {
+            gl::GetProgramiv(object, gl::INFO_LOG_LENGTH, &mut length);
+        } else {
+            gl::GetShaderiv(object, gl::INFO_LOG_LENGTH, &mut length);
+        }
+        let mut log = vec![0u8; length.max(1) as usize];
+        if is_program {
+            gl::GetProgramInfoLog(object, log.len() as i32, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+        } else {
+            gl::GetShaderInfoLog(object, log.len() as i32, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+        }
+        str::from_utf8(&log).unwrap_or("").trim_end_matches('\0').to_string()
+    }
+
+    // name written by one of the glGetActive* queries
+    unsafe fn active_name<F: FnOnce(*mut GLsizei, *mut GLchar)>(max_length: i32, query: F) -> String {
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let mut length = 0;
+        query(&mut length, name.as_mut_ptr() as *mut GLchar);
+        name.truncate(length.max(0) as usize);
+        String::from_utf8_lossy(&name).into_owned()
+    }
+}
+
+impl RenderDevice for GlDevice {
+    fn create_buffer(&mut self, kind: BufferKind, usage: BufferUsage, data: &[u8]) -> u32 {
+        let usage = match usage {
+            BufferUsage::STATIC => gl::STATIC_DRAW,
+            BufferUsage::DYNAMIC => gl::DYNAMIC_DRAW,
+            BufferUsage::STREAM => gl::STREAM_DRAW
+        };
+        let target = GlDevice::buffer_target(kind);
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(target, buffer);
+            let pointer = if data.is_empty() { ptr::null() } else { data.as_ptr() as *const c_void };
+            gl::BufferData(target, data.len() as isize, pointer, usage);
+        }
+        buffer
+    }
+
+    fn update_buffer(&mut self, buffer: u32, kind: BufferKind, offset: usize, data: &[u8]) {
+        let target = GlDevice::buffer_target(kind);
+        unsafe {
+            gl::BindBuffer(target, buffer);
+            gl::BufferSubData(target, offset as isize, data.len() as isize, data.as_ptr() as *const c_void);
+        }
+    }
+
+    fn delete_buffer(&mut self, buffer: u32) {
+        unsafe { gl::DeleteBuffers(1, &buffer) }
+    }
+
+    fn create_texture(&mut self, data: &TextureData, sampler: Sampler) -> u32 {
+        let (internal_format, format) = data.format.gl_formats();
+        let wrap = match sampler.wrap {
+            WrapMode::REPEAT => gl::REPEAT,
+            WrapMode::CLAMP_TO_EDGE => gl::CLAMP_TO_EDGE
+        };
+        let min_filter = if sampler.mipmaps { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            // rows of 1 and 3 channel images are not 4-byte aligned
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, data.width, data.height, 0,
+                           format, gl::UNSIGNED_BYTE, data.pixels.as_ptr() as *const c_void);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            if sampler.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+        texture
+    }
+
+    fn create_cubemap(&mut self, faces: &[TextureData]) -> u32 {
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            for (i, face) in faces.iter().enumerate() {
+                let (internal_format, format) = face.format.gl_formats();
+                gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32, 0, internal_format as i32,
+                               face.width, face.height, 0, format, gl::UNSIGNED_BYTE,
+                               face.pixels.as_ptr() as *const c_void);
+            }
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        }
+        texture
+    }
+
+    fn delete_texture(&mut self, texture: u32) {
+        unsafe { gl::DeleteTextures(1, &texture) }
+    }
+
+    fn create_vertex_array(&mut self, vertex_buffer: u32, index_buffer: Option<u32>, stride: i32, attributes: &[VertexAttribute]) -> u32 {
+        let mut vertex_array = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vertex_array);
+            gl::BindVertexArray(vertex_array);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
+            // the element buffer binding is part of the vertex array state
+            if let Some(index_buffer) = index_buffer {
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, index_buffer);
+            }
+            for attribute in attributes {
+                gl::EnableVertexAttribArray(attribute.location);
+                gl::VertexAttribPointer(attribute.location, attribute.components, gl::FLOAT, gl::FALSE, stride,
+                                        attribute.offset as *const c_void);
+            }
+            gl::BindVertexArray(0);
+        }
+        vertex_array
+    }
+
+    fn delete_vertex_array(&mut self, vertex_array: u32) {
+        unsafe { gl::DeleteVertexArrays(1, &vertex_array) }
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: u32) {
+        unsafe { gl::DeleteFramebuffers(1, &framebuffer) }
+    }
+
+    fn set_instance_buffer(&mut self, vertex_array: u32, buffer: u32, offset: usize, stride: i32, attributes: &[VertexAttribute]) {
+        unsafe {
+            gl::BindVertexArray(vertex_array);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+            for attribute in attributes {
+                gl::EnableVertexAttribArray(attribute.location);
+                gl::VertexAttribPointer(attribute.location, attribute.components, gl::FLOAT, gl::FALSE, stride,
+                                        (offset + attribute.offset) as *const c_void);
+                gl::VertexAttribDivisor(attribute.location, 1);
+            }
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn create_shader(&mut self, stage: ShaderStage, source: &str) -> Result<u32, String> {
+        let kind = match stage {
+            ShaderStage::VERTEX => gl::VERTEX_SHADER,
+            ShaderStage::TESS_CONTROL => gl::TESS_CONTROL_SHADER,
+            ShaderStage::TESS_EVALUATION => gl::TESS_EVALUATION_SHADER,
+            ShaderStage::GEOMETRY => gl::GEOMETRY_SHADER,
+            ShaderStage::FRAGMENT => gl::FRAGMENT_SHADER,
+            ShaderStage::COMPUTE => gl::COMPUTE_SHADER
+        };
+        let source = CString::new(source.as_bytes()).map_err(|e| e.to_string())?;
+        unsafe {
+            let shader = gl::CreateShader(kind);
+            gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+            gl::CompileShader(shader);
+
+            let mut success = gl::FALSE as GLint;
+            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+            if success != gl::TRUE as GLint {
+                let log = GlDevice::info_log(shader, false);
+                gl::DeleteShader(shader);
+                return Err(log);
+            }
+            Ok(shader)
+        }
+    }
+
+    fn delete_shader(&mut self, shader: u32) {
+        unsafe { gl::DeleteShader(shader) }
+    }
+
+    fn create_program(&mut self, shaders: &[u32]) -> Result<u32, String> {
+        unsafe {
+            let program = gl::CreateProgram();
+            for &shader in shaders {
+                gl::AttachShader(program, shader);
+            }
+            gl::LinkProgram(program);
+            for &shader in shaders {
+                gl::DetachShader(program, shader);
+            }
+
+            let mut success = gl::FALSE as GLint;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success != gl::TRUE as GLint {
+                let log = GlDevice::info_log(program, true);
+                gl::DeleteProgram(program);
+                return Err(log);
+            }
+            Ok(program)
+        }
+    }
+
+    fn delete_program(&mut self, program: u32) {
+        unsafe { gl::DeleteProgram(program) }
+    }
+
+    fn use_program(&mut self, program: u32) {
+        unsafe { gl::UseProgram(program) }
+    }
+
+    fn set_state(&mut self, state: &PipelineState) {
+        unsafe {
+            if state.depth_test { gl::Enable(gl::DEPTH_TEST) } else { gl::Disable(gl::DEPTH_TEST) }
+            gl::DepthMask(if state.depth_write { gl::TRUE } else { gl::FALSE });
+            gl::DepthFunc(match state.depth_func {
+                DepthFunc::LESS => gl::LESS,
+                DepthFunc::LEQUAL => gl::LEQUAL,
+                DepthFunc::ALWAYS => gl::ALWAYS
+            });
+            if state.blend.queue() == RenderQueue::TRANSPARENT {
+                gl::Enable(gl::BLEND);
+                state.blend.apply();
+            } else {
+                gl::Disable(gl::BLEND);
+            }
+            if state.cull_back_faces {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(gl::BACK);
+            } else {
+                gl::Disable(gl::CULL_FACE);
+            }
+        }
+    }
+
+    fn device_id(&self) -> DeviceId {
+        DeviceId::GL
+    }
+
+    fn features(&mut self) -> DeviceFeatures {
+        let (mut major, mut minor) = (0, 0);
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        }
+        DeviceFeatures::for_version(major.max(0) as u32, minor.max(0) as u32)
+    }
+
+    fn reflect_program(&mut self, program: u32) -> Option<ProgramReflection> {
+        let mut reflection = ProgramReflection::default();
+        unsafe {
+            let (mut count, mut max_length) = (0, 0);
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+            for i in 0..count as u32 {
+                let (mut size, mut type_) = (0, 0);
+                let name = GlDevice::active_name(max_length, |length, buffer| {
+                    gl::GetActiveUniform(program, i, max_length, length, &mut size, &mut type_, buffer)
+                });
+                // members of uniform blocks have no location and are left out
+                reflection.add_uniform(&name, UniformType::from_gl(type_), size, |element| {
+                    let element = CString::new(element).unwrap();
+                    gl::GetUniformLocation(program, element.as_ptr())
+                });
+            }
+
+            gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+            gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+            for i in 0..count as u32 {
+                let (mut size, mut type_) = (0, 0);
+                let name = GlDevice::active_name(max_length, |length, buffer| {
+                    gl::GetActiveAttrib(program, i, max_length, length, &mut size, &mut type_, buffer)
+                });
+                let c_name = CString::new(name.as_str()).unwrap();
+                let location = gl::GetAttribLocation(program, c_name.as_ptr());
+                reflection.attributes.push(AttributeInfo { name, location, type_: UniformType::from_gl(type_) });
+            }
+
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_length);
+            for index in 0..count as u32 {
+                let name = GlDevice::active_name(max_length, |length, buffer| {
+                    gl::GetActiveUniformBlockName(program, index, max_length, length, buffer)
+                });
+                let (mut size, mut binding) = (0, 0);
+                gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+                gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_BINDING, &mut binding);
+                reflection.blocks.push(UniformBlockInfo { name, index, size, binding: binding as u32 });
+            }
+        }
+        Some(reflection)
+    }
+
+    fn set_uniform(&mut self, program: u32, name: &CStr, value: UniformValue) {
+        let location = unsafe { gl::GetUniformLocation(program, name.as_ptr()) };
+        self.set_uniform_location(program, location, value);
+    }
+
+    fn set_uniform_location(&mut self, _program: u32, location: i32, value: UniformValue) {
+        unsafe {
+            match value {
+                UniformValue::INT(v) => gl::Uniform1i(location, v),
+                UniformValue::FLOAT(v) => gl::Uniform1f(location, v),
+                UniformValue::VEC2(v) => gl::Uniform2f(location, v[0], v[1]),
+                UniformValue::VEC3(v) => gl::Uniform3f(location, v[0], v[1], v[2]),
+                UniformValue::IVEC3(v) => gl::Uniform3i(location, v[0], v[1], v[2]),
+                UniformValue::MAT4(m) => gl::UniformMatrix4fv(location, 1, gl::FALSE, m[0].as_ptr())
+            }
+        }
+    }
+
+    fn bind_texture(&mut self, unit: u32, target: TextureTarget, texture: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(GlDevice::texture_target(target), texture);
+            // code binding textures directly expects the first unit to be active
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    fn bind_uniform_buffer(&mut self, binding: u32, buffer: u32, offset: usize, size: usize) {
+        unsafe {
+            gl::BindBufferRange(gl::UNIFORM_BUFFER, binding, buffer, offset as isize, size as isize);
+        }
+    }
+
+    fn bind_uniform_block(&mut self, program: u32, block: u32, binding: u32) {
+        unsafe {
+            gl::UniformBlockBinding(program, block, binding);
+        }
+    }
+
+    fn uniform_buffer_alignment(&mut self) -> usize {
+        let mut alignment = 0;
+        unsafe {
+            gl::GetIntegerv(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT, &mut alignment);
+        }
+        alignment.max(1) as usize
+    }
+
+    fn draw_arrays(&mut self, vertex_array: u32, first: i32, count: i32) {
+        unsafe {
+            gl::BindVertexArray(vertex_array);
+            gl::DrawArrays(gl::TRIANGLES, first, count);
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn draw_elements(&mut self, vertex_array: u32, count: i32) {
+        unsafe {
+            gl::BindVertexArray(vertex_array);
+            gl::DrawElements(gl::TRIANGLES, count, gl::UNSIGNED_INT, ptr::null());
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn draw_elements_instanced(&mut self, vertex_array: u32, count: i32, instances: i32) {
+        unsafe {
+            gl::BindVertexArray(vertex_array);
+            gl::DrawElementsInstanced(gl::TRIANGLES, count, gl::UNSIGNED_INT, ptr::null(), instances);
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn draw_patches(&mut self, vertex_array: u32, count: i32, patch_vertices: i32) {
+        unsafe {
+            gl::PatchParameteri(gl::PATCH_VERTICES, patch_vertices);
+            gl::BindVertexArray(vertex_array);
+            gl::DrawElements(gl::PATCHES, count, gl::UNSIGNED_INT, ptr::null());
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn bind_storage_buffer(&mut self, binding: u32, buffer: u32) {
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, buffer);
+        }
+    }
+
+    fn bind_image(&mut self, unit: u32, texture: u32, access: ImageAccess, format: ImageFormat) {
+        let access = match access {
+            ImageAccess::READ_ONLY => gl::READ_ONLY,
+            ImageAccess::WRITE_ONLY => gl::WRITE_ONLY,
+            ImageAccess::READ_WRITE => gl::READ_WRITE
+        };
+        let format = match format {
+            ImageFormat::R32F => gl::R32F,
+            ImageFormat::RGBA8 => gl::RGBA8,
+            ImageFormat::RGBA16F => gl::RGBA16F,
+            ImageFormat::RGBA32F => gl::RGBA32F
+        };
+        unsafe {
+            gl::BindImageTexture(unit, texture, 0, gl::FALSE, 0, access, format);
+        }
+    }
+
+    fn dispatch_compute(&mut self, groups: [u32; 3]) {
+        unsafe {
+            gl::DispatchCompute(groups[0], groups[1], groups[2]);
+        }
+    }
+
+    fn memory_barrier(&mut self) {
+        unsafe {
+            gl::MemoryBarrier(gl::ALL_BARRIER_BITS);
+        }
+    }
+}
+
+// Everything a NullDevice was asked to do, in order
+#[derive(Clone, PartialEq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum Command {
+    CREATE_BUFFER { buffer: u32, kind: BufferKind, usage: BufferUsage, size: usize },
+    UPDATE_BUFFER { buffer: u32, offset: usize, size: usize },
+    DELETE_BUFFER(u32),
+    CREATE_TEXTURE { texture: u32, width: i32, height: i32, format: PixelFormat, sampler: Sampler },
+    CREATE_CUBEMAP { texture: u32, faces: usize },
+    DELETE_TEXTURE(u32),
+    CREATE_VERTEX_ARRAY { vertex_array: u32, vertex_buffer: u32, index_buffer: Option<u32>, stride: i32, attributes: Vec<VertexAttribute> },
+    DELETE_VERTEX_ARRAY(u32),
+    DELETE_FRAMEBUFFER(u32),
+    SET_INSTANCE_BUFFER { vertex_array: u32, buffer: u32, offset: usize, stride: i32, attributes: Vec<VertexAttribute> },
+    CREATE_SHADER { shader: u32, stage: ShaderStage },
+    DELETE_SHADER(u32),
+    CREATE_PROGRAM { program: u32, shaders: Vec<u32> },
+    DELETE_PROGRAM(u32),
+    USE_PROGRAM(u32),
+    SET_STATE(PipelineState),
+    SET_UNIFORM { program: u32, name: String, value: UniformValue },
+    SET_UNIFORM_LOCATION { program: u32, location: i32, value: UniformValue },
+    BIND_UNIFORM_BUFFER { binding: u32, buffer: u32, offset: usize, size: usize },
+    BIND_UNIFORM_BLOCK { program: u32, block: u32, binding: u32 },
+    BIND_TEXTURE { unit: u32, target: TextureTarget, texture: u32 },
+    DRAW_ARRAYS { vertex_array: u32, first: i32, count: i32 },
+    DRAW_ELEMENTS { vertex_array: u32, count: i32 },
+    DRAW_ELEMENTS_INSTANCED { vertex_array: u32, count: i32, instances: i32 },
+    DRAW_PATCHES { vertex_array: u32, count: i32, patch_vertices: i32 },
+    BIND_STORAGE_BUFFER { binding: u32, buffer: u32 },
+    BIND_IMAGE { unit: u32, texture: u32, access: ImageAccess, format: ImageFormat },
+    DISPATCH_COMPUTE([u32; 3]),
+    MEMORY_BARRIER
+}
+
+impl Command {
+    pub fn is_draw(&self) -> bool {
+        matches!(self, Command::DRAW_ARRAYS { .. } | Command::DRAW_ELEMENTS { .. } | Command::DRAW_ELEMENTS_INSTANCED { .. } | Command::DRAW_PATCHES { .. })
+    }
+}
+
+// A backend without a GPU that records the commands it receives, so rendering code can be tested headless
+#[derive(Default)]
+pub struct NullDevice {
+    pub commands: Vec<Command>,
+    id: DeviceId,
+    // what the device claims to support, OpenGL 3.3 unless a test raises it
+    pub features: DeviceFeatures,
+    last_handle: u32,
+    // sources of the live shaders and reflection of the programs linked from them
+    shader_sources: HashMap<u32, String>,
+    reflections: HashMap<u32, ProgramReflection>
+}
+
+impl NullDevice {
+    pub fn new() -> NullDevice {
+        NullDevice::default()
+    }
+
+    pub fn draws(&self) -> Vec<&Command> {
+        self.commands.iter().filter(|c| c.is_draw()).collect()
+    }
+
+    // value of the last `name` uniform set on `program`, by name or by location
+    pub fn uniform(&self, program: u32, name: &str) -> Option<UniformValue> {
+        let location = self.reflections.get(&program).and_then(|reflection| reflection.uniform(name)).map(|uniform| uniform.location);
+        self.commands.iter().rev().find_map(|command| match command {
+            Command::SET_UNIFORM { program: p, name: n, value } if *p == program && n == name => Some(*value),
+            Command::SET_UNIFORM_LOCATION { program: p, location: l, value } if *p == program && Some(*l) == location => Some(*value),
+            _ => None
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    fn handle(&mut self) -> u32 {
+        self.last_handle += 1;
+        self.last_handle
+    }
+}
+
+impl RenderDevice for NullDevice {
+    fn device_id(&self) -> DeviceId {
+        self.id
+    }
+
+    fn features(&mut self) -> DeviceFeatures {
+        self.features
+    }
+
+    fn create_buffer(&mut self, kind: BufferKind, usage: BufferUsage, data: &[u8]) -> u32 {
+        let buffer = self.handle();
+        self.commands.push(Command::CREATE_BUFFER { buffer, kind, usage, size: data.len() });
+        buffer
+    }
+
+    fn update_buffer(&mut self, buffer: u32, _kind: BufferKind, offset: usize, data: &[u8]) {
+        self.commands.push(Command::UPDATE_BUFFER { buffer, offset, size: data.len() });
+    }
+
+    fn delete_buffer(&mut self, buffer: u32) {
+        self.commands.push(Command::DELETE_BUFFER(buffer));
+    }
+
+    fn create_texture(&mut self, data: &TextureData, sampler: Sampler) -> u32 {
+        let texture = self.handle();
+        self.commands.push(Command::CREATE_TEXTURE { texture, width: data.width, height: data.height, format: data.format, sampler });
+        texture
+    }
+
+    fn create_cubemap(&mut self, faces: &[TextureData]) -> u32 {
+        let texture = self.handle();
+        self.commands.push(Command::CREATE_CUBEMAP { texture, faces: faces.len() });
+        texture
+    }
+
+    fn delete_texture(&mut self, texture: u32) {
+        self.commands.push(Command::DELETE_TEXTURE(texture));
+    }
+
+    fn create_vertex_array(&mut self, vertex_buffer: u32, index_buffer: Option<u32>, stride: i32, attributes: &[VertexAttribute]) -> u32 {
+        let vertex_array = self.handle();
+        self.commands.push(Command::CREATE_VERTEX_ARRAY { vertex_array, vertex_buffer, index_buffer, stride, attributes: attributes.to_vec() });
+        vertex_array
+    }
+
+    fn delete_vertex_array(&mut self, vertex_array: u32) {
+        self.commands.push(Command::DELETE_VERTEX_ARRAY(vertex_array));
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: u32) {
+        self.commands.push(Command::DELETE_FRAMEBUFFER(framebuffer));
+    }
+
+    fn set_instance_buffer(&mut self, vertex_array: u32, buffer: u32, offset: usize, stride: i32, attributes: &[VertexAttribute]) {
+        self.commands.push(Command::SET_INSTANCE_BUFFER { vertex_array, buffer, offset, stride, attributes: attributes.to_vec() });
+    }
+
+    fn create_shader(&mut self, stage: ShaderStage, source: &str) -> Result<u32, String> {
+        // nothing is compiled, but an #error directive fails like it would on a driver
+        if let Some((number, line)) = source.lines().enumerate().find(|(_, line)| line.trim_start().starts_with("#error")) {
+            return Err(format!("0:{}: {}", number + 1, line.trim()));
+        }
+        let shader = self.handle();
+        self.shader_sources.insert(shader, source.to_string());
+        self.commands.push(Command::CREATE_SHADER { shader, stage });
+        Ok(shader)
+    }
+
+    fn delete_shader(&mut self, shader: u32) {
+        self.shader_sources.remove(&shader);
+        self.commands.push(Command::DELETE_SHADER(shader));
+    }
+
+    fn create_program(&mut self, shaders: &[u32]) -> Result<u32, String> {
+        let program = self.handle();
+        let sources: Vec<&str> = shaders.iter().filter_map(|shader| self.shader_sources.get(shader)).map(|s| s.as_str()).collect();
+        self.reflections.insert(program, ProgramReflection::from_sources(&sources));
+        self.commands.push(Command::CREATE_PROGRAM { program, shaders: shaders.to_vec() });
+        Ok(program)
+    }
+
+    fn delete_program(&mut self, program: u32) {
+        self.reflections.remove(&program);
+        self.commands.push(Command::DELETE_PROGRAM(program));
+    }
+
+    // declarations of the sources, nothing is compiled
+    fn reflect_program(&mut self, program: u32) -> Option<ProgramReflection> {
+        self.reflections.get(&program).cloned()
+    }
+
+    fn use_program(&mut self, program: u32) {
+        self.commands.push(Command::USE_PROGRAM(program));
+    }
+
+    fn set_state(&mut self, state: &PipelineState) {
+        self.commands.push(Command::SET_STATE(*state));
+    }
+
+    fn set_uniform(&mut self, program: u32, name: &CStr, value: UniformValue) {
+        self.commands.push(Command::SET_UNIFORM { program, name: name.to_string_lossy().into_owned(), value });
+    }
+
+    fn set_uniform_location(&mut self, program: u32, location: i32, value: UniformValue) {
+        self.commands.push(Command::SET_UNIFORM_LOCATION { program, location, value });
+    }
+
+    fn bind_texture(&mut self, unit: u32, target: TextureTarget, texture: u32) {
+        self.commands.push(Command::BIND_TEXTURE { unit, target, texture });
+    }
+
+    fn bind_uniform_buffer(&mut self, binding: u32, buffer: u32, offset: usize, size: usize) {
+        self.commands.push(Command::BIND_UNIFORM_BUFFER { binding, buffer, offset, size });
+    }
+
+    fn bind_uniform_block(&mut self, program: u32, block: u32, binding: u32) {
+        self.commands.push(Command::BIND_UNIFORM_BLOCK { program, block, binding });
+    }
+
+    fn draw_arrays(&mut self, vertex_array: u32, first: i32, count: i32) {
+        self.commands.push(Command::DRAW_ARRAYS { vertex_array, first, count });
+    }
+
+    fn draw_elements(&mut self, vertex_array: u32, count: i32) {
+        self.commands.push(Command::DRAW_ELEMENTS { vertex_array, count });
+    }
+
+    fn draw_elements_instanced(&mut self, vertex_array: u32, count: i32, instances: i32) {
+        self.commands.push(Command::DRAW_ELEMENTS_INSTANCED { vertex_array, count, instances });
+    }
+
+    fn draw_patches(&mut self, vertex_array: u32, count: i32, patch_vertices: i32) {
+        self.commands.push(Command::DRAW_PATCHES { vertex_array, count, patch_vertices });
+    }
+
+    fn bind_storage_buffer(&mut self, binding: u32, buffer: u32) {
+        self.commands.push(Command::BIND_STORAGE_BUFFER { binding, buffer });
+    }
+
+    fn bind_image(&mut self, unit: u32, texture: u32, access: ImageAccess, format: ImageFormat) {
+        self.commands.push(Command::BIND_IMAGE { unit, texture, access, format });
+    }
+
+    fn dispatch_compute(&mut self, groups: [u32; 3]) {
+        self.commands.push(Command::DISPATCH_COMPUTE(groups));
+    }
+
+    fn memory_barrier(&mut self) {
+        self.commands.push(Command::MEMORY_BARRIER);
+    }
+}
@@ -0,0 +1,100 @@
+use cgmath::Vector3;
+
+use crate::graphics::device::{ PipelineState, RenderDevice };
+use crate::graphics::material::RenderQueue;
+use crate::graphics::shader::Shader;
+use crate::graphics::uniform_buffer::UniformRing;
+use crate::world::scene::{ Culling, DrawItem, Scene };
+
+const DEPTH_MASK: u64 = (1 << 24) - 1;
+
+// 64-bit key ordering draws. From the high bits: the queue, then for opaque and alpha tested draws the
+// program, the material and the depth front to back. Transparent draws must blend back to front, their
+// depth comes right after the queue.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SortKey(pub u64);
+
+impl SortKey {
+    pub fn new(queue: RenderQueue, program: u32, material: u16, depth: f32) -> SortKey {
+        let program = program as u64 & 0xFFFF;
+        let material = material as u64;
+        let depth = depth_bits(depth);
+        let key = match queue {
+            RenderQueue::TRANSPARENT => (DEPTH_MASK - depth) << 32 | program << 16 | material,
+            _ => program << 40 | material << 24 | depth
+        };
+        SortKey((queue as u64) << 56 | key)
+    }
+}
+
+// positive floats order like their bits, the top 24 are kept
+fn depth_bits(depth: f32) -> u64 {
+    (depth.max(0.0).to_bits() >> 8) as u64
+}
+
+// The mesh draws of a pass with their keys, submitted in key order
+#[derive(Default)]
+pub struct DrawQueue {
+    draws: Vec<(SortKey, DrawItem)>
+}
+
+impl DrawQueue {
+    pub fn new() -> DrawQueue {
+        DrawQueue::default()
+    }
+
+    // queue the meshes of `queue` in `scene` left by `culling`, to be drawn with `program`
+    pub fn collect(&mut self, scene: &Scene, queue: RenderQueue, program: u32, view_position: Vector3<f32>, culling: &Culling) {
+        for item in scene.collect_visible_queue(queue, view_position, culling) {
+            let mesh = &scene.entities[item.entity].model.as_ref().unwrap().meshes[item.mesh];
+            self.push(SortKey::new(queue, program, mesh.material_key(), item.distance), item);
+        }
+    }
+
+    pub fn push(&mut self, key: SortKey, item: DrawItem) {
+        self.draws.push((key, item));
+    }
+
+    // draws with equal keys keep the order they were queued in
+    pub fn sort(&mut self) {
+        self.draws.sort_by_key(|(key, _)| *key);
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &DrawItem> {
+        self.draws.iter().map(|(_, item)| item)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = SortKey> + '_ {
+        self.draws.iter().map(|(key, _)| *key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.draws.clear();
+    }
+
+    // Draw the queue in its current order with `shader`, the model matrices streamed through `objects`.
+    // With `blended` each draw sets the blend mode of its material and leaves the depth buffer alone,
+    // otherwise the state is the caller's.
+    pub fn submit<D: RenderDevice>(&self, device: &mut D, scene: &Scene, shader: &Shader, objects: &UniformRing, blended: bool) {
+        device.use_program(shader.id);
+        for (_, item) in &self.draws {
+            let mesh = &scene.entities[item.entity].model.as_ref().unwrap().meshes[item.mesh];
+            if blended {
+                device.set_state(&PipelineState { depth_write: false, blend: mesh.material.blend_mode, ..PipelineState::default() });
+            }
+            objects.push_object(device, &item.model_matrix);
+            mesh.draw_with(device, shader);
+        }
+        if blended {
+            device.set_state(&PipelineState::default());
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::ptr;
+
+use gl;
+use gl::types::*;
+
+use crate::graphics::device::GlDevice;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+
+// Formats a framebuffer attachment can be created with, DEPTH24 is only used for render graph transients
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[allow(non_camel_case_types)]
+pub enum TextureFormat {
+    RGBA8,
+    RGBA16F,
+    RGB16F,
+    R11F_G11F_B10F,
+    R16F,
+    R8,
+    DEPTH24
+}
+
+impl TextureFormat {
+    // (internal format, format, type) triple passed to glTexImage2D
+    pub fn gl_formats(self) -> (GLenum, GLenum, GLenum) {
+        match self {
+            TextureFormat::RGBA8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            TextureFormat::RGBA16F => (gl::RGBA16F, gl::RGBA, gl::FLOAT),
+            TextureFormat::RGB16F => (gl::RGB16F, gl::RGB, gl::FLOAT),
+            TextureFormat::R11F_G11F_B10F => (gl::R11F_G11F_B10F, gl::RGB, gl::FLOAT),
+            TextureFormat::R16F => (gl::R16F, gl::RED, gl::FLOAT),
+            TextureFormat::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            TextureFormat::DEPTH24 => (gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::FLOAT)
+        }
+    }
+
+    pub fn is_depth(self) -> bool {
+        self == TextureFormat::DEPTH24
+    }
+}
+
+// An offscreen render target made of color textures and an optional depth texture, all deleted when dropped
+pub struct Framebuffer {
+    fbo: GpuHandle,
+    color_textures: Vec<GpuHandle>,
+    depth_texture: Option<GpuHandle>,
+    pub width: i32,
+    pub height: i32
+}
+
+impl Framebuffer {
+    pub fn new(width: i32, height: i32, color_formats: &[TextureFormat], with_depth: bool) -> Framebuffer {
+        let mut framebuffer = Framebuffer {
+            fbo: GpuHandle::default(),
+            color_textures: Vec::new(),
+            depth_texture: None,
+            width: width.max(1),
+            height: height.max(1)
+        };
+        unsafe { framebuffer.setup(color_formats, with_depth) }
+        framebuffer
+    }
+
+    unsafe fn setup(&mut self, color_formats: &[TextureFormat], with_depth: bool) {
+        let device = GlDevice::current();
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        self.fbo = GpuHandle::new(&device, ResourceKind::FRAMEBUFFER, fbo, "framebuffer");
+
+        let mut draw_buffers = Vec::new();
+        for (i, format) in color_formats.iter().enumerate() {
+            let (internal_format, format, type_) = format.gl_formats();
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, self.width, self.height, 0, format, type_, ptr::null());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + i as u32, gl::TEXTURE_2D, texture, 0);
+
+            self.color_textures.push(GpuHandle::new(&device, ResourceKind::TEXTURE, texture, "framebuffer color"));
+            draw_buffers.push(gl::COLOR_ATTACHMENT0 + i as u32);
+        }
+
+        if draw_buffers.is_empty() {
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+        } else {
+            gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+        }
+
+        if with_depth {
+            // depth is a texture rather than a renderbuffer so later passes can sample it
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT24 as i32, self.width, self.height, 0,
+                gl::DEPTH_COMPONENT, gl::FLOAT, ptr::null()
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, texture, 0);
+            self.depth_texture = Some(GpuHandle::new(&device, ResourceKind::TEXTURE, texture, "framebuffer depth"));
+        }
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            println!("ERROR::FRAMEBUFFER:: Framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    // bind as the render target and cover it with the viewport
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo.id());
+        gl::Viewport(0, 0, self.width, self.height);
+    }
+
+    pub unsafe fn bind_default(width: i32, height: i32) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::Viewport(0, 0, width, height);
+    }
+
+    pub fn fbo(&self) -> u32 {
+        self.fbo.id()
+    }
+
+    pub fn color_texture(&self, index: usize) -> u32 {
+        self.color_textures[index].id()
+    }
+
+    pub fn depth_texture(&self) -> Option<u32> {
+        self.depth_texture.as_ref().map(GpuHandle::id)
+    }
+}
+
+// A full-screen triangle generated from gl_VertexID in fullscreen.vs
+pub struct FullscreenTriangle {
+    vao: GpuHandle
+}
+
+impl FullscreenTriangle {
+    pub fn new() -> FullscreenTriangle {
+        let mut vao = 0;
+        // core profile requires a bound VAO even without any vertex attribute
+        let device = unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            GlDevice::current()
+        };
+        FullscreenTriangle { vao: GpuHandle::new(&device, ResourceKind::VERTEX_ARRAY, vao, "fullscreen triangle") }
+    }
+
+    pub unsafe fn draw(&self) {
+        gl::BindVertexArray(self.vao.id());
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::{ HashMap, VecDeque };
+use std::ffi::CStr;
+use std::ptr;
+
+use gl;
+use gl::types::*;
+
+use crate::graphics::capture::is_signaled;
+use crate::graphics::device::GlDevice;
+use crate::graphics::framebuffer::{ Framebuffer, FullscreenTriangle, TextureFormat };
+use crate::graphics::resource::{ self, GpuHandle, ResourceKind };
+use crate::graphics::shader::{ Shader, ShaderType };
+
+// size of the downsampled log-luminance target read back for the exposure histogram
+const LUMINANCE_SIZE: i32 = 64;
+// luminance readbacks in flight, the exposure trails the scene by this many frames at most
+const LUMINANCE_READBACKS: usize = 3;
+const HISTOGRAM_BINS: usize = 64;
+// middle grey the average scene luminance is exposed to
+pub const EXPOSURE_KEY: f32 = 0.18;
+
+// values must match the TONEMAP_* constants in tonemap.fs
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tonemapper {
+    REINHARD = 0,
+    ACES = 1,
+    AGX = 2
+}
+
+impl Tonemapper {
+    pub fn next(self) -> Tonemapper {
+        match self {
+            Tonemapper::REINHARD => Tonemapper::ACES,
+            Tonemapper::ACES => Tonemapper::AGX,
+            Tonemapper::AGX => Tonemapper::REINHARD
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Exposure {
+    // fixed linear exposure multiplier
+    MANUAL(f32),
+    // exposure driven by a histogram of the scene luminance
+    AUTO {
+        // log2 luminance range covered by the histogram
+        min_log2_luminance: f32,
+        max_log2_luminance: f32,
+        // fraction of the darkest and brightest pixels ignored when averaging
+        low_percentile: f32,
+        high_percentile: f32,
+        // how fast the exposure adapts to a change of luminance, per second
+        adaptation_speed: f32,
+        // exposure compensation in stops
+        compensation: f32
+    }
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure::AUTO {
+            min_log2_luminance: -10.0,
+            max_log2_luminance: 6.0,
+            low_percentile: 0.5,
+            high_percentile: 0.95,
+            adaptation_speed: 1.5,
+            compensation: 0.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    // brightness above which pixels start to bloom, with a soft knee around it
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    // radius of the upsampling tent filter in texture coordinates
+    pub filter_radius: f32,
+    // number of downsample/upsample steps, each one at half the resolution of the previous
+    pub mip_count: usize
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        BloomSettings {
+            enabled: true,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.05,
+            filter_radius: 0.005,
+            mip_count: 5
+        }
+    }
+}
+
+// Per camera HDR configuration. When disabled the scene is only clamped to LDR and gamma corrected.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HdrSettings {
+    pub enabled: bool,
+    pub tonemapper: Tonemapper,
+    pub exposure: Exposure,
+    pub bloom: BloomSettings
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        HdrSettings {
+            enabled: true,
+            tonemapper: Tonemapper::ACES,
+            exposure: Exposure::default(),
+            bloom: BloomSettings::default()
+        }
+    }
+}
+
+pub struct LuminanceHistogram {
+    pub bins: Vec<u32>,
+    pub min_log2: f32,
+    pub max_log2: f32
+}
+
+impl LuminanceHistogram {
+    pub fn new(bin_count: usize, min_log2: f32, max_log2: f32) -> LuminanceHistogram {
+        LuminanceHistogram {
+            bins: vec![0; bin_count],
+            min_log2,
+            max_log2
+        }
+    }
+
+    // fill the histogram from log2 luminance samples, values outside of the range land in the first or last bin
+    pub fn build(&mut self, log_luminance: &[f32]) {
+        for bin in self.bins.iter_mut() {
+            *bin = 0;
+        }
+
+        let count = self.bins.len();
+        let range = (self.max_log2 - self.min_log2).max(1e-5);
+        for &value in log_luminance {
+            let t = ((value - self.min_log2) / range).clamp(0.0, 1.0);
+            let bin = ((t * count as f32) as usize).min(count - 1);
+            self.bins[bin] += 1;
+        }
+    }
+
+    // average log2 luminance ignoring the samples below `low_percentile` and above `high_percentile`
+    pub fn average_log2(&self, low_percentile: f32, high_percentile: f32) -> f32 {
+        let total: u32 = self.bins.iter().sum();
+        let low = total as f32 * low_percentile;
+        let high = total as f32 * high_percentile;
+        let bin_width = (self.max_log2 - self.min_log2) / self.bins.len() as f32;
+
+        let mut cumulative = 0.0;
+        let mut weighted_sum = 0.0;
+        let mut weight_total = 0.0;
+        for (i, &count) in self.bins.iter().enumerate() {
+            let start = cumulative;
+            let end = cumulative + count as f32;
+            cumulative = end;
+
+            // part of this bin between the two percentiles
+            let weight = (end.min(high) - start.max(low)).max(0.0);
+            let center = self.min_log2 + (i as f32 + 0.5) * bin_width;
+            weighted_sum += weight * center;
+            weight_total += weight;
+        }
+
+        if weight_total > 0.0 {
+            weighted_sum / weight_total
+        } else {
+            (self.min_log2 + self.max_log2) * 0.5
+        }
+    }
+}
+
+pub struct HdrPipeline {
+    pub scene_target: Framebuffer,
+    bloom_mips: Vec<Framebuffer>,
+    luminance_target: Framebuffer,
+    // pixel pack buffers of the luminance target, the ones in flight wait for their fence oldest first
+    free_readbacks: Vec<GpuHandle>,
+    pending_readbacks: VecDeque<(GpuHandle, GLsync)>,
+    histogram: LuminanceHistogram,
+    // average log2 luminance of the last readback that completed
+    average_log2: Option<f32>,
+    exposure: f32,
+    fullscreen: FullscreenTriangle
+}
+
+impl HdrPipeline {
+    pub fn new(width: i32, height: i32) -> HdrPipeline {
+        let bloom = BloomSettings::default();
+        HdrPipeline {
+            scene_target: Framebuffer::new(width, height, &[TextureFormat::RGBA16F], true),
+            bloom_mips: create_bloom_mips(width, height, bloom.mip_count),
+            luminance_target: Framebuffer::new(LUMINANCE_SIZE, LUMINANCE_SIZE, &[TextureFormat::R16F], false),
+            free_readbacks: Vec::new(),
+            pending_readbacks: VecDeque::new(),
+            histogram: LuminanceHistogram::new(HISTOGRAM_BINS, -10.0, 6.0),
+            average_log2: None,
+            exposure: 1.0,
+            fullscreen: FullscreenTriangle::new()
+        }
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if self.scene_target.width == width && self.scene_target.height == height {
+            return;
+        }
+
+        self.scene_target = Framebuffer::new(width, height, &[TextureFormat::RGBA16F], true);
+        self.bloom_mips = create_bloom_mips(width, height, self.bloom_mips.len());
+    }
+
+    // measure the exposure and render the bloom mips of the HDR scene, a no-op when HDR is disabled
+    pub unsafe fn prepare(&mut self, settings: &HdrSettings, delta_time: f32, shaders: &HashMap<ShaderType, Shader>) {
+        gl::Disable(gl::DEPTH_TEST);
+
+        if settings.enabled {
+            // 1. exposure
+            self.exposure = match settings.exposure {
+                Exposure::MANUAL(exposure) => exposure,
+                Exposure::AUTO { .. } => self.auto_exposure(&settings.exposure, delta_time, get_shader(shaders, ShaderType::LUMINANCE))
+            };
+
+            // 2. bloom
+            if settings.bloom.enabled {
+                if settings.bloom.mip_count != self.bloom_mips.len() {
+                    self.bloom_mips = create_bloom_mips(self.scene_target.width, self.scene_target.height, settings.bloom.mip_count);
+                }
+                self.render_bloom(
+                    &settings.bloom,
+                    get_shader(shaders, ShaderType::BLOOM_DOWNSAMPLE),
+                    get_shader(shaders, ShaderType::BLOOM_UPSAMPLE)
+                );
+            }
+        }
+
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    // tonemap the HDR scene (plus bloom) into the bound framebuffer, after `prepare`.
+    // When HDR is disabled for the camera the scene is only clamped and gamma corrected.
+    pub unsafe fn tonemap(&self, settings: &HdrSettings, shaders: &HashMap<ShaderType, Shader>) {
+        gl::Disable(gl::DEPTH_TEST);
+
+        let tonemap = get_shader(shaders, ShaderType::TONEMAP);
+        tonemap.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.scene_target.color_texture(0));
+        tonemap.set_int(c_str!("hdrBuffer"), 0);
+        let bloom = settings.enabled && settings.bloom.enabled && !self.bloom_mips.is_empty();
+        if bloom {
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, self.bloom_mips[0].color_texture(0));
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        tonemap.set_int(c_str!("bloomBuffer"), 1);
+        tonemap.set_bool(c_str!("bloom"), bloom);
+        tonemap.set_float(c_str!("bloomIntensity"), settings.bloom.intensity);
+        tonemap.set_bool(c_str!("tonemapping"), settings.enabled);
+        tonemap.set_float(c_str!("exposure"), self.exposure);
+        tonemap.set_int(c_str!("tonemapper"), settings.tonemapper as i32);
+        self.fullscreen.draw();
+
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    unsafe fn auto_exposure(&mut self, exposure: &Exposure, delta_time: f32, shader: &Shader) -> f32 {
+        let (min_log2, max_log2, low, high, speed, compensation) = match *exposure {
+            Exposure::AUTO {
+                min_log2_luminance,
+                max_log2_luminance,
+                low_percentile,
+                high_percentile,
+                adaptation_speed,
+                compensation
+            } => (min_log2_luminance, max_log2_luminance, low_percentile, high_percentile, adaptation_speed, compensation),
+            Exposure::MANUAL(exposure) => return exposure
+        };
+
+        // downsample the scene into a small log2 luminance image
+        self.luminance_target.bind();
+        shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.scene_target.color_texture(0));
+        shader.set_int(c_str!("hdrBuffer"), 0);
+        self.fullscreen.draw();
+
+        // Read it back through pixel pack buffers so the CPU never waits on the GPU: the oldest readback
+        // is used once its fence signaled, a frame or two later, and a frame is skipped while all are in flight
+        if self.pending_readbacks.front().is_some_and(|(_, fence)| is_signaled(*fence)) {
+            let (buffer, fence) = self.pending_readbacks.pop_front().unwrap();
+            gl::DeleteSync(fence);
+            let count = (LUMINANCE_SIZE * LUMINANCE_SIZE) as usize;
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer.id());
+            let data = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, (count * 4) as GLsizeiptr, gl::MAP_READ_BIT) as *const f32;
+            if !data.is_null() {
+                self.histogram.min_log2 = min_log2;
+                self.histogram.max_log2 = max_log2;
+                self.histogram.build(std::slice::from_raw_parts(data, count));
+                self.average_log2 = Some(self.histogram.average_log2(low, high));
+            }
+            gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            self.free_readbacks.push(buffer);
+        }
+        if self.pending_readbacks.len() < LUMINANCE_READBACKS {
+            let buffer = self.free_readbacks.pop().unwrap_or_else(|| create_readback_buffer());
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer.id());
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::ReadPixels(0, 0, LUMINANCE_SIZE, LUMINANCE_SIZE, gl::RED, gl::FLOAT, ptr::null_mut());
+            let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            self.pending_readbacks.push_back((buffer, fence));
+        }
+
+        // keep the exposure until the first readback is in
+        match self.average_log2 {
+            Some(average) => adapt_exposure(self.exposure, target_exposure(average, compensation), speed, delta_time),
+            None => self.exposure
+        }
+    }
+
+    unsafe fn render_bloom(&self, bloom: &BloomSettings, downsample: &Shader, upsample: &Shader) {
+        // progressively downsample, the first step also applies the brightness threshold
+        downsample.use_program();
+        downsample.set_int(c_str!("srcTexture"), 0);
+        downsample.set_float(c_str!("threshold"), bloom.threshold);
+        downsample.set_float(c_str!("knee"), bloom.knee);
+        gl::ActiveTexture(gl::TEXTURE0);
+        for (i, mip) in self.bloom_mips.iter().enumerate() {
+            let source = if i == 0 { &self.scene_target } else { &self.bloom_mips[i - 1] };
+            mip.bind();
+            gl::BindTexture(gl::TEXTURE_2D, source.color_texture(0));
+            downsample.set_vec2(c_str!("srcResolution"), source.width as f32, source.height as f32);
+            downsample.set_bool(c_str!("prefilter"), i == 0);
+            self.fullscreen.draw();
+        }
+
+        // upsample back, accumulating every level into the one above it
+        upsample.use_program();
+        upsample.set_int(c_str!("srcTexture"), 0);
+        upsample.set_float(c_str!("filterRadius"), bloom.filter_radius);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        gl::BlendEquation(gl::FUNC_ADD);
+        for i in (0..self.bloom_mips.len().saturating_sub(1)).rev() {
+            self.bloom_mips[i].bind();
+            gl::BindTexture(gl::TEXTURE_2D, self.bloom_mips[i + 1].color_texture(0));
+            self.fullscreen.draw();
+        }
+        gl::Disable(gl::BLEND);
+    }
+}
+
+impl Drop for HdrPipeline {
+    fn drop(&mut self) {
+        // the fences go with the context when it is already gone
+        if resource::is_gl_thread() {
+            for (_, fence) in self.pending_readbacks.drain(..) {
+                unsafe { gl::DeleteSync(fence) };
+            }
+        }
+    }
+}
+
+// the exposure bringing a scene of `average_log2` luminance to middle grey, `compensation` stops brighter
+pub fn target_exposure(average_log2: f32, compensation: f32) -> f32 {
+    EXPOSURE_KEY * 2f32.powf(compensation) / 2f32.powf(average_log2)
+}
+
+// Move `current` towards `target` in log space, closing 1 - e^(-speed * delta_time) of the gap so the
+// adaptation doesn't depend on the frame rate
+pub fn adapt_exposure(current: f32, target: f32, speed: f32, delta_time: f32) -> f32 {
+    let t = 1.0 - (-delta_time * speed).exp();
+    let current = current.max(1e-5).log2();
+    2f32.powf(current + (target.log2() - current) * t)
+}
+
+fn create_bloom_mips(width: i32, height: i32, mip_count: usize) -> Vec<Framebuffer> {
+    (1..=mip_count)
+        .map(|i| Framebuffer::new((width >> i).max(1), (height >> i).max(1), &[TextureFormat::R11F_G11F_B10F], false))
+        .collect()
+}
+
+unsafe fn create_readback_buffer() -> GpuHandle {
+    let mut buffer = 0;
+    gl::GenBuffers(1, &mut buffer);
+    gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer);
+    let size = (LUMINANCE_SIZE * LUMINANCE_SIZE) as usize * 4;
+    gl::BufferData(gl::PIXEL_PACK_BUFFER, size as GLsizeiptr, ptr::null(), gl::STREAM_READ);
+    gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+    GpuHandle::new(&GlDevice::current(), ResourceKind::BUFFER, buffer, "luminance readback")
+}
+
+fn get_shader(shaders: &HashMap<ShaderType, Shader>, shader_type: ShaderType) -> &Shader {
+    shaders.get(&shader_type).expect("ShaderType is not initialized")
+}
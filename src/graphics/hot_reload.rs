@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant, SystemTime };
+
+use crate::graphics::shader::Shader;
+
+// Watches shader sources by polling their modification time, cheap enough for the few dozen files
+// the engine loads and free of any platform specific notification API
+pub struct ShaderWatcher {
+    pub interval: Duration,
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    last_poll: Option<Instant>
+}
+
+impl ShaderWatcher {
+    pub fn new(interval: Duration) -> ShaderWatcher {
+        ShaderWatcher {
+            interval,
+            files: HashMap::new(),
+            last_poll: None
+        }
+    }
+
+    pub fn watch(&mut self, path: &Path) {
+        if !self.files.contains_key(path) {
+            self.files.insert(path.to_path_buf(), modified(path));
+        }
+    }
+
+    pub fn watch_shader(&mut self, shader: &Shader) {
+        for path in shader.sources.iter().chain(&shader.includes) {
+            self.watch(path);
+        }
+    }
+
+    pub fn watched(&self) -> usize {
+        self.files.len()
+    }
+
+    // the files changed since the last check, checking at most once per interval
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        if self.last_poll.is_some_and(|last_poll| now.duration_since(last_poll) < self.interval) {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+        self.changed_files()
+    }
+
+    // the files changed since the last check. A file being written may be caught half way, it is then
+    // reported again once the editor is done with it.
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                // deleted files are reported once they come back, editors often save by replacing
+                if modified.is_some() {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        changed.sort();
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// whether any source or include of `shader` is among `changed`
+pub fn depends_on(shader: &Shader, changed: &[PathBuf]) -> bool {
+    shader.sources.iter().chain(&shader.includes).any(|source| changed.contains(source))
+}
@@ -0,0 +1,70 @@
+use std::cell::Cell;
+use std::mem::size_of;
+
+use crate::graphics::device::{ as_bytes, BufferKind, BufferUsage, GlDevice, RenderDevice, VertexAttribute };
+use crate::graphics::mesh::Mesh;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::shader::Shader;
+use crate::graphics::uniform_buffer::ObjectData;
+
+// First attribute location of the per-instance data, after the five of Vertex. The model matrix takes
+// four locations and the normal matrix the four after, see include/object.glsl.
+pub const INSTANCE_LOCATION: u32 = 5;
+
+// Streams the per-instance blocks of the batches through one vertex buffer. Each push writes after the
+// previous one and wraps to the start once the buffer is full, like UniformRing.
+pub struct InstanceBuffer {
+    pub buffer: u32,
+    // in instances
+    pub capacity: usize,
+    cursor: Cell<usize>,
+    // deletes `buffer` with the instance buffer
+    _handle: GpuHandle
+}
+
+impl InstanceBuffer {
+    pub fn new(capacity: usize) -> InstanceBuffer {
+        InstanceBuffer::with_device(unsafe { &mut GlDevice::current() }, capacity)
+    }
+
+    pub fn with_device<D: RenderDevice>(device: &mut D, capacity: usize) -> InstanceBuffer {
+        let buffer = device.create_buffer(BufferKind::VERTEX, BufferUsage::STREAM, &vec![0; capacity * size_of::<ObjectData>()]);
+        let handle = GpuHandle::new(device, ResourceKind::BUFFER, buffer, "instance buffer");
+        InstanceBuffer { buffer, capacity, cursor: Cell::new(0), _handle: handle }
+    }
+
+    // write `instances` into the next free range, returns the offset in bytes it was written at
+    pub fn push<D: RenderDevice>(&self, device: &mut D, instances: &[ObjectData]) -> usize {
+        assert!(instances.len() <= self.capacity, "more instances than the buffer holds");
+        let mut start = self.cursor.get();
+        if start + instances.len() > self.capacity {
+            start = 0;
+        }
+        self.cursor.set(start + instances.len());
+
+        let offset = start * size_of::<ObjectData>();
+        device.update_buffer(self.buffer, BufferKind::VERTEX, offset, unsafe { as_bytes(instances) });
+        offset
+    }
+
+    // Draw one copy of `mesh` per instance with a program built with the INSTANCED keyword. Instances
+    // that don't fit in the buffer at once are split over several draws.
+    pub fn draw<D: RenderDevice>(&self, device: &mut D, mesh: &Mesh, shader: &Shader, instances: &[ObjectData]) {
+        let attributes = instance_attributes();
+        for chunk in instances.chunks(self.capacity) {
+            let offset = self.push(device, chunk);
+            device.set_instance_buffer(mesh.vao, self.buffer, offset, size_of::<ObjectData>() as i32, &attributes);
+            mesh.draw_instanced_with(device, shader, chunk.len() as i32);
+        }
+    }
+}
+
+// one vec4 column of the model then the normal matrix per location
+fn instance_attributes() -> [VertexAttribute; 8] {
+    let mut attributes = [VertexAttribute { location: 0, components: 4, offset: 0 }; 8];
+    for (i, attribute) in attributes.iter_mut().enumerate() {
+        attribute.location = INSTANCE_LOCATION + i as u32;
+        attribute.offset = i * size_of::<[f32; 4]>();
+    }
+    attributes
+}
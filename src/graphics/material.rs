@@ -0,0 +1,95 @@
+use gl;
+
+// Queues are rendered in this order, see Renderer::render
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[allow(non_camel_case_types)]
+pub enum RenderQueue {
+    OPAQUE,
+    // opaque, except for the texels with an alpha under the cutoff which are discarded
+    ALPHA_TEST,
+    // blended over everything else, sorted back to front
+    TRANSPARENT
+}
+
+// How a mesh is combined with what is already rendered
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BlendMode {
+    OPAQUE,
+    CUTOUT,
+    // classic alpha blending
+    ALPHA,
+    // the colour is already multiplied by its alpha
+    PREMULTIPLIED,
+    // light adds to what is behind (fire, glows)
+    ADDITIVE
+}
+
+impl BlendMode {
+    pub fn queue(self) -> RenderQueue {
+        match self {
+            BlendMode::OPAQUE => RenderQueue::OPAQUE,
+            BlendMode::CUTOUT => RenderQueue::ALPHA_TEST,
+            BlendMode::ALPHA | BlendMode::PREMULTIPLIED | BlendMode::ADDITIVE => RenderQueue::TRANSPARENT
+        }
+    }
+
+    // set the blend function of this mode, blending must already be enabled for the transparent modes
+    pub unsafe fn apply(self) {
+        match self {
+            BlendMode::OPAQUE | BlendMode::CUTOUT => {}
+            BlendMode::ALPHA => gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            BlendMode::PREMULTIPLIED => gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+            BlendMode::ADDITIVE => gl::BlendFunc(gl::SRC_ALPHA, gl::ONE)
+        }
+    }
+}
+
+// Shading parameters of a mesh, read from the .mtl file at import
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    pub specular: f32,
+    pub shininess: f32,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    // texels with a lower alpha are discarded by CUTOUT materials
+    pub alpha_cutoff: f32
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            specular: 0.5,
+            shininess: 32.0,
+            opacity: 1.0,
+            blend_mode: BlendMode::OPAQUE,
+            alpha_cutoff: 0.5
+        }
+    }
+}
+
+impl Material {
+    pub fn from_obj(material: &tobj::Material) -> Material {
+        let specular = material.specular;
+        Material {
+            // the lighting only uses a single specular intensity
+            specular: (specular[0] + specular[1] + specular[2]) / 3.0,
+            shininess: material.shininess.max(1.0),
+            opacity: material.dissolve,
+            blend_mode: if material.dissolve < 1.0 { BlendMode::ALPHA } else { BlendMode::OPAQUE },
+            ..Material::default()
+        }
+    }
+
+    pub fn queue(&self) -> RenderQueue {
+        self.blend_mode.queue()
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.queue() == RenderQueue::TRANSPARENT
+    }
+
+    // alpha under which texels are discarded, 0 unless the material is a cutout
+    pub fn effective_alpha_cutoff(&self) -> f32 {
+        if self.blend_mode == BlendMode::CUTOUT { self.alpha_cutoff } else { 0.0 }
+    }
+}
@@ -1,13 +1,16 @@
-use std::ffi::CString;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::{ CStr, CString };
+use std::hash::{ Hash, Hasher };
 use std::mem::size_of;
-use std::os::raw::c_void;
-use std::ptr;
 
 use cgmath::{ Vector3, Vector2 };
 use cgmath::prelude::*;
-use gl;
 
+use crate::graphics::device::{ as_bytes, BufferKind, BufferUsage, GlDevice, RenderDevice, TextureTarget, UniformValue, VertexAttribute };
+use crate::graphics::material::Material;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
 use crate::graphics::shader::Shader;
+use crate::world::bounds::{ Aabb, BoundingSphere };
 
 // NOTE: without repr(C) the compiler may reorder the fields or use different padding/alignment than C.
 // Depending on how you pass the data to OpenGL, this may be bad. In this case it's not strictly
@@ -37,7 +40,9 @@ impl Default for Vertex {
 pub struct Texture {
     pub id: u32,
     pub type_: String,
-    pub path: String
+    pub path: String,
+    // some texels are not fully opaque
+    pub has_alpha: bool
 }
 
 pub struct Mesh {
@@ -45,119 +50,150 @@ pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub textures: Vec<Texture>,
+    pub material: Material,
+    // bounds of the vertices in model space
+    pub bounds: Aabb,
+    pub sphere: BoundingSphere,
     pub vao: u32,
 
-    // render data
-    vbo: u32,
-    ebo: u32,
+    // render data: vertex array, vertex and index buffers, deleted with the mesh
+    handles: [GpuHandle; 3],
+    // sampler uniform of each texture, built once instead of every draw
+    sampler_names: Vec<CString>
 }
 
 impl Mesh {
-    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>) -> Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>, material: Material) -> Mesh {
+        let mut device = unsafe { GlDevice::current() };
+        Mesh::with_device(&mut device, vertices, indices, textures, material)
+    }
+
+    // create the mesh and upload it through `device`
+    pub fn with_device<D: RenderDevice>(device: &mut D, vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>, material: Material) -> Mesh {
+        let positions: Vec<Vector3<f32>> = vertices.iter().map(|v| v.position).collect();
+        let bounds = Aabb::from_points(positions.iter().cloned());
+        let sphere = BoundingSphere::from_points(&positions);
+        let sampler_names = sampler_names(&textures);
         let mut mesh = Mesh {
             vertices,
             indices,
             textures,
+            material,
+            bounds,
+            sphere,
             vao: 0,
-            vbo: 0,
-            ebo: 0
+            handles: Default::default(),
+            sampler_names
         };
 
-        unsafe { mesh.setup_mesh() }
+        mesh.setup_mesh(device);
         mesh
     }
 
+    // Equal for meshes drawn with the same textures and material parameters, sorting draws by it keeps
+    // those together. Different materials may share a key, which only costs a few rebinds.
+    pub fn material_key(&self) -> u16 {
+        let mut hasher = DefaultHasher::new();
+        for texture in &self.textures {
+            texture.id.hash(&mut hasher);
+        }
+        self.material.blend_mode.hash(&mut hasher);
+        let material = &self.material;
+        for value in &[material.specular, material.shininess, material.opacity, material.alpha_cutoff] {
+            value.to_bits().hash(&mut hasher);
+        }
+        hasher.finish() as u16
+    }
+
     // render the mesh
     pub unsafe fn draw(&self, shader: &Shader) {
-        // bind appropriate textures
-        let mut diffuse_nr = 0;
-        let mut specular_nr = 0;
-        let mut normal_nr = 0;
-        let mut height_nr = 0;
-
-        for (i, texture) in self.textures.iter().enumerate() {
-            // active proper texture unit before binding
-            gl::ActiveTexture(gl::TEXTURE0 + i as u32);
-
-            // retrive texture number (the N in diffuse_textureN)
-            let name = &texture.type_;
-            let number = match name.as_str() {
-                "texture_diffuse" => {
-                    diffuse_nr += 1;
-                    diffuse_nr
-                },
-                "texture_specular" => {
-                    specular_nr += 1;
-                    specular_nr
-                }
-                "texture_normal" => {
-                    normal_nr += 1;
-                    normal_nr
-                }
-                "texture_height" => {
-                    height_nr += 1;
-                    height_nr
-                }
-                _ => panic!("unknown texture type")
-            };
+        self.draw_with(&mut GlDevice::current(), shader);
+    }
+
+    pub fn draw_with<D: RenderDevice>(&self, device: &mut D, shader: &Shader) {
+        self.bind_material(device, shader);
+        device.draw_elements(self.vao, self.indices.len() as i32);
+    }
 
+    // draw `instances` copies, their attributes were pointed at an instance buffer beforehand
+    pub fn draw_instanced_with<D: RenderDevice>(&self, device: &mut D, shader: &Shader, instances: i32) {
+        self.bind_material(device, shader);
+        device.draw_elements_instanced(self.vao, self.indices.len() as i32, instances);
+    }
+
+    fn bind_material<D: RenderDevice>(&self, device: &mut D, shader: &Shader) {
+        // bind appropriate textures
+        for (i, (texture, sampler)) in self.textures.iter().zip(&self.sampler_names).enumerate() {
             // now set the sampler to the correct texture unit
-            let sampler = CString::new(format!("{}{}", name, number)).unwrap();
-            gl::Uniform1i(gl::GetUniformLocation(shader.id, sampler.as_ptr()), i as i32);
+            shader.set_with(device, sampler, UniformValue::INT(i as i32));
 
             // and finally bind the texture
-            gl::BindTexture(gl::TEXTURE_2D, texture.id);
+            device.bind_texture(i as u32, TextureTarget::TEXTURE_2D, texture.id);
         }
 
-        // draw mesh
-        gl::BindVertexArray(self.vao);
-        gl::DrawElements(gl::TRIANGLES, self.indices.len() as i32, gl::UNSIGNED_INT, ptr::null());
-        gl::BindVertexArray(0);
-
-        // always good practice to set everything back to defaults once configured.
-        gl::ActiveTexture(gl::TEXTURE0);
+        // material parameters
+        unsafe {
+            shader.set_with(device, c_str!("material.specular"), UniformValue::FLOAT(self.material.specular));
+            shader.set_with(device, c_str!("material.shininess"), UniformValue::FLOAT(self.material.shininess));
+            shader.set_with(device, c_str!("material.opacity"), UniformValue::FLOAT(self.material.opacity));
+            shader.set_with(device, c_str!("material.alphaCutoff"), UniformValue::FLOAT(self.material.effective_alpha_cutoff()));
+        }
     }
 
-    unsafe fn setup_mesh(&mut self) {
-        // create buffers / arrays
-        gl::GenVertexArrays(1, &mut self.vao);
-        gl::GenBuffers(1, &mut self.vbo);
-        gl::GenBuffers(1, &mut self.ebo);
-
-        gl::BindVertexArray(self.vao);
-        
+    fn setup_mesh<D: RenderDevice>(&mut self, device: &mut D) {
         // load data into vertex buffers
-        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
         // A great thing about structs with repr(C) is that their memory layout is sequential for all its items.
         // The effect is that we can simply pass a pointer to the struct and it translates perfectly to a glm::vec3/2 array which
         // again translates to 3/2 floats which translates to a byte array.
-        let size = (self.vertices.len() * size_of::<Vertex>()) as isize;
-        let data = &self.vertices[0] as *const Vertex as *const c_void;
-        gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-        let size = (self.indices.len() * size_of::<u32>()) as isize;
-        let data = &self.indices[0] as *const u32 as *const c_void;
-        gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-    
-        // set the vertex attribute pointers
-        let size = size_of::<Vertex>() as i32;
-        // vertex Positions
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, position) as *const c_void);
-        // vertex normals
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, normal) as *const c_void);
-        // vertex texture coords
-        gl::EnableVertexAttribArray(2);
-        gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tex_coords) as *const c_void);
-        // vertex tangent
-        gl::EnableVertexAttribArray(3);
-        gl::VertexAttribPointer(3, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tangent) as *const c_void);
-        // vertex bitangent
-        gl::EnableVertexAttribArray(4);
-        gl::VertexAttribPointer(4, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, bitangent) as *const c_void);
-
-        gl::BindVertexArray(0);
+        let vbo = device.create_buffer(BufferKind::VERTEX, BufferUsage::STATIC, unsafe { as_bytes(&self.vertices) });
+        let ebo = device.create_buffer(BufferKind::INDEX, BufferUsage::STATIC, unsafe { as_bytes(&self.indices) });
+
+        // set the vertex attribute pointers: positions, normals, texture coords, tangent and bitangent
+        let attributes = [
+            VertexAttribute { location: 0, components: 3, offset: offset_of!(Vertex, position) },
+            VertexAttribute { location: 1, components: 3, offset: offset_of!(Vertex, normal) },
+            VertexAttribute { location: 2, components: 2, offset: offset_of!(Vertex, tex_coords) },
+            VertexAttribute { location: 3, components: 3, offset: offset_of!(Vertex, tangent) },
+            VertexAttribute { location: 4, components: 3, offset: offset_of!(Vertex, bitangent) }
+        ];
+        self.vao = device.create_vertex_array(vbo, Some(ebo), size_of::<Vertex>() as i32, &attributes);
+        self.handles = [
+            GpuHandle::new(device, ResourceKind::VERTEX_ARRAY, self.vao, "mesh vertex array"),
+            GpuHandle::new(device, ResourceKind::BUFFER, vbo, "mesh vertex buffer"),
+            GpuHandle::new(device, ResourceKind::BUFFER, ebo, "mesh index buffer")
+        ];
     }
-}
\ No newline at end of file
+}
+
+// diffuse_texture1, specular_texture1, diffuse_texture2, ... numbered per texture type
+fn sampler_names(textures: &[Texture]) -> Vec<CString> {
+    let mut diffuse_nr = 0;
+    let mut specular_nr = 0;
+    let mut normal_nr = 0;
+    let mut height_nr = 0;
+
+    textures.iter().map(|texture| {
+        // retrive texture number (the N in diffuse_textureN)
+        let name = &texture.type_;
+        let number = match name.as_str() {
+            "texture_diffuse" => {
+                diffuse_nr += 1;
+                diffuse_nr
+            },
+            "texture_specular" => {
+                specular_nr += 1;
+                specular_nr
+            }
+            "texture_normal" => {
+                normal_nr += 1;
+                normal_nr
+            }
+            "texture_height" => {
+                height_nr += 1;
+                height_nr
+            }
+            _ => panic!("unknown texture type")
+        };
+        CString::new(format!("{}{}", name, number)).unwrap()
+    }).collect()
+}
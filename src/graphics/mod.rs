@@ -1,4 +1,28 @@
 pub mod shader;
 pub mod mesh;
 pub mod model;
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod shadow;
+pub mod renderer;
+pub mod framebuffer;
+pub mod hdr;
+pub mod post_process;
+pub mod ssao;
+pub mod material;
+pub mod deferred;
+pub mod clustered;
+pub mod transparency;
+pub mod render_graph;
+pub mod device;
+pub mod software;
+pub mod capture;
+pub mod hot_reload;
+pub mod preprocessor;
+pub mod reflection;
+pub mod uniform_buffer;
+pub mod compute;
+pub mod resource;
+pub mod instancing;
+pub mod state_tracker;
+pub mod draw_queue;
+pub mod profiler;
@@ -1,16 +1,18 @@
-use std::os::raw::c_void;
 use std::path::Path;
-use core::ffi::CStr;
 
-use cgmath::{ vec2, vec3, Matrix4, Rad };
-use gl;
+use cgmath::{ vec2, vec3, Matrix4 };
 use image;
 use image::DynamicImage::*;
 use image::GenericImage;
 use tobj;
 
+use crate::graphics::device::{ GlDevice, PixelFormat, RenderDevice, Sampler, TextureData, WrapMode };
+use crate::graphics::material::{ BlendMode, Material };
 use crate::graphics::mesh::Mesh;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
 use crate::graphics::shader::Shader;
+use crate::graphics::uniform_buffer::UniformRing;
+use crate::world::bounds::{ Aabb, BoundingSphere };
 use crate::world::transform::Transform;
 
 use super::mesh::Texture;
@@ -22,37 +24,101 @@ pub struct Model {
     // Model data
     pub meshes: Vec<Mesh>,
     pub texture_loaded: Vec<Texture>, // stores all the textures loaded so far, optimization to make sure textures aren't loaded more than once.
-    directory: String
+    // bounds of every mesh in model space, kept up to date by update_bounds
+    pub bounds: Aabb,
+    pub sphere: BoundingSphere,
+    directory: String,
+    // the loaded textures, deleted with the model
+    textures: Vec<GpuHandle>
 }
 
 #[allow(dead_code)]
 impl Model {
     // constructor, expects a filepath to a 3D model.
     pub fn new(path: &str) -> Model {
+        Model::with_device(unsafe { &mut GlDevice::current() }, path)
+    }
+
+    // load the model and upload it through `device`
+    pub fn with_device<D: RenderDevice>(device: &mut D, path: &str) -> Model {
         let mut model = Model::default();
-        model.load_model(path);
+        model.load_model(device, path);
+        model.update_bounds();
         model
     }
 
-    pub unsafe fn render(&mut self, transform: &Transform, shader: &Shader) {
+    // a model made of meshes built by hand, e.g. uploaded through another RenderDevice. The textures of the
+    // meshes stay with the caller unless adopted.
+    pub fn from_meshes(meshes: Vec<Mesh>) -> Model {
+        let mut model = Model {
+            meshes,
+            ..Model::default()
+        };
+        model.update_bounds();
+        model
+    }
+
+    // recompute the model bounds after changing the meshes
+    pub fn update_bounds(&mut self) {
+        self.bounds = self.meshes.iter().fold(Aabb::empty(), |bounds, mesh| bounds.union(&mesh.bounds));
+        let spheres: Vec<BoundingSphere> = self.meshes.iter().filter(|mesh| !mesh.bounds.is_empty()).map(|mesh| mesh.sphere).collect();
+        self.sphere = BoundingSphere::enclosing(&spheres);
+    }
+
+    // take ownership of a texture created by `device` for the meshes, it is deleted with the model
+    pub fn adopt_texture<D: RenderDevice>(&mut self, device: &D, texture: Texture) {
+        self.textures.push(GpuHandle::new(device, ResourceKind::TEXTURE, texture.id, &texture.path));
+        self.texture_loaded.push(texture);
+    }
+
+    // the model matrix is streamed through `objects`
+    pub unsafe fn render(&self, transform: &Transform, shader: &Shader, objects: &UniformRing) {
+        self.render_filtered(transform, shader, objects, |_| true);
+    }
+
+    // render only the meshes accepted by `filter`
+    pub unsafe fn render_filtered<F: Fn(&Mesh) -> bool>(&self, transform: &Transform, shader: &Shader, objects: &UniformRing, filter: F) {
+        shader.use_program();
+        objects.push_object(&mut GlDevice::current(), &transform.matrix());
+        for mesh in self.meshes.iter().filter(|mesh| filter(mesh)) {
+            mesh.draw(shader);
+        }
+    }
+
+    // render a single mesh, used when meshes of several models are drawn in sorted order
+    pub unsafe fn draw_mesh(&self, index: usize, model_matrix: &Matrix4<f32>, shader: &Shader, objects: &UniformRing) {
         shader.use_program();
-        // apply position of entity
-        let mut model = Matrix4::<f32>::from_translation(transform.position);
-        
-        // apply rotation of entity
-        model = model
-            * Matrix4::from_angle_x(Rad(transform.rotation.x))
-            * Matrix4::from_angle_y(Rad(transform.rotation.y))
-            * Matrix4::from_angle_z(Rad(transform.rotation.z));
-        
-        // apply scale of entity
-        model = model * Matrix4::from_nonuniform_scale(
-            transform.scale.x,
-            transform.scale.y,
-            transform.scale.z
-        );
-        shader.set_mat4(c_str!("model"), &model);
-        self.draw(&shader);
+        objects.push_object(&mut GlDevice::current(), model_matrix);
+        self.meshes[index].draw(shader);
+    }
+
+    // A unit quad in the XY plane facing +Z, textured with a single image. Meant for foliage, windows
+    // and other flat cutout or transparent objects.
+    pub fn textured_quad(path: &str, material: Material) -> Model {
+        Model::textured_quad_with_device(unsafe { &mut GlDevice::current() }, path, material)
+    }
+
+    pub fn textured_quad_with_device<D: RenderDevice>(device: &mut D, path: &str, material: Material) -> Model {
+        let path = Path::new(path);
+        let mut model = Model {
+            directory: path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into(),
+            ..Model::default()
+        };
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let texture = model.load_material_texture(device, file_name, "texture_diffuse");
+
+        let corners = [(-0.5, -0.5, 0.0, 0.0), (0.5, -0.5, 1.0, 0.0), (0.5, 0.5, 1.0, 1.0), (-0.5, 0.5, 0.0, 1.0)];
+        let vertices = corners.iter()
+            .map(|&(x, y, u, v)| Vertex {
+                position: vec3(x, y, 0.0),
+                normal: vec3(0.0, 0.0, 1.0),
+                tex_coords: vec2(u, v),
+                ..Vertex::default()
+            })
+            .collect();
+        model.meshes.push(Mesh::with_device(device, vertices, vec![0, 1, 2, 0, 2, 3], vec![texture], material));
+        model.update_bounds();
+        model
     }
 
     pub fn draw(&self, shader: &Shader) {
@@ -61,7 +127,7 @@ impl Model {
         }
     }
 
-    fn load_model(&mut self, path: &str) {
+    fn load_model<D: RenderDevice>(&mut self, device: &mut D, path: &str) {
         let path = Path::new(path);
 
         // retrieve the directory path of the filepath
@@ -89,31 +155,36 @@ impl Model {
 
             // process material
             let mut textures = Vec::new();
+            let mut mesh_material = Material::default();
             if let Some(material_id) = mesh.material_id {
                 let material = &materials[material_id];
+                mesh_material = Material::from_obj(material);
 
-                // 1. diffuse map
+                // 1. diffuse map, an opaque material with see-through texels becomes a cutout
                 if !material.diffuse_texture.is_empty() {
-                    let texture = self.load_material_texture(&material.diffuse_texture, "texture_diffuse");
+                    let texture = self.load_material_texture(device, &material.diffuse_texture, "texture_diffuse");
+                    if texture.has_alpha && mesh_material.blend_mode == BlendMode::OPAQUE {
+                        mesh_material.blend_mode = BlendMode::CUTOUT;
+                    }
                     textures.push(texture);
                 }
                 // 2. specular map
                 if !material.specular_texture.is_empty() {
-                    let texture = self.load_material_texture(&material.specular_texture, "texture_specular");
+                    let texture = self.load_material_texture(device, &material.specular_texture, "texture_specular");
                     textures.push(texture);
                 }
                 // 3. normal map
                 if !material.normal_texture.is_empty() {
-                    let texture = self.load_material_texture(&material.normal_texture, "texture_normal");
+                    let texture = self.load_material_texture(device, &material.normal_texture, "texture_normal");
                     textures.push(texture);
                 }
             }
 
-            self.meshes.push(Mesh::new(vertices, indices, textures));
+            self.meshes.push(Mesh::with_device(device, vertices, indices, textures, mesh_material));
         }
     }
 
-    fn load_material_texture(&mut self, path: &str, type_name: &str) -> Texture {
+    fn load_material_texture<D: RenderDevice>(&mut self, device: &mut D, path: &str, type_name: &str) -> Texture {
         {
             let texture = self.texture_loaded.iter().find(|t| t.path == path);
             if let Some(texture) = texture {
@@ -121,51 +192,43 @@ impl Model {
             }
         }
 
+        // color textures are authored in sRGB, data textures are linear
+        let (id, has_alpha) = texture_from_file(device, path, &self.directory, type_name == "texture_diffuse");
         let texture = Texture {
-            id: unsafe { texture_from_file(path, &self.directory) },
+            id,
             type_: type_name.into(),
-            path: path.into()
+            path: path.into(),
+            has_alpha
         };
-        self.texture_loaded.push(texture.clone());
+        self.adopt_texture(device, texture.clone());
         texture
     }
 }
 
-unsafe fn texture_from_file(path: &str, directory: &str) -> u32 {
+// returns the texture and whether any of its texels is not fully opaque
+fn texture_from_file<D: RenderDevice>(device: &mut D, path: &str, directory: &str, srgb: bool) -> (u32, bool) {
     let filename = format!("{}/{}", directory, path);
 
-    let mut texture_id = 0;
-    gl::GenTextures(1, &mut texture_id);
-
     let img = image::open(&Path::new(&filename)).expect("Texture failed to laod");
     let img = img.flipv();
     let format = match img {
-        ImageLuma8(_) => gl::RED,
-        ImageLumaA8(_) => gl::RG,
-        ImageRgb8(_) => gl::RGB,
-        ImageRgba8(_) => gl::RGBA,
+        ImageLuma8(_) => PixelFormat::R8,
+        ImageLumaA8(_) => PixelFormat::RG8,
+        ImageRgb8(_) if srgb => PixelFormat::SRGB8,
+        ImageRgb8(_) => PixelFormat::RGB8,
+        ImageRgba8(_) if srgb => PixelFormat::SRGB8_ALPHA8,
+        ImageRgba8(_) => PixelFormat::RGBA8
     };
 
     let data = img.raw_pixels();
+    let has_alpha = match img {
+        ImageLumaA8(_) => data.chunks(2).any(|texel| texel[1] < 255),
+        ImageRgba8(_) => data.chunks(4).any(|texel| texel[3] < 255),
+        _ => false
+    };
+
+    let texture = TextureData { width: img.width() as i32, height: img.height() as i32, format, pixels: &data };
+    let texture_id = device.create_texture(&texture, Sampler { wrap: WrapMode::REPEAT, mipmaps: true });
 
-    gl::BindTexture(gl::TEXTURE_2D, texture_id);
-    gl::TexImage2D(
-        gl::TEXTURE_2D, 
-        0, 
-        format as i32, 
-        img.width() as i32, 
-        img.height() as i32, 
-        0,
-        format,
-        gl::UNSIGNED_BYTE,
-        &data[0] as *const u8 as *const c_void
-    );
-    gl::GenerateMipmap(gl::TEXTURE_2D);
-
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-
-    texture_id
+    (texture_id, has_alpha)
 }
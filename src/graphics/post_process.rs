@@ -0,0 +1,317 @@
+use std::ffi::{ CStr, CString };
+use std::fs;
+use std::os::raw::c_void;
+
+use gl;
+use gl::types::*;
+
+use crate::graphics::device::{ GlDevice, UniformValue };
+use crate::graphics::framebuffer::FullscreenTriangle;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::shader::Shader;
+
+// vertex shader every full-screen effect is compiled with
+pub const FULLSCREEN_VERTEX_SHADER: &str = "src/graphics/shaders/fullscreen.vs";
+
+// A uniform of an effect, the name is converted once rather than every frame
+pub struct EffectParameter {
+    pub uniform: CString,
+    pub value: UniformValue
+}
+
+impl EffectParameter {
+    pub fn new(uniform: &str, value: UniformValue) -> EffectParameter {
+        EffectParameter { uniform: CString::new(uniform).unwrap(), value }
+    }
+}
+
+// An extra texture an effect samples besides its input, bound from texture unit 1 onwards
+pub struct EffectTexture {
+    pub uniform: CString,
+    pub target: GLenum,
+    pub id: u32
+}
+
+// A full-screen fragment shader reading the previous effect's output. Every effect gets `inputTexture`,
+// `resolution` and `time`, plus one uniform per parameter. The locations come from the uniform
+// table of the shader, which is looked up again when the shader is reloaded.
+pub struct PostEffect {
+    pub name: String,
+    pub enabled: bool,
+    pub parameters: Vec<EffectParameter>,
+    pub textures: Vec<EffectTexture>,
+    // textures the effect created, deleted with it
+    owned_textures: Vec<GpuHandle>,
+    shader: Shader
+}
+
+impl PostEffect {
+    // register a user effect, `shader` should be built with FULLSCREEN_VERTEX_SHADER
+    pub fn custom(name: &str, shader: Shader) -> PostEffect {
+        PostEffect {
+            name: name.into(),
+            enabled: true,
+            parameters: Vec::new(),
+            textures: Vec::new(),
+            owned_textures: Vec::new(),
+            shader
+        }
+    }
+
+    fn builtin(name: &str, fragment_path: &str, parameters: &[(&str, f32)]) -> PostEffect {
+        let mut effect = PostEffect::custom(name, Shader::new(FULLSCREEN_VERTEX_SHADER, fragment_path));
+        effect.parameters = parameters.iter().map(|&(uniform, value)| EffectParameter::new(uniform, UniformValue::FLOAT(value))).collect();
+        effect
+    }
+
+    pub fn fxaa() -> PostEffect {
+        PostEffect::builtin("fxaa", "src/graphics/shaders/post_fxaa.fs", &[])
+    }
+
+    pub fn vignette(intensity: f32, radius: f32, smoothness: f32) -> PostEffect {
+        PostEffect::builtin("vignette", "src/graphics/shaders/post_vignette.fs", &[
+            ("intensity", intensity),
+            ("radius", radius),
+            ("smoothness", smoothness)
+        ])
+    }
+
+    pub fn chromatic_aberration(strength: f32) -> PostEffect {
+        PostEffect::builtin("chromatic_aberration", "src/graphics/shaders/post_chromatic_aberration.fs", &[
+            ("strength", strength)
+        ])
+    }
+
+    pub fn film_grain(intensity: f32) -> PostEffect {
+        PostEffect::builtin("film_grain", "src/graphics/shaders/post_film_grain.fs", &[
+            ("intensity", intensity)
+        ])
+    }
+
+    pub fn sharpen(amount: f32) -> PostEffect {
+        PostEffect::builtin("sharpen", "src/graphics/shaders/post_sharpen.fs", &[
+            ("amount", amount)
+        ])
+    }
+
+    // colour grading through a 3D LUT in the .cube format
+    pub fn color_grading(lut_path: &str, contribution: f32) -> Result<PostEffect, String> {
+        let source = fs::read_to_string(lut_path)
+            .map_err(|e| format!("Failed to read LUT {}: {}", lut_path, e))?;
+        let lut = CubeLut::parse(&source)?;
+
+        let mut effect = PostEffect::builtin("color_grading", "src/graphics/shaders/post_color_grading.fs", &[
+            ("contribution", contribution),
+            ("lutSize", lut.size as f32)
+        ]);
+        // the shader maps the input colour from the domain of the LUT onto the cube
+        effect.parameters.push(EffectParameter::new("domainMin", UniformValue::VEC3(lut.domain_min)));
+        effect.parameters.push(EffectParameter::new("domainMax", UniformValue::VEC3(lut.domain_max)));
+        let texture = unsafe { lut.upload() };
+        effect.textures.push(EffectTexture {
+            uniform: CString::new("lut").unwrap(),
+            target: gl::TEXTURE_3D,
+            id: texture.id()
+        });
+        effect.owned_textures.push(texture);
+        Ok(effect)
+    }
+
+    pub fn shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    unsafe fn draw(&self, input: u32, width: i32, height: i32, time: f32, fullscreen: &FullscreenTriangle) {
+        self.shader.use_program();
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, input);
+        self.shader.set_int(c_str!("inputTexture"), 0);
+        self.shader.set_vec2(c_str!("resolution"), width as f32, height as f32);
+        self.shader.set_float(c_str!("time"), time);
+
+        let mut device = GlDevice::current();
+        for parameter in &self.parameters {
+            self.shader.set_with(&mut device, &parameter.uniform, parameter.value);
+        }
+        for (i, texture) in self.textures.iter().enumerate() {
+            let unit = i as u32 + 1;
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(texture.target, texture.id);
+            self.shader.set_int(&texture.uniform, unit as i32);
+        }
+
+        fullscreen.draw();
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+// A 3D colour lookup table, red varying fastest as in the .cube format. The entries are the graded
+// colours, the domain is the range of input colours the cube spans.
+pub struct CubeLut {
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>
+}
+
+impl CubeLut {
+    pub fn parse(source: &str) -> Result<CubeLut, String> {
+        let mut size = 0;
+        let mut domain_min = [0.0f32; 3];
+        let mut domain_max = [1.0f32; 3];
+        let mut data: Vec<[f32; 3]> = Vec::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let keyword = tokens.next().unwrap();
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err("1D LUTs are not supported".into()),
+                "LUT_3D_SIZE" => {
+                    size = tokens.next()
+                        .and_then(|t| t.parse().ok())
+                        .ok_or_else(|| format!("Invalid LUT_3D_SIZE on line {}", number + 1))?;
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let values = parse_triplet(tokens, number)?;
+                    if keyword == "DOMAIN_MIN" { domain_min = values } else { domain_max = values }
+                }
+                _ => data.push(parse_triplet(line.split_whitespace(), number)?)
+            }
+        }
+
+        if size < 2 {
+            return Err("Missing LUT_3D_SIZE".into());
+        }
+        if data.len() != size * size * size {
+            return Err(format!("Expected {} LUT entries, found {}", size * size * size, data.len()));
+        }
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err(format!("Empty LUT domain from {:?} to {:?}", domain_min, domain_max));
+        }
+        Ok(CubeLut { size, domain_min, domain_max, data })
+    }
+
+    // where `color` falls in the cube, 0 to 1 over the domain on each axis as post_color_grading.fs computes it
+    pub fn coordinates(&self, color: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|c| ((color[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c])).clamp(0.0, 1.0))
+    }
+
+    // the graded colour, interpolated between the entries around `color` like the texture lookup
+    pub fn lookup(&self, color: [f32; 3]) -> [f32; 3] {
+        let position = self.coordinates(color).map(|t| t * (self.size - 1) as f32);
+        let base = position.map(|p| (p as usize).min(self.size - 2));
+        let mut graded = [0.0; 3];
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, corner >> 2];
+            let weight: f32 = (0..3)
+                .map(|c| {
+                    let fraction = position[c] - base[c] as f32;
+                    if offset[c] == 1 { fraction } else { 1.0 - fraction }
+                })
+                .product();
+            let (r, g, b) = (base[0] + offset[0], base[1] + offset[1], base[2] + offset[2]);
+            let entry = self.data[r + (g + b * self.size) * self.size];
+            for c in 0..3 {
+                graded[c] += entry[c] * weight;
+            }
+        }
+        graded
+    }
+
+    // upload as a linearly filtered 3D texture, deleted when the handle is dropped
+    pub unsafe fn upload(&self) -> GpuHandle {
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_3D, texture);
+        gl::TexImage3D(
+            gl::TEXTURE_3D, 0, gl::RGB16F as i32,
+            self.size as i32, self.size as i32, self.size as i32,
+            0, gl::RGB, gl::FLOAT, self.data.as_ptr() as *const c_void
+        );
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        GpuHandle::new(&GlDevice::current(), ResourceKind::TEXTURE, texture, "color grading LUT")
+    }
+}
+
+fn parse_triplet<'a>(mut tokens: impl Iterator<Item = &'a str>, number: usize) -> Result<[f32; 3], String> {
+    let mut values = [0.0; 3];
+    for value in values.iter_mut() {
+        *value = tokens.next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| format!("Invalid LUT entry on line {}", number + 1))?;
+    }
+    Ok(values)
+}
+
+// Ordered list of effects applied after tonemapping. Every enabled effect is a pass of the frame graph
+// reading the previous one's output, the intermediate targets are graph transients.
+pub struct PostProcessStack {
+    effects: Vec<PostEffect>,
+    fullscreen: FullscreenTriangle,
+    time: f32
+}
+
+impl PostProcessStack {
+    pub fn new() -> PostProcessStack {
+        PostProcessStack {
+            effects: Vec::new(),
+            fullscreen: FullscreenTriangle::new(),
+            time: 0.0
+        }
+    }
+
+    // the built-in effects, only FXAA enabled
+    pub fn with_default_effects() -> PostProcessStack {
+        let mut stack = PostProcessStack::new();
+        stack.push(PostEffect::fxaa());
+        stack.push(PostEffect { enabled: false, ..PostEffect::sharpen(0.3) });
+        stack.push(PostEffect { enabled: false, ..PostEffect::chromatic_aberration(0.004) });
+        stack.push(PostEffect { enabled: false, ..PostEffect::film_grain(0.05) });
+        stack.push(PostEffect { enabled: false, ..PostEffect::vignette(0.6, 0.85, 0.45) });
+        stack
+    }
+
+    pub fn push(&mut self, effect: PostEffect) {
+        self.effects.push(effect);
+    }
+
+    pub fn shaders_mut(&mut self) -> impl Iterator<Item = &mut Shader> {
+        self.effects.iter_mut().map(|e| &mut e.shader)
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    // returns whether the effect is enabled now, false when no effect has this name
+    pub fn toggle(&mut self, name: &str) -> bool {
+        match self.effects.iter_mut().find(|e| e.name == name) {
+            Some(effect) => {
+                effect.enabled = !effect.enabled;
+                effect.enabled
+            }
+            None => false
+        }
+    }
+
+    // advance the time given to the effects, once per frame
+    pub fn advance(&mut self, delta_time: f32) {
+        self.time += delta_time;
+    }
+
+    // draw the effect at `index` of `effects()` into the bound framebuffer
+    pub unsafe fn draw_effect(&self, index: usize, input: u32, width: i32, height: i32) {
+        self.effects[index].draw(input, width, height, self.time, &self.fullscreen);
+    }
+}
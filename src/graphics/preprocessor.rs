@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+// where the engine shaders and the files they include live
+pub const SHADER_DIRECTORY: &str = "src/graphics/shaders";
+
+// Resolves `#include "file"` and `#include <file>` and injects defines before GLSL reaches the driver.
+// Quoted includes are looked up next to the including file first, then in the search paths, angle
+// brackets only in the search paths. Includes are resolved whatever the surrounding #if says, and
+// `#pragma once` keeps a file from being pasted twice. The `#version` line stays first, the defines
+// follow it.
+pub struct ShaderPreprocessor {
+    pub search_paths: Vec<PathBuf>,
+    pub defines: Vec<(String, String)>
+}
+
+impl Default for ShaderPreprocessor {
+    fn default() -> Self {
+        ShaderPreprocessor {
+            search_paths: vec![PathBuf::from(SHADER_DIRECTORY)],
+            defines: Vec::new()
+        }
+    }
+}
+
+// Where an output line comes from. Variants are upper case like every enum of the engine, clippy only
+// asks otherwise of private ones.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum LineOrigin {
+    // index in `files` and line number in that file, from 1
+    FILE(usize, usize),
+    // a define injected by the preprocessor
+    DEFINE
+}
+
+// GLSL ready to compile, and where each of its lines comes from
+pub struct ProcessedSource {
+    pub code: String,
+    // every file the code was read from, the main one first
+    pub files: Vec<PathBuf>,
+    lines: Vec<LineOrigin>
+}
+
+impl ShaderPreprocessor {
+    pub fn new(search_paths: &[&str]) -> ShaderPreprocessor {
+        ShaderPreprocessor {
+            search_paths: search_paths.iter().map(PathBuf::from).collect(),
+            defines: Vec::new()
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.push((name.into(), value.into()));
+    }
+
+    // `keywords` are defined to 1 after the preprocessor's own defines
+    pub fn process_file(&self, path: &Path, keywords: &[String]) -> Result<ProcessedSource, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("ERROR::SHADER:: failed to read {}: {}", path.display(), e))?;
+        self.process(path, &source, keywords)
+    }
+
+    // `path` names the source in errors and is the directory its quoted includes are looked up from
+    pub fn process(&self, path: &Path, source: &str, keywords: &[String]) -> Result<ProcessedSource, String> {
+        let mut output = ProcessedSource { code: String::new(), files: vec![path.to_path_buf()], lines: Vec::new() };
+
+        let version = source.lines().position(|line| line.trim_start().starts_with("#version"));
+        if let Some(index) = version {
+            output.push(source.lines().nth(index).unwrap(), LineOrigin::FILE(0, index + 1));
+        }
+        for (name, value) in &self.defines {
+            output.push(&format!("#define {} {}", name, value), LineOrigin::DEFINE);
+        }
+        for keyword in keywords {
+            output.push(&format!("#define {} 1", keyword), LineOrigin::DEFINE);
+        }
+
+        let mut expansion = Expansion { stack: vec![path.to_path_buf()], once: HashSet::new() };
+        self.expand(source, 0, version, &mut expansion, &mut output)?;
+        Ok(output)
+    }
+
+    fn expand(&self, source: &str, file: usize, skip: Option<usize>, expansion: &mut Expansion, output: &mut ProcessedSource) -> Result<(), String> {
+        let path = output.files[file].clone();
+        for (index, line) in source.lines().enumerate() {
+            let directive = line.trim_start();
+            let location = || format!("{}:{}", path.display(), index + 1);
+            if Some(index) == skip {
+                continue;
+            } else if directive.starts_with("#pragma") && directive.split_whitespace().nth(1) == Some("once") {
+                expansion.once.insert(path.clone());
+            } else if directive.starts_with("#version") {
+                return Err(format!("ERROR::SHADER:: {}: #version is only allowed in the main file", location()));
+            } else if let Some(include) = directive.strip_prefix("#include") {
+                let included = self.resolve(include.trim(), &path).map_err(|e| format!("ERROR::SHADER:: {}: {}", location(), e))?;
+                if expansion.once.contains(&included) {
+                    continue;
+                }
+                if expansion.stack.contains(&included) {
+                    return Err(format!("ERROR::SHADER:: {}: {} includes itself", location(), included.display()));
+                }
+                let included_source = fs::read_to_string(&included)
+                    .map_err(|e| format!("ERROR::SHADER:: {}: failed to read {}: {}", location(), included.display(), e))?;
+
+                let index = match output.files.iter().position(|f| *f == included) {
+                    Some(index) => index,
+                    None => {
+                        output.files.push(included.clone());
+                        output.files.len() - 1
+                    }
+                };
+                expansion.stack.push(included);
+                self.expand(&included_source, index, None, expansion, output)?;
+                expansion.stack.pop();
+            } else {
+                output.push(line, LineOrigin::FILE(file, index + 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, include: &str, from: &Path) -> Result<PathBuf, String> {
+        let (name, relative) = if include.len() >= 2 && include.starts_with('"') && include.ends_with('"') {
+            (&include[1..include.len() - 1], true)
+        } else if include.len() >= 2 && include.starts_with('<') && include.ends_with('>') {
+            (&include[1..include.len() - 1], false)
+        } else {
+            return Err(format!("malformed #include {}", include));
+        };
+
+        let local = from.parent().filter(|_| relative).map(|directory| directory.join(name));
+        local.into_iter()
+            .chain(self.search_paths.iter().map(|directory| directory.join(name)))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| format!("cannot find include \"{}\"", name))
+    }
+}
+
+struct Expansion {
+    // files being expanded, to catch cycles
+    stack: Vec<PathBuf>,
+    // files with #pragma once already pasted
+    once: HashSet<PathBuf>
+}
+
+impl ProcessedSource {
+    fn push(&mut self, line: &str, origin: LineOrigin) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.lines.push(origin);
+    }
+
+    // the file and line an output line, counted from 1 like compilers do, was read from
+    pub fn source_location(&self, line: usize) -> Option<(&Path, usize)> {
+        match self.lines.get(line.checked_sub(1)?)? {
+            LineOrigin::FILE(file, line) => Some((&self.files[*file], *line)),
+            LineOrigin::DEFINE => None
+        }
+    }
+
+    // Rewrite the locations in a compiler log, `0:12(4): error` (Mesa), `0(12) : error` (NVIDIA) or
+    // `ERROR: 0:12: ...` (AMD, Intel), into the file and line they come from.
+    pub fn map_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| match find_location(line) {
+                Some((start, end, number)) if number >= 1 && number <= self.lines.len() => {
+                    let location = match self.lines[number - 1] {
+                        LineOrigin::FILE(file, line) => format!("{}:{}", self.files[file].display(), line),
+                        LineOrigin::DEFINE => format!("<defines>:{}", number)
+                    };
+                    format!("{}{}{}", &line[..start], location, &line[end..])
+                }
+                _ => line.to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// byte range and line number of the first `0:N` or `0(N)` of a log line, the source string is always 0
+fn find_location(line: &str) -> Option<(usize, usize, usize)> {
+    let bytes = line.as_bytes();
+    for start in 0..bytes.len() {
+        if bytes[start] != b'0' || (start > 0 && bytes[start - 1].is_ascii_alphanumeric()) {
+            continue;
+        }
+        let separator = match bytes.get(start + 1) {
+            Some(&b':') => b':',
+            Some(&b'(') => b'(',
+            _ => continue
+        };
+        let digits = bytes[start + 2..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            continue;
+        }
+        let mut end = start + 2 + digits;
+        if separator == b'(' {
+            if bytes.get(end) != Some(&b')') {
+                continue;
+            }
+            end += 1;
+        }
+        let number = line[start + 2..start + 2 + digits].parse().ok()?;
+        return Some((start, end, number));
+    }
+    None
+}
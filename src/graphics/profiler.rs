@@ -0,0 +1,102 @@
+use std::cell::Cell;
+
+use crate::graphics::state_tracker::{ StateCounts, StateStats };
+use crate::world::scene::CullingStats;
+
+// Adds up the state changes of the passes of a frame and what frustum culling kept. While enabled, the
+// average of the last second is printed with what the passes asked for next to what reached the GPU.
+#[derive(Default)]
+pub struct Profiler {
+    pub enabled: bool,
+    frame: Cell<StateStats>,
+    frame_culling: Cell<CullingStats>,
+    // the last complete frame
+    last_frame: StateStats,
+    last_culling: CullingStats,
+    // summed since the last report
+    accumulated: StateStats,
+    accumulated_culling: CullingStats,
+    frames: u32,
+    elapsed: f32
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.reset();
+        self.enabled
+    }
+
+    // passes only borrow the renderer, recording goes through a Cell
+    pub fn record(&self, stats: StateStats) {
+        let mut frame = self.frame.get();
+        frame += stats;
+        self.frame.set(frame);
+    }
+
+    pub fn record_culling(&self, stats: CullingStats) {
+        let mut frame = self.frame_culling.get();
+        frame += stats;
+        self.frame_culling.set(frame);
+    }
+
+    pub fn end_frame(&mut self, delta_time: f32) {
+        self.last_frame = self.frame.take();
+        self.last_culling = self.frame_culling.take();
+        if !self.enabled {
+            return;
+        }
+        self.accumulated += self.last_frame;
+        self.accumulated_culling += self.last_culling;
+        self.frames += 1;
+        self.elapsed += delta_time;
+        if self.elapsed >= 1.0 {
+            println!("{}", self.report());
+            self.reset();
+        }
+    }
+
+    pub fn last_frame(&self) -> StateStats {
+        self.last_frame
+    }
+
+    pub fn last_culling(&self) -> CullingStats {
+        self.last_culling
+    }
+
+    fn reset(&mut self) {
+        self.accumulated = StateStats::default();
+        self.accumulated_culling = CullingStats::default();
+        self.frames = 0;
+        self.elapsed = 0.0;
+    }
+
+    // per frame averages since the last report, requested -> issued
+    pub fn report(&self) -> String {
+        let frames = self.frames.max(1);
+        let average = |counts: &StateCounts| StateCounts {
+            programs: counts.programs / frames,
+            pipeline_states: counts.pipeline_states / frames,
+            textures: counts.textures / frames,
+            uniforms: counts.uniforms / frames,
+            uniform_buffers: counts.uniform_buffers / frames,
+            draws: counts.draws / frames
+        };
+        let (requested, issued) = (average(&self.accumulated.requested), average(&self.accumulated.issued));
+        let culling = &self.accumulated_culling;
+        format!(
+            "PROFILER:: {} frames, per frame: entities {} visible of {} | draws {} | programs {} -> {} | states {} -> {} | textures {} -> {} | uniforms {} -> {} | uniform buffers {} -> {} | total {} -> {}",
+            self.frames, culling.visible / frames, culling.tested / frames, issued.draws,
+            requested.programs, issued.programs,
+            requested.pipeline_states, issued.pipeline_states,
+            requested.textures, issued.textures,
+            requested.uniforms, issued.uniforms,
+            requested.uniform_buffers, issued.uniform_buffers,
+            requested.changes(), issued.changes()
+        )
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use gl;
+use gl::types::*;
+
+use crate::graphics::device::UniformValue;
+
+// Type of an active uniform or attribute, samplers of every dimension are folded into SAMPLER
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum UniformType {
+    BOOL,
+    INT,
+    UINT,
+    FLOAT,
+    VEC2,
+    VEC3,
+    VEC4,
+    IVEC2,
+    IVEC3,
+    IVEC4,
+    MAT2,
+    MAT3,
+    MAT4,
+    SAMPLER,
+    // anything the setters can't write, e.g. bvec3 or images
+    OTHER
+}
+
+impl UniformType {
+    pub fn from_gl(type_: GLenum) -> UniformType {
+        match type_ {
+            gl::BOOL => UniformType::BOOL,
+            gl::INT => UniformType::INT,
+            gl::UNSIGNED_INT => UniformType::UINT,
+            gl::FLOAT => UniformType::FLOAT,
+            gl::FLOAT_VEC2 => UniformType::VEC2,
+            gl::FLOAT_VEC3 => UniformType::VEC3,
+            gl::FLOAT_VEC4 => UniformType::VEC4,
+            gl::INT_VEC2 => UniformType::IVEC2,
+            gl::INT_VEC3 => UniformType::IVEC3,
+            gl::INT_VEC4 => UniformType::IVEC4,
+            gl::FLOAT_MAT2 => UniformType::MAT2,
+            gl::FLOAT_MAT3 => UniformType::MAT3,
+            gl::FLOAT_MAT4 => UniformType::MAT4,
+            gl::SAMPLER_1D | gl::SAMPLER_2D | gl::SAMPLER_3D | gl::SAMPLER_CUBE | gl::SAMPLER_2D_SHADOW
+            | gl::SAMPLER_2D_ARRAY | gl::SAMPLER_2D_ARRAY_SHADOW | gl::SAMPLER_CUBE_SHADOW | gl::SAMPLER_BUFFER
+            | gl::SAMPLER_2D_MULTISAMPLE | gl::INT_SAMPLER_2D | gl::INT_SAMPLER_3D | gl::INT_SAMPLER_BUFFER
+            | gl::UNSIGNED_INT_SAMPLER_2D | gl::UNSIGNED_INT_SAMPLER_3D | gl::UNSIGNED_INT_SAMPLER_BUFFER => UniformType::SAMPLER,
+            _ => UniformType::OTHER
+        }
+    }
+
+    // the type of a GLSL declaration, None for structs
+    pub fn from_glsl(type_: &str) -> Option<UniformType> {
+        let uniform_type = match type_ {
+            "bool" => UniformType::BOOL,
+            "int" => UniformType::INT,
+            "uint" => UniformType::UINT,
+            "float" => UniformType::FLOAT,
+            "vec2" => UniformType::VEC2,
+            "vec3" => UniformType::VEC3,
+            "vec4" => UniformType::VEC4,
+            "ivec2" => UniformType::IVEC2,
+            "ivec3" => UniformType::IVEC3,
+            "ivec4" => UniformType::IVEC4,
+            "mat2" => UniformType::MAT2,
+            "mat3" => UniformType::MAT3,
+            "mat4" => UniformType::MAT4,
+            _ if type_.starts_with("sampler") || type_.starts_with("isampler") || type_.starts_with("usampler") => UniformType::SAMPLER,
+            "bvec2" | "bvec3" | "bvec4" | "uvec2" | "uvec3" | "uvec4" | "mat2x3" | "mat3x4" | "mat4x3" => UniformType::OTHER,
+            _ => return None
+        };
+        Some(uniform_type)
+    }
+
+    // whether a setter writing `value` matches the declaration, booleans take ints and floats like GL does
+    pub fn accepts(self, value: &UniformValue) -> bool {
+        match value {
+            UniformValue::INT(_) => matches!(self, UniformType::INT | UniformType::BOOL | UniformType::SAMPLER),
+            UniformValue::FLOAT(_) => matches!(self, UniformType::FLOAT | UniformType::BOOL),
+            UniformValue::VEC2(_) => self == UniformType::VEC2,
+            UniformValue::VEC3(_) => self == UniformType::VEC3,
+            UniformValue::IVEC3(_) => self == UniformType::IVEC3,
+            UniformValue::MAT4(_) => self == UniformType::MAT4
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UniformInfo {
+    pub location: i32,
+    pub type_: UniformType,
+    // element count of arrays, 1 otherwise
+    pub size: i32
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub location: i32,
+    pub type_: UniformType
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct UniformBlockInfo {
+    pub name: String,
+    pub index: u32,
+    // bytes, 0 when the backend can't tell
+    pub size: i32,
+    pub binding: u32
+}
+
+// Active uniforms, attributes and uniform blocks of a linked program. Arrays are listed under their
+// name, which refers to the first element, and under every `name[i]`. Struct members are listed by
+// their full `name.member` path.
+#[derive(Clone, Default, Debug)]
+pub struct ProgramReflection {
+    pub uniforms: HashMap<String, UniformInfo>,
+    pub attributes: Vec<AttributeInfo>,
+    pub blocks: Vec<UniformBlockInfo>
+}
+
+impl ProgramReflection {
+    pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&AttributeInfo> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+
+    pub fn block(&self, name: &str) -> Option<&UniformBlockInfo> {
+        self.blocks.iter().find(|block| block.name == name)
+    }
+
+    // add a uniform as GL lists it, `name[0]` for arrays, asking `location` for the location of each element
+    pub fn add_uniform<F: FnMut(&str) -> i32>(&mut self, name: &str, type_: UniformType, size: i32, mut location: F) {
+        if size > 1 || name.ends_with("[0]") {
+            let base = name.trim_end_matches("[0]");
+            for i in 0..size {
+                let element = format!("{}[{}]", base, i);
+                let element_location = location(&element);
+                if element_location < 0 {
+                    continue;
+                }
+                if i == 0 {
+                    self.uniforms.insert(base.into(), UniformInfo { location: element_location, type_, size });
+                }
+                self.uniforms.insert(element, UniformInfo { location: element_location, type_, size: 1 });
+            }
+        } else {
+            let uniform_location = location(name);
+            if uniform_location >= 0 {
+                self.uniforms.insert(name.into(), UniformInfo { location: uniform_location, type_, size: 1 });
+            }
+        }
+    }
+
+    // Approximate the reflection of a program from the declarations of its preprocessed stages, for
+    // backends that don't compile GLSL. Every declared uniform counts as active and locations are
+    // handed out in declaration order.
+    pub fn from_sources(sources: &[&str]) -> ProgramReflection {
+        let mut reflection = ProgramReflection::default();
+        let mut next_location = 0;
+        let mut constants: HashMap<String, i32> = HashMap::new();
+        let mut structs: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+        for (stage, source) in sources.iter().enumerate() {
+            let mut statements = Statements::new(source);
+            while let Some(statement) = statements.next() {
+                let tokens: Vec<&str> = statement.split(|c: char| c.is_whitespace() || c == ',' || c == '=' || c == '(' || c == ')')
+                    .filter(|token| !token.is_empty())
+                    .collect();
+                match tokens.as_slice() {
+                    ["#define", name, value, ..] => {
+                        if let Ok(value) = value.parse() {
+                            constants.insert(name.to_string(), value);
+                        }
+                    }
+                    ["const", "int", name, value, ..] => {
+                        if let Ok(value) = value.trim_end_matches(';').parse() {
+                            constants.insert(name.to_string(), value);
+                        }
+                    }
+                    ["struct", name, ..] => {
+                        let body = statements.block();
+                        let members = body.split(';')
+                            .filter_map(|member| {
+                                let mut parts = member.split_whitespace();
+                                Some((parts.next()?.to_string(), parts.next()?.to_string()))
+                            })
+                            .collect();
+                        structs.insert(name.trim_end_matches('{').to_string(), members);
+                    }
+                    [.., "uniform", block] if statement.trim_end().ends_with('{') || statements.peek_brace() => {
+                        // a uniform block, its members are not individual uniforms
+                        let name = block.trim_end_matches('{');
+                        statements.block();
+                        let binding = tokens.windows(2).find(|pair| pair[0] == "binding").and_then(|pair| pair[1].parse().ok()).unwrap_or(0);
+                        let index = reflection.blocks.len() as u32;
+                        reflection.blocks.push(UniformBlockInfo { name: name.into(), index, size: 0, binding });
+                    }
+                    ["uniform", type_, declarators @ ..] => {
+                        for declarator in declarators {
+                            let (name, size) = array_declarator(declarator.trim_end_matches(';'), &constants);
+                            let mut location = |_: &str| {
+                                next_location += 1;
+                                next_location - 1
+                            };
+                            add_declaration(&mut reflection, &structs, type_, name, size, &mut location);
+                        }
+                    }
+                    [.., "in", type_, name] if stage == 0 => {
+                        let location = tokens.windows(2).find(|pair| pair[0] == "location").and_then(|pair| pair[1].parse().ok());
+                        let location = location.unwrap_or(reflection.attributes.len() as i32);
+                        let type_ = UniformType::from_glsl(type_).unwrap_or(UniformType::OTHER);
+                        reflection.attributes.push(AttributeInfo { name: name.trim_end_matches(';').into(), location, type_ });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        reflection
+    }
+}
+
+fn add_declaration<F: FnMut(&str) -> i32>(
+    reflection: &mut ProgramReflection,
+    structs: &HashMap<String, Vec<(String, String)>>,
+    type_: &str,
+    name: &str,
+    size: i32,
+    location: &mut F
+) {
+    match (UniformType::from_glsl(type_), structs.get(type_)) {
+        (Some(uniform_type), _) => {
+            let listed = if size > 1 { format!("{}[0]", name) } else { name.to_string() };
+            reflection.add_uniform(&listed, uniform_type, size, &mut *location);
+        }
+        (None, Some(members)) => {
+            for i in 0..size {
+                let prefix = if size > 1 { format!("{}[{}]", name, i) } else { name.to_string() };
+                for (member_type, member_name) in members {
+                    let (member_name, member_size) = array_declarator(member_name, &HashMap::new());
+                    add_declaration(reflection, structs, member_type, &format!("{}.{}", prefix, member_name), member_size, location);
+                }
+            }
+        }
+        (None, None) => {}
+    }
+}
+
+// `name[SIZE]` into the name and its size, SIZE being a number or a known constant
+fn array_declarator<'a>(declarator: &'a str, constants: &HashMap<String, i32>) -> (&'a str, i32) {
+    match declarator.find('[') {
+        Some(open) => {
+            let size = declarator[open + 1..].trim_end_matches(']');
+            let size = size.parse().ok().or_else(|| constants.get(size).copied()).unwrap_or(1);
+            (&declarator[..open], size)
+        }
+        None => (declarator, 1)
+    }
+}
+
+// GLSL split into statements: preprocessor lines, and code up to a `;` or an opening brace, comments removed
+struct Statements<'a> {
+    source: &'a str,
+    position: usize
+}
+
+impl<'a> Statements<'a> {
+    fn new(source: &'a str) -> Statements<'a> {
+        Statements { source, position: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn skip_blank(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.position += trimmed.find("*/").map_or(trimmed.len(), |end| end + 2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<String> {
+        self.skip_blank();
+        let rest = self.rest();
+        if rest.is_empty() {
+            return None;
+        }
+        let end = if rest.starts_with('#') {
+            rest.find('\n').unwrap_or(rest.len())
+        } else {
+            // a brace ends the statement before it, the brace itself is consumed with the block
+            rest.find([';', '{', '}']).map_or(rest.len(), |end| if rest.as_bytes()[end] == b';' { end + 1 } else { end.max(1) })
+        };
+        self.position += end;
+        let statement = rest[..end].split("//").next().unwrap_or("");
+        Some(statement.trim().to_string())
+    }
+
+    fn peek_brace(&mut self) -> bool {
+        self.skip_blank();
+        self.rest().starts_with('{')
+    }
+
+    // the content of the braces starting here, nested blocks included, and the trailing `name;` if any
+    fn block(&mut self) -> &'a str {
+        self.skip_blank();
+        let rest = self.rest();
+        if !rest.starts_with('{') {
+            return "";
+        }
+        let mut depth = 0;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.position += i + 1;
+                        // instance name of a block, or the end of a struct declaration
+                        let after = self.rest();
+                        if let Some(end) = after.find(';') {
+                            if after[..end].trim().chars().all(|c| c.is_alphanumeric() || c == '_') {
+                                self.position += end + 1;
+                            }
+                        }
+                        return &rest[1..i];
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.position = self.source.len();
+        &rest[1..]
+    }
+}
@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::ptr;
+
+use gl;
+use gl::types::*;
+
+use crate::graphics::device::GlDevice;
+use crate::graphics::framebuffer::TextureFormat;
+use crate::graphics::resource::{ self, GpuHandle };
+
+// Handle to a texture or buffer declared in a RenderGraph
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ResourceId(usize);
+
+impl ResourceId {
+    // position of the resource in declaration order, index of CompiledGraph::aliases
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureDesc {
+    pub width: i32,
+    pub height: i32,
+    pub format: TextureFormat
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ResourceKind {
+    // allocated by the graph for the frame, may share its storage with other transients
+    TRANSIENT(TextureDesc),
+    // a texture owned outside of the graph that passes can attach
+    IMPORTED(u32, TextureDesc),
+    // owned and bound by a subsystem (shadow map, G-buffer...), only used to order and cull the passes
+    EXTERNAL,
+    // the default framebuffer, passes writing it are never culled
+    BACKBUFFER(i32, i32)
+}
+
+struct Resource {
+    name: String,
+    kind: ResourceKind
+}
+
+// What happens to an attachment's previous content when a pass starts
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoadOp {
+    LOAD,
+    // cleared to the pass' clear colour, or depth 1.0
+    CLEAR
+}
+
+// Fixed-function state a pass runs with. The graph only issues the changes from the previous pass,
+// so a pass changing any of it itself must restore it before returning.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PassState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    // source and destination factors
+    pub blend: Option<(GLenum, GLenum)>,
+    pub cull_face: Option<GLenum>,
+    pub clear_color: [f32; 4]
+}
+
+impl Default for PassState {
+    fn default() -> Self {
+        PassState {
+            depth_test: true,
+            depth_write: true,
+            blend: None,
+            cull_face: None,
+            clear_color: [0.0, 0.0, 0.0, 1.0]
+        }
+    }
+}
+
+impl PassState {
+    unsafe fn apply(&self, previous: Option<&PassState>) {
+        let changed = |f: fn(&PassState) -> bool| previous.is_none_or(|p| f(p) != f(self));
+
+        if changed(|s| s.depth_test) {
+            if self.depth_test { gl::Enable(gl::DEPTH_TEST) } else { gl::Disable(gl::DEPTH_TEST) }
+        }
+        if changed(|s| s.depth_write) {
+            gl::DepthMask(if self.depth_write { gl::TRUE } else { gl::FALSE });
+        }
+        if previous.is_none_or(|p| p.blend != self.blend) {
+            match self.blend {
+                Some((source, destination)) => {
+                    gl::Enable(gl::BLEND);
+                    gl::BlendFunc(source, destination);
+                }
+                None => gl::Disable(gl::BLEND)
+            }
+        }
+        if previous.is_none_or(|p| p.cull_face != self.cull_face) {
+            match self.cull_face {
+                Some(face) => {
+                    gl::Enable(gl::CULL_FACE);
+                    gl::CullFace(face);
+                }
+                None => gl::Disable(gl::CULL_FACE)
+            }
+        }
+    }
+}
+
+// GL textures of the resources, handed to a pass when it executes
+pub struct PassResources<'a> {
+    textures: &'a [u32]
+}
+
+impl<'a> PassResources<'a> {
+    // texture of an imported or transient resource, 0 for the other kinds
+    pub fn texture(&self, resource: ResourceId) -> u32 {
+        self.textures[resource.0]
+    }
+}
+
+type PassFn<T> = Box<dyn FnMut(&mut T, &PassResources)>;
+
+struct PassNode<T> {
+    name: String,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    color_attachments: Vec<(ResourceId, LoadOp)>,
+    depth_attachment: Option<(ResourceId, LoadOp)>,
+    state: PassState,
+    side_effect: bool,
+    execute: PassFn<T>
+}
+
+// Declares what a pass reads and writes, handed to the setup closure of RenderGraph::add_pass
+pub struct PassBuilder<'g> {
+    resources: &'g mut Vec<Resource>,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    color_attachments: Vec<(ResourceId, LoadOp)>,
+    depth_attachment: Option<(ResourceId, LoadOp)>,
+    state: PassState,
+    side_effect: bool
+}
+
+impl<'g> PassBuilder<'g> {
+    // a texture living only for this frame, first written by this pass
+    pub fn create_texture(&mut self, name: &str, desc: TextureDesc) -> ResourceId {
+        self.resources.push(Resource { name: name.into(), kind: ResourceKind::TRANSIENT(desc) });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn read(&mut self, resource: ResourceId) -> ResourceId {
+        if !self.reads.contains(&resource) {
+            self.reads.push(resource);
+        }
+        resource
+    }
+
+    // a write the pass performs itself, e.g. into a framebuffer it binds on its own
+    pub fn write(&mut self, resource: ResourceId) -> ResourceId {
+        if !self.writes.contains(&resource) {
+            self.writes.push(resource);
+        }
+        resource
+    }
+
+    // render into `resource`, the graph binds a framebuffer with all the attachments before the pass runs
+    pub fn color_attachment(&mut self, resource: ResourceId, load: LoadOp) -> ResourceId {
+        self.color_attachments.push((resource, load));
+        self.write(resource)
+    }
+
+    pub fn depth_attachment(&mut self, resource: ResourceId, load: LoadOp) -> ResourceId {
+        self.depth_attachment = Some((resource, load));
+        self.write(resource)
+    }
+
+    pub fn state(&mut self, state: PassState) {
+        self.state = state;
+    }
+
+    // keep the pass even when nothing reads what it writes
+    pub fn side_effect(&mut self) {
+        self.side_effect = true;
+    }
+}
+
+// Passes in execution order after culling, and the storage assigned to the transient resources
+#[derive(Debug)]
+pub struct CompiledGraph {
+    pub order: Vec<usize>,
+    pub culled: Vec<usize>,
+    // index into `physical` of every transient resource, None for the other kinds and unused transients
+    pub aliases: Vec<Option<usize>>,
+    pub physical: Vec<TextureDesc>
+}
+
+// A frame graph. Passes declare the resources they read and write, then the graph orders them, culls the ones
+// whose results are never used, lets transients with disjoint lifetimes share a texture and sets up the
+// render targets and fixed-function state of every pass. `T` is the context handed to the passes when executing.
+//
+// Ordering rules for every resource:
+//  - passes writing it run in the order they were added
+//  - passes only reading it run after all of its writers
+pub struct RenderGraph<T> {
+    resources: Vec<Resource>,
+    passes: Vec<PassNode<T>>
+}
+
+impl<T> RenderGraph<T> {
+    pub fn new() -> RenderGraph<T> {
+        RenderGraph {
+            resources: Vec::new(),
+            passes: Vec::new()
+        }
+    }
+
+    pub fn import_texture(&mut self, name: &str, texture: u32, desc: TextureDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::IMPORTED(texture, desc))
+    }
+
+    pub fn external(&mut self, name: &str) -> ResourceId {
+        self.add_resource(name, ResourceKind::EXTERNAL)
+    }
+
+    pub fn backbuffer(&mut self, width: i32, height: i32) -> ResourceId {
+        self.add_resource("backbuffer", ResourceKind::BACKBUFFER(width, height))
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind) -> ResourceId {
+        self.resources.push(Resource { name: name.into(), kind });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn pass_name(&self, pass: usize) -> &str {
+        &self.passes[pass].name
+    }
+
+    // add a pass, `setup` declares its resources right away and returns what the caller needs from it
+    pub fn add_pass<R, S, E>(&mut self, name: &str, setup: S, execute: E) -> R
+    where
+        S: FnOnce(&mut PassBuilder) -> R,
+        E: FnMut(&mut T, &PassResources) + 'static
+    {
+        let mut builder = PassBuilder {
+            resources: &mut self.resources,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            color_attachments: Vec::new(),
+            depth_attachment: None,
+            state: PassState::default(),
+            side_effect: false
+        };
+        let result = setup(&mut builder);
+
+        let PassBuilder { reads, writes, color_attachments, depth_attachment, state, side_effect, .. } = builder;
+        self.passes.push(PassNode {
+            name: name.into(),
+            reads,
+            writes,
+            color_attachments,
+            depth_attachment,
+            state,
+            side_effect,
+            execute: Box::new(execute)
+        });
+        result
+    }
+
+    pub fn compile(&self) -> Result<CompiledGraph, String> {
+        let pass_count = self.passes.len();
+        let mut writers: Vec<Vec<usize>> = vec![Vec::new(); self.resources.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for resource in &pass.writes {
+                writers[resource.0].push(index);
+            }
+        }
+
+        // 1. culling, walk back from the passes with visible results
+        let mut live = vec![false; pass_count];
+        let mut stack: Vec<usize> = (0..pass_count)
+            .filter(|&i| {
+                let pass = &self.passes[i];
+                // what is drawn to the screen is visible, unless a later pass clears it
+                pass.side_effect || pass.writes.iter().any(|r| {
+                    matches!(self.resources[r.0].kind, ResourceKind::BACKBUFFER(..))
+                        && !writers[r.0].iter().any(|&w| w > i && self.clears(&self.passes[w], *r))
+                })
+            })
+            .collect();
+        while let Some(index) = stack.pop() {
+            if live[index] {
+                continue;
+            }
+            live[index] = true;
+            let pass = &self.passes[index];
+
+            let mut needed = Vec::new();
+            for resource in &pass.reads {
+                needed.extend(writers[resource.0].iter().copied());
+            }
+            // the previous writer matters unless this pass clears the whole resource, it pulls in the ones before
+            for resource in &pass.writes {
+                if self.clears(pass, *resource) {
+                    continue;
+                }
+                needed.extend(writers[resource.0].iter().copied().rfind(|&w| w < index));
+            }
+            stack.extend(needed.into_iter().filter(|&w| !live[w]));
+        }
+
+        // 2. ordering, Kahn's algorithm always picking the earliest added pass that is ready
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); pass_count];
+        for (index, pass) in self.passes.iter().enumerate().filter(|(i, _)| live[*i]) {
+            for resource in &pass.reads {
+                if pass.writes.contains(resource) {
+                    continue;
+                }
+                dependencies[index].extend(writers[resource.0].iter().copied().filter(|&w| live[w] && w != index));
+            }
+            for resource in &pass.writes {
+                let previous = writers[resource.0].iter().copied().rfind(|&w| live[w] && w < index);
+                dependencies[index].extend(previous);
+            }
+        }
+
+        let mut order = Vec::new();
+        let mut scheduled = vec![false; pass_count];
+        let live_count = live.iter().filter(|&&l| l).count();
+        while order.len() < live_count {
+            let next = (0..pass_count).find(|&i| {
+                live[i] && !scheduled[i] && dependencies[i].iter().all(|&d| scheduled[d])
+            });
+            match next {
+                Some(index) => {
+                    scheduled[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    let stuck: Vec<&str> = (0..pass_count)
+                        .filter(|&i| live[i] && !scheduled[i])
+                        .map(|i| self.passes[i].name.as_str())
+                        .collect();
+                    return Err(format!("Render graph has a dependency cycle between {:?}", stuck));
+                }
+            }
+        }
+        let culled = (0..pass_count).filter(|&i| !live[i]).collect();
+
+        // 3. aliasing, a transient gets the storage of one whose last use already happened
+        let mut first_use = vec![usize::MAX; self.resources.len()];
+        let mut last_use = vec![0; self.resources.len()];
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for resource in pass.reads.iter().chain(pass.writes.iter()) {
+                first_use[resource.0] = first_use[resource.0].min(position);
+                last_use[resource.0] = position;
+            }
+        }
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for resource in &pass.reads {
+                let is_transient = matches!(self.resources[resource.0].kind, ResourceKind::TRANSIENT(_));
+                if is_transient && first_use[resource.0] == position && !pass.writes.contains(resource) {
+                    return Err(format!(
+                        "Pass {} reads transient {} before anything writes it",
+                        pass.name, self.resources[resource.0].name
+                    ));
+                }
+            }
+        }
+
+        let mut aliases = vec![None; self.resources.len()];
+        let mut physical: Vec<TextureDesc> = Vec::new();
+        let mut free: Vec<usize> = Vec::new();
+        for position in 0..order.len() {
+            for (index, resource) in self.resources.iter().enumerate() {
+                if let ResourceKind::TRANSIENT(desc) = resource.kind {
+                    if first_use[index] != position {
+                        continue;
+                    }
+                    let slot = match free.iter().position(|&slot| physical[slot] == desc) {
+                        Some(i) => free.remove(i),
+                        None => {
+                            physical.push(desc);
+                            physical.len() - 1
+                        }
+                    };
+                    aliases[index] = Some(slot);
+                }
+            }
+            for (index, alias) in aliases.iter().enumerate() {
+                if let Some(slot) = alias {
+                    if last_use[index] == position {
+                        free.push(*slot);
+                    }
+                }
+            }
+        }
+
+        Ok(CompiledGraph { order, culled, aliases, physical })
+    }
+
+    fn clears(&self, pass: &PassNode<T>, resource: ResourceId) -> bool {
+        pass.color_attachments.iter().chain(pass.depth_attachment.iter())
+            .any(|&(r, load)| r == resource && load == LoadOp::CLEAR)
+    }
+}
+
+// Runs compiled graphs, keeps the transient textures and attachment framebuffers alive between frames.
+// Both are deleted with their handles.
+#[derive(Default)]
+pub struct GraphExecutor {
+    // transient storage, reused by the next frame when the description matches
+    pool: Vec<(TextureDesc, GpuHandle)>,
+    // keyed by texture names, which GL hands out again once a texture is deleted
+    framebuffers: HashMap<Vec<u32>, GpuHandle>,
+    // imported textures of the last frame, the framebuffers are rebuilt when they change
+    imports: Vec<(u32, TextureDesc)>
+}
+
+impl GraphExecutor {
+    pub fn new() -> GraphExecutor {
+        GraphExecutor::default()
+    }
+
+    // forget every framebuffer, to be called when textures imported by the graph are recreated
+    pub fn invalidate(&mut self) {
+        self.framebuffers.clear();
+    }
+
+    pub unsafe fn execute<T>(&mut self, graph: &mut RenderGraph<T>, compiled: &CompiledGraph, context: &mut T) {
+        let imports: Vec<(u32, TextureDesc)> = graph.resources.iter()
+            .filter_map(|resource| match resource.kind {
+                ResourceKind::IMPORTED(texture, desc) => Some((texture, desc)),
+                _ => None
+            })
+            .collect();
+        if imports != self.imports {
+            self.invalidate();
+            self.imports = imports;
+        }
+        self.allocate(&compiled.physical);
+
+        // GL texture of every resource
+        let textures: Vec<u32> = graph.resources.iter().enumerate()
+            .map(|(index, resource)| match resource.kind {
+                ResourceKind::TRANSIENT(_) => compiled.aliases[index].map_or(0, |slot| self.pool[slot].1.id()),
+                ResourceKind::IMPORTED(texture, _) => texture,
+                ResourceKind::EXTERNAL | ResourceKind::BACKBUFFER(..) => 0
+            })
+            .collect();
+
+        let mut previous_state: Option<PassState> = None;
+        for &index in &compiled.order {
+            let pass = &mut graph.passes[index];
+            self.bind_attachments(&graph.resources, &textures, pass);
+            pass.state.apply(previous_state.as_ref());
+            previous_state = Some(pass.state);
+            (pass.execute)(context, &PassResources { textures: &textures });
+        }
+
+        // leave the defaults the rest of the engine expects
+        PassState::default().apply(previous_state.as_ref());
+    }
+
+    // make the pool hold exactly the textures of this frame, keeping the ones that can be reused
+    unsafe fn allocate(&mut self, physical: &[TextureDesc]) {
+        let mut old = std::mem::take(&mut self.pool);
+        for desc in physical {
+            match old.iter().position(|(d, _)| d == desc) {
+                Some(i) => self.pool.push(old.remove(i)),
+                None => {
+                    let texture = create_texture(desc);
+                    self.pool.push((*desc, GpuHandle::new(&GlDevice::current(), resource::ResourceKind::TEXTURE, texture, "render graph transient")));
+                }
+            }
+        }
+
+        // the textures left are deleted as `old` drops, after the framebuffers attaching them
+        if !old.is_empty() {
+            let deleted: Vec<u32> = old.iter().map(|(_, texture)| texture.id()).collect();
+            self.framebuffers.retain(|attachments, _| !attachments.iter().any(|t| deleted.contains(t)));
+        }
+    }
+
+    unsafe fn bind_attachments<T>(&mut self, resources: &[Resource], textures: &[u32], pass: &PassNode<T>) {
+        let attachments: Vec<ResourceId> = pass.color_attachments.iter().chain(pass.depth_attachment.iter())
+            .map(|&(resource, _)| resource)
+            .collect();
+        let first = match attachments.first() {
+            Some(first) => *first,
+            None => return
+        };
+
+        let (width, height) = match resources[first.0].kind {
+            ResourceKind::TRANSIENT(desc) | ResourceKind::IMPORTED(_, desc) => (desc.width, desc.height),
+            ResourceKind::BACKBUFFER(width, height) => (width, height),
+            ResourceKind::EXTERNAL => panic!("External resource {} can't be attached", resources[first.0].name)
+        };
+
+        if let ResourceKind::BACKBUFFER(..) = resources[first.0].kind {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        } else {
+            let key: Vec<u32> = attachments.iter().map(|r| textures[r.0]).collect();
+            let fbo = match self.framebuffers.get(&key) {
+                Some(fbo) => fbo.id(),
+                None => {
+                    let fbo = create_framebuffer(&pass.color_attachments.iter().map(|(r, _)| textures[r.0]).collect::<Vec<_>>(),
+                        pass.depth_attachment.map(|(r, _)| textures[r.0]));
+                    self.framebuffers.insert(key, GpuHandle::new(&GlDevice::current(), resource::ResourceKind::FRAMEBUFFER, fbo, "render graph framebuffer"));
+                    fbo
+                }
+            };
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        }
+        gl::Viewport(0, 0, width, height);
+
+        // clears need the write masks enabled whatever the previous pass left
+        for (i, (_, load)) in pass.color_attachments.iter().enumerate() {
+            if *load == LoadOp::CLEAR {
+                gl::ClearBufferfv(gl::COLOR, i as i32, pass.state.clear_color.as_ptr());
+            }
+        }
+        if let Some((_, LoadOp::CLEAR)) = pass.depth_attachment {
+            gl::DepthMask(gl::TRUE);
+            gl::ClearBufferfv(gl::DEPTH, 0, &1.0);
+            gl::DepthMask(if pass.state.depth_write { gl::TRUE } else { gl::FALSE });
+        }
+    }
+}
+
+unsafe fn create_texture(desc: &TextureDesc) -> u32 {
+    let (internal_format, format, type_) = desc.format.gl_formats();
+    let filter = if desc.format.is_depth() { gl::NEAREST } else { gl::LINEAR };
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, desc.width, desc.height, 0, format, type_, ptr::null());
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    texture
+}
+
+unsafe fn create_framebuffer(color: &[u32], depth: Option<u32>) -> u32 {
+    let mut fbo = 0;
+    gl::GenFramebuffers(1, &mut fbo);
+    gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+    let mut draw_buffers = Vec::new();
+    for (i, texture) in color.iter().enumerate() {
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + i as u32, gl::TEXTURE_2D, *texture, 0);
+        draw_buffers.push(gl::COLOR_ATTACHMENT0 + i as u32);
+    }
+    if draw_buffers.is_empty() {
+        gl::DrawBuffer(gl::NONE);
+    } else {
+        gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+    }
+    if let Some(depth) = depth {
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth, 0);
+    }
+    if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+        println!("ERROR::FRAMEBUFFER:: Render graph framebuffer is not complete!");
+    }
+    fbo
+}
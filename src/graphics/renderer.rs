@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::time::Duration;
+
+use cgmath::{ EuclideanSpace, Matrix4, Vector3 };
+use gl;
+
+use crate::graphics::camera::Camera;
+use crate::graphics::clustered::{ ClusterConfig, ClusteredLighting };
+use crate::graphics::deferred::DeferredPipeline;
+use crate::graphics::device::{ DeviceFeatures, GlDevice, RenderDevice };
+use crate::graphics::draw_queue::DrawQueue;
+use crate::graphics::hdr::HdrPipeline;
+use crate::graphics::hot_reload::{ self, ShaderWatcher };
+use crate::graphics::instancing::InstanceBuffer;
+use crate::graphics::material::RenderQueue;
+use crate::graphics::framebuffer::TextureFormat;
+use crate::graphics::post_process::PostProcessStack;
+use crate::graphics::profiler::Profiler;
+use crate::graphics::render_graph::{ GraphExecutor, LoadOp, PassBuilder, PassState, RenderGraph, ResourceId, TextureDesc };
+use crate::graphics::resource;
+use crate::graphics::shader::{ Shader, ShaderType, ShaderVariants };
+use crate::graphics::shadow::{ CascadeConfig, CascadedShadowMap };
+use crate::graphics::ssao::{ SsaoConfig, SsaoPass };
+use crate::graphics::state_tracker::StateTracker;
+use crate::graphics::transparency::{ TransparencyMode, WeightedBlendedOit };
+use crate::graphics::uniform_buffer::{ FrameData, UniformBuffer, UniformRing, FRAME_BINDING };
+use crate::world::frustum::Frustum;
+use crate::world::scene::{ Culling, Scene };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderPath {
+    // every mesh is shaded with all the lights in a single pass
+    FORWARD,
+    // opaque meshes are written to a G-buffer and lit afterwards, transparent ones are still forward shaded
+    DEFERRED
+}
+
+pub struct RendererConfig {
+    pub path: RenderPath,
+    pub transparency: TransparencyMode,
+    // skip the entities out of the camera frustum in the passes drawing what the camera sees
+    pub frustum_culling: bool
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            path: RenderPath::FORWARD,
+            transparency: TransparencyMode::SORTED,
+            frustum_culling: true
+        }
+    }
+}
+
+// Everything the passes of the frame graph can use while they execute
+pub struct FrameContext<'a> {
+    pub renderer: &'a mut Renderer,
+    pub scene: &'a mut Scene,
+    pub camera: &'a Camera,
+    pub delta_time: f32,
+    pub projection: Matrix4<f32>,
+    pub view: Matrix4<f32>
+}
+
+// Resources of the built-in passes that custom passes can read or write
+#[derive(Clone, Copy, Debug)]
+pub struct FrameResources {
+    pub shadow_map: ResourceId,
+    pub light_lists: ResourceId,
+    // HDR colour and depth of the scene, complete once every pass writing them ran
+    pub scene_color: ResourceId,
+    pub scene_depth: ResourceId,
+    // tonemapped image, only when post effects are enabled
+    pub ldr: Option<ResourceId>,
+    pub backbuffer: ResourceId
+}
+
+// room for the per-object blocks of a few frames of draws
+const OBJECT_RING_SIZE: usize = 4 << 20;
+// instances streamed before the buffer wraps, 8 MiB
+const INSTANCE_CAPACITY: usize = 64 * 1024;
+
+type CustomPass = Box<dyn for<'a> Fn(&mut RenderGraph<FrameContext<'a>>, &FrameResources)>;
+type ReloadListener = Box<dyn FnMut(&Shader)>;
+
+pub struct Renderer {
+    pub config: RendererConfig,
+    // what the context supports beyond OpenGL 3.3, e.g. whether ComputeShader can be used
+    pub features: DeviceFeatures,
+    pub shaders: HashMap<ShaderType, Shader>,
+    // permutations of the forward model shader, e.g. WEIGHTED_OIT for the transparency accumulation
+    pub model_variants: ShaderVariants,
+    pub shadow_map: CascadedShadowMap,
+    pub hdr: HdrPipeline,
+    pub post_process: PostProcessStack,
+    pub ssao: SsaoPass,
+    pub deferred: DeferredPipeline,
+    pub clustered: ClusteredLighting,
+    pub oit: WeightedBlendedOit,
+    // camera and timing of the frame, bound at FRAME_BINDING for every pass
+    pub frame_uniforms: UniformBuffer,
+    // model matrices of the draws, streamed
+    pub object_uniforms: UniformRing,
+    // model matrices of the instanced batches of the opaque passes, streamed
+    pub instances: InstanceBuffer,
+    // state changes of the passes drawing the scene, printed while enabled
+    pub profiler: Profiler,
+    // entities in view this frame, written by the culling pass
+    pub culling: Culling,
+    graph_executor: GraphExecutor,
+    custom_passes: Vec<CustomPass>,
+    shader_watcher: ShaderWatcher,
+    reload_listeners: Vec<ReloadListener>,
+    // seconds of frames rendered
+    time: f32,
+    width: i32,
+    height: i32
+}
+
+impl Renderer {
+    pub fn new(width: i32, height: i32) -> Renderer {
+        // load all shaders
+        let mut shaders: HashMap<ShaderType, Shader> = HashMap::new();
+        shaders.insert(ShaderType::MODEL, Shader::new("src/graphics/shaders/model.vs", "src/graphics/shaders/model.fs"));
+        shaders.insert(ShaderType::SKYBOX, Shader::new("src/graphics/shaders/skybox.vs", "src/graphics/shaders/skybox.fs"));
+        // the passes drawing the whole scene draw it in instanced batches
+        let instanced = |vertex_path: &str, fragment_path: &str| {
+            Shader::with_device(unsafe { &mut GlDevice::current() }, vertex_path, fragment_path, &["INSTANCED"])
+        };
+        shaders.insert(ShaderType::SHADOW_DEPTH, instanced("src/graphics/shaders/shadow_depth.vs", "src/graphics/shaders/shadow_depth.fs"));
+        shaders.insert(ShaderType::LUMINANCE, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/luminance.fs"));
+        shaders.insert(ShaderType::BLOOM_DOWNSAMPLE, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/bloom_downsample.fs"));
+        shaders.insert(ShaderType::BLOOM_UPSAMPLE, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/bloom_upsample.fs"));
+        shaders.insert(ShaderType::TONEMAP, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/tonemap.fs"));
+        shaders.insert(ShaderType::SSAO_GEOMETRY, instanced("src/graphics/shaders/ssao_geometry.vs", "src/graphics/shaders/ssao_geometry.fs"));
+        shaders.insert(ShaderType::SSAO, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/ssao.fs"));
+        shaders.insert(ShaderType::SSAO_BLUR, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/ssao_blur.fs"));
+        shaders.insert(ShaderType::GBUFFER, instanced("src/graphics/shaders/model.vs", "src/graphics/shaders/gbuffer.fs"));
+        shaders.insert(ShaderType::DEFERRED_LIGHT, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/deferred_light.fs"));
+        shaders.insert(ShaderType::DEFERRED_POINT, Shader::new("src/graphics/shaders/deferred_point.vs", "src/graphics/shaders/deferred_point.fs"));
+        shaders.insert(ShaderType::OIT_COMPOSITE, Shader::new("src/graphics/shaders/fullscreen.vs", "src/graphics/shaders/oit_composite.fs"));
+
+        // passes only borrow the renderer, the variants they use are compiled upfront
+        let mut model_variants = ShaderVariants::new("src/graphics/shaders/model.vs", "src/graphics/shaders/model.fs");
+        model_variants.variant(&["WEIGHTED_OIT"]);
+        model_variants.variant(&["INSTANCED"]);
+
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+
+        Renderer {
+            config: RendererConfig::default(),
+            features: unsafe { GlDevice::current() }.features(),
+            shaders,
+            model_variants,
+            shadow_map: CascadedShadowMap::new(CascadeConfig::default()),
+            hdr: HdrPipeline::new(width, height),
+            post_process: PostProcessStack::with_default_effects(),
+            ssao: SsaoPass::new(SsaoConfig::default(), width, height),
+            deferred: DeferredPipeline::new(width, height),
+            clustered: ClusteredLighting::new(ClusterConfig::default()),
+            oit: WeightedBlendedOit::new(width, height),
+            frame_uniforms: UniformBuffer::new(size_of::<FrameData>()),
+            object_uniforms: UniformRing::new(OBJECT_RING_SIZE),
+            instances: InstanceBuffer::new(INSTANCE_CAPACITY),
+            profiler: Profiler::new(),
+            culling: Culling::none(),
+            graph_executor: GraphExecutor::new(),
+            custom_passes: Vec::new(),
+            shader_watcher: ShaderWatcher::new(Duration::from_millis(250)),
+            reload_listeners: Vec::new(),
+            time: 0.0,
+            width,
+            height
+        }
+    }
+
+    pub fn shader(&self, shader_type: ShaderType) -> &Shader {
+        self.shaders.get(&shader_type).expect("ShaderType is not initialized")
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.hdr.resize(width, height);
+        self.ssao.resize(width, height);
+        self.deferred.resize(width, height);
+        self.oit.resize(width, height);
+        // the imported targets were recreated, their names may come back for other textures
+        self.graph_executor.invalidate();
+    }
+
+    pub fn toggle_render_path(&mut self) {
+        self.config.path = match self.config.path {
+            RenderPath::FORWARD => RenderPath::DEFERRED,
+            RenderPath::DEFERRED => RenderPath::FORWARD
+        };
+    }
+
+    pub fn toggle_transparency_mode(&mut self) {
+        self.config.transparency = match self.config.transparency {
+            TransparencyMode::SORTED => TransparencyMode::WEIGHTED_BLENDED,
+            TransparencyMode::WEIGHTED_BLENDED => TransparencyMode::SORTED
+        };
+    }
+
+    pub fn toggle_frustum_culling(&mut self) {
+        self.config.frustum_culling = !self.config.frustum_culling;
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+
+    // register a pass added to the frame graph every frame, after the built-in ones
+    pub fn add_pass<F>(&mut self, setup: F)
+    where
+        F: for<'a> Fn(&mut RenderGraph<FrameContext<'a>>, &FrameResources) + 'static
+    {
+        self.custom_passes.push(Box::new(setup));
+    }
+
+    // called with every program replaced by a hot reload, to rebuild what was cached for the old one
+    pub fn on_shader_reload<F: FnMut(&Shader) + 'static>(&mut self, listener: F) {
+        self.reload_listeners.push(Box::new(listener));
+    }
+
+    // Recompile the programs whose source files changed, including the post effects. A program that fails
+    // to build is reported and the previous one stays in use.
+    pub fn reload_changed_shaders(&mut self) {
+        // effects can be added at any time, watching a file twice is a no-op
+        let effects = self.post_process.effects().iter().map(|e| e.shader());
+        for shader in self.shaders.values().chain(self.model_variants.shaders()).chain(effects) {
+            self.shader_watcher.watch_shader(shader);
+        }
+        let changed = self.shader_watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+
+        let listeners = &mut self.reload_listeners;
+        let shaders = self.shaders.values_mut()
+            .chain(self.model_variants.shaders_mut())
+            .chain(self.post_process.shaders_mut());
+        for shader in shaders.filter(|shader| hot_reload::depends_on(shader, &changed)) {
+            let name = shader.sources.last().map(|path| path.display().to_string()).unwrap_or_default();
+            match shader.reload() {
+                Ok(()) => {
+                    println!("Reloaded shader {}", name);
+                    for listener in listeners.iter_mut() {
+                        listener(shader);
+                    }
+                }
+                Err(error) => println!("{}\nERROR::SHADER:: keeping the previous program of {}", error, name)
+            }
+        }
+    }
+
+    pub unsafe fn render(&mut self, scene: &mut Scene, camera: &Camera, delta_time: f32) {
+        let aspect = self.aspect_ratio();
+        let projection = camera.get_projection_matrix(aspect);
+        let view = camera.get_view_matrix();
+        self.post_process.advance(delta_time);
+        // objects dropped away from the GL thread since the last frame
+        resource::flush_deletions(&mut GlDevice::current());
+
+        self.time += delta_time;
+        let frame = FrameData::new(&projection, &view, camera.position.to_vec(), self.time, delta_time, self.width, self.height);
+        self.frame_uniforms.update(&mut GlDevice::current(), FRAME_BINDING, &frame);
+
+        let mut graph = RenderGraph::new();
+        let resources = self.build_graph(&mut graph, camera);
+        for setup in &self.custom_passes {
+            setup(&mut graph, &resources);
+        }
+
+        let compiled = match graph.compile() {
+            Ok(compiled) => compiled,
+            Err(error) => {
+                println!("ERROR::RENDER_GRAPH:: {}", error);
+                return;
+            }
+        };
+
+        let mut executor = std::mem::take(&mut self.graph_executor);
+        let mut context = FrameContext {
+            renderer: self,
+            scene,
+            camera,
+            delta_time,
+            projection,
+            view
+        };
+        executor.execute(&mut graph, &compiled, &mut context);
+        // the passes hold the lifetime of the context
+        drop(graph);
+        self.graph_executor = executor;
+        self.profiler.end_frame(delta_time);
+    }
+
+    // declare the built-in passes of a frame
+    fn build_graph<'a>(&self, graph: &mut RenderGraph<FrameContext<'a>>, camera: &Camera) -> FrameResources {
+        let (width, height) = (self.width, self.height);
+        let scene_target = &self.hdr.scene_target;
+        let scene_desc = |format| TextureDesc { width: scene_target.width, height: scene_target.height, format };
+
+        let backbuffer = graph.backbuffer(width, height);
+        let shadow_map = graph.external("shadow_map");
+        let ssao = graph.external("ssao");
+        let light_lists = graph.external("light_lists");
+        let visibility = graph.external("visibility");
+        let gbuffer = graph.external("gbuffer");
+        let bloom = graph.external("bloom");
+        let scene_color = graph.import_texture("scene_color", scene_target.color_texture(0), scene_desc(TextureFormat::RGBA16F));
+        let scene_depth = graph.import_texture(
+            "scene_depth",
+            scene_target.depth_texture().expect("HDR scene target has no depth"),
+            scene_desc(TextureFormat::DEPTH24)
+        );
+        let ssao_enabled = self.ssao.config.enabled;
+
+        // 1. shadow pass: render the scene depth from the light into every cascade
+        graph.add_pass("shadows", |pass| { pass.write(shadow_map); }, |frame, _| unsafe {
+            let aspect = frame.renderer.aspect_ratio();
+            let renderer = &mut *frame.renderer;
+            renderer.shadow_map.update(frame.camera, aspect, frame.scene.directional_light.direction);
+            let stats = renderer.shadow_map.render(frame.scene, renderer.shaders.get(&ShaderType::SHADOW_DEPTH).unwrap(), &renderer.instances);
+            renderer.profiler.record(stats);
+        });
+
+        // entities in the camera frustum, the shadow casters are not culled as they can be out of view
+        graph.add_pass("culling", |pass| { pass.write(visibility); }, |frame, _| {
+            let renderer = &mut *frame.renderer;
+            renderer.culling = if renderer.config.frustum_culling {
+                frame.scene.update_bvh();
+                frame.scene.cull(&Frustum::from_matrix(&(frame.projection * frame.view)))
+            } else {
+                Culling::none()
+            };
+            renderer.profiler.record_culling(renderer.culling.stats);
+        });
+
+        // 2. ambient occlusion from view-space normals and depth
+        if ssao_enabled {
+            graph.add_pass("ssao", |pass| {
+                pass.read(visibility);
+                pass.write(ssao);
+            }, |frame, _| unsafe {
+                let renderer = &mut *frame.renderer;
+                let stats = renderer.ssao.render(frame.scene, frame.camera, &renderer.instances, &renderer.culling, &renderer.shaders);
+                renderer.profiler.record(stats);
+            });
+        }
+
+        // assign the point and spot lights to the clusters of the view frustum for forward shading
+        graph.add_pass("light_culling", |pass| { pass.write(light_lists); }, |frame, _| unsafe {
+            frame.renderer.clustered.update(frame.scene, &frame.view, &frame.projection, frame.camera.near, frame.camera.far);
+        });
+
+        let forward_inputs = move |pass: &mut PassBuilder| {
+            pass.read(visibility);
+            pass.read(shadow_map);
+            pass.read(light_lists);
+            if ssao_enabled {
+                pass.read(ssao);
+            }
+        };
+
+        // 3. opaque and alpha tested geometry, into the floating point target
+        match self.config.path {
+            RenderPath::FORWARD => {
+                graph.add_pass("opaque", |pass| {
+                    forward_inputs(pass);
+                    pass.color_attachment(scene_color, LoadOp::CLEAR);
+                    pass.depth_attachment(scene_depth, LoadOp::CLEAR);
+                    pass.state(PassState { clear_color: [0.1, 0.1, 0.1, 1.0], ..PassState::default() });
+                }, |frame, _| unsafe {
+                    let renderer = &*frame.renderer;
+                    let model_shader = renderer.model_variants.get(&["INSTANCED"]).expect("INSTANCED variant is not compiled");
+                    renderer.setup_model_shader(model_shader, frame.scene);
+                    // the batches come sorted by queue, opaque before alpha tested
+                    let mut device = GlDevice::current();
+                    let mut tracker = StateTracker::new(&mut device);
+                    frame.scene.render_visible_with(&mut tracker, model_shader, &renderer.instances, &renderer.culling, |mesh| !mesh.material.is_transparent());
+                    renderer.profiler.record(tracker.stats());
+                });
+            }
+            RenderPath::DEFERRED => {
+                graph.add_pass("gbuffer", |pass| {
+                    pass.read(visibility);
+                    pass.write(gbuffer);
+                }, |frame, _| unsafe {
+                    let renderer = &*frame.renderer;
+                    let stats = renderer.deferred.geometry_pass(frame.scene, renderer.shader(ShaderType::GBUFFER), &renderer.instances, &renderer.culling);
+                    renderer.profiler.record(stats);
+                });
+                graph.add_pass("deferred_lighting", |pass| {
+                    pass.read(gbuffer);
+                    pass.read(shadow_map);
+                    if ssao_enabled {
+                        pass.read(ssao);
+                    }
+                    pass.write(scene_color);
+                    pass.write(scene_depth);
+                }, |frame, _| unsafe {
+                    let renderer = &*frame.renderer;
+                    renderer.deferred.lighting_pass(
+                        frame.scene,
+                        &renderer.hdr.scene_target,
+                        &renderer.shaders,
+                        &renderer.shadow_map,
+                        &renderer.ssao
+                    );
+                });
+            }
+        }
+
+        // 4. skybox pass
+        graph.add_pass("skybox", |pass| {
+            pass.color_attachment(scene_color, LoadOp::LOAD);
+            pass.depth_attachment(scene_depth, LoadOp::LOAD);
+        }, |frame, _| unsafe {
+            frame.scene.skybox.draw(&mut GlDevice::current(), frame.renderer.shader(ShaderType::SKYBOX));
+        });
+
+        // 5. transparent geometry, forward shaded on top of everything else
+        graph.add_pass("transparent", |pass| {
+            forward_inputs(pass);
+            pass.color_attachment(scene_color, LoadOp::LOAD);
+            pass.depth_attachment(scene_depth, LoadOp::LOAD);
+        }, |frame, _| unsafe {
+            frame.renderer.render_transparent(frame.scene, frame.camera.position.to_vec());
+        });
+
+        // 6. resolve: exposure and bloom, then tonemapping into the post-processing input when any effect is enabled
+        let fullscreen_state = PassState { depth_test: false, depth_write: false, ..PassState::default() };
+        if camera.hdr.enabled {
+            graph.add_pass("exposure_bloom", |pass| {
+                pass.read(scene_color);
+                pass.write(bloom);
+            }, |frame, _| unsafe {
+                let renderer = &mut *frame.renderer;
+                renderer.hdr.prepare(&frame.camera.hdr, frame.delta_time, &renderer.shaders);
+            });
+        }
+
+        let ldr_desc = TextureDesc { width, height, format: TextureFormat::RGBA8 };
+        let effects: Vec<(usize, String)> = self.post_process.effects().iter().enumerate()
+            .filter(|(_, effect)| effect.enabled)
+            .map(|(index, effect)| (index, effect.name.clone()))
+            .collect();
+        let ldr = graph.add_pass("tonemap", |pass| {
+            pass.read(scene_color);
+            if camera.hdr.enabled {
+                pass.read(bloom);
+            }
+            let output = if effects.is_empty() { backbuffer } else { pass.create_texture("ldr", ldr_desc) };
+            pass.color_attachment(output, LoadOp::LOAD);
+            pass.state(fullscreen_state);
+            output
+        }, |frame, _| unsafe {
+            let renderer = &*frame.renderer;
+            renderer.hdr.tonemap(&frame.camera.hdr, &renderer.shaders);
+        });
+
+        // 7. post-processing, the last effect writes into the window
+        let mut input = ldr;
+        for (n, (index, name)) in effects.iter().enumerate() {
+            let index = *index;
+            let source = input;
+            input = graph.add_pass(name, |pass| {
+                pass.read(source);
+                let output = if n + 1 == effects.len() { backbuffer } else { pass.create_texture(name, ldr_desc) };
+                pass.color_attachment(output, LoadOp::LOAD);
+                pass.state(fullscreen_state);
+                output
+            }, move |frame, resources| unsafe {
+                frame.renderer.post_process.draw_effect(index, resources.texture(source), width, height);
+            });
+        }
+
+        FrameResources {
+            shadow_map,
+            light_lists,
+            scene_color,
+            scene_depth,
+            ldr: if effects.is_empty() { None } else { Some(ldr) },
+            backbuffer
+        }
+    }
+
+    // blend the transparent queue over the opaque scene, either sorted back to front or order independent
+    unsafe fn render_transparent(&self, scene: &Scene, view_position: Vector3<f32>) {
+        match self.config.transparency {
+            TransparencyMode::SORTED => {
+                let model_shader = self.shader(ShaderType::MODEL);
+                let mut queue = DrawQueue::new();
+                queue.collect(scene, RenderQueue::TRANSPARENT, model_shader.id, view_position, &self.culling);
+                if queue.is_empty() {
+                    return;
+                }
+                queue.sort();
+                self.setup_model_shader(model_shader, scene);
+                let mut device = GlDevice::current();
+                let mut tracker = StateTracker::new(&mut device);
+                queue.submit(&mut tracker, scene, model_shader, &self.object_uniforms, true);
+                self.profiler.record(tracker.stats());
+            }
+            TransparencyMode::WEIGHTED_BLENDED => {
+                let items = scene.collect_visible_queue(RenderQueue::TRANSPARENT, view_position, &self.culling);
+                if items.is_empty() {
+                    return;
+                }
+                let oit_shader = self.model_variants.get(&["WEIGHTED_OIT"]).expect("WEIGHTED_OIT variant is not compiled");
+                self.setup_model_shader(oit_shader, scene);
+                self.oit.begin(&self.hdr.scene_target);
+                for item in &items {
+                    scene.draw_item(item, oit_shader, &self.object_uniforms);
+                }
+                self.oit.composite(&self.hdr.scene_target, &self.shaders);
+            }
+        }
+    }
+
+    // upload the lights, shadows and ambient occlusion used by the forward model shader, the camera is
+    // in the frame uniforms
+    unsafe fn setup_model_shader(&self, shader: &Shader, scene: &Scene) {
+        shader.use_program();
+
+        let light = &scene.directional_light;
+        shader.set_vector3(c_str!("light.direction"), &light.direction);
+        shader.set_vector3(c_str!("light.color"), &light.color);
+        shader.set_float(c_str!("light.intensity"), light.intensity);
+        shader.set_float(c_str!("light.ambient"), light.ambient);
+
+        self.clustered.bind(shader, self.width, self.height);
+        self.shadow_map.bind(shader);
+        self.ssao.bind(shader, self.width, self.height);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{ LazyLock, Mutex };
+use std::thread::{ self, ThreadId };
+
+use crate::graphics::device::{ DeviceId, GlDevice, RenderDevice };
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[allow(non_camel_case_types)]
+pub enum ResourceKind {
+    BUFFER,
+    TEXTURE,
+    VERTEX_ARRAY,
+    PROGRAM,
+    FRAMEBUFFER
+}
+
+// the device an object belongs to, what it is and its name on that device
+type ResourceKey = (DeviceId, ResourceKind, u32);
+
+// handles dropped where they couldn't be deleted, waiting for their device
+static DELETION_QUEUE: Mutex<Vec<ResourceKey>> = Mutex::new(Vec::new());
+// the thread the GL context is current on
+static GL_THREAD: Mutex<Option<ThreadId>> = Mutex::new(None);
+// handles alive and what they are, debug builds only
+static LIVE: LazyLock<Mutex<HashMap<ResourceKey, String>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Owns an object of a device and deletes it when dropped. On the GL thread a GL object is deleted right
+// away, anything else waits in the deletion queue until flush_deletions runs with its device.
+pub struct GpuHandle {
+    device: DeviceId,
+    kind: ResourceKind,
+    id: u32
+}
+
+impl GpuHandle {
+    // take ownership of `id`, created by `device`. `label` names it in the leak report.
+    pub fn new<D: RenderDevice>(device: &D, kind: ResourceKind, id: u32, label: &str) -> GpuHandle {
+        let handle = GpuHandle { device: device.device_id(), kind, id };
+        if cfg!(debug_assertions) && id != 0 {
+            LIVE.lock().unwrap().insert(handle.key(), label.to_string());
+        }
+        handle
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn kind(&self) -> ResourceKind {
+        self.kind
+    }
+
+    // delete the object now through `device`, which must be the one that created it
+    pub fn release_with<D: RenderDevice>(mut self, device: &mut D) {
+        debug_assert!(self.id == 0 || self.device == device.device_id(), "GPU handle released through another device");
+        let id = mem::take(&mut self.id);
+        if id != 0 {
+            untrack(self.device, self.kind, id);
+            delete(device, self.kind, id);
+        }
+    }
+
+    // give up ownership, the object is no longer deleted nor reported
+    pub fn into_raw(mut self) -> u32 {
+        let id = mem::take(&mut self.id);
+        untrack(self.device, self.kind, id);
+        id
+    }
+
+    fn key(&self) -> ResourceKey {
+        (self.device, self.kind, self.id)
+    }
+}
+
+// a handle owning nothing
+impl Default for GpuHandle {
+    fn default() -> Self {
+        GpuHandle { device: DeviceId::GL, kind: ResourceKind::BUFFER, id: 0 }
+    }
+}
+
+impl Drop for GpuHandle {
+    fn drop(&mut self) {
+        if self.id == 0 {
+            return;
+        }
+        untrack(self.device, self.kind, self.id);
+        if self.device == DeviceId::GL && is_gl_thread() {
+            delete(unsafe { &mut GlDevice::current() }, self.kind, self.id);
+        } else {
+            DELETION_QUEUE.lock().unwrap().push((self.device, self.kind, self.id));
+        }
+    }
+}
+
+// Marks the thread the GL context is current on while alive, GL handles dropped on it are deleted right
+// away. Drop it before the context so later drops are queued instead of calling into a dead context.
+pub struct GlThreadGuard {
+    _private: ()
+}
+
+pub fn register_gl_thread() -> GlThreadGuard {
+    *GL_THREAD.lock().unwrap() = Some(thread::current().id());
+    GlThreadGuard { _private: () }
+}
+
+impl Drop for GlThreadGuard {
+    fn drop(&mut self) {
+        *GL_THREAD.lock().unwrap() = None;
+    }
+}
+
+pub(crate) fn is_gl_thread() -> bool {
+    *GL_THREAD.lock().unwrap() == Some(thread::current().id())
+}
+
+// delete the queued handles of `device`, returns how many
+pub fn flush_deletions<D: RenderDevice>(device: &mut D) -> usize {
+    let owner = device.device_id();
+    let pending: Vec<(ResourceKind, u32)> = {
+        let mut queue = DELETION_QUEUE.lock().unwrap();
+        let (mine, others) = queue.drain(..).partition(|(device, _, _)| *device == owner);
+        *queue = others;
+        mine.into_iter().map(|(_, kind, id)| (kind, id)).collect::<Vec<_>>()
+    };
+    for &(kind, id) in &pending {
+        delete(device, kind, id);
+    }
+    pending.len()
+}
+
+pub fn pending_deletions(device: DeviceId) -> usize {
+    DELETION_QUEUE.lock().unwrap().iter().filter(|(owner, _, _)| *owner == device).count()
+}
+
+// handles of `device` not dropped yet with their label, empty in release builds
+pub fn live_resources(device: DeviceId) -> Vec<(ResourceKind, u32, String)> {
+    let mut live: Vec<(ResourceKind, u32, String)> = LIVE.lock().unwrap().iter()
+        .filter(|((owner, _, _), _)| *owner == device)
+        .map(|(&(_, kind, id), label)| (kind, id, label.clone()))
+        .collect();
+    live.sort_by_key(|(_, id, _)| *id);
+    live
+}
+
+// Print the GL handles still alive, meant to run at shutdown once everything owning them was dropped.
+// Returns how many were reported.
+pub fn report_leaks() -> usize {
+    let leaks = live_resources(DeviceId::GL);
+    if !leaks.is_empty() {
+        println!("WARNING::RESOURCE:: {} GPU resources were never released", leaks.len());
+        for (kind, id, label) in &leaks {
+            println!("    {:?} {} {}", kind, id, label);
+        }
+    }
+    leaks.len()
+}
+
+fn untrack(device: DeviceId, kind: ResourceKind, id: u32) {
+    if cfg!(debug_assertions) {
+        LIVE.lock().unwrap().remove(&(device, kind, id));
+    }
+}
+
+fn delete<D: RenderDevice>(device: &mut D, kind: ResourceKind, id: u32) {
+    match kind {
+        ResourceKind::BUFFER => device.delete_buffer(id),
+        ResourceKind::TEXTURE => device.delete_texture(id),
+        ResourceKind::VERTEX_ARRAY => device.delete_vertex_array(id),
+        ResourceKind::PROGRAM => device.delete_program(id),
+        ResourceKind::FRAMEBUFFER => device.delete_framebuffer(id)
+    }
+}
@@ -1,139 +1,368 @@
-use core::str;
-use std::ffi::{ CString, CStr };
-use std::fs::File;
-use std::io::Read;
-use std::ptr;
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+use std::ffi::CStr;
+use std::mem;
+use std::path::PathBuf;
 
-use gl;
-use gl::types::*;
+use cgmath::{ Matrix4, Vector3 };
 
-use cgmath::{ Matrix, Matrix4, Vector3 };
-use cgmath::prelude::*;
+use crate::graphics::device::{ DeviceFeatures, GlDevice, RenderDevice, ShaderStage, UniformValue };
+use crate::graphics::preprocessor::ShaderPreprocessor;
+use crate::graphics::reflection::ProgramReflection;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::uniform_buffer;
 
-#[derive(Hash, Eq, PartialEq)]
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
+#[allow(non_camel_case_types)]
 pub enum ShaderType {
     MODEL,
-    SKYBOX
+    SKYBOX,
+    SHADOW_DEPTH,
+    LUMINANCE,
+    BLOOM_DOWNSAMPLE,
+    BLOOM_UPSAMPLE,
+    TONEMAP,
+    SSAO_GEOMETRY,
+    SSAO,
+    SSAO_BLUR,
+    GBUFFER,
+    DEFERRED_LIGHT,
+    DEFERRED_POINT,
+    OIT_COMPOSITE
 }
 
+#[derive(Default)]
 pub struct Shader {
-    pub id: u32
+    pub id: u32,
+    // owns the program `id` names, it is deleted with the shader
+    pub handle: GpuHandle,
+    // files the program is built from, empty when it was built from strings
+    pub sources: Vec<PathBuf>,
+    // stage of each source, vertex then fragment when left empty
+    pub stages: Vec<ShaderStage>,
+    // files the sources #include, a change to one of them rebuilds the program too
+    pub includes: Vec<PathBuf>,
+    // variant keywords defined before the sources
+    pub keywords: Vec<String>,
+    // incremented each time a reload replaces the program, state cached per program compares it
+    pub generation: u32,
+    pub uniforms: UniformTable
+}
+
+// Uniform locations of the current program, looked up once after each link instead of every time a
+// uniform is set. Names the program doesn't have, or values of the wrong type, are reported once.
+#[derive(Default)]
+pub struct UniformTable {
+    pub reflection: Option<ProgramReflection>,
+    warned: RefCell<HashSet<String>>
+}
+
+impl UniformTable {
+    // reflect a freshly linked program and assign its engine uniform blocks to their binding points
+    fn reflect<D: RenderDevice>(device: &mut D, program: u32) -> UniformTable {
+        let reflection = device.reflect_program(program);
+        if let Some(reflection) = &reflection {
+            uniform_buffer::bind_blocks(device, program, reflection);
+        }
+        UniformTable {
+            reflection,
+            warned: RefCell::new(HashSet::new())
+        }
+    }
+
+    fn warn_once(&self, program: u32, name: &str, message: &str) {
+        if self.warned.borrow_mut().insert(name.to_string()) {
+            println!("WARNING::SHADER:: program {}: uniform \"{}\" {}", program, name, message);
+        }
+    }
 }
 
 #[allow(dead_code)]
 impl Shader {
     pub fn new(vertex_path: &str, fragment_path: &str) -> Shader {
-        let mut shader = Shader { id: 0 };
-
-        // 1. retrieve the vertex/fragment source code from filesystem
-        let mut v_shader_file = File::open(vertex_path)
-            .unwrap_or_else(|_| panic!("Failed to open {}", vertex_path));
-        let mut f_shader_file = File::open(fragment_path)
-            .unwrap_or_else(|_| panic!("Failed to open {}", fragment_path));
-
-        let mut vertex_code = String::new();
-        let mut fragment_code = String::new();
-
-        v_shader_file
-            .read_to_string(&mut vertex_code)
-            .expect("Failed to read vertex shader");
-        f_shader_file
-            .read_to_string(&mut fragment_code)
-            .expect("Failed to read fragment shader");
-
-        let v_shader_code = CString::new(vertex_code.as_bytes()).unwrap();
-        let f_shader_code = CString::new(fragment_code.as_bytes()).unwrap();
-
-        // 2. compile shaders
-        unsafe {
-            // vertex shader
-            let vertex = gl::CreateShader(gl::VERTEX_SHADER);
-            gl::ShaderSource(vertex, 1, &v_shader_code.as_ptr(), ptr::null());
-            gl::CompileShader(vertex);
-            shader.check_compile_errors(vertex, "VERTEX");
-
-            // fragment shader
-            let fragment = gl::CreateShader(gl::FRAGMENT_SHADER);
-            gl::ShaderSource(fragment, 1, &f_shader_code.as_ptr(), ptr::null());
-            gl::CompileShader(fragment);
-            shader.check_compile_errors(fragment, "FRAGMENT");
-
-            // shader program
-            let id = gl::CreateProgram();
-            gl::AttachShader(id, vertex);
-            gl::AttachShader(id, fragment);
-            gl::LinkProgram(id);
-            shader.check_compile_errors(id, "PROGRAM");
-            
-            // delete the shaders as they're linked into our program now and no longer necessary
-            gl::DeleteShader(vertex);
-            gl::DeleteShader(fragment);
-
-            shader.id = id;
-        }
+        Shader::with_device(unsafe { &mut GlDevice::current() }, vertex_path, fragment_path, &[])
+    }
 
+    // Build the program from files run through the preprocessor with `keywords` defined. A broken program
+    // is reported and left unbound, the sources are kept so a hot reload can fix it.
+    pub fn with_device<D: RenderDevice>(device: &mut D, vertex_path: &str, fragment_path: &str, keywords: &[&str]) -> Shader {
+        let mut shader = Shader {
+            sources: vec![vertex_path.into(), fragment_path.into()],
+            stages: vec![ShaderStage::VERTEX, ShaderStage::FRAGMENT],
+            keywords: keyword_set(keywords),
+            ..Shader::default()
+        };
+        if let Err(error) = shader.reload_with(device) {
+            println!("{}", error);
+        }
         shader
     }
 
+    // a program with any set of stages, e.g. vertex, tessellation and fragment, or a lone compute stage
+    pub fn builder() -> ProgramBuilder {
+        ProgramBuilder::default()
+    }
+
+    pub fn from_source<D: RenderDevice>(device: &mut D, vertex_code: &str, fragment_code: &str) -> Result<Shader, String> {
+        let stages = [(ShaderStage::VERTEX, vertex_code), (ShaderStage::FRAGMENT, fragment_code)];
+        let id = link(device, &stages, |_, log| log.to_string())?;
+        Ok(Shader {
+            id,
+            handle: GpuHandle::new(device, ResourceKind::PROGRAM, id, "program from source"),
+            uniforms: UniformTable::reflect(device, id),
+            ..Shader::default()
+        })
+    }
+
+    // Rebuild the program from its source files. The new program only replaces the current one once it
+    // compiled and linked, on failure the current one is kept and the error returned.
+    pub fn reload(&mut self) -> Result<(), String> {
+        self.reload_with(unsafe { &mut GlDevice::current() })
+    }
+
+    pub fn reload_with<D: RenderDevice>(&mut self, device: &mut D) -> Result<(), String> {
+        let stages = match (self.stages.len(), self.sources.len()) {
+            (0, 2) => vec![ShaderStage::VERTEX, ShaderStage::FRAGMENT],
+            (stages, sources) if stages == sources && sources > 0 => self.stages.clone(),
+            _ => return Err("ERROR::SHADER:: the program was not built from files".into())
+        };
+        let preprocessor = ShaderPreprocessor::default();
+        let processed = self.sources.iter()
+            .map(|path| preprocessor.process_file(path, &self.keywords))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // compile errors point at the file and line the faulty code was included from
+        let codes: Vec<(ShaderStage, &str)> = stages.iter().zip(&processed).map(|(stage, source)| (*stage, source.code.as_str())).collect();
+        let id = link(device, &codes, |index, log| processed[index].map_log(log))?;
+        mem::take(&mut self.handle).release_with(device);
+        let label = self.sources.last().map(|path| path.display().to_string()).unwrap_or_default();
+        self.handle = GpuHandle::new(device, ResourceKind::PROGRAM, id, &label);
+        self.id = id;
+        self.generation += 1;
+        self.uniforms = UniformTable::reflect(device, id);
+
+        self.includes.clear();
+        for path in processed.iter().flat_map(|source| source.files.iter().skip(1)) {
+            if !self.includes.contains(path) {
+                self.includes.push(path.clone());
+            }
+        }
+        Ok(())
+    }
+
     // activate the shader
     pub unsafe fn use_program(&self) {
-        gl::UseProgram(self.id);
+        GlDevice::current().use_program(self.id);
     }
 
     // utility uniform functions
     pub unsafe fn set_bool(&self, name: &CStr, value: bool) {
-        gl::Uniform1i(gl::GetUniformLocation(self.id, name.as_ptr()), value as i32);
+        self.set(name, UniformValue::INT(value as i32));
     }
 
     pub unsafe fn set_int(&self, name: &CStr, value: i32) {
-        gl::Uniform1i(gl::GetUniformLocation(self.id, name.as_ptr()), value);
+        self.set(name, UniformValue::INT(value));
     }
 
     pub unsafe fn set_float(&self, name: &CStr, value: f32) {
-        gl::Uniform1f(gl::GetUniformLocation(self.id, name.as_ptr()), value);
+        self.set(name, UniformValue::FLOAT(value));
+    }
+
+    pub unsafe fn set_vec2(&self, name: &CStr, x: f32, y: f32) {
+        self.set(name, UniformValue::VEC2([x, y]));
     }
 
     pub unsafe fn set_vector3(&self, name: &CStr, value: &Vector3<f32>) {
-        gl::Uniform3fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, value.as_ptr());
+        self.set(name, UniformValue::VEC3((*value).into()));
     }
 
     pub unsafe fn set_vec3(&self, name: &CStr, x: f32, y: f32, z: f32) {
-        gl::Uniform3f(gl::GetUniformLocation(self.id, name.as_ptr()), x, y, z);
+        self.set(name, UniformValue::VEC3([x, y, z]));
+    }
+
+    pub unsafe fn set_ivec3(&self, name: &CStr, x: i32, y: i32, z: i32) {
+        self.set(name, UniformValue::IVEC3([x, y, z]));
     }
 
     pub unsafe fn set_mat4(&self, name: &CStr, mat: &Matrix4<f32>) {
-        gl::UniformMatrix4fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, gl::FALSE, mat.as_ptr());
-    }
-
-    // utility function for checking shader compilation/linking errors
-    unsafe fn check_compile_errors(&self, shader: u32, type_: &str) {
-        let mut success = gl::FALSE as GLint;
-        let mut info_log: Vec<u8> = Vec::with_capacity(1024);
-        info_log.set_len(1024 - 1);
-
-        if type_ != "PROGRAM" {
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                gl::GetShaderInfoLog(shader, 1024, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-                println!(
-                    "ERROR_SHADER_COMPILATION_ERROR of type: {}\n{}\n\
-                    -- --------------------------------------------------- --",
-                    type_,
-                    str::from_utf8(&info_log).unwrap()
-                );
+        self.set(name, UniformValue::MAT4((*mat).into()));
+    }
+
+    // Set a uniform through its cached location. A value of the wrong type is dropped, a name missing
+    // from the reflection still goes by name in case the backend knows better.
+    pub fn set_with<D: RenderDevice>(&self, device: &mut D, name: &CStr, value: UniformValue) {
+        let reflection = match &self.uniforms.reflection {
+            Some(reflection) if self.id != 0 => reflection,
+            _ => return device.set_uniform(self.id, name, value)
+        };
+        let name_str = name.to_string_lossy();
+        match reflection.uniform(&name_str) {
+            Some(uniform) if uniform.type_.accepts(&value) => device.set_uniform_location(self.id, uniform.location, value),
+            Some(uniform) => self.uniforms.warn_once(self.id, &name_str, &format!("is {:?}, not {:?}", uniform.type_, value)),
+            None => {
+                self.uniforms.warn_once(self.id, &name_str, "is not an active uniform");
+                device.set_uniform(self.id, name, value);
             }
         }
-        else {
-            gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                gl::GetProgramInfoLog(shader, 1024, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-                println!(
-                    "ERROR::PROGRAM_LINKING_ERROR of type: {}\n{}\n \
-                    -- --------------------------------------------------- -- ",
-                    type_,
-                    str::from_utf8(&info_log).unwrap()
-                );
+    }
+
+    unsafe fn set(&self, name: &CStr, value: UniformValue) {
+        self.set_with(&mut GlDevice::current(), name, value);
+    }
+}
+
+// Permutations of a program, compiled the first time a set of keywords is asked for. The order of the
+// keywords doesn't matter.
+pub struct ShaderVariants {
+    vertex_path: String,
+    fragment_path: String,
+    variants: HashMap<Vec<String>, Shader>
+}
+
+impl ShaderVariants {
+    pub fn new(vertex_path: &str, fragment_path: &str) -> ShaderVariants {
+        ShaderVariants {
+            vertex_path: vertex_path.into(),
+            fragment_path: fragment_path.into(),
+            variants: HashMap::new()
+        }
+    }
+
+    pub fn variant(&mut self, keywords: &[&str]) -> &Shader {
+        self.variant_with(unsafe { &mut GlDevice::current() }, keywords)
+    }
+
+    pub fn variant_with<D: RenderDevice>(&mut self, device: &mut D, keywords: &[&str]) -> &Shader {
+        let (vertex_path, fragment_path) = (&self.vertex_path, &self.fragment_path);
+        self.variants.entry(keyword_set(keywords))
+            .or_insert_with(|| Shader::with_device(device, vertex_path, fragment_path, keywords))
+    }
+
+    // an already compiled variant
+    pub fn get(&self, keywords: &[&str]) -> Option<&Shader> {
+        self.variants.get(&keyword_set(keywords))
+    }
+
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+
+    pub fn shaders(&self) -> impl Iterator<Item = &Shader> {
+        self.variants.values()
+    }
+
+    pub fn shaders_mut(&mut self) -> impl Iterator<Item = &mut Shader> {
+        self.variants.values_mut()
+    }
+}
+
+fn keyword_set(keywords: &[&str]) -> Vec<String> {
+    let mut set: Vec<String> = keywords.iter().map(|keyword| keyword.to_string()).collect();
+    set.sort();
+    set.dedup();
+    set
+}
+
+// Files and keywords of a program with an arbitrary set of stages
+#[derive(Default)]
+pub struct ProgramBuilder {
+    stages: Vec<(ShaderStage, PathBuf)>,
+    keywords: Vec<String>
+}
+
+impl ProgramBuilder {
+    pub fn stage(mut self, stage: ShaderStage, path: &str) -> ProgramBuilder {
+        self.stages.push((stage, path.into()));
+        self
+    }
+
+    pub fn keywords(mut self, keywords: &[&str]) -> ProgramBuilder {
+        self.keywords = keyword_set(keywords);
+        self
+    }
+
+    pub fn build(self) -> Result<Shader, String> {
+        self.build_with(unsafe { &mut GlDevice::current() })
+    }
+
+    // fails when the stages don't form a program or the device doesn't support one of them
+    pub fn build_with<D: RenderDevice>(mut self, device: &mut D) -> Result<Shader, String> {
+        self.stages.sort_by_key(|(stage, _)| *stage);
+        let mut shader = Shader {
+            stages: self.stages.iter().map(|(stage, _)| *stage).collect(),
+            sources: self.stages.into_iter().map(|(_, path)| path).collect(),
+            keywords: self.keywords,
+            ..Shader::default()
+        };
+        shader.reload_with(device)?;
+        Ok(shader)
+    }
+}
+
+// whether `stages`, in pipeline order, make a program `features` can run
+fn check_stages(stages: &[ShaderStage], features: &DeviceFeatures) -> Result<(), String> {
+    if let Some(stage) = stages.iter().find(|stage| !features.supports(**stage)) {
+        let (major, minor) = features.version;
+        return Err(format!("ERROR::SHADER:: {:?} shaders are not supported by this OpenGL {}.{} context", stage, major, minor));
+    }
+    if stages.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err("ERROR::SHADER:: a stage is given twice".into());
+    }
+    if stages.contains(&ShaderStage::COMPUTE) && stages.len() > 1 {
+        return Err("ERROR::SHADER:: a compute stage can't be linked with other stages".into());
+    }
+    if !stages.contains(&ShaderStage::COMPUTE) && !stages.contains(&ShaderStage::VERTEX) {
+        return Err("ERROR::SHADER:: the program has no vertex stage".into());
+    }
+    Ok(())
+}
+
+// compile the stages and link them, `map_log` rewrites the compiler log of the stage at an index
+fn link<D, F>(device: &mut D, stages: &[(ShaderStage, &str)], map_log: F) -> Result<u32, String>
+where
+    D: RenderDevice,
+    F: Fn(usize, &str) -> String
+{
+    let kinds: Vec<ShaderStage> = stages.iter().map(|(stage, _)| *stage).collect();
+    check_stages(&kinds, &device.features())?;
+
+    let mut shaders = Vec::with_capacity(stages.len());
+    for (index, (stage, code)) in stages.iter().enumerate() {
+        match device.create_shader(*stage, code) {
+            Ok(shader) => shaders.push(shader),
+            Err(log) => {
+                for shader in shaders {
+                    device.delete_shader(shader);
+                }
+                return Err(compile_error(*stage, &map_log(index, &log)));
             }
         }
     }
+
+    // shader program
+    let program = device.create_program(&shaders);
+
+    // delete the shaders as they're linked into our program now and no longer necessary
+    for shader in shaders {
+        device.delete_shader(shader);
+    }
+
+    program.map_err(|log| format!(
+        "ERROR::PROGRAM_LINKING_ERROR of type: PROGRAM\n{}\n \
+        -- --------------------------------------------------- -- ",
+        log
+    ))
+}
+
+fn compile_error(stage: ShaderStage, log: &str) -> String {
+    format!(
+        "ERROR_SHADER_COMPILATION_ERROR of type: {:?}\n{}\n\
+        -- --------------------------------------------------- --",
+        stage,
+        log
+    )
 }
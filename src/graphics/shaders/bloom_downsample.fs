@@ -0,0 +1,53 @@
+#version 330 core
+layout (location = 0) out vec3 Downsample;
+
+in vec2 TexCoords;
+
+uniform sampler2D srcTexture;
+uniform vec2 srcResolution;
+uniform bool prefilter;
+uniform float threshold;
+uniform float knee;
+
+// soft threshold keeping only the bright parts of the image
+vec3 prefilterColor(vec3 color) {
+    float brightness = max(color.r, max(color.g, color.b));
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-5);
+    float contribution = max(soft, brightness - threshold) / max(brightness, 1e-5);
+    return color * contribution;
+}
+
+void main() {
+    vec2 texel = 1.0 / srcResolution;
+    float x = texel.x;
+    float y = texel.y;
+
+    // 13-tap filter, see "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+    vec3 a = texture(srcTexture, vec2(TexCoords.x - 2.0 * x, TexCoords.y + 2.0 * y)).rgb;
+    vec3 b = texture(srcTexture, vec2(TexCoords.x,           TexCoords.y + 2.0 * y)).rgb;
+    vec3 c = texture(srcTexture, vec2(TexCoords.x + 2.0 * x, TexCoords.y + 2.0 * y)).rgb;
+
+    vec3 d = texture(srcTexture, vec2(TexCoords.x - 2.0 * x, TexCoords.y)).rgb;
+    vec3 e = texture(srcTexture, vec2(TexCoords.x,           TexCoords.y)).rgb;
+    vec3 f = texture(srcTexture, vec2(TexCoords.x + 2.0 * x, TexCoords.y)).rgb;
+
+    vec3 g = texture(srcTexture, vec2(TexCoords.x - 2.0 * x, TexCoords.y - 2.0 * y)).rgb;
+    vec3 h = texture(srcTexture, vec2(TexCoords.x,           TexCoords.y - 2.0 * y)).rgb;
+    vec3 i = texture(srcTexture, vec2(TexCoords.x + 2.0 * x, TexCoords.y - 2.0 * y)).rgb;
+
+    vec3 j = texture(srcTexture, vec2(TexCoords.x - x, TexCoords.y + y)).rgb;
+    vec3 k = texture(srcTexture, vec2(TexCoords.x + x, TexCoords.y + y)).rgb;
+    vec3 l = texture(srcTexture, vec2(TexCoords.x - x, TexCoords.y - y)).rgb;
+    vec3 m = texture(srcTexture, vec2(TexCoords.x + x, TexCoords.y - y)).rgb;
+
+    Downsample = e * 0.125;
+    Downsample += (a + c + g + i) * 0.03125;
+    Downsample += (b + d + f + h) * 0.0625;
+    Downsample += (j + k + l + m) * 0.125;
+
+    if (prefilter) {
+        Downsample = prefilterColor(Downsample);
+    }
+    Downsample = max(Downsample, 0.0001);
+}
@@ -0,0 +1,57 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+#include "include/material.glsl"
+
+uniform sampler2D gAlbedo;
+uniform sampler2D gNormal;
+uniform sampler2D gMaterial;
+uniform sampler2D gDepth;
+
+#include "include/camera.glsl"
+
+uniform sampler2D ssaoTexture;
+uniform bool ssaoEnabled;
+uniform vec2 screenSize;
+
+// reconstructed from the G-buffer, named like the forward shader inputs so the lighting code is shared
+vec3 FragPos;
+float ViewDepth;
+Material material;
+
+#include "include/shadows.glsl"
+#include "include/lighting.glsl"
+
+void main() {
+    float depth = texture(gDepth, TexCoords).r;
+    if (depth >= 1.0) {
+        // nothing was rendered here, the skybox fills it later
+        discard;
+    }
+
+    vec4 world = inverseViewProjection * vec4(vec3(TexCoords, depth) * 2.0 - 1.0, 1.0);
+    FragPos = world.xyz / world.w;
+    ViewDepth = -(view * vec4(FragPos, 1.0)).z;
+    vec4 packedMaterial = texture(gMaterial, TexCoords);
+    material = Material(packedMaterial.r, packedMaterial.g * MAX_SHININESS, 1.0, 0.0);
+
+    vec3 albedo = texture(gAlbedo, TexCoords).rgb;
+    vec3 normal = normalize(texture(gNormal, TexCoords).xyz);
+    vec3 viewDir = normalize(cameraPosition.xyz - FragPos);
+    vec3 lightDir = normalize(-light.direction);
+
+    // ambient and directional light
+    int cascade;
+    float shadow = directionalShadow(normal, lightDir, cascade);
+    float ambientOcclusion = ssaoEnabled ? texture(ssaoTexture, gl_FragCoord.xy / screenSize).r : 1.0;
+    vec3 color = albedo * light.color * light.ambient * ambientOcclusion;
+    color += (1.0 - shadow) * blinnPhong(lightDir, light.color * light.intensity, normal, viewDir, albedo);
+
+    if (debugCascades && cascadeCount > 0) {
+        color *= cascadeColors[cascade];
+    }
+
+    FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,55 @@
+#version 330 core
+out vec4 FragColor;
+
+// a point light, or a spot light when cosOuter > -1
+struct LocalLight {
+    vec3 position;
+    vec3 color;
+    float intensity;
+    float radius;
+    vec3 direction;
+    float cosInner;
+    float cosOuter;
+};
+
+#include "include/material.glsl"
+
+uniform sampler2D gAlbedo;
+uniform sampler2D gNormal;
+uniform sampler2D gMaterial;
+uniform sampler2D gDepth;
+
+#include "include/camera.glsl"
+uniform vec2 screenSize;
+uniform LocalLight light;
+
+Material material;
+
+#include "include/lighting.glsl"
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / screenSize;
+    float depth = texture(gDepth, uv).r;
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    vec4 world = inverseViewProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    vec3 fragPos = world.xyz / world.w;
+    vec3 toLight = light.position - fragPos;
+    float distance = length(toLight);
+    if (distance >= light.radius) {
+        discard;
+    }
+
+    vec4 packedMaterial = texture(gMaterial, uv);
+    material = Material(packedMaterial.r, packedMaterial.g * MAX_SHININESS, 1.0, 0.0);
+    vec3 albedo = texture(gAlbedo, uv).rgb;
+    vec3 normal = normalize(texture(gNormal, uv).xyz);
+    vec3 viewDir = normalize(cameraPosition.xyz - fragPos);
+
+    vec3 lightDir = toLight / distance;
+    float cone = smoothstep(light.cosOuter, light.cosInner, dot(-lightDir, light.direction));
+    vec3 radiance = light.color * light.intensity * attenuation(distance, light.radius) * cone;
+    FragColor = vec4(blinnPhong(lightDir, radiance, normal, viewDir, albedo), 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+#include "include/camera.glsl"
+// bounding sphere of the light
+uniform vec3 volumeCenter;
+uniform float volumeRadius;
+// the sphere mesh is inscribed in the unit sphere, grow it so it fully covers the light
+uniform float volumeScale;
+
+void main() {
+    vec3 position = aPos * volumeRadius * volumeScale + volumeCenter;
+    gl_Position = projection * view * vec4(position, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+layout (location = 0) out vec4 gAlbedo;
+layout (location = 1) out vec3 gNormal;
+layout (location = 2) out vec4 gMaterial;
+
+in vec2 TexCoords;
+in vec3 Normal;
+
+#include "include/material.glsl"
+
+uniform sampler2D texture_diffuse1;
+uniform Material material;
+
+void main() {
+    vec4 albedo = texture(texture_diffuse1, TexCoords);
+    if (albedo.a < material.alphaCutoff) {
+        discard;
+    }
+
+    gAlbedo = vec4(albedo.rgb, 1.0);
+    gNormal = normalize(Normal);
+    gMaterial = vec4(material.specular, material.shininess / MAX_SHININESS, 0.0, 1.0);
+}
@@ -0,0 +1,16 @@
+#pragma once
+// per-frame data, uploaded once per frame into the uniform buffer at FRAME_BINDING
+layout (std140) uniform FrameData {
+    mat4 view;
+    mat4 projection;
+    mat4 viewProjection;
+    mat4 inverseView;
+    mat4 inverseProjection;
+    mat4 inverseViewProjection;
+    // xyz, w unused
+    vec4 cameraPosition;
+    // seconds since start, seconds since the last frame
+    vec4 time;
+    // width and height in pixels, then their inverse
+    vec4 resolution;
+};
@@ -0,0 +1,20 @@
+#pragma once
+// expects the `material` being shaded to be declared before the include
+#include "material.glsl"
+
+// windowed inverse square falloff reaching zero at the light radius
+float attenuation(float distance, float radius) {
+    float ratio = distance / radius;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+vec3 blinnPhong(vec3 lightDir, vec3 radiance, vec3 normal, vec3 viewDir, vec3 albedo) {
+    float diffuse = max(dot(normal, lightDir), 0.0);
+    if (diffuse <= 0.0) {
+        return vec3(0.0);
+    }
+    vec3 halfway = normalize(lightDir + viewDir);
+    float specular = pow(max(dot(normal, halfway), 0.0), material.shininess) * material.specular;
+    return (albedo * diffuse + vec3(specular)) * radiance;
+}
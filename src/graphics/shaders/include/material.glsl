@@ -0,0 +1,10 @@
+#pragma once
+struct Material {
+    float specular;
+    float shininess;
+    float opacity;
+    float alphaCutoff;
+};
+
+// the G-buffer stores shininess / MAX_SHININESS in a unorm channel
+const float MAX_SHININESS = 256.0;
@@ -0,0 +1,15 @@
+#pragma once
+#ifdef INSTANCED
+// per-instance data, streamed into an instance buffer and read as attributes from INSTANCE_LOCATION
+layout (location = 5) in mat4 instanceModel;
+layout (location = 9) in mat4 instanceNormalMatrix;
+#define model instanceModel
+#define normalMatrix instanceNormalMatrix
+#else
+// per-draw data, streamed into a ring of uniform buffer ranges bound at OBJECT_BINDING
+layout (std140) uniform ObjectData {
+    mat4 model;
+    // inverse transpose of model, only the upper 3x3 is meaningful
+    mat4 normalMatrix;
+};
+#endif
@@ -0,0 +1,75 @@
+#pragma once
+// directional light with cascaded shadows, expects FragPos and ViewDepth to be declared before the include
+
+struct DirectionalLight {
+    vec3 direction;
+    vec3 color;
+    float intensity;
+    float ambient;
+};
+
+// must match MAX_CASCADES in shadow.rs
+const int MAX_CASCADES = 4;
+
+const vec3 cascadeColors[MAX_CASCADES] = vec3[](
+    vec3(1.0, 0.25, 0.25),
+    vec3(0.25, 1.0, 0.25),
+    vec3(0.25, 0.25, 1.0),
+    vec3(1.0, 1.0, 0.25)
+);
+
+uniform DirectionalLight light;
+
+uniform sampler2DArray shadowMap;
+uniform int cascadeCount;
+uniform float cascadeSplits[MAX_CASCADES];
+uniform mat4 lightSpaceMatrices[MAX_CASCADES];
+uniform float cascadeBlendFraction;
+uniform bool debugCascades;
+
+float cascadeShadow(int cascade, vec3 normal, vec3 lightDir) {
+    vec4 lightSpacePos = lightSpaceMatrices[cascade] * vec4(FragPos, 1.0);
+    vec3 projCoords = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+    if (projCoords.z > 1.0) {
+        return 0.0;
+    }
+
+    float bias = max(0.002 * (1.0 - dot(normal, lightDir)), 0.0005);
+
+    // 3x3 PCF
+    float shadow = 0.0;
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+    for (int x = -1; x <= 1; ++x) {
+        for (int y = -1; y <= 1; ++y) {
+            float closest = texture(shadowMap, vec3(projCoords.xy + vec2(x, y) * texelSize, cascade)).r;
+            shadow += projCoords.z - bias > closest ? 1.0 : 0.0;
+        }
+    }
+    return shadow / 9.0;
+}
+
+float directionalShadow(vec3 normal, vec3 lightDir, out int cascade) {
+    cascade = cascadeCount - 1;
+    for (int i = 0; i < cascadeCount; ++i) {
+        if (ViewDepth < cascadeSplits[i]) {
+            cascade = i;
+            break;
+        }
+    }
+    if (cascadeCount == 0 || ViewDepth >= cascadeSplits[cascadeCount - 1]) {
+        return 0.0;
+    }
+
+    float shadow = cascadeShadow(cascade, normal, lightDir);
+
+    // blend with the next cascade (or fade out after the last one) to hide the seams
+    float splitNear = cascade == 0 ? 0.0 : cascadeSplits[cascade - 1];
+    float splitFar = cascadeSplits[cascade];
+    float blendStart = splitFar - (splitFar - splitNear) * cascadeBlendFraction;
+    if (ViewDepth > blendStart) {
+        float t = (ViewDepth - blendStart) / (splitFar - blendStart);
+        float next = cascade + 1 < cascadeCount ? cascadeShadow(cascade + 1, normal, lightDir) : 0.0;
+        shadow = mix(shadow, next, t);
+    }
+    return shadow;
+}
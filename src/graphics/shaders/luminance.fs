@@ -0,0 +1,21 @@
+#version 330 core
+layout (location = 0) out float LogLuminance;
+
+in vec2 TexCoords;
+
+uniform sampler2D hdrBuffer;
+
+void main() {
+    // average a 4x4 grid over the footprint of this texel in the source image,
+    // must match LUMINANCE_SIZE in hdr.rs
+    vec2 footprint = vec2(1.0 / 64.0);
+    float luminance = 0.0;
+    for (int x = 0; x < 4; ++x) {
+        for (int y = 0; y < 4; ++y) {
+            vec2 offset = (vec2(x, y) + 0.5) / 4.0 - 0.5;
+            vec3 color = texture(hdrBuffer, TexCoords + offset * footprint).rgb;
+            luminance += dot(color, vec3(0.2126, 0.7152, 0.0722));
+        }
+    }
+    LogLuminance = log2(max(luminance / 16.0, 1e-5));
+}
@@ -1,9 +1,87 @@
 #version 330 core
-out vec4 FragColor;
+layout (location = 0) out vec4 FragColor;
+#ifdef WEIGHTED_OIT
+layout (location = 1) out float OitWeight;
+#endif
 
 in vec2 TexCoords;
+in vec3 Normal;
+in vec3 FragPos;
+in float ViewDepth;
+
+#include "include/material.glsl"
 
 uniform sampler2D texture_diffuse1;
+uniform Material material;
+#include "include/camera.glsl"
+
+// clustered point and spot lights, see clustered.rs for the layout
+uniform samplerBuffer lightData;
+uniform usamplerBuffer clusterGrid;
+uniform usamplerBuffer lightIndices;
+uniform ivec3 clusterDims;
+uniform vec2 clusterDepthScaleBias;
+uniform vec2 clusterTileScale;
+
+uniform sampler2D ssaoTexture;
+uniform bool ssaoEnabled;
+uniform vec2 screenSize;
+
+#include "include/shadows.glsl"
+#include "include/lighting.glsl"
+
+int clusterIndex() {
+    int slice = int(floor(log(ViewDepth) * clusterDepthScaleBias.x + clusterDepthScaleBias.y));
+    slice = clamp(slice, 0, clusterDims.z - 1);
+    ivec2 tile = min(ivec2(gl_FragCoord.xy * clusterTileScale), clusterDims.xy - 1);
+    return tile.x + clusterDims.x * (tile.y + clusterDims.y * slice);
+}
+
 void main() {
-    FragColor = texture(texture_diffuse1, TexCoords);
+    vec4 albedo = texture(texture_diffuse1, TexCoords);
+    if (albedo.a < material.alphaCutoff) {
+        discard;
+    }
+    vec3 normal = normalize(Normal);
+    vec3 viewDir = normalize(cameraPosition.xyz - FragPos);
+    vec3 lightDir = normalize(-light.direction);
+
+    // ambient and directional light
+    int cascade;
+    float shadow = directionalShadow(normal, lightDir, cascade);
+    float ambientOcclusion = ssaoEnabled ? texture(ssaoTexture, gl_FragCoord.xy / screenSize).r : 1.0;
+    vec3 color = albedo.rgb * light.color * light.ambient * ambientOcclusion;
+    color += (1.0 - shadow) * blinnPhong(lightDir, light.color * light.intensity, normal, viewDir, albedo.rgb);
+
+    // point and spot lights reaching this fragment's cluster
+    uvec2 cluster = texelFetch(clusterGrid, clusterIndex()).rg;
+    for (uint i = 0u; i < cluster.y; ++i) {
+        int base = int(texelFetch(lightIndices, int(cluster.x + i)).r) * 3;
+        vec4 positionRadius = texelFetch(lightData, base);
+        vec4 colorInner = texelFetch(lightData, base + 1);
+        vec4 directionOuter = texelFetch(lightData, base + 2);
+
+        vec3 toLight = positionRadius.xyz - FragPos;
+        float distance = length(toLight);
+        if (distance < positionRadius.w) {
+            vec3 L = toLight / distance;
+            float cone = smoothstep(directionOuter.w, colorInner.w, dot(-L, directionOuter.xyz));
+            vec3 radiance = colorInner.rgb * attenuation(distance, positionRadius.w) * cone;
+            color += blinnPhong(L, radiance, normal, viewDir, albedo.rgb);
+        }
+    }
+
+    if (debugCascades && cascadeCount > 0) {
+        color *= cascadeColors[cascade];
+    }
+
+    float alpha = albedo.a * material.opacity;
+#ifdef WEIGHTED_OIT
+    // favour surfaces close to the camera
+    float weight = clamp(alpha * max(1e-2, 3e3 * pow(1.0 - gl_FragCoord.z, 3.0)), 1e-2, 3e3);
+    FragColor = vec4(color * alpha * weight, alpha);
+    OitWeight = alpha * weight;
+#else
+    FragColor = vec4(color, alpha);
+#endif
 }
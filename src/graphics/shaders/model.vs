@@ -5,12 +5,20 @@ layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTexCoords;
 
 out vec2 TexCoords;
+out vec3 Normal;
+out vec3 FragPos;
+out float ViewDepth;
 
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+#include "include/object.glsl"
+#include "include/camera.glsl"
 
 void main() {
+    vec4 worldPos = model * vec4(aPos, 1.0);
+    vec4 viewPos = view * worldPos;
+
     TexCoords = aTexCoords;
-    gl_Position = projection * view * model * vec4(aPos, 1.0);
+    Normal = mat3(normalMatrix) * aNormal;
+    FragPos = worldPos.xyz;
+    ViewDepth = -viewPos.z;
+    gl_Position = projection * viewPos;
 }
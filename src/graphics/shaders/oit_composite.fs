@@ -0,0 +1,18 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D accumulationTexture;
+uniform sampler2D weightTexture;
+
+void main() {
+    vec4 accumulation = texture(accumulationTexture, TexCoords);
+    float revealage = accumulation.a;
+    if (revealage >= 1.0) {
+        discard;
+    }
+
+    float weight = max(texture(weightTexture, TexCoords).r, 1e-5);
+    FragColor = vec4(accumulation.rgb / weight, revealage);
+}
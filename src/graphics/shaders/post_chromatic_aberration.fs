@@ -0,0 +1,16 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D inputTexture;
+uniform float strength;
+
+void main() {
+    // shift red and blue in opposite directions, more towards the edges
+    vec2 offset = (TexCoords - 0.5) * strength;
+    float r = texture(inputTexture, TexCoords + offset).r;
+    vec4 center = texture(inputTexture, TexCoords);
+    float b = texture(inputTexture, TexCoords - offset).b;
+    FragColor = vec4(r, center.g, b, center.a);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D inputTexture;
+uniform sampler3D lut;
+uniform float lutSize;
+uniform float contribution;
+// range of input colours the LUT covers, DOMAIN_MIN and DOMAIN_MAX of the .cube file
+uniform vec3 domainMin;
+uniform vec3 domainMax;
+
+void main() {
+    vec4 color = texture(inputTexture, TexCoords);
+    // sample at texel centers so the first and last entries are hit exactly
+    vec3 domain = clamp((color.rgb - domainMin) / (domainMax - domainMin), 0.0, 1.0);
+    vec3 uvw = domain * ((lutSize - 1.0) / lutSize) + 0.5 / lutSize;
+    vec3 graded = texture(lut, uvw).rgb;
+    FragColor = vec4(mix(color.rgb, graded, contribution), color.a);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D inputTexture;
+uniform float time;
+uniform float intensity;
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main() {
+    vec4 color = texture(inputTexture, TexCoords);
+    float noise = hash(TexCoords * 1000.0 + fract(time) * 100.0) - 0.5;
+    // grain is more visible in the mid tones
+    float luma = dot(color.rgb, vec3(0.299, 0.587, 0.114));
+    float response = 1.0 - abs(luma * 2.0 - 1.0);
+    FragColor = vec4(color.rgb + noise * intensity * response, color.a);
+}
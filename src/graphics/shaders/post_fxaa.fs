@@ -0,0 +1,50 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D inputTexture;
+uniform vec2 resolution;
+
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_SPAN_MAX = 8.0;
+
+void main() {
+    vec2 texel = 1.0 / resolution;
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    vec3 rgbNW = texture(inputTexture, TexCoords + vec2(-1.0, -1.0) * texel).rgb;
+    vec3 rgbNE = texture(inputTexture, TexCoords + vec2( 1.0, -1.0) * texel).rgb;
+    vec3 rgbSW = texture(inputTexture, TexCoords + vec2(-1.0,  1.0) * texel).rgb;
+    vec3 rgbSE = texture(inputTexture, TexCoords + vec2( 1.0,  1.0) * texel).rgb;
+    vec4 rgbaM = texture(inputTexture, TexCoords);
+
+    float lumaNW = dot(rgbNW, luma);
+    float lumaNE = dot(rgbNE, luma);
+    float lumaSW = dot(rgbSW, luma);
+    float lumaSE = dot(rgbSE, luma);
+    float lumaM = dot(rgbaM.rgb, luma);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // direction perpendicular to the local luma gradient, i.e. along the edge
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+         ((lumaNW + lumaSW) - (lumaNE + lumaSE))
+    );
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 rgbA = 0.5 * (
+        texture(inputTexture, TexCoords + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(inputTexture, TexCoords + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        texture(inputTexture, TexCoords + dir * -0.5).rgb +
+        texture(inputTexture, TexCoords + dir * 0.5).rgb);
+
+    float lumaB = dot(rgbB, luma);
+    vec3 color = (lumaB < lumaMin || lumaB > lumaMax) ? rgbA : rgbB;
+    FragColor = vec4(color, rgbaM.a);
+}
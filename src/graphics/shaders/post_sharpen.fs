@@ -0,0 +1,22 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D inputTexture;
+uniform vec2 resolution;
+uniform float amount;
+
+void main() {
+    vec2 texel = 1.0 / resolution;
+    vec4 center = texture(inputTexture, TexCoords);
+    vec3 neighbours =
+        texture(inputTexture, TexCoords + vec2(texel.x, 0.0)).rgb +
+        texture(inputTexture, TexCoords - vec2(texel.x, 0.0)).rgb +
+        texture(inputTexture, TexCoords + vec2(0.0, texel.y)).rgb +
+        texture(inputTexture, TexCoords - vec2(0.0, texel.y)).rgb;
+
+    // unsharp mask
+    vec3 color = center.rgb * (1.0 + 4.0 * amount) - neighbours * amount;
+    FragColor = vec4(clamp(color, 0.0, 1.0), center.a);
+}
@@ -0,0 +1,17 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D inputTexture;
+uniform float intensity;
+uniform float radius;
+uniform float smoothness;
+
+void main() {
+    vec4 color = texture(inputTexture, TexCoords);
+    // 0 at the center, 1 in the corners
+    float dist = length(TexCoords - 0.5) * 1.41421356;
+    float vignette = smoothstep(radius, radius - smoothness, dist);
+    FragColor = vec4(color.rgb * mix(1.0, vignette, intensity), color.a);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+
+in vec2 TexCoords;
+
+struct Material {
+    float specular;
+    float shininess;
+    float opacity;
+    float alphaCutoff;
+};
+
+uniform sampler2D texture_diffuse1;
+uniform Material material;
+
+void main() {
+    // cutout texels don't cast shadows, depth is written by the rasterizer
+    if (texture(texture_diffuse1, TexCoords).a < material.alphaCutoff) {
+        discard;
+    }
+}
@@ -0,0 +1,59 @@
+#version 330 core
+layout (location = 0) out float Occlusion;
+
+in vec2 TexCoords;
+
+// must match MAX_KERNEL_SIZE in ssao.rs
+const int MAX_KERNEL_SIZE = 64;
+
+uniform sampler2D normalTexture;
+uniform sampler2D depthTexture;
+uniform sampler2D noiseTexture;
+
+uniform vec3 samples[MAX_KERNEL_SIZE];
+uniform int kernelSize;
+uniform float radius;
+uniform float bias;
+uniform float power;
+uniform vec2 noiseScale;
+#include "include/camera.glsl"
+
+vec3 viewPosition(vec2 uv) {
+    float depth = texture(depthTexture, uv).r;
+    vec4 ndc = vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    vec4 position = inverseProjection * ndc;
+    return position.xyz / position.w;
+}
+
+void main() {
+    // nothing to occlude on the sky
+    if (texture(depthTexture, TexCoords).r >= 1.0) {
+        Occlusion = 1.0;
+        return;
+    }
+
+    vec3 fragPos = viewPosition(TexCoords);
+    vec3 normal = normalize(texture(normalTexture, TexCoords).xyz);
+    vec3 randomVec = normalize(texture(noiseTexture, TexCoords * noiseScale).xyz);
+
+    // TBN from tangent space to view space, randomly rotated around the normal
+    vec3 tangent = normalize(randomVec - normal * dot(randomVec, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 TBN = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < kernelSize; ++i) {
+        vec3 samplePos = fragPos + TBN * samples[i] * radius;
+
+        // project the sample to find the depth of the surface in front of it
+        vec4 offset = projection * vec4(samplePos, 1.0);
+        offset.xyz = offset.xyz / offset.w * 0.5 + 0.5;
+        float sampleDepth = viewPosition(offset.xy).z;
+
+        // ignore occluders far away from the fragment
+        float rangeCheck = smoothstep(0.0, 1.0, radius / abs(fragPos.z - sampleDepth));
+        occlusion += (sampleDepth >= samplePos.z + bias ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    Occlusion = pow(1.0 - occlusion / float(kernelSize), power);
+}
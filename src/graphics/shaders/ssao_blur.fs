@@ -0,0 +1,34 @@
+#version 330 core
+layout (location = 0) out float Occlusion;
+
+in vec2 TexCoords;
+
+uniform sampler2D ssaoInput;
+uniform sampler2D depthTexture;
+uniform float near;
+uniform float far;
+uniform float depthSigma;
+
+float linearDepth(vec2 uv) {
+    float z = texture(depthTexture, uv).r * 2.0 - 1.0;
+    return (2.0 * near * far) / (far + near - z * (far - near));
+}
+
+void main() {
+    // 4x4 box matching the noise tile, weighted by depth similarity
+    vec2 texel = 1.0 / vec2(textureSize(ssaoInput, 0));
+    float centerDepth = linearDepth(TexCoords);
+
+    float result = 0.0;
+    float weightSum = 0.0;
+    for (int x = -2; x < 2; ++x) {
+        for (int y = -2; y < 2; ++y) {
+            vec2 uv = TexCoords + (vec2(x, y) + 0.5) * texel;
+            float difference = linearDepth(uv) - centerDepth;
+            float weight = exp(-(difference * difference) / (2.0 * depthSigma * depthSigma));
+            result += texture(ssaoInput, uv).r * weight;
+            weightSum += weight;
+        }
+    }
+    Occlusion = result / max(weightSum, 1e-5);
+}
@@ -0,0 +1,22 @@
+#version 330 core
+layout (location = 0) out vec3 NormalOut;
+
+in vec3 ViewNormal;
+in vec2 TexCoords;
+
+struct Material {
+    float specular;
+    float shininess;
+    float opacity;
+    float alphaCutoff;
+};
+
+uniform sampler2D texture_diffuse1;
+uniform Material material;
+
+void main() {
+    if (texture(texture_diffuse1, TexCoords).a < material.alphaCutoff) {
+        discard;
+    }
+    NormalOut = normalize(ViewNormal);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+
+out vec3 ViewNormal;
+out vec2 TexCoords;
+
+#include "include/object.glsl"
+#include "include/camera.glsl"
+
+void main() {
+    mat4 modelView = view * model;
+    TexCoords = aTexCoords;
+    // the view matrix is a rotation and a translation, its own inverse transpose
+    ViewNormal = mat3(view) * mat3(normalMatrix) * aNormal;
+    gl_Position = projection * modelView * vec4(aPos, 1.0);
+}
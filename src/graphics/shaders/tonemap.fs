@@ -0,0 +1,82 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+// must match the values of Tonemapper in hdr.rs
+const int TONEMAP_REINHARD = 0;
+const int TONEMAP_ACES = 1;
+const int TONEMAP_AGX = 2;
+
+uniform sampler2D hdrBuffer;
+uniform sampler2D bloomBuffer;
+uniform bool bloom;
+uniform float bloomIntensity;
+uniform bool tonemapping;
+uniform float exposure;
+uniform int tonemapper;
+
+vec3 reinhard(vec3 x) {
+    return x / (x + vec3(1.0));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+// minimal AgX with a polynomial fit of the default contrast curve, the result is already display encoded
+vec3 agxContrast(vec3 x) {
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+vec3 agx(vec3 x) {
+    const mat3 agxInset = mat3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    const mat3 agxOutset = mat3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    const float minEv = -12.47393;
+    const float maxEv = 4.026069;
+
+    x = agxInset * max(x, vec3(1e-10));
+    x = clamp(log2(x), minEv, maxEv);
+    x = (x - minEv) / (maxEv - minEv);
+    return clamp(agxOutset * agxContrast(x), 0.0, 1.0);
+}
+
+void main() {
+    vec3 hdr = texture(hdrBuffer, TexCoords).rgb;
+    if (!tonemapping) {
+        FragColor = vec4(pow(clamp(hdr, 0.0, 1.0), vec3(1.0 / 2.2)), 1.0);
+        return;
+    }
+
+    if (bloom) {
+        hdr += texture(bloomBuffer, TexCoords).rgb * bloomIntensity;
+    }
+    hdr *= exposure;
+
+    vec3 color;
+    if (tonemapper == TONEMAP_AGX) {
+        color = agx(hdr);
+    } else {
+        vec3 mapped = tonemapper == TONEMAP_ACES ? aces(hdr) : reinhard(hdr);
+        // gamma correction
+        color = pow(mapped, vec3(1.0 / 2.2));
+    }
+
+    FragColor = vec4(color, 1.0);
+}
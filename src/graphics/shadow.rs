@@ -0,0 +1,258 @@
+use std::ffi::{ CStr, CString };
+use std::ptr;
+
+use cgmath::{ vec3, vec4, Deg, EuclideanSpace, Matrix4, Point3, Vector3, Vector4 };
+use cgmath::{ ortho, perspective };
+use cgmath::prelude::*;
+use gl;
+
+use crate::graphics::camera::Camera;
+use crate::graphics::device::{ GlDevice, UniformValue };
+use crate::graphics::instancing::InstanceBuffer;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::shader::Shader;
+use crate::graphics::state_tracker::{ StateStats, StateTracker };
+use crate::world::scene::Scene;
+
+// must match MAX_CASCADES in model.fs
+pub const MAX_CASCADES: usize = 4;
+
+// texture unit the cascade array is bound to, far above the units used by mesh materials
+pub const SHADOW_MAP_UNIT: u32 = 10;
+
+pub struct CascadeConfig {
+    // number of cascades the view frustum is split into (1..=MAX_CASCADES)
+    pub cascade_count: usize,
+    // blend between a uniform (0.0) and a logarithmic (1.0) split scheme
+    pub split_lambda: f32,
+    // shadows are not rendered past this distance from the camera
+    pub shadow_distance: f32,
+    // width and height of every cascade in texels
+    pub resolution: i32,
+    // fraction of each cascade, at its far end, used to blend into the next one
+    pub blend_fraction: f32,
+    // extra depth in front of each cascade so casters outside of the view frustum still cast shadows
+    pub caster_margin: f32,
+    // tint every cascade with its own colour in the model pass
+    pub debug: bool
+}
+
+impl Default for CascadeConfig {
+    fn default() -> Self {
+        CascadeConfig {
+            cascade_count: 4,
+            split_lambda: 0.75,
+            shadow_distance: 100.0,
+            resolution: 2048,
+            blend_fraction: 0.1,
+            caster_margin: 50.0,
+            debug: false
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Cascade {
+    // view-space distance at which this cascade ends
+    pub split_far: f32,
+    pub light_space: Matrix4<f32>
+}
+
+pub struct CascadedShadowMap {
+    pub config: CascadeConfig,
+    pub cascades: Vec<Cascade>,
+    fbo: GpuHandle,
+    depth_texture: GpuHandle,
+    // cascadeSplits[i] and lightSpaceMatrices[i], converted once rather than every frame
+    split_uniforms: Vec<CString>,
+    matrix_uniforms: Vec<CString>
+}
+
+// Computes the far distance of every cascade, blending the logarithmic and uniform split schemes
+// with `lambda` (the "practical" split scheme).
+pub fn compute_split_distances(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    (1..=count)
+        .map(|i| {
+            let p = i as f32 / count as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            lambda * log + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+// Returns the eight world-space corners of the camera frustum slice between `near` and `far`
+pub fn frustum_corners(camera: &Camera, aspect: f32, near: f32, far: f32) -> [Vector3<f32>; 8] {
+    let projection = perspective(Deg(camera.zoom), aspect, near, far);
+    let inverse = (projection * camera.get_view_matrix())
+        .invert()
+        .expect("Camera view-projection matrix is not invertible");
+
+    let mut corners = [Vector3::zero(); 8];
+    let mut i = 0;
+    for &x in &[-1.0, 1.0] {
+        for &y in &[-1.0, 1.0] {
+            for &z in &[-1.0, 1.0] {
+                let point: Vector4<f32> = inverse * vec4(x, y, z, 1.0);
+                corners[i] = point.truncate() / point.w;
+                i += 1;
+            }
+        }
+    }
+    corners
+}
+
+// Builds a light-space matrix enclosing the given frustum corners. The cascade is fitted to a bounding sphere
+// so its size doesn't change when the camera rotates, and its origin is snapped to whole shadow map texels
+// so the shadows don't shimmer when the camera moves.
+pub fn fit_cascade(corners: &[Vector3<f32>; 8], light_direction: Vector3<f32>, resolution: i32, caster_margin: f32) -> Matrix4<f32> {
+    let center = corners.iter().fold(Vector3::zero(), |acc, c| acc + c) / 8.0;
+    let radius = corners.iter()
+        .map(|c| (c - center).magnitude())
+        .fold(0.0f32, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = light_direction.normalize();
+    let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+    let eye = center - direction * (radius + caster_margin);
+    let light_view = Matrix4::look_at(Point3::from_vec(eye), Point3::from_vec(center), up);
+    let mut light_projection = ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius + caster_margin);
+
+    // texel snapping: move the projection so the world origin always lands on a texel corner
+    let half_resolution = resolution as f32 / 2.0;
+    let origin = (light_projection * light_view) * vec4(0.0, 0.0, 0.0, 1.0);
+    let origin = vec3(origin.x * half_resolution, origin.y * half_resolution, 0.0);
+    let offset = vec3(origin.x.round() - origin.x, origin.y.round() - origin.y, 0.0) / half_resolution;
+    light_projection.w.x += offset.x;
+    light_projection.w.y += offset.y;
+
+    light_projection * light_view
+}
+
+impl CascadedShadowMap {
+    pub fn new(config: CascadeConfig) -> CascadedShadowMap {
+        assert!(
+            config.cascade_count >= 1 && config.cascade_count <= MAX_CASCADES,
+            "cascade_count must be between 1 and {}", MAX_CASCADES
+        );
+
+        let mut shadow_map = CascadedShadowMap {
+            config,
+            cascades: Vec::new(),
+            fbo: GpuHandle::default(),
+            depth_texture: GpuHandle::default(),
+            split_uniforms: (0..MAX_CASCADES).map(|i| CString::new(format!("cascadeSplits[{}]", i)).unwrap()).collect(),
+            matrix_uniforms: (0..MAX_CASCADES).map(|i| CString::new(format!("lightSpaceMatrices[{}]", i)).unwrap()).collect()
+        };
+        unsafe { shadow_map.setup_framebuffer() }
+        shadow_map
+    }
+
+    unsafe fn setup_framebuffer(&mut self) {
+        let resolution = self.config.resolution;
+        let device = GlDevice::current();
+
+        let mut depth_texture = 0;
+        gl::GenTextures(1, &mut depth_texture);
+        gl::BindTexture(gl::TEXTURE_2D_ARRAY, depth_texture);
+        gl::TexImage3D(
+            gl::TEXTURE_2D_ARRAY,
+            0,
+            gl::DEPTH_COMPONENT32F as i32,
+            resolution,
+            resolution,
+            MAX_CASCADES as i32,
+            0,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            ptr::null()
+        );
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+        // everything outside of a cascade is lit
+        let border_color = [1.0f32, 1.0, 1.0, 1.0];
+        gl::TexParameterfv(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BORDER_COLOR, border_color.as_ptr());
+        self.depth_texture = GpuHandle::new(&device, ResourceKind::TEXTURE, depth_texture, "shadow cascades");
+
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, depth_texture, 0, 0);
+        self.fbo = GpuHandle::new(&device, ResourceKind::FRAMEBUFFER, fbo, "shadow framebuffer");
+        // depth only, no color buffer
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            println!("ERROR::FRAMEBUFFER:: Shadow map framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    // recompute the split distances and light-space matrices for the current camera
+    pub fn update(&mut self, camera: &Camera, aspect: f32, light_direction: Vector3<f32>) {
+        let near = camera.near;
+        let far = camera.far.min(self.config.shadow_distance);
+        let splits = compute_split_distances(near, far, self.config.cascade_count, self.config.split_lambda);
+
+        self.cascades.clear();
+        let mut split_near = near;
+        for split_far in splits {
+            let corners = frustum_corners(camera, aspect, split_near, split_far);
+            self.cascades.push(Cascade {
+                split_far,
+                light_space: fit_cascade(&corners, light_direction, self.config.resolution, self.config.caster_margin)
+            });
+            split_near = split_far;
+        }
+    }
+
+    // render the depth of the whole scene into every cascade
+    pub unsafe fn render(&self, scene: &mut Scene, shader: &Shader, instances: &InstanceBuffer) -> StateStats {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo.id());
+        gl::Viewport(0, 0, self.config.resolution, self.config.resolution);
+        gl::Enable(gl::POLYGON_OFFSET_FILL);
+        gl::PolygonOffset(2.0, 4.0);
+
+        shader.use_program();
+        let mut batches = scene.collect_batches(|_| true);
+        scene.sort_batches(&mut batches, shader.id);
+        let mut device = GlDevice::current();
+        let mut tracker = StateTracker::new(&mut device);
+        for (i, cascade) in self.cascades.iter().enumerate() {
+            gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.depth_texture.id(), 0, i as i32);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+            shader.set_mat4(c_str!("lightSpaceMatrix"), &cascade.light_space);
+            scene.draw_batches_with(&mut tracker, shader, instances, &batches);
+        }
+
+        gl::Disable(gl::POLYGON_OFFSET_FILL);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        tracker.stats()
+    }
+
+    // bind the cascades and their uniforms on a shader sampling them
+    pub unsafe fn bind(&self, shader: &Shader) {
+        shader.use_program();
+
+        gl::ActiveTexture(gl::TEXTURE0 + SHADOW_MAP_UNIT);
+        gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.depth_texture.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        shader.set_int(c_str!("shadowMap"), SHADOW_MAP_UNIT as i32);
+        shader.set_int(c_str!("cascadeCount"), self.cascades.len() as i32);
+        shader.set_float(c_str!("cascadeBlendFraction"), self.config.blend_fraction);
+        shader.set_bool(c_str!("debugCascades"), self.config.debug);
+        let mut device = GlDevice::current();
+        for (i, cascade) in self.cascades.iter().enumerate() {
+            shader.set_with(&mut device, &self.split_uniforms[i], UniformValue::FLOAT(cascade.split_far));
+            shader.set_with(&mut device, &self.matrix_uniforms[i], UniformValue::MAT4(cascade.light_space.into()));
+        }
+    }
+
+    pub fn toggle_debug(&mut self) {
+        self.config.debug = !self.config.debug;
+    }
+}
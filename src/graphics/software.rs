@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+use cgmath::{ vec2, vec3, vec4, Matrix, Matrix3, Matrix4, SquareMatrix, Vector2, Vector3, Vector4 };
+use cgmath::prelude::*;
+use image::{ Rgba, RgbaImage };
+
+use crate::graphics::camera::Camera;
+use crate::graphics::device::*;
+use crate::graphics::hdr::{ target_exposure, Exposure, HdrSettings, LuminanceHistogram, Tonemapper };
+use crate::graphics::material::{ BlendMode, Material, RenderQueue };
+use crate::graphics::mesh::Mesh;
+use crate::world::light::{ DirectionalLight, PointLight, SpotLight };
+use crate::world::frustum::Frustum;
+use crate::world::scene::{ DrawItem, Scene };
+
+// same as the clear color of the opaque pass
+const CLEAR_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+
+// A texture kept in memory as linear floats, with its mip chain when it was created with mipmaps
+pub struct SoftwareTexture {
+    levels: Vec<MipLevel>,
+    wrap: WrapMode
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Vector4<f32>>
+}
+
+impl SoftwareTexture {
+    pub fn new(data: &TextureData, sampler: Sampler) -> SoftwareTexture {
+        let channels = data.format.channels();
+        let srgb = matches!(data.format, PixelFormat::SRGB8 | PixelFormat::SRGB8_ALPHA8);
+
+        // missing channels read like they do on the GPU: green and blue 0, alpha 1
+        let texels = data.pixels.chunks_exact(channels)
+            .map(|texel| {
+                let color = |i: usize| texel.get(i).map_or(0.0, |&c| {
+                    let c = c as f32 / 255.0;
+                    if srgb { srgb_to_linear(c) } else { c }
+                });
+                let alpha = if channels == 4 { texel[3] as f32 / 255.0 } else { 1.0 };
+                vec4(color(0), color(1), color(2), alpha)
+            })
+            .collect();
+
+        let mut levels = vec![MipLevel { width: data.width.max(1) as usize, height: data.height.max(1) as usize, texels }];
+        if sampler.mipmaps {
+            while levels.last().is_some_and(|level| level.width > 1 || level.height > 1) {
+                let next = levels.last().unwrap().downsample();
+                levels.push(next);
+            }
+        }
+        SoftwareTexture { levels, wrap: sampler.wrap }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    // trilinear sample, v = 0 is the first row of the uploaded data
+    pub fn sample(&self, uv: Vector2<f32>, lod: f32) -> Vector4<f32> {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f32);
+        let base = lod.floor() as usize;
+        let color = self.levels[base].sample(uv, self.wrap);
+        let t = lod - base as f32;
+        if t > 0.0 {
+            color.lerp(self.levels[base + 1].sample(uv, self.wrap), t)
+        } else {
+            color
+        }
+    }
+
+    // mip level to sample for the given texture coordinate derivatives along x and y
+    pub fn lod(&self, duv_dx: Vector2<f32>, duv_dy: Vector2<f32>) -> f32 {
+        let size = vec2(self.width() as f32, self.height() as f32);
+        let dx = vec2(duv_dx.x * size.x, duv_dx.y * size.y);
+        let dy = vec2(duv_dy.x * size.x, duv_dy.y * size.y);
+        dx.magnitude2().max(dy.magnitude2()).max(1e-12).log2() * 0.5
+    }
+}
+
+impl MipLevel {
+    fn texel(&self, x: i64, y: i64, wrap: WrapMode) -> Vector4<f32> {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let (x, y) = match wrap {
+            WrapMode::REPEAT => (x.rem_euclid(width), y.rem_euclid(height)),
+            WrapMode::CLAMP_TO_EDGE => (x.clamp(0, width - 1), y.clamp(0, height - 1))
+        };
+        self.texels[(y * width + x) as usize]
+    }
+
+    // bilinear
+    fn sample(&self, uv: Vector2<f32>, wrap: WrapMode) -> Vector4<f32> {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0, wrap).lerp(self.texel(x0 + 1, y0, wrap), fx);
+        let bottom = self.texel(x0, y0 + 1, wrap).lerp(self.texel(x0 + 1, y0 + 1, wrap), fx);
+        top.lerp(bottom, fy)
+    }
+
+    // 2x2 box filter, the last row or column is repeated for odd sizes
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = (x as i64 * 2, y as i64 * 2);
+                let sum = self.texel(x, y, WrapMode::CLAMP_TO_EDGE) + self.texel(x + 1, y, WrapMode::CLAMP_TO_EDGE)
+                    + self.texel(x, y + 1, WrapMode::CLAMP_TO_EDGE) + self.texel(x + 1, y + 1, WrapMode::CLAMP_TO_EDGE);
+                texels.push(sum * 0.25);
+            }
+        }
+        MipLevel { width, height, texels }
+    }
+}
+
+// Six faces in the +X, -X, +Y, -Y, +Z, -Z order
+pub struct SoftwareCubemap {
+    faces: Vec<SoftwareTexture>
+}
+
+impl SoftwareCubemap {
+    // face selection of the GL specification, table 8.19
+    pub fn sample(&self, direction: Vector3<f32>) -> Vector4<f32> {
+        let abs = vec3(direction.x.abs(), direction.y.abs(), direction.z.abs());
+        let (face, sc, tc, major) = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x > 0.0 { (0, -direction.z, -direction.y, abs.x) } else { (1, direction.z, -direction.y, abs.x) }
+        } else if abs.y >= abs.z {
+            if direction.y > 0.0 { (2, direction.x, direction.z, abs.y) } else { (3, direction.x, -direction.z, abs.y) }
+        } else if direction.z > 0.0 {
+            (4, direction.x, -direction.y, abs.z)
+        } else {
+            (5, -direction.x, -direction.y, abs.z)
+        };
+        let uv = vec2((sc / major + 1.0) * 0.5, (tc / major + 1.0) * 0.5);
+        match self.faces.get(face) {
+            Some(texture) => texture.sample(uv, 0.0),
+            None => vec4(0.0, 0.0, 0.0, 1.0)
+        }
+    }
+}
+
+// A RenderDevice keeping everything in memory so scenes can be created without a GPU and drawn
+// by the SoftwareRasterizer. Draw calls are accepted and ignored.
+#[derive(Default)]
+pub struct SoftwareDevice {
+    id: DeviceId,
+    buffers: HashMap<u32, Vec<u8>>,
+    textures: HashMap<u32, SoftwareTexture>,
+    cubemaps: HashMap<u32, SoftwareCubemap>,
+    last_handle: u32
+}
+
+impl SoftwareDevice {
+    pub fn new() -> SoftwareDevice {
+        SoftwareDevice::default()
+    }
+
+    pub fn buffer(&self, buffer: u32) -> Option<&[u8]> {
+        self.buffers.get(&buffer).map(|data| data.as_slice())
+    }
+
+    pub fn texture(&self, texture: u32) -> Option<&SoftwareTexture> {
+        self.textures.get(&texture)
+    }
+
+    pub fn cubemap(&self, texture: u32) -> Option<&SoftwareCubemap> {
+        self.cubemaps.get(&texture)
+    }
+
+    fn handle(&mut self) -> u32 {
+        self.last_handle += 1;
+        self.last_handle
+    }
+}
+
+impl RenderDevice for SoftwareDevice {
+    fn device_id(&self) -> DeviceId {
+        self.id
+    }
+
+    fn create_buffer(&mut self, _kind: BufferKind, _usage: BufferUsage, data: &[u8]) -> u32 {
+        let buffer = self.handle();
+        self.buffers.insert(buffer, data.to_vec());
+        buffer
+    }
+
+    fn update_buffer(&mut self, buffer: u32, _kind: BufferKind, offset: usize, data: &[u8]) {
+        if let Some(content) = self.buffers.get_mut(&buffer) {
+            let end = (offset + data.len()).min(content.len());
+            content[offset..end].copy_from_slice(&data[..end - offset]);
+        }
+    }
+
+    fn delete_buffer(&mut self, buffer: u32) {
+        self.buffers.remove(&buffer);
+    }
+
+    fn create_texture(&mut self, data: &TextureData, sampler: Sampler) -> u32 {
+        let texture = self.handle();
+        self.textures.insert(texture, SoftwareTexture::new(data, sampler));
+        texture
+    }
+
+    fn create_cubemap(&mut self, faces: &[TextureData]) -> u32 {
+        let texture = self.handle();
+        let sampler = Sampler { wrap: WrapMode::CLAMP_TO_EDGE, mipmaps: false };
+        let faces = faces.iter().map(|face| SoftwareTexture::new(face, sampler)).collect();
+        self.cubemaps.insert(texture, SoftwareCubemap { faces });
+        texture
+    }
+
+    fn delete_texture(&mut self, texture: u32) {
+        self.textures.remove(&texture);
+        self.cubemaps.remove(&texture);
+    }
+
+    fn create_vertex_array(&mut self, _vertex_buffer: u32, _index_buffer: Option<u32>, _stride: i32, _attributes: &[VertexAttribute]) -> u32 {
+        self.handle()
+    }
+
+    fn delete_vertex_array(&mut self, _vertex_array: u32) {}
+
+    fn delete_framebuffer(&mut self, _framebuffer: u32) {}
+
+    fn set_instance_buffer(&mut self, _vertex_array: u32, _buffer: u32, _offset: usize, _stride: i32, _attributes: &[VertexAttribute]) {}
+
+    fn create_shader(&mut self, _stage: ShaderStage, _source: &str) -> Result<u32, String> {
+        Ok(self.handle())
+    }
+
+    fn delete_shader(&mut self, _shader: u32) {}
+
+    fn create_program(&mut self, _shaders: &[u32]) -> Result<u32, String> {
+        Ok(self.handle())
+    }
+
+    fn delete_program(&mut self, _program: u32) {}
+    fn use_program(&mut self, _program: u32) {}
+    fn set_state(&mut self, _state: &PipelineState) {}
+    fn set_uniform(&mut self, _program: u32, _name: &CStr, _value: UniformValue) {}
+    fn set_uniform_location(&mut self, _program: u32, _location: i32, _value: UniformValue) {}
+    fn bind_texture(&mut self, _unit: u32, _target: TextureTarget, _texture: u32) {}
+    fn bind_uniform_buffer(&mut self, _binding: u32, _buffer: u32, _offset: usize, _size: usize) {}
+    fn bind_uniform_block(&mut self, _program: u32, _block: u32, _binding: u32) {}
+    fn draw_arrays(&mut self, _vertex_array: u32, _first: i32, _count: i32) {}
+    fn draw_elements(&mut self, _vertex_array: u32, _count: i32) {}
+    fn draw_elements_instanced(&mut self, _vertex_array: u32, _count: i32, _instances: i32) {}
+    fn draw_patches(&mut self, _vertex_array: u32, _count: i32, _patch_vertices: i32) {}
+    fn bind_storage_buffer(&mut self, _binding: u32, _buffer: u32) {}
+    fn bind_image(&mut self, _unit: u32, _texture: u32, _access: ImageAccess, _format: ImageFormat) {}
+    fn dispatch_compute(&mut self, _groups: [u32; 3]) {}
+    fn memory_barrier(&mut self) {}
+}
+
+// what the vertex stage of model.vs passes to the fragment stage
+#[derive(Clone, Copy)]
+struct Varyings {
+    world_position: Vector3<f32>,
+    normal: Vector3<f32>,
+    tex_coords: Vector2<f32>
+}
+
+impl Varyings {
+    fn lerp(&self, other: &Varyings, t: f32) -> Varyings {
+        Varyings {
+            world_position: self.world_position.lerp(other.world_position, t),
+            normal: self.normal.lerp(other.normal, t),
+            tex_coords: self.tex_coords.lerp(other.tex_coords, t)
+        }
+    }
+
+    fn weighted(v: &[Varyings; 3], w: [f32; 3]) -> Varyings {
+        Varyings {
+            world_position: v[0].world_position * w[0] + v[1].world_position * w[1] + v[2].world_position * w[2],
+            normal: v[0].normal * w[0] + v[1].normal * w[1] + v[2].normal * w[2],
+            tex_coords: v[0].tex_coords * w[0] + v[1].tex_coords * w[1] + v[2].tex_coords * w[2]
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClipVertex {
+    position: Vector4<f32>,
+    varyings: Varyings
+}
+
+// Renders scenes on the CPU with the forward path of the renderer: opaque and cutout meshes, the skybox,
+// then the transparent meshes blended back to front, and finally tonemapping. Shadows, SSAO, bloom and
+// post-processing are left out.
+pub struct SoftwareRasterizer {
+    pub width: usize,
+    pub height: usize,
+    // linear HDR colors, rows top to bottom
+    color: Vec<Vector4<f32>>,
+    // window space depth, 1 at the far plane
+    depth: Vec<f32>
+}
+
+impl SoftwareRasterizer {
+    pub fn new(width: usize, height: usize) -> SoftwareRasterizer {
+        let (width, height) = (width.max(1), height.max(1));
+        SoftwareRasterizer {
+            width,
+            height,
+            color: vec![Vector4::from(CLEAR_COLOR); width * height],
+            depth: vec![1.0; width * height]
+        }
+    }
+
+    // depth buffer of the last frame, rows top to bottom
+    pub fn depth(&self) -> &[f32] {
+        &self.depth
+    }
+
+    pub fn render(&mut self, device: &SoftwareDevice, scene: &Scene, camera: &Camera) -> RgbaImage {
+        for color in self.color.iter_mut() {
+            *color = Vector4::from(CLEAR_COLOR);
+        }
+        for depth in self.depth.iter_mut() {
+            *depth = 1.0;
+        }
+
+        let projection = camera.get_projection_matrix(self.width as f32 / self.height as f32);
+        let view = camera.get_view_matrix();
+        let view_position = camera.position.to_vec();
+        let culling = scene.cull(&Frustum::from_matrix(&(projection * view)));
+
+        let opaque = PipelineState::default();
+        for queue in &[RenderQueue::OPAQUE, RenderQueue::ALPHA_TEST] {
+            for item in scene.collect_visible_queue(*queue, view_position, &culling) {
+                self.draw_item(device, scene, &item, &projection, &view, view_position, &opaque);
+            }
+        }
+
+        if let Some(cubemap) = device.cubemap(scene.skybox.texture()) {
+            self.draw_skybox(cubemap, &projection, &view);
+        }
+
+        for item in scene.collect_visible_queue(RenderQueue::TRANSPARENT, view_position, &culling) {
+            let blend = mesh_of(scene, &item).material.blend_mode;
+            let state = PipelineState { depth_write: false, blend, ..PipelineState::default() };
+            self.draw_item(device, scene, &item, &projection, &view, view_position, &state);
+        }
+
+        self.resolve(&camera.hdr)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_item(&mut self, device: &SoftwareDevice, scene: &Scene, item: &DrawItem, projection: &Matrix4<f32>,
+                 view: &Matrix4<f32>, view_position: Vector3<f32>, state: &PipelineState) {
+        let mesh = mesh_of(scene, item);
+        let normal_matrix = match Matrix3::from_cols(item.model_matrix.x.truncate(), item.model_matrix.y.truncate(), item.model_matrix.z.truncate()).invert() {
+            Some(inverse) => inverse.transpose(),
+            None => return
+        };
+        let view_projection = projection * view;
+        let diffuse = mesh.textures.iter()
+            .find(|texture| texture.type_ == "texture_diffuse")
+            .and_then(|texture| device.texture(texture.id));
+
+        let vertices: Vec<ClipVertex> = mesh.vertices.iter()
+            .map(|vertex| {
+                let world = item.model_matrix * vertex.position.extend(1.0);
+                ClipVertex {
+                    position: view_projection * world,
+                    varyings: Varyings {
+                        world_position: world.truncate(),
+                        normal: normal_matrix * vertex.normal,
+                        tex_coords: vertex.tex_coords
+                    }
+                }
+            })
+            .collect();
+
+        let shading = Shading { material: &mesh.material, diffuse, scene, view_position };
+        for triangle in mesh.indices.chunks_exact(3) {
+            let corners = [vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]];
+            for clipped in clip_near(&corners) {
+                self.rasterize(&clipped, state, &shading);
+            }
+        }
+    }
+
+    fn rasterize(&mut self, triangle: &[ClipVertex; 3], state: &PipelineState, shading: &Shading) {
+        // to window space, y down to match the image rows
+        let mut screen = [Vector3::zero(); 3];
+        let mut inv_w = [0.0; 3];
+        for (i, vertex) in triangle.iter().enumerate() {
+            inv_w[i] = 1.0 / vertex.position.w;
+            let ndc = vertex.position.truncate() * inv_w[i];
+            screen[i] = vec3(
+                (ndc.x * 0.5 + 0.5) * self.width as f32,
+                (0.5 - ndc.y * 0.5) * self.height as f32,
+                ndc.z * 0.5 + 0.5
+            );
+        }
+
+        let area = edge(screen[0], screen[1], screen[2].x, screen[2].y);
+        if area.abs() < 1e-8 {
+            return;
+        }
+
+        let min_x = screen.iter().map(|p| p.x).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+        let max_x = screen.iter().map(|p| p.x).fold(f32::MIN, f32::max).ceil().min(self.width as f32) as usize;
+        let min_y = screen.iter().map(|p| p.y).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+        let max_y = screen.iter().map(|p| p.y).fold(f32::MIN, f32::max).ceil().min(self.height as f32) as usize;
+
+        // barycentrics of a window position, perspective corrected
+        let barycentrics = |x: f32, y: f32| {
+            let linear = [
+                edge(screen[1], screen[2], x, y) / area,
+                edge(screen[2], screen[0], x, y) / area,
+                edge(screen[0], screen[1], x, y) / area
+            ];
+            let weights = [linear[0] * inv_w[0], linear[1] * inv_w[1], linear[2] * inv_w[2]];
+            let sum = weights[0] + weights[1] + weights[2];
+            (linear, [weights[0] / sum, weights[1] / sum, weights[2] / sum])
+        };
+        let tex_coords = |w: [f32; 3]| {
+            triangle[0].varyings.tex_coords * w[0] + triangle[1].varyings.tex_coords * w[1] + triangle[2].varyings.tex_coords * w[2]
+        };
+        let varyings = [triangle[0].varyings, triangle[1].varyings, triangle[2].varyings];
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let (linear, perspective) = barycentrics(px, py);
+                if linear.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+
+                let depth = linear[0] * screen[0].z + linear[1] * screen[1].z + linear[2] * screen[2].z;
+                let index = y * self.width + x;
+                if !(0.0..=1.0).contains(&depth) || (state.depth_test && !depth_passes(state.depth_func, depth, self.depth[index])) {
+                    continue;
+                }
+
+                // screen space derivatives of the texture coordinates pick the mip level
+                let uv = tex_coords(perspective);
+                let duv_dx = tex_coords(barycentrics(px + 1.0, py).1) - uv;
+                let duv_dy = tex_coords(barycentrics(px, py + 1.0).1) - uv;
+                let fragment = Varyings::weighted(&varyings, perspective);
+
+                let color = match shading.shade(&fragment, duv_dx, duv_dy) {
+                    Some(color) => color,
+                    None => continue
+                };
+                self.color[index] = blend(state.blend, color, self.color[index]);
+                if state.depth_write {
+                    self.depth[index] = depth;
+                }
+            }
+        }
+    }
+
+    // the skybox is drawn at the far plane, so it only covers the pixels nothing else was drawn to
+    fn draw_skybox(&mut self, cubemap: &SoftwareCubemap, projection: &Matrix4<f32>, view: &Matrix4<f32>) {
+        let mut view = *view;
+        view.w = vec4(0.0, 0.0, 0.0, 1.0);
+        let inverse = match (projection * view).invert() {
+            Some(inverse) => inverse,
+            None => return
+        };
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = y * self.width + x;
+                if self.depth[index] < 1.0 {
+                    continue;
+                }
+                let ndc = vec2((x as f32 + 0.5) / self.width as f32 * 2.0 - 1.0, 1.0 - (y as f32 + 0.5) / self.height as f32 * 2.0);
+                let far = inverse * vec4(ndc.x, ndc.y, 1.0, 1.0);
+                let direction = far.truncate() / far.w;
+                self.color[index] = cubemap.sample(direction);
+            }
+        }
+    }
+
+    // tonemap.fs without bloom. Auto exposure is computed from this frame alone, without adaptation.
+    fn resolve(&self, settings: &HdrSettings) -> RgbaImage {
+        let exposure = match settings.exposure {
+            Exposure::MANUAL(exposure) => exposure,
+            Exposure::AUTO { min_log2_luminance, max_log2_luminance, low_percentile, high_percentile, compensation, .. } => {
+                let log_luminance: Vec<f32> = self.color.iter()
+                    .map(|c| (c.x * 0.2126 + c.y * 0.7152 + c.z * 0.0722).max(1e-5).log2())
+                    .collect();
+                let mut histogram = LuminanceHistogram::new(64, min_log2_luminance, max_log2_luminance);
+                histogram.build(&log_luminance);
+                target_exposure(histogram.average_log2(low_percentile, high_percentile), compensation)
+            }
+        };
+
+        let mut image = RgbaImage::new(self.width as u32, self.height as u32);
+        for (i, pixel) in image.pixels_mut().enumerate() {
+            let hdr = self.color[i].truncate();
+            let color = if settings.enabled {
+                tonemap(hdr * exposure, settings.tonemapper)
+            } else {
+                let clamped = hdr.map(|c| c.clamp(0.0, 1.0));
+                clamped.map(|c| c.powf(1.0 / 2.2))
+            };
+            let to_byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+            *pixel = Rgba([to_byte(color.x), to_byte(color.y), to_byte(color.z), 255]);
+        }
+        image
+    }
+}
+
+fn mesh_of<'a>(scene: &'a Scene, item: &DrawItem) -> &'a Mesh {
+    &scene.entities[item.entity].model.as_ref().unwrap().meshes[item.mesh]
+}
+
+// model.fs without shadows, ambient occlusion or clustering: every light is evaluated
+struct Shading<'a> {
+    material: &'a Material,
+    diffuse: Option<&'a SoftwareTexture>,
+    scene: &'a Scene,
+    view_position: Vector3<f32>
+}
+
+impl<'a> Shading<'a> {
+    fn shade(&self, fragment: &Varyings, duv_dx: Vector2<f32>, duv_dy: Vector2<f32>) -> Option<Vector4<f32>> {
+        let albedo = match self.diffuse {
+            Some(texture) => texture.sample(fragment.tex_coords, texture.lod(duv_dx, duv_dy)),
+            None => vec4(1.0, 1.0, 1.0, 1.0)
+        };
+        if albedo.w < self.material.effective_alpha_cutoff() {
+            return None;
+        }
+        let albedo_rgb = albedo.truncate();
+        let normal = fragment.normal.normalize();
+        let view_dir = (self.view_position - fragment.world_position).normalize();
+
+        // ambient and directional light
+        let light: &DirectionalLight = &self.scene.directional_light;
+        let light_dir = -light.direction.normalize();
+        let mut color = albedo_rgb.mul_element_wise(light.color) * light.ambient;
+        color += self.blinn_phong(light_dir, light.color * light.intensity, normal, view_dir, albedo_rgb);
+
+        // point and spot lights
+        for point in &self.scene.point_lights {
+            color += self.local_light(point_as_local(point), fragment, normal, view_dir, albedo_rgb);
+        }
+        for spot in &self.scene.spot_lights {
+            color += self.local_light(spot_as_local(spot), fragment, normal, view_dir, albedo_rgb);
+        }
+
+        Some(color.extend(albedo.w * self.material.opacity))
+    }
+
+    fn local_light(&self, light: LocalLight, fragment: &Varyings, normal: Vector3<f32>, view_dir: Vector3<f32>, albedo: Vector3<f32>) -> Vector3<f32> {
+        let to_light = light.position - fragment.world_position;
+        let distance = to_light.magnitude();
+        if distance >= light.radius || distance <= 0.0 {
+            return Vector3::zero();
+        }
+        let l = to_light / distance;
+        let cone = smoothstep(light.cos_outer, light.cos_inner, (-l).dot(light.direction));
+        let radiance = light.radiance * attenuation(distance, light.radius) * cone;
+        self.blinn_phong(l, radiance, normal, view_dir, albedo)
+    }
+
+    fn blinn_phong(&self, light_dir: Vector3<f32>, radiance: Vector3<f32>, normal: Vector3<f32>, view_dir: Vector3<f32>, albedo: Vector3<f32>) -> Vector3<f32> {
+        let diffuse = normal.dot(light_dir).max(0.0);
+        if diffuse <= 0.0 {
+            return Vector3::zero();
+        }
+        let halfway = (light_dir + view_dir).normalize();
+        let specular = normal.dot(halfway).max(0.0).powf(self.material.shininess) * self.material.specular;
+        (albedo * diffuse + vec3(specular, specular, specular)).mul_element_wise(radiance)
+    }
+}
+
+// point and spot lights share the same evaluation, like in the light texels of clustered.rs
+struct LocalLight {
+    position: Vector3<f32>,
+    radius: f32,
+    radiance: Vector3<f32>,
+    direction: Vector3<f32>,
+    cos_inner: f32,
+    cos_outer: f32
+}
+
+fn point_as_local(light: &PointLight) -> LocalLight {
+    LocalLight {
+        position: light.position,
+        radius: light.radius,
+        radiance: light.color * light.intensity,
+        direction: Vector3::unit_z(),
+        cos_inner: -1.0,
+        cos_outer: -2.0
+    }
+}
+
+fn spot_as_local(light: &SpotLight) -> LocalLight {
+    LocalLight {
+        position: light.position,
+        radius: light.radius,
+        radiance: light.color * light.intensity,
+        direction: light.direction,
+        cos_inner: light.inner_angle.to_radians().cos(),
+        cos_outer: light.outer_angle.to_radians().cos()
+    }
+}
+
+// windowed inverse square falloff reaching zero at the light radius
+fn attenuation(distance: f32, radius: f32) -> f32 {
+    let ratio = distance / radius;
+    let window = (1.0 - ratio * ratio * ratio * ratio).clamp(0.0, 1.0);
+    window * window / (distance * distance + 1.0)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn edge(a: Vector3<f32>, b: Vector3<f32>, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+fn depth_passes(func: DepthFunc, depth: f32, stored: f32) -> bool {
+    match func {
+        DepthFunc::LESS => depth < stored,
+        DepthFunc::LEQUAL => depth <= stored,
+        DepthFunc::ALWAYS => true
+    }
+}
+
+// the fixed-function blending of BlendMode::apply
+fn blend(mode: BlendMode, source: Vector4<f32>, destination: Vector4<f32>) -> Vector4<f32> {
+    let alpha = source.w;
+    match mode {
+        BlendMode::OPAQUE | BlendMode::CUTOUT => source,
+        BlendMode::ALPHA => source * alpha + destination * (1.0 - alpha),
+        BlendMode::PREMULTIPLIED => source + destination * (1.0 - alpha),
+        BlendMode::ADDITIVE => source * alpha + destination
+    }
+}
+
+// clip a triangle against the near plane (z > -w), giving zero, one or two triangles
+fn clip_near(triangle: &[ClipVertex; 3]) -> Vec<[ClipVertex; 3]> {
+    let distance = |v: &ClipVertex| v.position.z + v.position.w;
+    let mut polygon: Vec<ClipVertex> = Vec::with_capacity(4);
+    for i in 0..3 {
+        let current = &triangle[i];
+        let next = &triangle[(i + 1) % 3];
+        let (d0, d1) = (distance(current), distance(next));
+        if d0 >= 0.0 {
+            polygon.push(*current);
+        }
+        if (d0 >= 0.0) != (d1 >= 0.0) {
+            let t = d0 / (d0 - d1);
+            polygon.push(ClipVertex {
+                position: current.position.lerp(next.position, t),
+                varyings: current.varyings.lerp(&next.varyings, t)
+            });
+        }
+    }
+
+    (1..polygon.len().saturating_sub(1))
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+fn tonemap(color: Vector3<f32>, tonemapper: Tonemapper) -> Vector3<f32> {
+    let gamma = |c: Vector3<f32>| c.map(|v| v.max(0.0).powf(1.0 / 2.2));
+    match tonemapper {
+        Tonemapper::REINHARD => gamma(color.map(|x| x / (x + 1.0))),
+        Tonemapper::ACES => gamma(color.map(|x| ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0))),
+        Tonemapper::AGX => agx(color)
+    }
+}
+
+// same fit as tonemap.fs, the result is already display encoded
+#[allow(clippy::excessive_precision)]
+fn agx(color: Vector3<f32>) -> Vector3<f32> {
+    let inset = Matrix3::new(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    let outset = Matrix3::new(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    let (min_ev, max_ev) = (-12.47393f32, 4.026069f32);
+
+    let x = inset * color.map(|c| c.max(1e-10));
+    let x = x.map(|c| (c.log2().clamp(min_ev, max_ev) - min_ev) / (max_ev - min_ev));
+    let contrast = x.map(|x| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    });
+    (outset * contrast).map(|c| c.clamp(0.0, 1.0))
+}
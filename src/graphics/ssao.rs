@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::ffi::{ CStr, CString };
+use std::os::raw::c_void;
+
+use cgmath::{ vec3, Vector3 };
+use cgmath::prelude::*;
+use gl;
+use rand::Rng;
+
+use crate::graphics::camera::Camera;
+use crate::graphics::device::{ GlDevice, UniformValue };
+use crate::graphics::framebuffer::{ Framebuffer, FullscreenTriangle, TextureFormat };
+use crate::graphics::instancing::InstanceBuffer;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+use crate::graphics::shader::{ Shader, ShaderType };
+use crate::graphics::state_tracker::{ StateStats, StateTracker };
+use crate::world::scene::{ Culling, Scene };
+
+// must match MAX_KERNEL_SIZE in ssao.fs
+pub const MAX_KERNEL_SIZE: usize = 64;
+
+// texture unit the blurred occlusion is bound to in the model pass
+pub const SSAO_UNIT: u32 = 11;
+
+const NOISE_SIZE: i32 = 4;
+
+pub struct SsaoConfig {
+    pub enabled: bool,
+    // view-space radius of the sampling hemisphere
+    pub radius: f32,
+    // depth bias avoiding self occlusion on flat surfaces
+    pub bias: f32,
+    // number of kernel samples per pixel (up to MAX_KERNEL_SIZE)
+    pub sample_count: usize,
+    // compute the occlusion at half the screen resolution
+    pub half_resolution: bool,
+    // contrast applied to the final occlusion
+    pub power: f32,
+    // how strongly depth differences stop the blur, in view-space units
+    pub blur_depth_sigma: f32
+}
+
+impl Default for SsaoConfig {
+    fn default() -> Self {
+        SsaoConfig {
+            enabled: true,
+            radius: 0.5,
+            bias: 0.025,
+            sample_count: 32,
+            half_resolution: true,
+            power: 1.5,
+            blur_depth_sigma: 0.1
+        }
+    }
+}
+
+// Generates `count` sample offsets in the unit hemisphere around +Z, denser close to the origin
+pub fn generate_kernel<R: Rng>(count: usize, rng: &mut R) -> Vec<Vector3<f32>> {
+    (0..count)
+        .map(|i| {
+            let sample = vec3(
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>()
+            );
+            let sample = sample.normalize() * rng.gen::<f32>();
+
+            // scale samples so they're more aligned to the center of the kernel
+            let t = i as f32 / count as f32;
+            let scale = 0.1 + 0.9 * t * t;
+            sample * scale
+        })
+        .collect()
+}
+
+pub struct SsaoPass {
+    pub config: SsaoConfig,
+    // view-space normals and depth of the scene
+    geometry: Framebuffer,
+    occlusion: Framebuffer,
+    blurred: Framebuffer,
+    kernel: Vec<Vector3<f32>>,
+    // samples[i], converted once rather than every frame
+    sample_uniforms: Vec<CString>,
+    noise_texture: GpuHandle,
+    fullscreen: FullscreenTriangle,
+    half_resolution: bool
+}
+
+impl SsaoPass {
+    pub fn new(config: SsaoConfig, width: i32, height: i32) -> SsaoPass {
+        let mut rng = rand::thread_rng();
+        let kernel = generate_kernel(MAX_KERNEL_SIZE, &mut rng);
+        let noise_texture = unsafe { create_noise_texture(&mut rng) };
+        let half_resolution = config.half_resolution;
+        let (geometry, occlusion, blurred) = create_targets(width, height, half_resolution);
+
+        SsaoPass {
+            config,
+            geometry,
+            occlusion,
+            blurred,
+            kernel,
+            sample_uniforms: (0..MAX_KERNEL_SIZE).map(|i| CString::new(format!("samples[{}]", i)).unwrap()).collect(),
+            noise_texture,
+            fullscreen: FullscreenTriangle::new(),
+            half_resolution
+        }
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if self.geometry.width == width && self.geometry.height == height && self.half_resolution == self.config.half_resolution {
+            return;
+        }
+
+        self.half_resolution = self.config.half_resolution;
+        let (geometry, occlusion, blurred) = create_targets(width, height, self.half_resolution);
+        self.geometry = geometry;
+        self.occlusion = occlusion;
+        self.blurred = blurred;
+    }
+
+    pub fn toggle(&mut self) {
+        self.config.enabled = !self.config.enabled;
+    }
+
+    // normals/depth prepass, occlusion and bilateral blur
+    pub unsafe fn render(
+        &mut self,
+        scene: &mut Scene,
+        camera: &Camera,
+        instances: &InstanceBuffer,
+        culling: &Culling,
+        shaders: &HashMap<ShaderType, Shader>
+    ) -> StateStats {
+        // pick up a change of half_resolution
+        let (width, height) = (self.geometry.width, self.geometry.height);
+        self.resize(width, height);
+
+        // 1. view-space normals and depth
+        self.geometry.bind();
+        gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        let geometry_shader = get_shader(shaders, ShaderType::SSAO_GEOMETRY);
+        let mut device = GlDevice::current();
+        let mut tracker = StateTracker::new(&mut device);
+        scene.render_visible_with(&mut tracker, geometry_shader, instances, culling, |_| true);
+        let stats = tracker.stats();
+
+        gl::Disable(gl::DEPTH_TEST);
+        let depth_texture = self.geometry.depth_texture().expect("SSAO geometry target has no depth");
+
+        // 2. occlusion
+        self.occlusion.bind();
+        let ssao_shader = get_shader(shaders, ShaderType::SSAO);
+        ssao_shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.geometry.color_texture(0));
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, depth_texture);
+        gl::ActiveTexture(gl::TEXTURE2);
+        gl::BindTexture(gl::TEXTURE_2D, self.noise_texture.id());
+        ssao_shader.set_int(c_str!("normalTexture"), 0);
+        ssao_shader.set_int(c_str!("depthTexture"), 1);
+        ssao_shader.set_int(c_str!("noiseTexture"), 2);
+
+        let sample_count = self.config.sample_count.clamp(1, MAX_KERNEL_SIZE);
+        for (sample, uniform) in self.kernel.iter().zip(&self.sample_uniforms).take(sample_count) {
+            ssao_shader.set_with(&mut device, uniform, UniformValue::VEC3((*sample).into()));
+        }
+        ssao_shader.set_int(c_str!("kernelSize"), sample_count as i32);
+        ssao_shader.set_float(c_str!("radius"), self.config.radius);
+        ssao_shader.set_float(c_str!("bias"), self.config.bias);
+        ssao_shader.set_float(c_str!("power"), self.config.power);
+        ssao_shader.set_vec2(
+            c_str!("noiseScale"),
+            self.occlusion.width as f32 / NOISE_SIZE as f32,
+            self.occlusion.height as f32 / NOISE_SIZE as f32
+        );
+        self.fullscreen.draw();
+
+        // 3. depth aware blur to remove the noise pattern without bleeding over edges
+        self.blurred.bind();
+        let blur_shader = get_shader(shaders, ShaderType::SSAO_BLUR);
+        blur_shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.occlusion.color_texture(0));
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, depth_texture);
+        blur_shader.set_int(c_str!("ssaoInput"), 0);
+        blur_shader.set_int(c_str!("depthTexture"), 1);
+        blur_shader.set_float(c_str!("near"), camera.near);
+        blur_shader.set_float(c_str!("far"), camera.far);
+        blur_shader.set_float(c_str!("depthSigma"), self.config.blur_depth_sigma);
+        self.fullscreen.draw();
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Enable(gl::DEPTH_TEST);
+        stats
+    }
+
+    // bind the blurred occlusion on a shader using it for its ambient term
+    pub unsafe fn bind(&self, shader: &Shader, width: i32, height: i32) {
+        shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0 + SSAO_UNIT);
+        gl::BindTexture(gl::TEXTURE_2D, self.blurred.color_texture(0));
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        shader.set_int(c_str!("ssaoTexture"), SSAO_UNIT as i32);
+        shader.set_bool(c_str!("ssaoEnabled"), self.config.enabled);
+        shader.set_vec2(c_str!("screenSize"), width as f32, height as f32);
+    }
+}
+
+fn create_targets(width: i32, height: i32, half_resolution: bool) -> (Framebuffer, Framebuffer, Framebuffer) {
+    let (ao_width, ao_height) = if half_resolution { (width / 2, height / 2) } else { (width, height) };
+    (
+        Framebuffer::new(width, height, &[TextureFormat::RGB16F], true),
+        Framebuffer::new(ao_width, ao_height, &[TextureFormat::R8], false),
+        Framebuffer::new(ao_width, ao_height, &[TextureFormat::R8], false)
+    )
+}
+
+// small tiled texture of random rotations around the surface normal
+unsafe fn create_noise_texture<R: Rng>(rng: &mut R) -> GpuHandle {
+    let noise: Vec<f32> = (0..NOISE_SIZE * NOISE_SIZE)
+        .flat_map(|_| vec![rng.gen::<f32>() * 2.0 - 1.0, rng.gen::<f32>() * 2.0 - 1.0, 0.0])
+        .collect();
+
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(
+        gl::TEXTURE_2D, 0, gl::RGB16F as i32, NOISE_SIZE, NOISE_SIZE, 0,
+        gl::RGB, gl::FLOAT, noise.as_ptr() as *const c_void
+    );
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+    GpuHandle::new(&GlDevice::current(), ResourceKind::TEXTURE, texture, "SSAO noise")
+}
+
+fn get_shader(shaders: &HashMap<ShaderType, Shader>, shader_type: ShaderType) -> &Shader {
+    shaders.get(&shader_type).expect("ShaderType is not initialized")
+}
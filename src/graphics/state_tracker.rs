@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::ops::AddAssign;
+
+use crate::graphics::device::{ BufferKind, BufferUsage, DeviceFeatures, DeviceId, ImageAccess, ImageFormat, PipelineState, RenderDevice, Sampler,
+                               ShaderStage, TextureData, TextureTarget, UniformValue, VertexAttribute };
+use crate::graphics::reflection::ProgramReflection;
+
+// How many state changes of each kind
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct StateCounts {
+    pub programs: u32,
+    pub pipeline_states: u32,
+    pub textures: u32,
+    pub uniforms: u32,
+    pub uniform_buffers: u32,
+    pub draws: u32
+}
+
+impl StateCounts {
+    // every state change, draws aside
+    pub fn changes(&self) -> u32 {
+        self.programs + self.pipeline_states + self.textures + self.uniforms + self.uniform_buffers
+    }
+}
+
+impl AddAssign for StateCounts {
+    fn add_assign(&mut self, other: StateCounts) {
+        self.programs += other.programs;
+        self.pipeline_states += other.pipeline_states;
+        self.textures += other.textures;
+        self.uniforms += other.uniforms;
+        self.uniform_buffers += other.uniform_buffers;
+        self.draws += other.draws;
+    }
+}
+
+// The state changes asked of a StateTracker and those it let through to the device
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct StateStats {
+    pub requested: StateCounts,
+    pub issued: StateCounts
+}
+
+impl AddAssign for StateStats {
+    fn add_assign(&mut self, other: StateStats) {
+        self.requested += other.requested;
+        self.issued += other.issued;
+    }
+}
+
+// Sits in front of a device and drops the calls setting state that is already set. It only knows what
+// went through it, so it is created per pass, after any GL call made around the device.
+pub struct StateTracker<'a, D: RenderDevice> {
+    device: &'a mut D,
+    program: Option<u32>,
+    state: Option<PipelineState>,
+    textures: HashMap<u32, (TextureTarget, u32)>,
+    // uniforms are program state, they survive switching programs
+    uniforms: HashMap<(u32, i32), UniformValue>,
+    uniform_buffers: HashMap<u32, (u32, usize, usize)>,
+    stats: StateStats
+}
+
+impl<'a, D: RenderDevice> StateTracker<'a, D> {
+    pub fn new(device: &'a mut D) -> StateTracker<'a, D> {
+        StateTracker {
+            device,
+            program: None,
+            state: None,
+            textures: HashMap::new(),
+            uniforms: HashMap::new(),
+            uniform_buffers: HashMap::new(),
+            stats: StateStats::default()
+        }
+    }
+
+    pub fn stats(&self) -> StateStats {
+        self.stats
+    }
+
+    // forget the cached state, e.g. after binding a texture directly through GL
+    pub fn invalidate(&mut self) {
+        self.program = None;
+        self.state = None;
+        self.textures.clear();
+        self.uniforms.clear();
+        self.uniform_buffers.clear();
+    }
+
+    fn count_draw(&mut self) {
+        self.stats.requested.draws += 1;
+        self.stats.issued.draws += 1;
+    }
+}
+
+impl<'a, D: RenderDevice> RenderDevice for StateTracker<'a, D> {
+    fn device_id(&self) -> DeviceId {
+        self.device.device_id()
+    }
+
+    fn features(&mut self) -> DeviceFeatures {
+        self.device.features()
+    }
+
+    fn create_buffer(&mut self, kind: BufferKind, usage: BufferUsage, data: &[u8]) -> u32 {
+        self.device.create_buffer(kind, usage, data)
+    }
+
+    fn update_buffer(&mut self, buffer: u32, kind: BufferKind, offset: usize, data: &[u8]) {
+        self.device.update_buffer(buffer, kind, offset, data);
+    }
+
+    fn delete_buffer(&mut self, buffer: u32) {
+        self.uniform_buffers.retain(|_, range| range.0 != buffer);
+        self.device.delete_buffer(buffer);
+    }
+
+    // creating a texture binds it, what was bound before is unknown afterwards
+    fn create_texture(&mut self, data: &TextureData, sampler: Sampler) -> u32 {
+        self.textures.clear();
+        self.device.create_texture(data, sampler)
+    }
+
+    fn create_cubemap(&mut self, faces: &[TextureData]) -> u32 {
+        self.textures.clear();
+        self.device.create_cubemap(faces)
+    }
+
+    fn delete_texture(&mut self, texture: u32) {
+        self.textures.retain(|_, binding| binding.1 != texture);
+        self.device.delete_texture(texture);
+    }
+
+    fn create_vertex_array(&mut self, vertex_buffer: u32, index_buffer: Option<u32>, stride: i32, attributes: &[VertexAttribute]) -> u32 {
+        self.device.create_vertex_array(vertex_buffer, index_buffer, stride, attributes)
+    }
+
+    fn delete_vertex_array(&mut self, vertex_array: u32) {
+        self.device.delete_vertex_array(vertex_array);
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: u32) {
+        self.device.delete_framebuffer(framebuffer);
+    }
+
+    fn set_instance_buffer(&mut self, vertex_array: u32, buffer: u32, offset: usize, stride: i32, attributes: &[VertexAttribute]) {
+        self.device.set_instance_buffer(vertex_array, buffer, offset, stride, attributes);
+    }
+
+    fn create_shader(&mut self, stage: ShaderStage, source: &str) -> Result<u32, String> {
+        self.device.create_shader(stage, source)
+    }
+
+    fn delete_shader(&mut self, shader: u32) {
+        self.device.delete_shader(shader);
+    }
+
+    fn create_program(&mut self, shaders: &[u32]) -> Result<u32, String> {
+        self.device.create_program(shaders)
+    }
+
+    fn delete_program(&mut self, program: u32) {
+        if self.program == Some(program) {
+            self.program = None;
+        }
+        self.uniforms.retain(|key, _| key.0 != program);
+        self.device.delete_program(program);
+    }
+
+    fn reflect_program(&mut self, program: u32) -> Option<ProgramReflection> {
+        self.device.reflect_program(program)
+    }
+
+    fn use_program(&mut self, program: u32) {
+        self.stats.requested.programs += 1;
+        if self.program != Some(program) {
+            self.stats.issued.programs += 1;
+            self.program = Some(program);
+            self.device.use_program(program);
+        }
+    }
+
+    fn set_state(&mut self, state: &PipelineState) {
+        self.stats.requested.pipeline_states += 1;
+        if self.state != Some(*state) {
+            self.stats.issued.pipeline_states += 1;
+            self.state = Some(*state);
+            self.device.set_state(state);
+        }
+    }
+
+    // the location is unknown, the value always goes through
+    fn set_uniform(&mut self, program: u32, name: &CStr, value: UniformValue) {
+        self.stats.requested.uniforms += 1;
+        self.stats.issued.uniforms += 1;
+        self.device.set_uniform(program, name, value);
+    }
+
+    fn set_uniform_location(&mut self, program: u32, location: i32, value: UniformValue) {
+        self.stats.requested.uniforms += 1;
+        if self.uniforms.get(&(program, location)) != Some(&value) {
+            self.stats.issued.uniforms += 1;
+            self.uniforms.insert((program, location), value);
+            self.device.set_uniform_location(program, location, value);
+        }
+    }
+
+    fn bind_texture(&mut self, unit: u32, target: TextureTarget, texture: u32) {
+        self.stats.requested.textures += 1;
+        if self.textures.get(&unit) != Some(&(target, texture)) {
+            self.stats.issued.textures += 1;
+            self.textures.insert(unit, (target, texture));
+            self.device.bind_texture(unit, target, texture);
+        }
+    }
+
+    fn bind_uniform_buffer(&mut self, binding: u32, buffer: u32, offset: usize, size: usize) {
+        self.stats.requested.uniform_buffers += 1;
+        if self.uniform_buffers.get(&binding) != Some(&(buffer, offset, size)) {
+            self.stats.issued.uniform_buffers += 1;
+            self.uniform_buffers.insert(binding, (buffer, offset, size));
+            self.device.bind_uniform_buffer(binding, buffer, offset, size);
+        }
+    }
+
+    fn bind_uniform_block(&mut self, program: u32, block: u32, binding: u32) {
+        self.device.bind_uniform_block(program, block, binding);
+    }
+
+    fn uniform_buffer_alignment(&mut self) -> usize {
+        self.device.uniform_buffer_alignment()
+    }
+
+    fn draw_arrays(&mut self, vertex_array: u32, first: i32, count: i32) {
+        self.count_draw();
+        self.device.draw_arrays(vertex_array, first, count);
+    }
+
+    fn draw_elements(&mut self, vertex_array: u32, count: i32) {
+        self.count_draw();
+        self.device.draw_elements(vertex_array, count);
+    }
+
+    fn draw_elements_instanced(&mut self, vertex_array: u32, count: i32, instances: i32) {
+        self.count_draw();
+        self.device.draw_elements_instanced(vertex_array, count, instances);
+    }
+
+    fn draw_patches(&mut self, vertex_array: u32, count: i32, patch_vertices: i32) {
+        self.count_draw();
+        self.device.draw_patches(vertex_array, count, patch_vertices);
+    }
+
+    fn bind_storage_buffer(&mut self, binding: u32, buffer: u32) {
+        self.device.bind_storage_buffer(binding, buffer);
+    }
+
+    fn bind_image(&mut self, unit: u32, texture: u32, access: ImageAccess, format: ImageFormat) {
+        self.device.bind_image(unit, texture, access, format);
+    }
+
+    fn dispatch_compute(&mut self, groups: [u32; 3]) {
+        self.device.dispatch_compute(groups);
+    }
+
+    fn memory_barrier(&mut self) {
+        self.device.memory_barrier();
+    }
+}
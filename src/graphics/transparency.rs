@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+use gl;
+
+use crate::graphics::framebuffer::{ Framebuffer, FullscreenTriangle, TextureFormat };
+use crate::graphics::shader::{ Shader, ShaderType };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum TransparencyMode {
+    // sorted back to front and blended with the blend mode of every material
+    SORTED,
+    // weighted blended order-independent transparency, no sorting but an approximation of the blend
+    // which ignores the per-material blend modes
+    WEIGHTED_BLENDED
+}
+
+// Weighted blended OIT (McGuire and Bavoil 2013) with a single blend function, so it runs on GL 3.3
+// without per-attachment blending:
+//  0: RGBA16F, rgb accumulates colour * alpha * weight, a multiplies the revealage (1 - alpha)
+//  1: R16F, accumulates alpha * weight
+pub struct WeightedBlendedOit {
+    target: Framebuffer,
+    fullscreen: FullscreenTriangle
+}
+
+impl WeightedBlendedOit {
+    pub fn new(width: i32, height: i32) -> WeightedBlendedOit {
+        WeightedBlendedOit {
+            target: create_target(width, height),
+            fullscreen: FullscreenTriangle::new()
+        }
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if self.target.width == width && self.target.height == height {
+            return;
+        }
+        self.target = create_target(width, height);
+    }
+
+    // start accumulating transparent surfaces, depth tested against the opaque geometry of `scene_target`
+    pub unsafe fn begin(&self, scene_target: &Framebuffer) {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, scene_target.fbo());
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.target.fbo());
+        gl::BlitFramebuffer(
+            0, 0, scene_target.width, scene_target.height,
+            0, 0, self.target.width, self.target.height,
+            gl::DEPTH_BUFFER_BIT, gl::NEAREST
+        );
+
+        self.target.bind();
+        let accumulation = [0.0f32, 0.0, 0.0, 1.0];
+        let weight = [0.0f32, 0.0, 0.0, 0.0];
+        gl::ClearBufferfv(gl::COLOR, 0, accumulation.as_ptr());
+        gl::ClearBufferfv(gl::COLOR, 1, weight.as_ptr());
+
+        gl::Enable(gl::BLEND);
+        gl::BlendFuncSeparate(gl::ONE, gl::ONE, gl::ZERO, gl::ONE_MINUS_SRC_ALPHA);
+        gl::DepthMask(gl::FALSE);
+    }
+
+    // blend the weighted average of the transparent surfaces over `scene_target`
+    pub unsafe fn composite(&self, scene_target: &Framebuffer, shaders: &HashMap<ShaderType, Shader>) {
+        gl::DepthMask(gl::TRUE);
+        scene_target.bind();
+        gl::Disable(gl::DEPTH_TEST);
+        // result = average * (1 - revealage) + background * revealage
+        gl::BlendFunc(gl::ONE_MINUS_SRC_ALPHA, gl::SRC_ALPHA);
+
+        let shader = shaders.get(&ShaderType::OIT_COMPOSITE).expect("ShaderType is not initialized");
+        shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.target.color_texture(0));
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.target.color_texture(1));
+        shader.set_int(c_str!("accumulationTexture"), 0);
+        shader.set_int(c_str!("weightTexture"), 1);
+        self.fullscreen.draw();
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+fn create_target(width: i32, height: i32) -> Framebuffer {
+    Framebuffer::new(width, height, &[TextureFormat::RGBA16F, TextureFormat::R16F], true)
+}
@@ -0,0 +1,164 @@
+use std::cell::Cell;
+use std::mem::size_of;
+
+use cgmath::{ Matrix, Matrix4, SquareMatrix, Vector3 };
+
+use crate::graphics::device::{ as_bytes, BufferKind, BufferUsage, GlDevice, RenderDevice };
+use crate::graphics::reflection::ProgramReflection;
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
+
+// Binding points of the blocks declared by the engine includes, every program linked by Shader gets
+// its blocks assigned to them
+pub const FRAME_BINDING: u32 = 0;
+pub const OBJECT_BINDING: u32 = 1;
+
+// binding point of a block declared in include/camera.glsl or include/object.glsl
+pub fn block_binding(name: &str) -> Option<u32> {
+    match name {
+        "FrameData" => Some(FRAME_BINDING),
+        "ObjectData" => Some(OBJECT_BINDING),
+        _ => None
+    }
+}
+
+// assign the engine blocks of a linked program to their binding points
+pub fn bind_blocks<D: RenderDevice>(device: &mut D, program: u32, reflection: &ProgramReflection) {
+    for block in &reflection.blocks {
+        if let Some(binding) = block_binding(&block.name) {
+            device.bind_uniform_block(program, block.index, binding);
+        }
+    }
+}
+
+// The FrameData block of include/camera.glsl, std140 layout
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FrameData {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub view_projection: [[f32; 4]; 4],
+    pub inverse_view: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    pub inverse_view_projection: [[f32; 4]; 4],
+    // xyz, w unused
+    pub camera_position: [f32; 4],
+    // seconds since the renderer started, seconds since the last frame
+    pub time: [f32; 4],
+    // width and height in pixels, then their inverse
+    pub resolution: [f32; 4]
+}
+
+impl FrameData {
+    pub fn new(projection: &Matrix4<f32>, view: &Matrix4<f32>, camera_position: Vector3<f32>, time: f32, delta_time: f32, width: i32, height: i32) -> FrameData {
+        let view_projection = projection * view;
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        FrameData {
+            view: (*view).into(),
+            projection: (*projection).into(),
+            view_projection: view_projection.into(),
+            inverse_view: view.invert().unwrap_or_else(Matrix4::identity).into(),
+            inverse_projection: projection.invert().unwrap_or_else(Matrix4::identity).into(),
+            inverse_view_projection: view_projection.invert().unwrap_or_else(Matrix4::identity).into(),
+            camera_position: camera_position.extend(1.0).into(),
+            time: [time, delta_time, 0.0, 0.0],
+            resolution: [width, height, 1.0 / width, 1.0 / height]
+        }
+    }
+}
+
+// The ObjectData block of include/object.glsl, std140 layout
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ObjectData {
+    pub model: [[f32; 4]; 4],
+    // inverse transpose of the model matrix, only the upper 3x3 is used
+    pub normal_matrix: [[f32; 4]; 4]
+}
+
+impl ObjectData {
+    pub fn new(model: &Matrix4<f32>) -> ObjectData {
+        let normal_matrix = model.invert().map(|inverse| inverse.transpose()).unwrap_or_else(Matrix4::identity);
+        ObjectData {
+            model: (*model).into(),
+            normal_matrix: normal_matrix.into()
+        }
+    }
+}
+
+// A uniform buffer holding one block, rewritten whole, e.g. once per frame
+pub struct UniformBuffer {
+    pub buffer: u32,
+    pub size: usize,
+    // deletes `buffer` with the uniform buffer
+    _handle: GpuHandle
+}
+
+impl UniformBuffer {
+    pub fn new(size: usize) -> UniformBuffer {
+        UniformBuffer::with_device(unsafe { &mut GlDevice::current() }, size)
+    }
+
+    pub fn with_device<D: RenderDevice>(device: &mut D, size: usize) -> UniformBuffer {
+        let buffer = device.create_buffer(BufferKind::UNIFORM, BufferUsage::DYNAMIC, &vec![0; size]);
+        let handle = GpuHandle::new(device, ResourceKind::BUFFER, buffer, "uniform buffer");
+        UniformBuffer { buffer, size, _handle: handle }
+    }
+
+    // upload `data` and bind it to `binding`
+    pub fn update<D: RenderDevice, T: Copy>(&self, device: &mut D, binding: u32, data: &T) {
+        let bytes = unsafe { as_bytes(std::slice::from_ref(data)) };
+        debug_assert!(bytes.len() <= self.size, "uniform data larger than its buffer");
+        device.update_buffer(self.buffer, BufferKind::UNIFORM, 0, bytes);
+        device.bind_uniform_buffer(binding, self.buffer, 0, bytes.len());
+    }
+}
+
+// Streams small per-draw blocks through one large buffer. Each push writes after the previous one and
+// binds just that range, the cursor wraps to the start once the buffer is full. The buffer is sized
+// for several frames of draws so the range being rewritten was consumed by the GPU long ago, GL still
+// orders the update after the draws reading it when it wasn't.
+pub struct UniformRing {
+    pub buffer: u32,
+    pub capacity: usize,
+    alignment: usize,
+    cursor: Cell<usize>,
+    _handle: GpuHandle
+}
+
+impl UniformRing {
+    pub fn new(capacity: usize) -> UniformRing {
+        UniformRing::with_device(unsafe { &mut GlDevice::current() }, capacity)
+    }
+
+    pub fn with_device<D: RenderDevice>(device: &mut D, capacity: usize) -> UniformRing {
+        let alignment = device.uniform_buffer_alignment();
+        let buffer = device.create_buffer(BufferKind::UNIFORM, BufferUsage::STREAM, &vec![0; capacity]);
+        let handle = GpuHandle::new(device, ResourceKind::BUFFER, buffer, "uniform ring");
+        UniformRing { buffer, capacity, alignment, cursor: Cell::new(0), _handle: handle }
+    }
+
+    // write `data` into the next free range and bind it to `binding`, returns the offset it was written at
+    pub fn push<D: RenderDevice, T: Copy>(&self, device: &mut D, binding: u32, data: &T) -> usize {
+        let size = size_of::<T>();
+        assert!(size <= self.capacity, "uniform data larger than its ring");
+        let mut offset = self.cursor.get();
+        if offset + size > self.capacity {
+            offset = 0;
+        }
+        self.cursor.set(align(offset + size, self.alignment));
+
+        let bytes = unsafe { as_bytes(std::slice::from_ref(data)) };
+        device.update_buffer(self.buffer, BufferKind::UNIFORM, offset, bytes);
+        device.bind_uniform_buffer(binding, self.buffer, offset, size);
+        offset
+    }
+
+    // bind the per-object block of a draw with `model` as model matrix
+    pub fn push_object<D: RenderDevice>(&self, device: &mut D, model: &Matrix4<f32>) -> usize {
+        self.push(device, OBJECT_BINDING, &ObjectData::new(model))
+    }
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
@@ -0,0 +1,7 @@
+// every GL call is unsafe, the wrappers are unsafe for the same reason: they need a current context
+#![allow(clippy::missing_safety_doc, clippy::new_without_default)]
+
+pub mod core;
+pub mod graphics;
+pub mod physics;
+pub mod world;
@@ -1,13 +1,19 @@
 #[link(name = "shell32")]
 extern "C" {}
 
-mod core;
-mod graphics;
-mod world;
-
-use crate::core::application::Application;
+use argus_engine::core::application::{ Application, Demo };
+use argus_engine::graphics::resource;
 
 fn main() {
-    let mut app = Application::new();
+    // `argus_engine asteroids` opens the instancing demo
+    let demo = match std::env::args().nth(1).as_deref() {
+        Some("asteroids") => Demo::ASTEROIDS,
+        _ => Demo::SHOWCASE
+    };
+    let mut app = Application::with_demo(demo);
     app.run();
-}
\ No newline at end of file
+    drop(app);
+    if cfg!(debug_assertions) {
+        resource::report_leaks();
+    }
+}
@@ -0,0 +1,333 @@
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use cgmath::{ Matrix3, Matrix4, Quaternion, Vector3 };
+use cgmath::prelude::*;
+
+use crate::graphics::model::Model;
+use crate::world::bounds::Aabb;
+use crate::world::bvh::Bvh;
+
+// The shape of a collider around the origin of its entity, in world units
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub enum Shape {
+    SPHERE { radius: f32 },
+    BOX { half_extents: Vector3<f32> },
+    // along the y axis, `half_height` from the center to the center of either cap
+    CAPSULE { radius: f32, half_height: f32 },
+    CONVEX_HULL(Rc<ConvexHull>),
+    // for static geometry, meshes do not collide with each other
+    TRIANGLE_MESH(Rc<TriangleMesh>)
+}
+
+// The collision shape of an entity. Without a RigidBody the entity is static geometry. The physics
+// does not read Transform::scale, the shape is built at the size drawn and the entity keeps a scale of one.
+#[derive(Clone)]
+pub struct Collider {
+    pub shape: Shape,
+    pub friction: f32,
+    // how much of the approaching speed is kept when bouncing off, from 0 to 1
+    pub restitution: f32
+}
+
+impl Collider {
+    pub fn new(shape: Shape) -> Collider {
+        Collider { shape, friction: 0.5, restitution: 0.0 }
+    }
+
+    pub fn sphere(radius: f32) -> Collider {
+        Collider::new(Shape::SPHERE { radius })
+    }
+
+    pub fn cuboid(half_extents: Vector3<f32>) -> Collider {
+        Collider::new(Shape::BOX { half_extents })
+    }
+
+    pub fn capsule(radius: f32, half_height: f32) -> Collider {
+        Collider::new(Shape::CAPSULE { radius, half_height })
+    }
+
+    // None when the points are all on a plane
+    pub fn convex_hull(points: &[Vector3<f32>]) -> Option<Collider> {
+        ConvexHull::new(points).map(|hull| Collider::new(Shape::CONVEX_HULL(Rc::new(hull))))
+    }
+
+    // the hull around every vertex of `model`, scaled like the entity drawing it
+    pub fn model_hull(model: &Model, scale: Vector3<f32>) -> Option<Collider> {
+        let points: Vec<Vector3<f32>> = model.meshes.iter()
+            .flat_map(|mesh| mesh.vertices.iter().map(|vertex| scale_point(vertex.position, scale)))
+            .collect();
+        Collider::convex_hull(&points)
+    }
+
+    // every triangle of `model`, scaled like the entity drawing it
+    pub fn triangle_mesh(model: &Model, scale: Vector3<f32>) -> Collider {
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+        for mesh in &model.meshes {
+            let first = vertices.len() as u32;
+            vertices.extend(mesh.vertices.iter().map(|vertex| scale_point(vertex.position, scale)));
+            triangles.extend(mesh.indices.chunks_exact(3).map(|corners| [first + corners[0], first + corners[1], first + corners[2]]));
+        }
+        Collider::new(Shape::TRIANGLE_MESH(Rc::new(TriangleMesh::new(vertices, triangles))))
+    }
+
+    pub fn local_bounds(&self) -> Aabb {
+        match &self.shape {
+            Shape::SPHERE { radius } => Aabb::new(-Vector3::new(*radius, *radius, *radius), Vector3::new(*radius, *radius, *radius)),
+            Shape::BOX { half_extents } => Aabb::new(-*half_extents, *half_extents),
+            Shape::CAPSULE { radius, half_height } => {
+                let extents = Vector3::new(*radius, radius + half_height, *radius);
+                Aabb::new(-extents, extents)
+            }
+            Shape::CONVEX_HULL(hull) => Aabb::from_points(hull.vertices.iter().cloned()),
+            Shape::TRIANGLE_MESH(mesh) => Aabb::from_points(mesh.vertices.iter().cloned())
+        }
+    }
+
+    pub fn world_bounds(&self, position: Vector3<f32>, orientation: Quaternion<f32>) -> Aabb {
+        self.local_bounds().transformed(&(Matrix4::from_translation(position) * Matrix4::from(orientation)))
+    }
+
+    // The diagonal of the inertia tensor around the origin for a body of `mass`. Hulls and meshes use
+    // their bounding box, which is close enough for a stable simulation.
+    pub fn inertia(&self, mass: f32) -> Vector3<f32> {
+        let box_inertia = |h: Vector3<f32>| Vector3::new(h.y * h.y + h.z * h.z, h.x * h.x + h.z * h.z, h.x * h.x + h.y * h.y) * (mass / 3.0);
+        match &self.shape {
+            Shape::SPHERE { radius } => {
+                let inertia = 0.4 * mass * radius * radius;
+                Vector3::new(inertia, inertia, inertia)
+            }
+            Shape::BOX { half_extents } => box_inertia(*half_extents),
+            Shape::CAPSULE { radius, half_height } => {
+                // a cylinder and the two caps making up a sphere, sharing the mass by volume
+                let (r, height) = (*radius, 2.0 * half_height);
+                let cylinder = PI * r * r * height;
+                let sphere = 4.0 / 3.0 * PI * r * r * r;
+                let cylinder_mass = mass * cylinder / (cylinder + sphere);
+                let caps_mass = mass - cylinder_mass;
+                let along = cylinder_mass * r * r * 0.5 + caps_mass * 0.4 * r * r;
+                let across = cylinder_mass * (r * r / 4.0 + height * height / 12.0)
+                    + caps_mass * (0.4 * r * r + height * height / 4.0 + 3.0 / 8.0 * r * height);
+                Vector3::new(across, along, across)
+            }
+            Shape::CONVEX_HULL(_) | Shape::TRIANGLE_MESH(_) => box_inertia(self.local_bounds().extents())
+        }
+    }
+
+    // the inverse inertia tensor in world space, zero for a body contacts cannot turn
+    pub fn inverse_inertia(&self, inverse_mass: f32, orientation: Quaternion<f32>) -> Matrix3<f32> {
+        if inverse_mass == 0.0 {
+            return Matrix3::zero();
+        }
+        let inertia = self.inertia(1.0 / inverse_mass);
+        let inverse = |i: f32| if i > 0.0 { 1.0 / i } else { 0.0 };
+        let rotation = Matrix3::from(orientation);
+        let local = Matrix3::from_diagonal(Vector3::new(inverse(inertia.x), inverse(inertia.y), inverse(inertia.z)));
+        rotation * local * rotation.transpose()
+    }
+}
+
+fn scale_point(point: Vector3<f32>, scale: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(point.x * scale.x, point.y * scale.y, point.z * scale.z)
+}
+
+// A polygon of a hull, its vertices counter-clockwise seen from outside
+#[derive(Clone, Debug)]
+pub struct HullFace {
+    pub normal: Vector3<f32>,
+    // of the plane, normal . p = distance on the face
+    pub distance: f32,
+    pub vertices: Vec<usize>
+}
+
+// A convex polyhedron with its faces merged into polygons, as the separating axis test needs them
+#[derive(Clone, Debug)]
+pub struct ConvexHull {
+    pub vertices: Vec<Vector3<f32>>,
+    pub faces: Vec<HullFace>,
+    // every edge once
+    pub edges: Vec<(usize, usize)>
+}
+
+impl ConvexHull {
+    // The hull of `points`, built one point at a time: the faces a point sees are replaced by a fan
+    // from the point to their outline. None when the points span no volume.
+    pub fn new(points: &[Vector3<f32>]) -> Option<ConvexHull> {
+        let bounds = Aabb::from_points(points.iter().cloned());
+        if bounds.is_empty() {
+            return None;
+        }
+        let size = bounds.size();
+        let epsilon = 1e-5 * size.x.max(size.y).max(size.z);
+
+        // a first tetrahedron from points far apart
+        let a = (0..points.len()).min_by(|&i, &j| points[i].x.total_cmp(&points[j].x))?;
+        let b = farthest(points, |p| (p - points[a]).magnitude())?;
+        let c = farthest(points, |p| (p - points[a]).cross(points[b] - points[a]).magnitude())?;
+        let normal = (points[b] - points[a]).cross(points[c] - points[a]);
+        let d = farthest(points, |p| (p - points[a]).dot(normal).abs())?;
+        if (points[d] - points[a]).dot(normal).abs() <= epsilon * normal.magnitude() {
+            return None;
+        }
+        let mut triangles: Vec<[usize; 3]> = if (points[d] - points[a]).dot(normal) > 0.0 {
+            vec![[a, c, b], [a, b, d], [b, c, d], [c, a, d]]
+        } else {
+            vec![[a, b, c], [a, d, b], [b, d, c], [c, d, a]]
+        };
+
+        let plane = |triangle: &[usize; 3]| {
+            let [p, q, r] = triangle.map(|i| points[i]);
+            let normal = (q - p).cross(r - p).normalize();
+            (normal, normal.dot(p))
+        };
+        for (index, point) in points.iter().enumerate() {
+            let visible: Vec<bool> = triangles.iter().map(|triangle| {
+                let (normal, distance) = plane(triangle);
+                normal.dot(*point) - distance > epsilon
+            }).collect();
+            if !visible.contains(&true) {
+                continue;
+            }
+            // the outline is made of the edges of seen faces whose neighbour is not seen
+            let seen_edges: Vec<(usize, usize)> = triangles.iter().zip(&visible)
+                .filter(|(_, &seen)| seen)
+                .flat_map(|(t, _)| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+                .collect();
+            let horizon: Vec<(usize, usize)> = seen_edges.iter().cloned().filter(|&(p, q)| !seen_edges.contains(&(q, p))).collect();
+            let mut seen = visible.iter();
+            triangles.retain(|_| !seen.next().unwrap());
+            triangles.extend(horizon.into_iter().map(|(p, q)| [p, q, index]));
+        }
+
+        // triangles on the same plane become one face, its corners sorted around the center
+        let mut faces: Vec<HullFace> = Vec::new();
+        for triangle in &triangles {
+            let (normal, distance) = plane(triangle);
+            let face = match faces.iter_mut().position(|f| f.normal.dot(normal) > 1.0 - 1e-4 && (f.distance - distance).abs() <= epsilon) {
+                Some(face) => &mut faces[face],
+                None => {
+                    faces.push(HullFace { normal, distance, vertices: Vec::new() });
+                    faces.last_mut().unwrap()
+                }
+            };
+            for &corner in triangle {
+                if !face.vertices.contains(&corner) {
+                    face.vertices.push(corner);
+                }
+            }
+        }
+        for face in faces.iter_mut() {
+            let center = face.vertices.iter().map(|&i| points[i]).fold(Vector3::zero(), |sum, p| sum + p) / face.vertices.len() as f32;
+            let u = (points[face.vertices[0]] - center).normalize();
+            let v = face.normal.cross(u);
+            let angle = |i: usize| (points[i] - center).dot(v).atan2((points[i] - center).dot(u));
+            face.vertices.sort_by(|&i, &j| angle(i).total_cmp(&angle(j)));
+        }
+
+        // keep only the points on the hull
+        let mut remap = vec![usize::MAX; points.len()];
+        let mut vertices = Vec::new();
+        for face in faces.iter_mut() {
+            for corner in face.vertices.iter_mut() {
+                if remap[*corner] == usize::MAX {
+                    remap[*corner] = vertices.len();
+                    vertices.push(points[*corner]);
+                }
+                *corner = remap[*corner];
+            }
+        }
+        Some(ConvexHull::from_faces(vertices, faces))
+    }
+
+    pub fn cuboid(half_extents: Vector3<f32>) -> ConvexHull {
+        let h = half_extents;
+        let vertices = (0..8).map(|i| Vector3::new(
+            if i & 1 == 0 { -h.x } else { h.x },
+            if i & 2 == 0 { -h.y } else { h.y },
+            if i & 4 == 0 { -h.z } else { h.z }
+        )).collect();
+        let face = |normal: Vector3<f32>, distance: f32, vertices: [usize; 4]| HullFace { normal, distance, vertices: vertices.to_vec() };
+        let faces = vec![
+            face(Vector3::unit_x(), h.x, [1, 3, 7, 5]),
+            face(-Vector3::unit_x(), h.x, [0, 4, 6, 2]),
+            face(Vector3::unit_y(), h.y, [2, 6, 7, 3]),
+            face(-Vector3::unit_y(), h.y, [0, 1, 5, 4]),
+            face(Vector3::unit_z(), h.z, [4, 5, 7, 6]),
+            face(-Vector3::unit_z(), h.z, [0, 2, 3, 1])
+        ];
+        ConvexHull::from_faces(vertices, faces)
+    }
+
+    // a flat hull with a face on either side, for the triangles of a mesh
+    pub fn triangle(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> ConvexHull {
+        let normal = (b - a).cross(c - a).normalize();
+        let faces = vec![
+            HullFace { normal, distance: normal.dot(a), vertices: vec![0, 1, 2] },
+            HullFace { normal: -normal, distance: -normal.dot(a), vertices: vec![0, 2, 1] }
+        ];
+        ConvexHull::from_faces(vec![a, b, c], faces)
+    }
+
+    fn from_faces(vertices: Vec<Vector3<f32>>, faces: Vec<HullFace>) -> ConvexHull {
+        let mut edges = Vec::new();
+        for face in &faces {
+            for (k, &p) in face.vertices.iter().enumerate() {
+                let q = face.vertices[(k + 1) % face.vertices.len()];
+                if !edges.contains(&(p.min(q), p.max(q))) {
+                    edges.push((p.min(q), p.max(q)));
+                }
+            }
+        }
+        ConvexHull { vertices, faces, edges }
+    }
+
+    // moved to `position` and turned by `orientation`
+    pub fn transformed(&self, position: Vector3<f32>, orientation: Quaternion<f32>) -> ConvexHull {
+        ConvexHull {
+            vertices: self.vertices.iter().map(|v| position + orientation.rotate_vector(*v)).collect(),
+            faces: self.faces.iter().map(|face| {
+                let normal = orientation.rotate_vector(face.normal);
+                HullFace { normal, distance: face.distance + normal.dot(position), vertices: face.vertices.clone() }
+            }).collect(),
+            edges: self.edges.clone()
+        }
+    }
+
+    pub fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        *self.vertices.iter().max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction))).unwrap()
+    }
+}
+
+fn farthest<F: Fn(Vector3<f32>) -> f32>(points: &[Vector3<f32>], measure: F) -> Option<usize> {
+    (0..points.len()).max_by(|&i, &j| measure(points[i]).total_cmp(&measure(points[j])))
+}
+
+// Triangles with a tree over their boxes, in the space of the entity
+pub struct TriangleMesh {
+    pub vertices: Vec<Vector3<f32>>,
+    pub triangles: Vec<[u32; 3]>,
+    bvh: Bvh<usize>
+}
+
+impl TriangleMesh {
+    pub fn new(vertices: Vec<Vector3<f32>>, triangles: Vec<[u32; 3]>) -> TriangleMesh {
+        let mut bvh = Bvh::new(0.0);
+        for (index, triangle) in triangles.iter().enumerate() {
+            bvh.insert(Aabb::from_points(triangle.iter().map(|&i| vertices[i as usize])), index);
+        }
+        TriangleMesh { vertices, triangles, bvh }
+    }
+
+    pub fn corners(&self, triangle: usize) -> [Vector3<f32>; 3] {
+        self.triangles[triangle].map(|i| self.vertices[i as usize])
+    }
+
+    // the triangles whose box meets `aabb`, in the space of the mesh
+    pub fn query(&self, aabb: &Aabb) -> Vec<usize> {
+        let mut triangles = self.bvh.query_aabb(aabb);
+        triangles.sort_unstable();
+        triangles
+    }
+}
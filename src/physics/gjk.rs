@@ -0,0 +1,153 @@
+use cgmath::Vector3;
+use cgmath::prelude::*;
+
+// a corner of the Minkowski difference with the points of either shape it came from
+#[derive(Clone, Copy)]
+struct SimplexVertex {
+    w: Vector3<f32>,
+    a: Vector3<f32>,
+    b: Vector3<f32>
+}
+
+const MAX_ITERATIONS: usize = 64;
+
+// The closest points of two convex shapes given by their support functions (GJK), None when they
+// overlap or touch. The simplex of the difference A - B closes in on the point nearest the origin.
+pub fn closest_points<A, B>(support_a: A, support_b: B) -> Option<(Vector3<f32>, Vector3<f32>)>
+where
+    A: Fn(Vector3<f32>) -> Vector3<f32>,
+    B: Fn(Vector3<f32>) -> Vector3<f32>
+{
+    let vertex = |direction: Vector3<f32>| {
+        let a = support_a(direction);
+        let b = support_b(-direction);
+        SimplexVertex { w: a - b, a, b }
+    };
+    let mut simplex = vec![vertex(Vector3::unit_x())];
+    let mut weights = vec![1.0];
+    let mut closest = simplex[0].w;
+
+    for _ in 0..MAX_ITERATIONS {
+        let distance2 = closest.magnitude2();
+        if distance2 < 1e-12 {
+            return None;
+        }
+        let next = vertex(-closest);
+        // nothing on the difference is nearer the origin by more than the tolerance
+        if distance2 - closest.dot(next.w) <= 1e-6 * distance2 || simplex.iter().any(|v| (v.w - next.w).magnitude2() < 1e-12) {
+            break;
+        }
+        simplex.push(next);
+        let points: Vec<Vector3<f32>> = simplex.iter().map(|v| v.w).collect();
+        let found_weights = closest_to_origin(&points)?;
+        let found = combine(&points, &found_weights);
+        // no progress means rounding has taken over
+        if found.magnitude2() >= distance2 {
+            simplex.pop();
+            break;
+        }
+        // the corners not needed for the closest point are dropped
+        let kept: Vec<usize> = (0..simplex.len()).filter(|&i| found_weights[i] > 0.0).collect();
+        simplex = kept.iter().map(|&i| simplex[i]).collect();
+        weights = kept.iter().map(|&i| found_weights[i]).collect();
+        closest = found;
+    }
+
+    let a: Vec<Vector3<f32>> = simplex.iter().map(|v| v.a).collect();
+    let b: Vec<Vector3<f32>> = simplex.iter().map(|v| v.b).collect();
+    Some((combine(&a, &weights), combine(&b, &weights)))
+}
+
+fn combine(points: &[Vector3<f32>], weights: &[f32]) -> Vector3<f32> {
+    points.iter().zip(weights).fold(Vector3::zero(), |sum, (p, &weight)| sum + p * weight)
+}
+
+// weights of the simplex corners giving its point closest to the origin, None when a tetrahedron holds the origin
+fn closest_to_origin(points: &[Vector3<f32>]) -> Option<Vec<f32>> {
+    match points.len() {
+        1 => Some(vec![1.0]),
+        2 => Some(segment_weights(points[0], points[1]).to_vec()),
+        3 => Some(triangle_weights(points[0], points[1], points[2]).to_vec()),
+        _ => {
+            let mut closest: Option<(f32, Vec<f32>)> = None;
+            for (i, j, k, l) in [(0, 1, 2, 3), (0, 1, 3, 2), (0, 2, 3, 1), (1, 2, 3, 0)] {
+                let normal = (points[j] - points[i]).cross(points[k] - points[i]);
+                // the origin is on the far side of this face from the fourth corner
+                if normal.dot(-points[i]) * normal.dot(points[l] - points[i]) > 0.0 {
+                    continue;
+                }
+                let [u, v, w] = triangle_weights(points[i], points[j], points[k]);
+                let mut weights = vec![0.0; 4];
+                weights[i] = u;
+                weights[j] = v;
+                weights[k] = w;
+                let distance2 = combine(points, &weights).magnitude2();
+                if closest.as_ref().is_none_or(|(best, _)| distance2 < *best) {
+                    closest = Some((distance2, weights));
+                }
+            }
+            closest.map(|(_, weights)| weights)
+        }
+    }
+}
+
+fn segment_weights(a: Vector3<f32>, b: Vector3<f32>) -> [f32; 2] {
+    let ab = b - a;
+    let length2 = ab.magnitude2();
+    if length2 < 1e-12 {
+        return [1.0, 0.0];
+    }
+    let t = (-a.dot(ab) / length2).clamp(0.0, 1.0);
+    [1.0 - t, t]
+}
+
+// Weights of the point of triangle abc closest to the origin, found by the Voronoi region holding it
+// (Ericson, Real-Time Collision Detection 5.1.5)
+fn triangle_weights(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> [f32; 3] {
+    let ab = b - a;
+    let ac = c - a;
+    let d1 = ab.dot(-a);
+    let d2 = ac.dot(-a);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return [1.0, 0.0, 0.0];
+    }
+    let d3 = ab.dot(-b);
+    let d4 = ac.dot(-b);
+    if d3 >= 0.0 && d4 <= d3 {
+        return [0.0, 1.0, 0.0];
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return [1.0 - v, v, 0.0];
+    }
+    let d5 = ab.dot(-c);
+    let d6 = ac.dot(-c);
+    if d6 >= 0.0 && d5 <= d6 {
+        return [0.0, 0.0, 1.0];
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return [1.0 - w, 0.0, w];
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return [0.0, 1.0 - w, w];
+    }
+    let total = va + vb + vc;
+    if total <= 1e-12 {
+        // a degenerate triangle, the closest of its edges stands in for it
+        let edges = [
+            { let [u, v] = segment_weights(a, b); [u, v, 0.0] },
+            { let [u, v] = segment_weights(b, c); [0.0, u, v] },
+            { let [u, v] = segment_weights(a, c); [u, 0.0, v] }
+        ];
+        let distance2 = |w: &[f32; 3]| (a * w[0] + b * w[1] + c * w[2]).magnitude2();
+        return *edges.iter().min_by(|x, y| distance2(x).total_cmp(&distance2(y))).unwrap();
+    }
+    let v = vb / total;
+    let w = vc / total;
+    [1.0 - v - w, v, w]
+}
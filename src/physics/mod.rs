@@ -0,0 +1,6 @@
+pub mod rigid_body;
+pub mod collider;
+pub mod gjk;
+pub mod narrow_phase;
+pub mod solver;
+pub mod world;
@@ -0,0 +1,389 @@
+use cgmath::{ Matrix4, Quaternion, Vector3 };
+use cgmath::prelude::*;
+
+use crate::physics::collider::{ Collider, ConvexHull, Shape, TriangleMesh };
+use crate::physics::gjk;
+use crate::world::bounds::Aabb;
+
+// Contacts are kept this far before the shapes touch, so the solver can stop them arriving
+pub const CONTACT_MARGIN: f32 = 0.02;
+// a face is preferred over a slightly better axis, so the contacts of a resting box do not flicker
+const AXIS_TOLERANCE: f32 = 0.005;
+const MAX_CONTACTS: usize = 4;
+
+// A point where two colliders touch
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Contact {
+    pub position: Vector3<f32>,
+    // from the first collider to the second
+    pub normal: Vector3<f32>,
+    // negative when they overlap
+    pub separation: f32
+}
+
+// the inner shape of a collider, its surface is `radius` further out
+// Variants are upper case like every enum of the engine, clippy only asks otherwise of private ones.
+#[allow(clippy::upper_case_acronyms)]
+enum Core {
+    POINT(Vector3<f32>),
+    SEGMENT(Vector3<f32>, Vector3<f32>),
+    HULL(ConvexHull)
+}
+
+struct Convex {
+    core: Core,
+    radius: f32
+}
+
+impl Core {
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            Core::POINT(p) => *p,
+            Core::SEGMENT(p, q) => if p.dot(direction) >= q.dot(direction) { *p } else { *q },
+            Core::HULL(hull) => hull.support(direction)
+        }
+    }
+
+    fn points(&self) -> Vec<Vector3<f32>> {
+        match self {
+            Core::POINT(p) => vec![*p],
+            Core::SEGMENT(p, q) => vec![*p, *q],
+            Core::HULL(hull) => hull.vertices.clone()
+        }
+    }
+}
+
+// None for triangle meshes, which are tested a triangle at a time
+fn convex(collider: &Collider, position: Vector3<f32>, orientation: Quaternion<f32>) -> Option<Convex> {
+    let (core, radius) = match &collider.shape {
+        Shape::SPHERE { radius } => (Core::POINT(position), *radius),
+        Shape::BOX { half_extents } => (Core::HULL(ConvexHull::cuboid(*half_extents).transformed(position, orientation)), 0.0),
+        Shape::CAPSULE { radius, half_height } => {
+            let axis = orientation.rotate_vector(Vector3::unit_y()) * *half_height;
+            (Core::SEGMENT(position - axis, position + axis), *radius)
+        }
+        Shape::CONVEX_HULL(hull) => (Core::HULL(hull.transformed(position, orientation)), 0.0),
+        Shape::TRIANGLE_MESH(_) => return None
+    };
+    Some(Convex { core, radius })
+}
+
+// The contacts between two placed colliders, at most four. Hulls and boxes are separated along the
+// axis of least overlap (SAT) and clipped against each other, rounded shapes use the closest points
+// of their cores (GJK).
+pub fn collide(
+    a: &Collider, position_a: Vector3<f32>, orientation_a: Quaternion<f32>,
+    b: &Collider, position_b: Vector3<f32>, orientation_b: Quaternion<f32>
+) -> Vec<Contact> {
+    match (convex(a, position_a, orientation_a), convex(b, position_b, orientation_b)) {
+        (Some(a), Some(b)) => collide_convex(&a, &b),
+        (Some(a), None) => match &b.shape {
+            Shape::TRIANGLE_MESH(mesh) => collide_mesh(&a, mesh, position_b, orientation_b),
+            _ => Vec::new()
+        },
+        (None, Some(b)) => match &a.shape {
+            Shape::TRIANGLE_MESH(mesh) => flipped(collide_mesh(&b, mesh, position_a, orientation_a)),
+            _ => Vec::new()
+        },
+        (None, None) => Vec::new()
+    }
+}
+
+fn flipped(contacts: Vec<Contact>) -> Vec<Contact> {
+    contacts.into_iter().map(|contact| Contact { normal: -contact.normal, ..contact }).collect()
+}
+
+fn collide_convex(a: &Convex, b: &Convex) -> Vec<Contact> {
+    match (&a.core, &b.core) {
+        (Core::HULL(hull_a), Core::HULL(hull_b)) if a.radius == 0.0 && b.radius == 0.0 => collide_hulls(hull_a, hull_b),
+        _ => collide_rounded(a, b)
+    }
+}
+
+// each triangle near `convex` as a flat hull
+fn collide_mesh(convex: &Convex, mesh: &TriangleMesh, position: Vector3<f32>, orientation: Quaternion<f32>) -> Vec<Contact> {
+    let world = Aabb::from_points(convex.core.points()).expanded(convex.radius + CONTACT_MARGIN);
+    // the inverse of a rotation and translation
+    let inverse = orientation.conjugate();
+    let to_mesh = Matrix4::from(inverse) * Matrix4::from_translation(-position);
+
+    let mut contacts = Vec::new();
+    for triangle in mesh.query(&world.transformed(&to_mesh)) {
+        let [p, q, r] = mesh.corners(triangle).map(|corner| position + orientation.rotate_vector(corner));
+        if (q - p).cross(r - p).magnitude2() == 0.0 {
+            continue;
+        }
+        let triangle = Convex { core: Core::HULL(ConvexHull::triangle(p, q, r)), radius: 0.0 };
+        contacts.extend(collide_convex(convex, &triangle));
+    }
+    reduce(contacts)
+}
+
+fn contact(on_a: Vector3<f32>, on_b: Vector3<f32>, normal: Vector3<f32>, separation: f32) -> Contact {
+    Contact { position: (on_a + on_b) * 0.5, normal, separation }
+}
+
+// spheres and capsules against anything convex
+fn collide_rounded(a: &Convex, b: &Convex) -> Vec<Contact> {
+    let closest = gjk::closest_points(|d| a.core.support(d), |d| b.core.support(d));
+    let (on_a, on_b) = match closest {
+        Some((on_a, on_b)) if (on_b - on_a).magnitude2() > 1e-12 => (on_a, on_b),
+        // the cores overlap
+        _ => return match (&a.core, &b.core) {
+            (_, Core::HULL(hull)) => penetration(&a.core.points(), a.radius, hull),
+            (Core::HULL(hull), _) => flipped(penetration(&b.core.points(), b.radius, hull)),
+            _ => overlapping_cores(a, b)
+        }
+    };
+    let distance = (on_b - on_a).magnitude();
+    let normal = (on_b - on_a) / distance;
+    let separation = distance - a.radius - b.radius;
+    if separation > CONTACT_MARGIN {
+        return Vec::new();
+    }
+
+    // a capsule lying along the other shape rests on both ends
+    if let Core::SEGMENT(p, q) = a.core {
+        let ends = segment_ends(&[p, q], a.radius, b, normal);
+        if ends.len() == 2 {
+            return ends;
+        }
+    }
+    if let Core::SEGMENT(p, q) = b.core {
+        let ends = segment_ends(&[p, q], b.radius, a, -normal);
+        if ends.len() == 2 {
+            return flipped(ends);
+        }
+    }
+    vec![contact(on_a + normal * a.radius, on_b - normal * b.radius, normal, separation)]
+}
+
+// Sphere and capsule cores that meet are pushed apart along the line between their closest points,
+// crossing segments across both directions, away from the other's center. Coincident centers have
+// no better axis than up.
+fn overlapping_cores(a: &Convex, b: &Convex) -> Vec<Contact> {
+    let ends = |core: &Core| match *core {
+        Core::POINT(p) => (p, p),
+        Core::SEGMENT(p, q) => (p, q),
+        Core::HULL(_) => unreachable!("hulls leave through a face")
+    };
+    let ((p, q), (r, s)) = (ends(&a.core), ends(&b.core));
+    let (on_a, on_b) = closest_on_segments(p, q, r, s);
+    let between = (r + s - p - q) * 0.5;
+    let across = (q - p).cross(s - r);
+    let normal = if (on_b - on_a).magnitude2() > 1e-12 {
+        (on_b - on_a).normalize()
+    } else if across.magnitude2() > 1e-12 {
+        if across.dot(between) < 0.0 { -across.normalize() } else { across.normalize() }
+    } else if between.magnitude2() > 1e-12 {
+        between.normalize()
+    } else {
+        Vector3::unit_y()
+    };
+    let separation = normal.dot(on_b - on_a) - a.radius - b.radius;
+    vec![contact(on_a + normal * a.radius, on_b - normal * b.radius, normal, separation)]
+}
+
+// the contacts of the ends of a segment against `other` pushing along about `normal`
+fn segment_ends(ends: &[Vector3<f32>], radius: f32, other: &Convex, normal: Vector3<f32>) -> Vec<Contact> {
+    ends.iter().filter_map(|&end| {
+        let (on_end, on_other) = gjk::closest_points(|_| end, |d| other.core.support(d))?;
+        let distance = (on_other - on_end).magnitude();
+        if distance < 1e-6 {
+            return None;
+        }
+        let end_normal = (on_other - on_end) / distance;
+        let separation = distance - radius - other.radius;
+        if separation > CONTACT_MARGIN || end_normal.dot(normal) < 0.99 {
+            return None;
+        }
+        Some(contact(on_end + end_normal * radius, on_other - end_normal * other.radius, end_normal, separation))
+    }).collect()
+}
+
+// A point or segment inside a hull leaves through the face it is least deep behind. The normal goes
+// from the rounded shape into the hull.
+fn penetration(points: &[Vector3<f32>], radius: f32, hull: &ConvexHull) -> Vec<Contact> {
+    let depth = |normal: Vector3<f32>, distance: f32| points.iter().map(|p| normal.dot(*p) - distance).fold(f32::INFINITY, f32::min);
+    let face = hull.faces.iter().max_by(|f, g| depth(f.normal, f.distance).total_cmp(&depth(g.normal, g.distance))).unwrap();
+    points.iter().filter_map(|&p| {
+        let height = face.normal.dot(p) - face.distance;
+        let separation = height - radius;
+        if separation > CONTACT_MARGIN {
+            return None;
+        }
+        Some(contact(p - face.normal * radius, p - face.normal * height, -face.normal, separation))
+    }).collect()
+}
+
+// the separation of b from a along `axis`, pointing from a to b
+fn axis_separation(a: &ConvexHull, b: &ConvexHull, axis: Vector3<f32>) -> f32 {
+    axis.dot(b.support(-axis)) - axis.dot(a.support(axis))
+}
+
+fn best_face(reference: &ConvexHull, other: &ConvexHull) -> (usize, f32) {
+    reference.faces.iter().enumerate()
+        .map(|(index, face)| (index, face.normal.dot(other.support(-face.normal)) - face.distance))
+        .fold((0, f32::NEG_INFINITY), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+}
+
+fn collide_hulls(a: &ConvexHull, b: &ConvexHull) -> Vec<Contact> {
+    let (face_a, separation_a) = best_face(a, b);
+    if separation_a > CONTACT_MARGIN {
+        return Vec::new();
+    }
+    let (face_b, separation_b) = best_face(b, a);
+    if separation_b > CONTACT_MARGIN {
+        return Vec::new();
+    }
+
+    // the cross products of every pair of edge directions
+    let mut best_edges: Option<(usize, usize, Vector3<f32>, f32)> = None;
+    for (ea, &(p, q)) in a.edges.iter().enumerate() {
+        let direction_a = a.vertices[q] - a.vertices[p];
+        for (eb, &(r, s)) in b.edges.iter().enumerate() {
+            let direction_b = b.vertices[s] - b.vertices[r];
+            let axis = direction_a.cross(direction_b);
+            if axis.magnitude2() <= 1e-6 * direction_a.magnitude2() * direction_b.magnitude2() {
+                continue;
+            }
+            let axis = axis.normalize();
+            let (axis, separation) = {
+                let (forward, backward) = (axis_separation(a, b, axis), axis_separation(a, b, -axis));
+                if forward >= backward { (axis, forward) } else { (-axis, backward) }
+            };
+            if separation > CONTACT_MARGIN {
+                return Vec::new();
+            }
+            if best_edges.is_none_or(|(_, _, _, best)| separation > best) {
+                best_edges = Some((ea, eb, axis, separation));
+            }
+        }
+    }
+
+    let separation_faces = separation_a.max(separation_b);
+    if let Some((ea, eb, normal, separation)) = best_edges {
+        if separation > separation_faces + AXIS_TOLERANCE {
+            return edge_contact(a, b, ea, eb, normal, separation);
+        }
+    }
+    if separation_b > separation_a + AXIS_TOLERANCE {
+        flipped(clip_faces(b, face_b, a))
+    } else {
+        clip_faces(a, face_a, b)
+    }
+}
+
+// Two crossing edges touch at one point. The edges giving the axis may be any of the parallel ones,
+// the touching ones are the furthest along the normal on either hull.
+fn edge_contact(a: &ConvexHull, b: &ConvexHull, ea: usize, eb: usize, normal: Vector3<f32>, separation: f32) -> Vec<Contact> {
+    let support_edge = |hull: &ConvexHull, edge: usize, direction: Vector3<f32>| {
+        let (p, q) = hull.edges[edge];
+        let along = (hull.vertices[q] - hull.vertices[p]).normalize();
+        hull.edges.iter()
+            .map(|&(r, s)| (hull.vertices[r], hull.vertices[s]))
+            .filter(|(r, s)| (s - r).normalize().cross(along).magnitude2() < 1e-6)
+            .max_by(|x, y| direction.dot(x.0 + x.1).total_cmp(&direction.dot(y.0 + y.1)))
+            .unwrap()
+    };
+    let (p1, q1) = support_edge(a, ea, normal);
+    let (p2, q2) = support_edge(b, eb, -normal);
+    let (on_a, on_b) = closest_on_segments(p1, q1, p2, q2);
+    vec![contact(on_a, on_b, normal, separation)]
+}
+
+// closest points of segments pq and rs, either may be a single point (Ericson 5.1.9)
+fn closest_on_segments(p: Vector3<f32>, q: Vector3<f32>, r: Vector3<f32>, s: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let d1 = q - p;
+    let d2 = s - r;
+    let offset = p - r;
+    let a = d1.magnitude2();
+    let e = d2.magnitude2();
+    let f = d2.dot(offset);
+    if a <= 1e-12 {
+        return (p, if e <= 1e-12 { r } else { r + d2 * (f / e).clamp(0.0, 1.0) });
+    }
+    let c = d1.dot(offset);
+    if e <= 1e-12 {
+        return (p + d1 * (-c / a).clamp(0.0, 1.0), r);
+    }
+    let b = d1.dot(d2);
+    let denominator = a * e - b * b;
+    let mut t = if denominator > 1e-12 { ((b * f - c * e) / denominator).clamp(0.0, 1.0) } else { 0.0 };
+    let mut u = (b * t + f) / e;
+    if u < 0.0 {
+        u = 0.0;
+        t = (-c / a).clamp(0.0, 1.0);
+    } else if u > 1.0 {
+        u = 1.0;
+        t = ((b - c) / a).clamp(0.0, 1.0);
+    }
+    (p + d1 * t, r + d2 * u)
+}
+
+// The face of `incident` most against the reference face, cut down to the inside of the reference
+// face's edges. Its points under the reference plane are the contacts, the normal points away from
+// the reference hull.
+fn clip_faces(reference: &ConvexHull, face: usize, incident: &ConvexHull) -> Vec<Contact> {
+    let face = &reference.faces[face];
+    let normal = face.normal;
+    let incident_face = incident.faces.iter().min_by(|f, g| f.normal.dot(normal).total_cmp(&g.normal.dot(normal))).unwrap();
+    let mut polygon: Vec<Vector3<f32>> = incident_face.vertices.iter().map(|&i| incident.vertices[i]).collect();
+
+    for (k, &corner) in face.vertices.iter().enumerate() {
+        let start = reference.vertices[corner];
+        let end = reference.vertices[face.vertices[(k + 1) % face.vertices.len()]];
+        // faces wind counter-clockwise, so this points out of the face across the edge
+        let side = (end - start).cross(normal);
+        polygon = clip(&polygon, side, start);
+        if polygon.is_empty() {
+            return Vec::new();
+        }
+    }
+
+    let contacts = polygon.into_iter().filter_map(|point| {
+        let separation = normal.dot(point) - face.distance;
+        if separation > CONTACT_MARGIN {
+            return None;
+        }
+        Some(contact(point - normal * separation, point, normal, separation))
+    }).collect();
+    reduce(contacts)
+}
+
+// the part of a polygon behind the plane through `point` facing along `normal` (Sutherland-Hodgman)
+fn clip(polygon: &[Vector3<f32>], normal: Vector3<f32>, point: Vector3<f32>) -> Vec<Vector3<f32>> {
+    let mut clipped = Vec::new();
+    for (k, &current) in polygon.iter().enumerate() {
+        let next = polygon[(k + 1) % polygon.len()];
+        let (d_current, d_next) = (normal.dot(current - point), normal.dot(next - point));
+        if d_current <= 0.0 {
+            clipped.push(current);
+        }
+        if (d_current <= 0.0) != (d_next <= 0.0) {
+            clipped.push(current + (next - current) * (d_current / (d_current - d_next)));
+        }
+    }
+    clipped
+}
+
+// Keeps the deepest contact and three more spread around it, four points hold a resting body as well
+// as any more
+fn reduce(mut contacts: Vec<Contact>) -> Vec<Contact> {
+    if contacts.len() <= MAX_CONTACTS {
+        return contacts;
+    }
+    let mut kept = Vec::with_capacity(MAX_CONTACTS);
+    let take = |contacts: &mut Vec<Contact>, score: &dyn Fn(&Contact) -> f32| {
+        let index = (0..contacts.len()).max_by(|&i, &j| score(&contacts[i]).total_cmp(&score(&contacts[j]))).unwrap();
+        contacts.swap_remove(index)
+    };
+    kept.push(take(&mut contacts, &|c| -c.separation));
+    let first = kept[0].position;
+    kept.push(take(&mut contacts, &|c| (c.position - first).magnitude2()));
+    let second = kept[1].position;
+    kept.push(take(&mut contacts, &|c| (second - first).cross(c.position - first).magnitude2()));
+    let center = (first + second + kept[2].position) / 3.0;
+    kept.push(take(&mut contacts, &|c| (c.position - center).magnitude2()));
+    kept
+}
@@ -0,0 +1,110 @@
+use cgmath::{ Quaternion, Vector3 };
+use cgmath::prelude::*;
+
+use crate::world::transform::Transform;
+
+// How a body moves. Kinematic bodies follow their velocity and push dynamic ones without being
+// pushed back, static ones never move.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BodyType {
+    DYNAMIC,
+    KINEMATIC,
+    STATIC
+}
+
+// The motion of an entity, simulated by the PhysicsWorld when the entity also has a collider
+#[derive(Clone, Copy, Debug)]
+pub struct RigidBody {
+    pub body_type: BodyType,
+    pub mass: f32,
+    pub linear_velocity: Vector3<f32>,
+    // radians per second around each world axis
+    pub angular_velocity: Vector3<f32>,
+    // Slow the velocities down, each step divides them by 1 + time_step * damping. About the fraction
+    // lost per second while it is small, and never turns a body around however large.
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    // multiplies the gravity of the world, zero for a body floating in place
+    pub gravity_scale: f32,
+    // applied over the next step, then cleared
+    force: Vector3<f32>,
+    torque: Vector3<f32>,
+    // the orientation integrated by the solver, Transform only keeps Euler angles
+    orientation: Quaternion<f32>,
+    // the transform last written back, any other one was set from outside and is read again
+    synced: Option<Transform>
+}
+
+impl RigidBody {
+    pub fn new(body_type: BodyType, mass: f32) -> RigidBody {
+        RigidBody {
+            body_type,
+            mass,
+            linear_velocity: Vector3::zero(),
+            angular_velocity: Vector3::zero(),
+            linear_damping: 0.01,
+            angular_damping: 0.05,
+            gravity_scale: 1.0,
+            force: Vector3::zero(),
+            torque: Vector3::zero(),
+            orientation: Quaternion::one(),
+            synced: None
+        }
+    }
+
+    pub fn dynamic(mass: f32) -> RigidBody {
+        RigidBody::new(BodyType::DYNAMIC, mass)
+    }
+
+    pub fn kinematic() -> RigidBody {
+        RigidBody::new(BodyType::KINEMATIC, 0.0)
+    }
+
+    pub fn fixed() -> RigidBody {
+        RigidBody::new(BodyType::STATIC, 0.0)
+    }
+
+    // zero for the bodies contacts cannot move
+    pub fn inverse_mass(&self) -> f32 {
+        if self.body_type == BodyType::DYNAMIC && self.mass > 0.0 { 1.0 / self.mass } else { 0.0 }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.body_type == BodyType::DYNAMIC
+    }
+
+    pub fn apply_force(&mut self, force: Vector3<f32>) {
+        self.force += force;
+    }
+
+    pub fn apply_torque(&mut self, torque: Vector3<f32>) {
+        self.torque += torque;
+    }
+
+    // an instant change of momentum through the center of mass
+    pub fn apply_impulse(&mut self, impulse: Vector3<f32>) {
+        self.linear_velocity += impulse * self.inverse_mass();
+    }
+
+    pub(crate) fn take_force_and_torque(&mut self) -> (Vector3<f32>, Vector3<f32>) {
+        let taken = (self.force, self.torque);
+        self.force = Vector3::zero();
+        self.torque = Vector3::zero();
+        taken
+    }
+
+    // the orientation to simulate from, `transform` is read again when edited since the last step
+    pub(crate) fn orientation(&mut self, transform: &Transform) -> Quaternion<f32> {
+        if self.synced != Some(*transform) {
+            self.orientation = transform.orientation();
+        }
+        self.orientation
+    }
+
+    pub(crate) fn write_back(&mut self, transform: &mut Transform, position: Vector3<f32>, orientation: Quaternion<f32>) {
+        transform.position = position;
+        transform.set_orientation(orientation);
+        self.orientation = orientation;
+        self.synced = Some(*transform);
+    }
+}
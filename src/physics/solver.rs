@@ -0,0 +1,171 @@
+use cgmath::{ Matrix3, Quaternion, Vector3 };
+use cgmath::prelude::*;
+
+use crate::physics::narrow_phase::Contact;
+
+// fraction of the overlap pushed out per step, more makes stacks jitter
+const BAUMGARTE: f32 = 0.2;
+// overlap left alone so resting contacts stay touching from one step to the next
+const SLOP: f32 = 0.005;
+// slower impacts do not bounce, or resting bodies would never settle
+const RESTITUTION_THRESHOLD: f32 = 1.0;
+// a contact this close in both bodies to one of the last step keeps its impulses
+const MATCH_DISTANCE: f32 = 0.05;
+
+// The state of a body while a step is solved
+#[derive(Clone, Copy, Debug)]
+pub struct SolverBody {
+    pub position: Vector3<f32>,
+    pub orientation: Quaternion<f32>,
+    pub linear_velocity: Vector3<f32>,
+    pub angular_velocity: Vector3<f32>,
+    pub inverse_mass: f32,
+    // in world space
+    pub inverse_inertia: Matrix3<f32>
+}
+
+impl SolverBody {
+    fn apply_impulse(&mut self, impulse: Vector3<f32>, offset: Vector3<f32>) {
+        self.linear_velocity += impulse * self.inverse_mass;
+        self.angular_velocity += self.inverse_inertia * offset.cross(impulse);
+    }
+
+    fn velocity_at(&self, offset: Vector3<f32>) -> Vector3<f32> {
+        self.linear_velocity + self.angular_velocity.cross(offset)
+    }
+}
+
+// A contact with the impulses applied at it, kept from one step to the next so the solver starts
+// from the last answer (warm starting)
+#[derive(Clone, Copy, Debug)]
+pub struct ContactPoint {
+    pub contact: Contact,
+    pub normal_impulse: f32,
+    pub tangent_impulse: [f32; 2],
+    // the point in the space of either body, to find it again
+    local_a: Vector3<f32>,
+    local_b: Vector3<f32>,
+    // from the bodies to the point
+    offset_a: Vector3<f32>,
+    offset_b: Vector3<f32>,
+    tangents: [Vector3<f32>; 2],
+    normal_mass: f32,
+    tangent_mass: [f32; 2],
+    // the normal velocity the solver aims for
+    target_velocity: f32
+}
+
+// The contacts between two entities
+#[derive(Clone, Debug)]
+pub struct Manifold {
+    pub entities: (usize, usize),
+    pub points: Vec<ContactPoint>,
+    pub friction: f32,
+    pub restitution: f32
+}
+
+impl Manifold {
+    // `previous` is the manifold of the same entities in the last step
+    pub fn new(entities: (usize, usize), contacts: Vec<Contact>, a: &SolverBody, b: &SolverBody, friction: f32, restitution: f32, previous: Option<&Manifold>) -> Manifold {
+        let points = contacts.into_iter().map(|contact| {
+            let local_a = a.orientation.invert().rotate_vector(contact.position - a.position);
+            let local_b = b.orientation.invert().rotate_vector(contact.position - b.position);
+            let matched = previous.and_then(|previous| previous.points.iter().find(|point| {
+                (point.local_a - local_a).magnitude2() < MATCH_DISTANCE * MATCH_DISTANCE
+                    && (point.local_b - local_b).magnitude2() < MATCH_DISTANCE * MATCH_DISTANCE
+            }));
+            ContactPoint {
+                contact,
+                normal_impulse: matched.map_or(0.0, |point| point.normal_impulse),
+                tangent_impulse: matched.map_or([0.0; 2], |point| point.tangent_impulse),
+                local_a,
+                local_b,
+                offset_a: Vector3::zero(),
+                offset_b: Vector3::zero(),
+                tangents: [Vector3::zero(); 2],
+                normal_mass: 0.0,
+                tangent_mass: [0.0; 2],
+                target_velocity: 0.0
+            }
+        }).collect();
+        Manifold { entities, points, friction, restitution }
+    }
+
+    // effective masses and target velocities, from the velocities before solving
+    pub fn prepare(&mut self, a: &SolverBody, b: &SolverBody, time_step: f32) {
+        let restitution = self.restitution;
+        for point in self.points.iter_mut() {
+            let normal = point.contact.normal;
+            point.offset_a = point.contact.position - a.position;
+            point.offset_b = point.contact.position - b.position;
+            point.tangents = tangents(normal);
+
+            let mass = |direction: Vector3<f32>| {
+                let ra = point.offset_a.cross(direction);
+                let rb = point.offset_b.cross(direction);
+                let k = a.inverse_mass + b.inverse_mass + ra.dot(a.inverse_inertia * ra) + rb.dot(b.inverse_inertia * rb);
+                if k > 0.0 { 1.0 / k } else { 0.0 }
+            };
+            point.normal_mass = mass(normal);
+            point.tangent_mass = [mass(point.tangents[0]), mass(point.tangents[1])];
+
+            // Separated contacts may close the gap within the step (speculative), overlapping ones
+            // are pushed apart a little each step
+            let separation = point.contact.separation;
+            let mut target = if separation > 0.0 {
+                -separation / time_step
+            } else {
+                BAUMGARTE / time_step * (-separation - SLOP).max(0.0)
+            };
+            let approach = normal.dot(b.velocity_at(point.offset_b) - a.velocity_at(point.offset_a));
+            if approach < -RESTITUTION_THRESHOLD {
+                target = target.max(-restitution * approach);
+            }
+            point.target_velocity = target;
+        }
+    }
+
+    // apply the impulses carried over from the last step
+    pub fn warm_start(&self, a: &mut SolverBody, b: &mut SolverBody) {
+        for point in &self.points {
+            let impulse = point.contact.normal * point.normal_impulse
+                + point.tangents[0] * point.tangent_impulse[0]
+                + point.tangents[1] * point.tangent_impulse[1];
+            a.apply_impulse(-impulse, point.offset_a);
+            b.apply_impulse(impulse, point.offset_b);
+        }
+    }
+
+    // One pass of sequential impulses: the friction of each contact is bounded by its normal impulse,
+    // which only ever pushes. Impulses are accumulated and clamped as a whole.
+    pub fn solve(&mut self, a: &mut SolverBody, b: &mut SolverBody) {
+        let friction = self.friction;
+        for point in self.points.iter_mut() {
+            let limit = friction * point.normal_impulse;
+            for axis in 0..2 {
+                let tangent = point.tangents[axis];
+                let velocity = tangent.dot(b.velocity_at(point.offset_b) - a.velocity_at(point.offset_a));
+                let total = (point.tangent_impulse[axis] - velocity * point.tangent_mass[axis]).clamp(-limit, limit);
+                let impulse = tangent * (total - point.tangent_impulse[axis]);
+                point.tangent_impulse[axis] = total;
+                a.apply_impulse(-impulse, point.offset_a);
+                b.apply_impulse(impulse, point.offset_b);
+            }
+
+            let normal = point.contact.normal;
+            let velocity = normal.dot(b.velocity_at(point.offset_b) - a.velocity_at(point.offset_a));
+            let total = (point.normal_impulse + (point.target_velocity - velocity) * point.normal_mass).max(0.0);
+            let impulse = normal * (total - point.normal_impulse);
+            point.normal_impulse = total;
+            a.apply_impulse(-impulse, point.offset_a);
+            b.apply_impulse(impulse, point.offset_b);
+        }
+    }
+}
+
+// two directions across `normal`, the same ones for the same normal so friction can be warm started
+fn tangents(normal: Vector3<f32>) -> [Vector3<f32>; 2] {
+    let helper = if normal.x.abs() < 0.57 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let first = normal.cross(helper).normalize();
+    [first, normal.cross(first)]
+}
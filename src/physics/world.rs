@@ -0,0 +1,227 @@
+use std::collections::{ BTreeMap, BTreeSet };
+
+use cgmath::{ Matrix3, Quaternion, Vector3 };
+use cgmath::prelude::*;
+
+use crate::physics::narrow_phase;
+use crate::physics::rigid_body::BodyType;
+use crate::physics::solver::{ Manifold, SolverBody };
+use crate::world::bvh::{ Bvh, ProxyId };
+use crate::world::scene::Scene;
+
+// the most steps a single update catches up, a long frame slows the simulation down instead
+const MAX_STEPS: usize = 8;
+
+// Simulates the entities of a scene with a RigidBody or a Collider in fixed steps and writes their
+// motion back into their transforms. An entity with only a collider is static geometry.
+pub struct PhysicsWorld {
+    pub gravity: Vector3<f32>,
+    // seconds simulated per step
+    pub time_step: f32,
+    pub velocity_iterations: usize,
+    // time not simulated yet
+    accumulator: f32,
+    // fattened world boxes of the colliders, the leaves hold entity indices
+    broad_phase: Bvh<usize>,
+    proxies: Vec<Option<ProxyId>>,
+    // by entity pair, ordered so every run solves them in the same order
+    manifolds: BTreeMap<(usize, usize), Manifold>
+}
+
+impl PhysicsWorld {
+    pub fn new() -> PhysicsWorld {
+        PhysicsWorld {
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            time_step: 1.0 / 60.0,
+            velocity_iterations: 10,
+            accumulator: 0.0,
+            broad_phase: Bvh::default(),
+            proxies: Vec::new(),
+            manifolds: BTreeMap::new()
+        }
+    }
+
+    // run the steps that fit into the time passed, returns how many ran
+    pub fn update(&mut self, scene: &mut Scene, delta_time: f32) -> usize {
+        self.accumulator += delta_time;
+        let mut steps = 0;
+        while self.accumulator >= self.time_step && steps < MAX_STEPS {
+            self.step(scene);
+            self.accumulator -= self.time_step;
+            steps += 1;
+        }
+        if steps == MAX_STEPS {
+            self.accumulator = 0.0;
+        }
+        steps
+    }
+
+    // the contacts found in the last step
+    pub fn manifolds(&self) -> impl Iterator<Item = &Manifold> {
+        self.manifolds.values()
+    }
+
+    // Advances the simulation by one time_step: integrate the velocities, find the contacts, solve
+    // them and move the bodies.
+    pub fn step(&mut self, scene: &mut Scene) {
+        let time_step = self.time_step;
+        let (entities, mut bodies) = self.gather(scene);
+        self.update_broad_phase(scene, &entities, &bodies);
+
+        // every pair with a dynamic body whose boxes meet
+        let mut slot_of = vec![usize::MAX; scene.entities.len()];
+        for (slot, &entity) in entities.iter().enumerate() {
+            slot_of[entity] = slot;
+        }
+        let mut pairs = BTreeSet::new();
+        for (slot, &entity) in entities.iter().enumerate() {
+            let proxy = match self.proxies[entity] {
+                Some(proxy) if bodies[slot].inverse_mass > 0.0 => proxy,
+                _ => continue
+            };
+            for other in self.broad_phase.query_aabb(&self.broad_phase.fat_bounds(proxy)) {
+                if other != entity {
+                    pairs.insert((entity.min(other), entity.max(other)));
+                }
+            }
+        }
+
+        let previous = std::mem::take(&mut self.manifolds);
+        for (first, second) in pairs {
+            let (a, b) = (&scene.entities[first], &scene.entities[second]);
+            let (collider_a, collider_b) = (a.collider.as_ref().unwrap(), b.collider.as_ref().unwrap());
+            let (body_a, body_b) = (&bodies[slot_of[first]], &bodies[slot_of[second]]);
+            let contacts = narrow_phase::collide(
+                collider_a, body_a.position, body_a.orientation,
+                collider_b, body_b.position, body_b.orientation
+            );
+            if contacts.is_empty() {
+                continue;
+            }
+            let friction = (collider_a.friction * collider_b.friction).sqrt();
+            let restitution = collider_a.restitution.max(collider_b.restitution);
+            let manifold = Manifold::new((first, second), contacts, body_a, body_b, friction, restitution, previous.get(&(first, second)));
+            self.manifolds.insert((first, second), manifold);
+        }
+
+        for manifold in self.manifolds.values_mut() {
+            let (a, b) = (slot_of[manifold.entities.0], slot_of[manifold.entities.1]);
+            manifold.prepare(&bodies[a], &bodies[b], time_step);
+        }
+        for manifold in self.manifolds.values() {
+            let (a, b) = pair_mut(&mut bodies, slot_of[manifold.entities.0], slot_of[manifold.entities.1]);
+            manifold.warm_start(a, b);
+        }
+        for _ in 0..self.velocity_iterations {
+            for manifold in self.manifolds.values_mut() {
+                let (a, b) = pair_mut(&mut bodies, slot_of[manifold.entities.0], slot_of[manifold.entities.1]);
+                manifold.solve(a, b);
+            }
+        }
+
+        for (slot, &index) in entities.iter().enumerate() {
+            let entity = &mut scene.entities[index];
+            let body = match &mut entity.rigid_body {
+                Some(body) if body.body_type != BodyType::STATIC => body,
+                _ => continue
+            };
+            let state = &bodies[slot];
+            let position = state.position + state.linear_velocity * time_step;
+            let spin = Quaternion::from_sv(0.0, state.angular_velocity) * state.orientation * (0.5 * time_step);
+            let orientation = (state.orientation + spin).normalize();
+            body.linear_velocity = state.linear_velocity;
+            body.angular_velocity = state.angular_velocity;
+            body.write_back(&mut entity.transform, position, orientation);
+        }
+    }
+
+    // The entities taking part and their state. The velocities of the dynamic ones already have gravity
+    // and forces added over the step, then are damped by v / (1 + time_step * damping).
+    fn gather(&self, scene: &mut Scene) -> (Vec<usize>, Vec<SolverBody>) {
+        let time_step = self.time_step;
+        let mut entities = Vec::new();
+        let mut bodies = Vec::new();
+        for (index, entity) in scene.entities.iter_mut().enumerate() {
+            if entity.rigid_body.is_none() && entity.collider.is_none() {
+                continue;
+            }
+            // the scale is part of the collider, see Collider
+            debug_assert!(
+                entity.collider.is_none() || entity.transform.scale == Vector3::new(1.0, 1.0, 1.0),
+                "entity {} has a collider and a scale of {:?}", index, entity.transform.scale
+            );
+            let mut state = SolverBody {
+                position: entity.transform.position,
+                orientation: entity.transform.orientation(),
+                linear_velocity: Vector3::zero(),
+                angular_velocity: Vector3::zero(),
+                inverse_mass: 0.0,
+                inverse_inertia: Matrix3::zero()
+            };
+            if let Some(body) = &mut entity.rigid_body {
+                state.orientation = body.orientation(&entity.transform);
+                if body.body_type != BodyType::STATIC {
+                    state.linear_velocity = body.linear_velocity;
+                    state.angular_velocity = body.angular_velocity;
+                }
+                state.inverse_mass = body.inverse_mass();
+                if let Some(collider) = &entity.collider {
+                    state.inverse_inertia = collider.inverse_inertia(state.inverse_mass, state.orientation);
+                }
+                let (force, torque) = body.take_force_and_torque();
+                if state.inverse_mass > 0.0 {
+                    state.linear_velocity += (self.gravity * body.gravity_scale + force * state.inverse_mass) * time_step;
+                    state.angular_velocity += state.inverse_inertia * torque * time_step;
+                    state.linear_velocity *= 1.0 / (1.0 + time_step * body.linear_damping);
+                    state.angular_velocity *= 1.0 / (1.0 + time_step * body.angular_damping);
+                }
+            }
+            entities.push(index);
+            bodies.push(state);
+        }
+        (entities, bodies)
+    }
+
+    // Like Scene::update_bvh, over the colliders where they are now
+    fn update_broad_phase(&mut self, scene: &Scene, entities: &[usize], bodies: &[SolverBody]) {
+        while self.proxies.len() > scene.entities.len() {
+            if let Some(Some(proxy)) = self.proxies.pop() {
+                self.broad_phase.remove(proxy);
+            }
+        }
+        self.proxies.resize(scene.entities.len(), None);
+
+        let mut bounds = vec![None; scene.entities.len()];
+        for (&entity, state) in entities.iter().zip(bodies) {
+            bounds[entity] = scene.entities[entity].collider.as_ref()
+                .map(|collider| collider.world_bounds(state.position, state.orientation).expanded(narrow_phase::CONTACT_MARGIN));
+        }
+        for (index, bounds) in bounds.into_iter().enumerate() {
+            self.proxies[index] = match (self.proxies[index], bounds) {
+                (None, Some(bounds)) => Some(self.broad_phase.insert(bounds, index)),
+                (Some(proxy), Some(bounds)) => {
+                    if self.broad_phase.bounds(proxy) != bounds {
+                        self.broad_phase.update(proxy, bounds);
+                    }
+                    Some(proxy)
+                }
+                (Some(proxy), None) => {
+                    self.broad_phase.remove(proxy);
+                    None
+                }
+                (None, None) => None
+            };
+        }
+    }
+}
+
+// two different bodies borrowed at once
+fn pair_mut(bodies: &mut [SolverBody], a: usize, b: usize) -> (&mut SolverBody, &mut SolverBody) {
+    if a < b {
+        let (low, high) = bodies.split_at_mut(b);
+        (&mut low[a], &mut high[0])
+    } else {
+        let (low, high) = bodies.split_at_mut(a);
+        (&mut high[0], &mut low[b])
+    }
+}
@@ -0,0 +1,83 @@
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use cgmath::{ vec3, Vector3 };
+use rand::Rng;
+
+use crate::graphics::model::Model;
+use crate::world::entity::Entity;
+use crate::world::scene::Scene;
+use crate::world::transform::Transform;
+
+// where a rock circles the planet
+struct Orbit {
+    radius: f32,
+    angle: f32,
+    height: f32,
+    // radians per second
+    speed: f32
+}
+
+// A planet circled by a belt of rocks. The rocks share one model so the whole belt is a single
+// instanced draw per mesh of the rock.
+pub struct AsteroidBelt {
+    // the rocks are the entities following this one
+    first_rock: usize,
+    orbits: Vec<Orbit>
+}
+
+impl AsteroidBelt {
+    pub const RADIUS: f32 = 60.0;
+    // rocks are scattered this far around the circle of RADIUS
+    pub const SPREAD: f32 = 10.0;
+
+    pub fn new(scene: &mut Scene, rocks: usize) -> AsteroidBelt {
+        let planet = Model::new("resources/objects/planet/planet.obj");
+        let rock = Rc::new(Model::new("resources/objects/rock/rock.obj"));
+        AsteroidBelt::with_models(scene, planet, rock, rocks, &mut rand::thread_rng())
+    }
+
+    // add the planet at the origin and `rocks` copies of `rock` around it
+    pub fn with_models<R: Rng>(scene: &mut Scene, planet: Model, rock: Rc<Model>, rocks: usize, rng: &mut R) -> AsteroidBelt {
+        scene.entities.push(Entity::new(Some(planet), Transform::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(4.0, 4.0, 4.0))));
+
+        let first_rock = scene.entities.len();
+        let mut orbits = Vec::with_capacity(rocks);
+        for i in 0..rocks {
+            // evenly around the circle, then displaced inside the spread
+            let radius = AsteroidBelt::RADIUS + rng.gen_range(-AsteroidBelt::SPREAD, AsteroidBelt::SPREAD);
+            let orbit = Orbit {
+                radius,
+                angle: i as f32 / rocks as f32 * 2.0 * PI,
+                height: rng.gen_range(-AsteroidBelt::SPREAD, AsteroidBelt::SPREAD) * 0.4,
+                // the same pace along the orbit, inner rocks go round faster
+                speed: 2.0 / radius
+            };
+            let scale = rng.gen_range(0.05, 0.25);
+            let rotation = vec3(rng.gen_range(0.0, 2.0 * PI), rng.gen_range(0.0, 2.0 * PI), rng.gen_range(0.0, 2.0 * PI));
+            let transform = Transform::new(orbit.position(), rotation, vec3(scale, scale, scale));
+            scene.entities.push(Entity::with_shared_model(Some(rock.clone()), transform));
+            orbits.push(orbit);
+        }
+        AsteroidBelt { first_rock, orbits }
+    }
+
+    pub fn rocks(&self) -> usize {
+        self.orbits.len()
+    }
+
+    // move every rock along its orbit
+    pub fn update(&mut self, scene: &mut Scene, delta_time: f32) {
+        let rocks = &mut scene.entities[self.first_rock..self.first_rock + self.orbits.len()];
+        for (orbit, rock) in self.orbits.iter_mut().zip(rocks) {
+            orbit.angle += orbit.speed * delta_time;
+            rock.transform.position = orbit.position();
+        }
+    }
+}
+
+impl Orbit {
+    fn position(&self) -> Vector3<f32> {
+        vec3(self.angle.sin() * self.radius, self.height, self.angle.cos() * self.radius)
+    }
+}
@@ -0,0 +1,264 @@
+use cgmath::{ Matrix4, Vector3 };
+use cgmath::prelude::*;
+
+// Axis-aligned bounding box. The empty box has min above max, so any point or box grows it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Aabb::empty()
+    }
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)
+        }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = Vector3<f32>>>(points: I) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for point in points {
+            aabb.grow(point);
+        }
+        aabb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    // half the size along each axis
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    // the cost measure of the BVH, proportional to the chance a random ray hits the box
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // grown by `margin` on every side
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        let margin = Vector3::new(margin, margin, margin);
+        Aabb { min: self.min - margin, max: self.max + margin }
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y && self.min.z <= other.min.z
+            && self.max.x >= other.max.x && self.max.y >= other.max.y && self.max.z >= other.max.z
+    }
+
+    // zero inside the box
+    pub fn distance_to_point(&self, point: Vector3<f32>) -> f32 {
+        let closest = Vector3::new(
+            point.x.clamp(self.min.x, self.max.x),
+            point.y.clamp(self.min.y, self.max.y),
+            point.z.clamp(self.min.z, self.max.z)
+        );
+        (point - closest).magnitude()
+    }
+
+    // Distance along `ray` at which it enters the box, zero when it starts inside (slab test)
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        if self.is_empty() {
+            return None;
+        }
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from a ray parallel to a slab it starts on keeps the previous bounds
+            near = near.max(t0);
+            far = far.min(t1);
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    pub fn grow(&mut self, point: Vector3<f32>) {
+        self.min = Vector3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = Vector3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z))
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x
+            && point.y >= self.min.y && point.y <= self.max.y
+            && point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    // touching boxes intersect
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x
+            && self.min.y <= other.max.y && self.max.y >= other.min.y
+            && self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    // The box around this one once transformed by `matrix`. Each new extent adds up the old extents
+    // projected on its axis, which is exact for the transformed corners (Arvo).
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = (matrix * self.center().extend(1.0)).truncate();
+        let e = self.extents();
+        let extents = Vector3::new(
+            matrix.x.x.abs() * e.x + matrix.y.x.abs() * e.y + matrix.z.x.abs() * e.z,
+            matrix.x.y.abs() * e.x + matrix.y.y.abs() * e.y + matrix.z.y.abs() * e.z,
+            matrix.x.z.abs() * e.x + matrix.y.z.abs() * e.y + matrix.z.z.abs() * e.z
+        );
+        Aabb { min: center - extents, max: center + extents }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32
+}
+
+impl Default for BoundingSphere {
+    fn default() -> Self {
+        BoundingSphere { center: Vector3::zero(), radius: 0.0 }
+    }
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vector3<f32>, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    // Centered on the bounding box of the points, so it is not the smallest sphere but never far from it
+    // and stable when a mesh is re-imported.
+    pub fn from_points(points: &[Vector3<f32>]) -> BoundingSphere {
+        let aabb = Aabb::from_points(points.iter().cloned());
+        if aabb.is_empty() {
+            return BoundingSphere::default();
+        }
+        let center = aabb.center();
+        let radius = points.iter().map(|p| (p - center).magnitude2()).fold(0.0, f32::max).sqrt();
+        BoundingSphere { center, radius }
+    }
+
+    // a sphere around `spheres`, centered on the box around them
+    pub fn enclosing(spheres: &[BoundingSphere]) -> BoundingSphere {
+        let aabb = spheres.iter().fold(Aabb::empty(), |aabb, sphere| aabb.union(&sphere.aabb()));
+        if aabb.is_empty() {
+            return BoundingSphere::default();
+        }
+        let center = aabb.center();
+        let radius = spheres.iter().map(|s| (s.center - center).magnitude() + s.radius).fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb { min: self.center - r, max: self.center + r }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        (point - self.center).magnitude2() <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        let radius = self.radius + other.radius;
+        (other.center - self.center).magnitude2() <= radius * radius
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let c = self.center;
+        let closest = Vector3::new(c.x.clamp(aabb.min.x, aabb.max.x), c.y.clamp(aabb.min.y, aabb.max.y), c.z.clamp(aabb.min.z, aabb.max.z));
+        (closest - c).magnitude2() <= self.radius * self.radius
+    }
+
+    // distance along `ray` at which it enters the sphere, zero when it starts inside
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        let to_center = self.center - ray.origin;
+        let along = to_center.dot(ray.direction);
+        let squared = to_center.magnitude2() - along * along;
+        let radius2 = self.radius * self.radius;
+        if squared > radius2 {
+            return None;
+        }
+        let half_chord = (radius2 - squared).sqrt();
+        if along + half_chord < 0.0 {
+            return None;
+        }
+        Some((along - half_chord).max(0.0))
+    }
+
+    // the radius grows with the largest scale of `matrix`, so non-uniform scales stay covered
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let center = (matrix * self.center.extend(1.0)).truncate();
+        let scale = matrix.x.truncate().magnitude2()
+            .max(matrix.y.truncate().magnitude2())
+            .max(matrix.z.truncate().magnitude2())
+            .sqrt();
+        BoundingSphere { center, radius: self.radius * scale }
+    }
+}
+
+// A half-line, the direction is normalized so distances along it are in world units
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray { origin, direction: direction.normalize() }
+    }
+
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+// The bounds of a model placed in the world
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct WorldBounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere
+}
+
+impl WorldBounds {
+    pub fn new(aabb: &Aabb, sphere: &BoundingSphere, matrix: &Matrix4<f32>) -> WorldBounds {
+        WorldBounds {
+            aabb: aabb.transformed(matrix),
+            sphere: sphere.transformed(matrix)
+        }
+    }
+}
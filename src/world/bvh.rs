@@ -0,0 +1,459 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use cgmath::Vector3;
+
+use crate::world::bounds::{ Aabb, BoundingSphere, Ray };
+use crate::world::frustum::{ Containment, Frustum };
+
+const NULL: usize = usize::MAX;
+
+// how far leaves are fattened by default, in world units
+pub const DEFAULT_MARGIN: f32 = 0.1;
+
+// Identifies a leaf of a Bvh, stable for as long as the leaf is in the tree
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ProxyId(usize);
+
+struct Node<T> {
+    // fattened by the margin for leaves, around both children otherwise
+    aabb: Aabb,
+    // the bounds given for a leaf
+    bounds: Aabb,
+    parent: usize,
+    children: [usize; 2],
+    // 0 for leaves, -1 for nodes in the free list
+    height: i32,
+    data: Option<T>
+}
+
+impl<T> Node<T> {
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NULL
+    }
+}
+
+// Dynamic AABB tree. Leaves keep a box fattened by `margin` so bounds moving a little stay inside and
+// only need a refit when they leave it. Insertion picks the sibling adding the least surface area and
+// rotations keep the tree balanced, as in Box2D's b2DynamicTree.
+pub struct Bvh<T: Copy> {
+    nodes: Vec<Node<T>>,
+    root: usize,
+    free: Vec<usize>,
+    leaves: usize,
+    pub margin: f32
+}
+
+impl<T: Copy> Default for Bvh<T> {
+    fn default() -> Self {
+        Bvh::new(DEFAULT_MARGIN)
+    }
+}
+
+impl<T: Copy> Bvh<T> {
+    pub fn new(margin: f32) -> Bvh<T> {
+        Bvh {
+            nodes: Vec::new(),
+            root: NULL,
+            free: Vec::new(),
+            leaves: 0,
+            margin
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+
+    // levels below the root, 0 for a single leaf
+    pub fn height(&self) -> i32 {
+        if self.root == NULL { 0 } else { self.nodes[self.root].height }
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = NULL;
+        self.leaves = 0;
+    }
+
+    pub fn insert(&mut self, bounds: Aabb, data: T) -> ProxyId {
+        let leaf = self.allocate();
+        let node = &mut self.nodes[leaf];
+        node.aabb = bounds.expanded(self.margin);
+        node.bounds = bounds;
+        node.height = 0;
+        node.data = Some(data);
+        self.insert_leaf(leaf);
+        self.leaves += 1;
+        ProxyId(leaf)
+    }
+
+    pub fn remove(&mut self, proxy: ProxyId) -> T {
+        let data = self.nodes[proxy.0].data.take().expect("proxy is not in the tree");
+        self.remove_leaf(proxy.0);
+        self.release(proxy.0);
+        self.leaves -= 1;
+        data
+    }
+
+    // Move a leaf to new bounds. Returns whether it left its fat box and was reinserted, otherwise only
+    // the bounds used by the queries change.
+    pub fn update(&mut self, proxy: ProxyId, bounds: Aabb) -> bool {
+        let leaf = proxy.0;
+        self.nodes[leaf].bounds = bounds;
+        if self.nodes[leaf].aabb.contains(&bounds) {
+            return false;
+        }
+        self.remove_leaf(leaf);
+        self.nodes[leaf].aabb = bounds.expanded(self.margin);
+        self.insert_leaf(leaf);
+        true
+    }
+
+    pub fn data(&self, proxy: ProxyId) -> T {
+        self.nodes[proxy.0].data.expect("proxy is not in the tree")
+    }
+
+    pub fn bounds(&self, proxy: ProxyId) -> Aabb {
+        self.nodes[proxy.0].bounds
+    }
+
+    pub fn fat_bounds(&self, proxy: ProxyId) -> Aabb {
+        self.nodes[proxy.0].aabb
+    }
+
+    // leaves whose bounds overlap `aabb`
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<T> {
+        self.query(|node| node.intersects(aabb))
+    }
+
+    pub fn query_sphere(&self, sphere: &BoundingSphere) -> Vec<T> {
+        self.query(|node| sphere.intersects_aabb(node))
+    }
+
+    // leaves whose bounds are at least partly in `frustum`, subtrees fully inside are taken without testing
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<T> {
+        let mut results = Vec::new();
+        let mut stack = self.root_stack();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            match frustum.classify_aabb(&node.aabb) {
+                Containment::OUTSIDE => {}
+                Containment::INSIDE => self.collect_leaves(index, &mut results),
+                Containment::INTERSECTS if node.is_leaf() => {
+                    if frustum.intersects_aabb(&node.bounds) {
+                        results.push(node.data.unwrap());
+                    }
+                }
+                Containment::INTERSECTS => stack.extend_from_slice(&node.children)
+            }
+        }
+        results
+    }
+
+    // the closest leaf whose bounds `ray` enters before `max_distance`, with that distance
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<(T, f32)> {
+        self.raycast_with(ray, max_distance, |_, distance| Some(distance))
+    }
+
+    // Like raycast, with `hit` refining each leaf the ray enters the bounds of, e.g. testing the
+    // triangles. It gets the distance to the bounds and returns the exact one, or None for a miss.
+    pub fn raycast_with<F>(&self, ray: &Ray, max_distance: f32, mut hit: F) -> Option<(T, f32)>
+    where
+        F: FnMut(T, f32) -> Option<f32>
+    {
+        let mut closest: Option<(T, f32)> = None;
+        let mut max_distance = max_distance;
+        let mut stack = self.root_stack();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            match node.aabb.intersect_ray(ray) {
+                Some(distance) if distance <= max_distance => {}
+                _ => continue
+            }
+            if !node.is_leaf() {
+                stack.extend_from_slice(&node.children);
+                continue;
+            }
+            let data = node.data.unwrap();
+            let distance = match node.bounds.intersect_ray(ray).and_then(|distance| hit(data, distance)) {
+                Some(distance) if distance <= max_distance => distance,
+                _ => continue
+            };
+            // later nodes further than the hit are skipped
+            max_distance = distance;
+            closest = Some((data, distance));
+        }
+        closest
+    }
+
+    // The leaf with the bounds closest to `point` and their distance, zero when inside of them. Nodes
+    // are visited nearest first so most of the tree is never reached.
+    pub fn nearest(&self, point: Vector3<f32>) -> Option<(T, f32)> {
+        let mut heap = BinaryHeap::new();
+        if self.root != NULL {
+            heap.push(Candidate { distance: self.nodes[self.root].aabb.distance_to_point(point), node: self.root });
+        }
+        while let Some(Candidate { distance, node: index }) = heap.pop() {
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                // pushed with the distance to its own bounds below, nothing left can be closer
+                if distance == node.bounds.distance_to_point(point) {
+                    return Some((node.data.unwrap(), distance));
+                }
+                heap.push(Candidate { distance: node.bounds.distance_to_point(point), node: index });
+                continue;
+            }
+            for &child in &node.children {
+                heap.push(Candidate { distance: self.nodes[child].aabb.distance_to_point(point), node: child });
+            }
+        }
+        None
+    }
+
+    // panics when a parent does not enclose its children or a height or parent link is wrong
+    pub fn validate(&self) {
+        if self.root == NULL {
+            assert_eq!(self.leaves, 0);
+            return;
+        }
+        assert_eq!(self.nodes[self.root].parent, NULL);
+        let mut leaves = 0;
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                assert_eq!(node.height, 0);
+                assert!(node.aabb.contains(&node.bounds));
+                leaves += 1;
+                continue;
+            }
+            let [a, b] = node.children;
+            assert_eq!(self.nodes[a].parent, index);
+            assert_eq!(self.nodes[b].parent, index);
+            assert_eq!(node.height, 1 + self.nodes[a].height.max(self.nodes[b].height));
+            assert!((self.nodes[a].height - self.nodes[b].height).abs() <= 1, "unbalanced node");
+            assert!(node.aabb.contains(&self.nodes[a].aabb) && node.aabb.contains(&self.nodes[b].aabb));
+            stack.extend_from_slice(&node.children);
+        }
+        assert_eq!(leaves, self.leaves);
+    }
+
+    fn root_stack(&self) -> Vec<usize> {
+        if self.root == NULL { Vec::new() } else { vec![self.root] }
+    }
+
+    fn query<F: Fn(&Aabb) -> bool>(&self, overlaps: F) -> Vec<T> {
+        let mut results = Vec::new();
+        let mut stack = self.root_stack();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !overlaps(&node.aabb) {
+                continue;
+            }
+            if node.is_leaf() {
+                if overlaps(&node.bounds) {
+                    results.push(node.data.unwrap());
+                }
+            } else {
+                stack.extend_from_slice(&node.children);
+            }
+        }
+        results
+    }
+
+    fn collect_leaves(&self, index: usize, results: &mut Vec<T>) {
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                results.push(node.data.unwrap());
+            } else {
+                stack.extend_from_slice(&node.children);
+            }
+        }
+    }
+
+    fn allocate(&mut self) -> usize {
+        let node = Node { aabb: Aabb::empty(), bounds: Aabb::empty(), parent: NULL, children: [NULL, NULL], height: 0, data: None };
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, index: usize) {
+        self.nodes[index].height = -1;
+        self.free.push(index);
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL;
+            return;
+        }
+
+        // walk down to the sibling costing the least area, counting what the ancestors grow by
+        let leaf_aabb = self.nodes[leaf].aabb;
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let node = &self.nodes[index];
+            let area = node.aabb.surface_area();
+            let combined_area = node.aabb.union(&leaf_aabb).surface_area();
+            // a new parent of this node and the leaf
+            let cost = 2.0 * combined_area;
+            // pushing the leaf further down grows this node anyway
+            let inheritance = 2.0 * (combined_area - area);
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let grown = child.aabb.union(&leaf_aabb).surface_area();
+                if child.is_leaf() { grown + inheritance } else { grown - child.aabb.surface_area() + inheritance }
+            };
+            let [a, b] = node.children;
+            let (cost_a, cost_b) = (child_cost(a), child_cost(b));
+            if cost < cost_a && cost < cost_b {
+                break;
+            }
+            index = if cost_a < cost_b { a } else { b };
+        }
+
+        // a new parent for the sibling and the leaf
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate();
+        self.nodes[new_parent].parent = old_parent;
+        self.nodes[new_parent].aabb = leaf_aabb.union(&self.nodes[sibling].aabb);
+        self.nodes[new_parent].height = self.nodes[sibling].height + 1;
+        self.nodes[new_parent].children = [sibling, leaf];
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+        if old_parent == NULL {
+            self.root = new_parent;
+        } else {
+            self.replace_child(old_parent, sibling, new_parent);
+        }
+
+        self.refit(self.nodes[leaf].parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL;
+            return;
+        }
+        let parent = self.nodes[leaf].parent;
+        let grand_parent = self.nodes[parent].parent;
+        let [a, b] = self.nodes[parent].children;
+        let sibling = if a == leaf { b } else { a };
+
+        // the sibling takes the place of the parent
+        self.nodes[sibling].parent = grand_parent;
+        self.release(parent);
+        if grand_parent == NULL {
+            self.root = sibling;
+        } else {
+            self.replace_child(grand_parent, parent, sibling);
+            self.refit(grand_parent);
+        }
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        let children = &mut self.nodes[parent].children;
+        if children[0] == old {
+            children[0] = new;
+        } else {
+            children[1] = new;
+        }
+    }
+
+    // rebalance and recompute the boxes and heights from `index` up to the root
+    fn refit(&mut self, mut index: usize) {
+        while index != NULL {
+            index = self.balance(index);
+            let [a, b] = self.nodes[index].children;
+            self.nodes[index].height = 1 + self.nodes[a].height.max(self.nodes[b].height);
+            self.nodes[index].aabb = self.nodes[a].aabb.union(&self.nodes[b].aabb);
+            index = self.nodes[index].parent;
+        }
+    }
+
+    // Rotate the taller child of `a` up when the heights of its children differ by more than one.
+    // Returns the node now in the place of `a`.
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+            return a;
+        }
+        let [b, c] = self.nodes[a].children;
+        let difference = self.nodes[c].height - self.nodes[b].height;
+        if difference > 1 {
+            self.rotate_up(a, c, 1)
+        } else if difference < -1 {
+            self.rotate_up(a, b, 0)
+        } else {
+            a
+        }
+    }
+
+    // `child` at `slot` of `a` takes its place, `a` keeps the other child and the lower grandchild
+    fn rotate_up(&mut self, a: usize, child: usize, slot: usize) -> usize {
+        let other = self.nodes[a].children[1 - slot];
+        let [f, g] = self.nodes[child].children;
+
+        // child becomes the parent of a
+        self.nodes[child].children[0] = a;
+        self.nodes[child].parent = self.nodes[a].parent;
+        self.nodes[a].parent = child;
+        match self.nodes[child].parent {
+            NULL => self.root = child,
+            parent => self.replace_child(parent, a, child)
+        }
+
+        let (kept, moved) = if self.nodes[f].height > self.nodes[g].height { (f, g) } else { (g, f) };
+        self.nodes[child].children[1] = kept;
+        self.nodes[a].children[slot] = moved;
+        self.nodes[moved].parent = a;
+        self.nodes[a].aabb = self.nodes[other].aabb.union(&self.nodes[moved].aabb);
+        self.nodes[a].height = 1 + self.nodes[other].height.max(self.nodes[moved].height);
+        self.nodes[child].aabb = self.nodes[a].aabb.union(&self.nodes[kept].aabb);
+        self.nodes[child].height = 1 + self.nodes[a].height.max(self.nodes[kept].height);
+        child
+    }
+}
+
+// a node waiting in the nearest search, the heap pops the smallest distance first
+struct Candidate {
+    distance: f32,
+    node: usize
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance).then_with(|| other.node.cmp(&self.node))
+    }
+}
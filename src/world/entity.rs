@@ -1,19 +1,53 @@
+use std::cell::RefCell;
+use std::rc::{ Rc, Weak };
+
 use crate::{graphics::model::Model, world::component::Component};
+use crate::physics::collider::Collider;
+use crate::physics::rigid_body::RigidBody;
+use crate::world::bounds::WorldBounds;
 use crate::world::transform::Transform;
 
 pub struct Entity {
     components: Vec<Component>,
     pub transform: Transform,
-    pub model: Option<Model>
+    // entities sharing a model are drawn in a single instanced batch
+    pub model: Option<Rc<Model>>,
+    // simulated by a PhysicsWorld, a collider without a body is static geometry
+    pub rigid_body: Option<RigidBody>,
+    pub collider: Option<Collider>,
+    // world bounds with the transform and model they were computed for, the transform is changed in place.
+    // The weak reference keeps the model's allocation, so a new model can't come back at the same address.
+    bounds_cache: RefCell<Option<(Transform, Weak<Model>, WorldBounds)>>
 }
 
 impl Entity {
     pub fn new(model: Option<Model>, transform: Transform) -> Self {
+        Entity::with_shared_model(model.map(Rc::new), transform)
+    }
+
+    pub fn with_shared_model(model: Option<Rc<Model>>, transform: Transform) -> Self {
         Entity {
             components: Vec::new(),
             transform,
-            model
+            model,
+            rigid_body: None,
+            collider: None,
+            bounds_cache: RefCell::new(None)
+        }
+    }
+
+    // bounds of the model in world space, recomputed only when the transform or the model changed
+    pub fn world_bounds(&self) -> Option<WorldBounds> {
+        let model = self.model.as_ref()?;
+        let model_ref = Rc::downgrade(model);
+        if let Some((transform, cached_model, bounds)) = self.bounds_cache.borrow().as_ref() {
+            if *transform == self.transform && Weak::ptr_eq(cached_model, &model_ref) {
+                return Some(*bounds);
+            }
         }
+        let bounds = WorldBounds::new(&model.bounds, &model.sphere, &self.transform.matrix());
+        self.bounds_cache.replace(Some((self.transform, model_ref, bounds)));
+        Some(bounds)
     }
 
     pub fn update(&mut self) {
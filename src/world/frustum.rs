@@ -0,0 +1,95 @@
+use cgmath::{ Matrix4, Vector3, Vector4 };
+use cgmath::prelude::*;
+
+use crate::world::bounds::{ Aabb, BoundingSphere };
+
+// Points with normal.dot(p) + distance >= 0 are on the inner side
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32
+}
+
+impl Plane {
+    // from the (a, b, c, d) coefficients, normalized so distances are in world units
+    fn from_coefficients(coefficients: Vector4<f32>) -> Plane {
+        let length = coefficients.truncate().magnitude();
+        Plane { normal: coefficients.truncate() / length, distance: coefficients.w / length }
+    }
+
+    pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Containment {
+    OUTSIDE,
+    INTERSECTS,
+    INSIDE
+}
+
+// The six planes bounding what a projection sees: left, right, bottom, top, near and far, facing in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Frustum {
+    pub planes: [Plane; 6]
+}
+
+impl Frustum {
+    // Gribb-Hartmann extraction from projection * view, or from a projection alone for a view-space
+    // frustum. Clip space is OpenGL's, -w <= z <= w.
+    pub fn from_matrix(view_projection: &Matrix4<f32>) -> Frustum {
+        let m = view_projection;
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                Plane::from_coefficients(w + x),
+                Plane::from_coefficients(w - x),
+                Plane::from_coefficients(w + y),
+                Plane::from_coefficients(w - y),
+                Plane::from_coefficients(w + z),
+                Plane::from_coefficients(w - z)
+            ]
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative: a box near a corner of the frustum may pass while outside of it, which only costs a draw
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.classify_aabb(aabb) != Containment::OUTSIDE
+    }
+
+    // whether the box is outside of a plane, inside of all of them or in between
+    pub fn classify_aabb(&self, aabb: &Aabb) -> Containment {
+        if aabb.is_empty() {
+            return Containment::OUTSIDE;
+        }
+        let mut containment = Containment::INSIDE;
+        for plane in &self.planes {
+            // the corners furthest along and against the normal
+            let n = plane.normal;
+            let positive = Vector3::new(
+                if n.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if n.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if n.z >= 0.0 { aabb.max.z } else { aabb.min.z }
+            );
+            if plane.signed_distance(positive) < 0.0 {
+                return Containment::OUTSIDE;
+            }
+            let negative = aabb.min + aabb.max - positive;
+            if plane.signed_distance(negative) < 0.0 {
+                containment = Containment::INTERSECTS;
+            }
+        }
+        containment
+    }
+}
@@ -0,0 +1,76 @@
+use cgmath::{ vec3, Vector3 };
+use cgmath::prelude::*;
+
+// A light infinitely far away (sun, moon), only its direction matters
+pub struct DirectionalLight {
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub ambient: f32
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        DirectionalLight {
+            direction: vec3(-0.3, -1.0, -0.4).normalize(),
+            color: vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            ambient: 0.15
+        }
+    }
+}
+
+// A light radiating in every direction from a point, fading out to nothing at `radius`
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub radius: f32
+}
+
+impl PointLight {
+    pub fn new(position: Vector3<f32>, color: Vector3<f32>, intensity: f32, radius: f32) -> PointLight {
+        PointLight {
+            position,
+            color,
+            intensity,
+            radius
+        }
+    }
+}
+
+// A cone of light, full intensity inside `inner_angle` and fading out to `outer_angle` (in degrees)
+pub struct SpotLight {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub radius: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32
+}
+
+impl SpotLight {
+    pub fn new(position: Vector3<f32>, direction: Vector3<f32>, color: Vector3<f32>, intensity: f32, radius: f32) -> SpotLight {
+        SpotLight {
+            position,
+            direction: direction.normalize(),
+            color,
+            intensity,
+            radius,
+            inner_angle: 20.0,
+            outer_angle: 30.0
+        }
+    }
+
+    // center and radius of the smallest sphere enclosing the cone
+    pub fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+        let half_angle = self.outer_angle.to_radians();
+        if half_angle > std::f32::consts::FRAC_PI_4 {
+            (self.position + self.direction * self.radius * half_angle.cos(), self.radius * half_angle.sin())
+        } else {
+            let radius = self.radius / (2.0 * half_angle.cos());
+            (self.position + self.direction * radius, radius)
+        }
+    }
+}
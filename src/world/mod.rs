@@ -2,4 +2,11 @@ pub mod scene;
 pub mod entity;
 pub mod component;
 pub mod transform;
-pub mod skybox;
\ No newline at end of file
+pub mod skybox;
+pub mod light;
+pub mod scene_file;
+pub mod asteroids;
+pub mod bounds;
+pub mod frustum;
+pub mod bvh;
+pub mod picking;
@@ -0,0 +1,72 @@
+use cgmath::Vector3;
+use cgmath::prelude::*;
+
+use crate::graphics::mesh::Mesh;
+use crate::world::bounds::Ray;
+
+// The closest triangle along a ray, see Scene::raycast
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hit {
+    pub entity: usize,
+    pub mesh: usize,
+    // the corners are indices[3 * triangle..3 * triangle + 3] of the mesh
+    pub triangle: usize,
+    // weights of the three corners at the hit, they sum to one
+    pub barycentrics: Vector3<f32>,
+    pub distance: f32,
+    pub position: Vector3<f32>,
+    // in world space, facing back along the ray
+    pub normal: Vector3<f32>
+}
+
+// Distance along `ray` to the triangle abc and the weights of b and c there (Moller-Trumbore). Both
+// sides are hit. The direction need not be normalized, the distance is then in its lengths.
+pub fn intersect_triangle(ray: &Ray, a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Option<(f32, f32, f32)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(ac);
+    let determinant = ab.dot(p);
+    // parallel to the plane, or a degenerate triangle; relative so it holds at any scale
+    if determinant.abs() <= f32::EPSILON * ab.cross(ac).magnitude() * ray.direction.magnitude() {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let to_origin = ray.origin - a;
+    let u = to_origin.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(ab);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(q) * inverse;
+    if distance < 0.0 {
+        return None;
+    }
+    Some((distance, u, v))
+}
+
+// The closest triangle of `mesh` along `ray` within `max_distance`, with the distance and barycentrics.
+// The ray is in the space of the mesh, its bounds are tested before any triangle.
+pub fn raycast_mesh(mesh: &Mesh, ray: &Ray, max_distance: f32) -> Option<(usize, f32, Vector3<f32>)> {
+    match mesh.bounds.intersect_ray(ray) {
+        Some(distance) if distance <= max_distance => {}
+        _ => return None
+    }
+    let mut closest = None;
+    let mut max_distance = max_distance;
+    for (triangle, corners) in mesh.indices.chunks_exact(3).enumerate() {
+        let a = mesh.vertices[corners[0] as usize].position;
+        let b = mesh.vertices[corners[1] as usize].position;
+        let c = mesh.vertices[corners[2] as usize].position;
+        if let Some((distance, u, v)) = intersect_triangle(ray, a, b, c) {
+            if distance <= max_distance {
+                max_distance = distance;
+                closest = Some((triangle, distance, Vector3::new(1.0 - u - v, u, v)));
+            }
+        }
+    }
+    closest
+}
@@ -1,10 +1,92 @@
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::rc::Rc;
+
+use cgmath::{ Matrix3, Matrix4, Vector3 };
+use cgmath::prelude::*;
+
 use crate::{graphics::shader::Shader, world::entity::Entity};
+use crate::graphics::device::{ GlDevice, RenderDevice };
+use crate::graphics::draw_queue::SortKey;
+use crate::graphics::instancing::InstanceBuffer;
+use crate::graphics::material::RenderQueue;
+use crate::graphics::mesh::Mesh;
+use crate::graphics::model::Model;
+use crate::graphics::uniform_buffer::{ ObjectData, UniformRing };
+use crate::world::bounds::{ Aabb, BoundingSphere, Ray, WorldBounds };
+use crate::world::bvh::{ Bvh, ProxyId };
+use crate::world::frustum::Frustum;
+use crate::world::picking::{ raycast_mesh, Hit };
 
+use super::light::{ DirectionalLight, PointLight, SpotLight };
 use super::skybox::SkyBox;
 
+// One mesh of an entity ready to be drawn on its own
+pub struct DrawItem {
+    pub entity: usize,
+    pub mesh: usize,
+    pub model_matrix: Matrix4<f32>,
+    // distance from the viewer to the center of the mesh
+    pub distance: f32
+}
+
+// One mesh of a model and the per-instance data of every entity sharing that model
+pub struct InstanceBatch {
+    // the first entity with the model
+    pub entity: usize,
+    pub mesh: usize,
+    pub instances: Vec<ObjectData>
+}
+
+// How many entities with a model a frustum test saw and let through
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct CullingStats {
+    pub tested: u32,
+    pub visible: u32
+}
+
+impl CullingStats {
+    pub fn culled(&self) -> u32 {
+        self.tested - self.visible
+    }
+}
+
+impl AddAssign for CullingStats {
+    fn add_assign(&mut self, other: CullingStats) {
+        self.tested += other.tested;
+        self.visible += other.visible;
+    }
+}
+
+// Which entities are in view, indexed like Scene::entities. Entities added since are visible.
+#[derive(Default)]
+pub struct Culling {
+    // empty when nothing is culled
+    visible: Vec<bool>,
+    pub stats: CullingStats
+}
+
+impl Culling {
+    // every entity visible, e.g. for the shadow casters
+    pub fn none() -> Culling {
+        Culling::default()
+    }
+
+    pub fn is_visible(&self, entity: usize) -> bool {
+        self.visible.get(entity).cloned().unwrap_or(true)
+    }
+}
+
 pub struct Scene {
     pub entities: Vec<Entity>,
-    pub skybox: SkyBox
+    pub skybox: SkyBox,
+    pub directional_light: DirectionalLight,
+    pub point_lights: Vec<PointLight>,
+    pub spot_lights: Vec<SpotLight>,
+    // world boxes of the entities with a model, synchronized by update_bvh
+    bvh: Bvh<usize>,
+    // the leaf of each entity
+    proxies: Vec<Option<ProxyId>>
 }
 
 impl Scene {
@@ -22,21 +104,297 @@ impl Scene {
                 &skybox_shader
             )
         };
+        Scene::with_skybox(skybox)
+    }
 
+    // an empty scene around an already created skybox
+    pub fn with_skybox(skybox: SkyBox) -> Scene {
         Scene {
             entities: Vec::new(),
-            skybox
+            skybox,
+            directional_light: DirectionalLight::default(),
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+            bvh: Bvh::default(),
+            proxies: Vec::new()
         }
     }
 
-    pub fn render(&mut self, shader: &Shader) {
-        for entity in self.entities.iter_mut() {
-            if let Some(model) = &mut entity.model {
-                unsafe {
-                    model.render(&entity.transform, shader);
+    // draw every mesh instanced, `shader` is built with the INSTANCED keyword
+    pub fn render(&self, shader: &Shader, instances: &InstanceBuffer) {
+        self.render_filtered(shader, instances, |_| true);
+    }
+
+    // render only the meshes accepted by `filter`, e.g. opaque or transparent ones
+    pub fn render_filtered<F: Fn(&Mesh) -> bool>(&self, shader: &Shader, instances: &InstanceBuffer, filter: F) {
+        self.render_filtered_with(unsafe { &mut GlDevice::current() }, shader, instances, filter);
+    }
+
+    // one instanced draw per mesh of each model, however many entities share it
+    pub fn render_filtered_with<D, F>(&self, device: &mut D, shader: &Shader, instances: &InstanceBuffer, filter: F)
+    where
+        D: RenderDevice,
+        F: Fn(&Mesh) -> bool
+    {
+        self.render_visible_with(device, shader, instances, &Culling::none(), filter);
+    }
+
+    // render_filtered_with skipping the entities culled by `culling`
+    pub fn render_visible_with<D, F>(&self, device: &mut D, shader: &Shader, instances: &InstanceBuffer, culling: &Culling, filter: F)
+    where
+        D: RenderDevice,
+        F: Fn(&Mesh) -> bool
+    {
+        let mut batches = self.collect_visible_batches(culling, filter);
+        self.sort_batches(&mut batches, shader.id);
+        self.draw_batches_with(device, shader, instances, &batches);
+    }
+
+    // order batches by queue and material so the textures and states they share are set once
+    pub fn sort_batches(&self, batches: &mut [InstanceBatch], program: u32) {
+        batches.sort_by_cached_key(|batch| {
+            let mesh = &self.entities[batch.entity].model.as_ref().unwrap().meshes[batch.mesh];
+            SortKey::new(mesh.material.queue(), program, mesh.material_key(), 0.0)
+        });
+    }
+
+    // draw batches collected once for several passes, e.g. the cascades of a shadow map
+    pub fn draw_batches_with<D: RenderDevice>(&self, device: &mut D, shader: &Shader, instances: &InstanceBuffer, batches: &[InstanceBatch]) {
+        device.use_program(shader.id);
+        for batch in batches {
+            let mesh = &self.entities[batch.entity].model.as_ref().unwrap().meshes[batch.mesh];
+            instances.draw(device, mesh, shader, &batch.instances);
+        }
+    }
+
+    // Groups the meshes accepted by `filter` by model, in the order the models first appear. Entities
+    // share a model through the same Rc, equal models loaded twice are batched apart.
+    pub fn collect_batches<F: Fn(&Mesh) -> bool>(&self, filter: F) -> Vec<InstanceBatch> {
+        self.collect_visible_batches(&Culling::none(), filter)
+    }
+
+    pub fn collect_visible_batches<F: Fn(&Mesh) -> bool>(&self, culling: &Culling, filter: F) -> Vec<InstanceBatch> {
+        let mut batches: Vec<InstanceBatch> = Vec::new();
+        let mut batch_of: HashMap<(*const Model, usize), usize> = HashMap::new();
+        for (entity_index, entity) in self.entities.iter().enumerate() {
+            if !culling.is_visible(entity_index) {
+                continue;
+            }
+            let model = match &entity.model {
+                Some(model) => model,
+                None => continue
+            };
+            let object = ObjectData::new(&entity.transform.matrix());
+            for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                if !filter(mesh) {
+                    continue;
                 }
+                let index = *batch_of.entry((Rc::as_ptr(model), mesh_index)).or_insert_with(|| {
+                    batches.push(InstanceBatch { entity: entity_index, mesh: mesh_index, instances: Vec::new() });
+                    batches.len() - 1
+                });
+                batches[index].instances.push(object);
             }
         }
+        batches
+    }
+
+    // Collects the meshes of a queue. The transparent queue is sorted back to front from `view_position`
+    // so blending composes correctly, the others front to back to reject hidden fragments early.
+    pub fn collect_queue(&self, queue: RenderQueue, view_position: Vector3<f32>) -> Vec<DrawItem> {
+        self.collect_visible_queue(queue, view_position, &Culling::none())
+    }
+
+    pub fn collect_visible_queue(&self, queue: RenderQueue, view_position: Vector3<f32>, culling: &Culling) -> Vec<DrawItem> {
+        let mut items = Vec::new();
+        for (entity_index, entity) in self.entities.iter().enumerate() {
+            if !culling.is_visible(entity_index) {
+                continue;
+            }
+            let model = match &entity.model {
+                Some(model) => model,
+                None => continue
+            };
+            let model_matrix = entity.transform.matrix();
+            for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                if mesh.material.queue() != queue {
+                    continue;
+                }
+                let center = (model_matrix * mesh.sphere.center.extend(1.0)).truncate();
+                items.push(DrawItem {
+                    entity: entity_index,
+                    mesh: mesh_index,
+                    model_matrix,
+                    distance: (center - view_position).magnitude()
+                });
+            }
+        }
+
+        if queue == RenderQueue::TRANSPARENT {
+            items.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        } else {
+            items.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        }
+        items
+    }
+
+    // Entities with a model whose world bounds are in `frustum`. The BVH answers for the entities in it,
+    // as of the last update_bvh, the ones added since are tested one by one.
+    pub fn cull(&self, frustum: &Frustum) -> Culling {
+        let mut visible: Vec<bool> = (0..self.entities.len()).map(|entity| self.proxy(entity).is_none()).collect();
+        for entity in self.bvh.query_frustum(frustum) {
+            visible[entity] = true;
+        }
+
+        let mut stats = CullingStats::default();
+        for (index, entity) in self.entities.iter().enumerate() {
+            let bounds = match entity.world_bounds() {
+                Some(bounds) => bounds,
+                None => continue
+            };
+            if self.proxy(index).is_none() {
+                visible[index] = frustum.intersects_sphere(&bounds.sphere) && frustum.intersects_aabb(&bounds.aabb);
+            }
+            stats.tested += 1;
+            if visible[index] {
+                stats.visible += 1;
+            }
+        }
+        Culling { visible, stats }
+    }
+
+    // The closest triangle of any entity along `ray`. Entity bounds come first, through the BVH for the
+    // entities it holds with their current bounds, then mesh bounds and only then the triangles.
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        self.bvh.raycast_with(ray, f32::INFINITY, |entity, _| {
+            let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+            let hit = self.raycast_entity(entity, ray, max_distance)?;
+            closest = Some(hit);
+            Some(hit.distance)
+        });
+
+        // added or moved since the last update_bvh
+        for (index, entity) in self.entities.iter().enumerate() {
+            let bounds = match entity.world_bounds() {
+                Some(bounds) => bounds.aabb,
+                None => continue
+            };
+            if self.proxy(index).is_some_and(|proxy| self.bvh.bounds(proxy) == bounds) {
+                continue;
+            }
+            let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+            if bounds.intersect_ray(ray).is_none_or(|distance| distance > max_distance) {
+                continue;
+            }
+            if let Some(hit) = self.raycast_entity(index, ray, max_distance) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    fn raycast_entity(&self, index: usize, ray: &Ray, max_distance: f32) -> Option<Hit> {
+        let model = self.entities[index].model.as_ref()?;
+        let inverse = self.entities[index].transform.matrix().invert()?;
+        // the direction is not renormalized, so distances along the local ray are still world distances
+        let local = Ray {
+            origin: (inverse * ray.origin.extend(1.0)).truncate(),
+            direction: (inverse * ray.direction.extend(0.0)).truncate()
+        };
+
+        let mut closest = None;
+        let mut max_distance = max_distance;
+        for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+            if let Some((triangle, distance, barycentrics)) = raycast_mesh(mesh, &local, max_distance) {
+                max_distance = distance;
+                closest = Some((mesh_index, triangle, distance, barycentrics));
+            }
+        }
+        let (mesh_index, triangle, distance, barycentrics) = closest?;
+
+        // the interpolated vertex normal, the face normal for meshes without any
+        let mesh = &model.meshes[mesh_index];
+        let corners = &mesh.indices[3 * triangle..3 * triangle + 3];
+        let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[corners[i] as usize]);
+        let mut normal = a.normal * barycentrics.x + b.normal * barycentrics.y + c.normal * barycentrics.z;
+        if normal.magnitude2() == 0.0 {
+            normal = (b.position - a.position).cross(c.position - a.position);
+        }
+        let normal_matrix = Matrix3::from_cols(inverse.x.truncate(), inverse.y.truncate(), inverse.z.truncate()).transpose();
+        let mut normal = (normal_matrix * normal).normalize();
+        if normal.dot(ray.direction) > 0.0 {
+            normal = -normal;
+        }
+
+        Some(Hit {
+            entity: index,
+            mesh: mesh_index,
+            triangle,
+            barycentrics,
+            distance,
+            position: ray.at(distance),
+            normal
+        })
+    }
+
+    // The acceleration structure over the world boxes of the entities, its leaves hold entity indices.
+    // Up to date as of the last update_bvh.
+    pub fn bvh(&self) -> &Bvh<usize> {
+        &self.bvh
+    }
+
+    pub fn proxy(&self, entity: usize) -> Option<ProxyId> {
+        self.proxies.get(entity).cloned().flatten()
+    }
+
+    // Bring the BVH up to date with the entities: new ones are inserted and moved ones refitted, which
+    // only restructures the tree for those leaving their fattened box. Entities are only ever appended
+    // or truncated, an index keeps naming the same entity.
+    pub fn update_bvh(&mut self) {
+        while self.proxies.len() > self.entities.len() {
+            if let Some(Some(proxy)) = self.proxies.pop() {
+                self.bvh.remove(proxy);
+            }
+        }
+        self.proxies.resize(self.entities.len(), None);
+
+        for (index, entity) in self.entities.iter().enumerate() {
+            let bounds = entity.world_bounds().map(|bounds| bounds.aabb);
+            self.proxies[index] = match (self.proxies[index], bounds) {
+                (None, Some(bounds)) => Some(self.bvh.insert(bounds, index)),
+                (Some(proxy), Some(bounds)) => {
+                    if self.bvh.bounds(proxy) != bounds {
+                        self.bvh.update(proxy, bounds);
+                    }
+                    Some(proxy)
+                }
+                (Some(proxy), None) => {
+                    self.bvh.remove(proxy);
+                    None
+                }
+                (None, None) => None
+            };
+        }
+    }
+
+    // world bounds of every entity with a model, None for an empty scene
+    pub fn bounds(&self) -> Option<WorldBounds> {
+        let bounds: Vec<WorldBounds> = self.entities.iter().filter_map(|entity| entity.world_bounds()).collect();
+        if bounds.is_empty() {
+            return None;
+        }
+        let spheres: Vec<BoundingSphere> = bounds.iter().map(|b| b.sphere).collect();
+        Some(WorldBounds {
+            aabb: bounds.iter().fold(Aabb::empty(), |aabb, b| aabb.union(&b.aabb)),
+            sphere: BoundingSphere::enclosing(&spheres)
+        })
+    }
+
+    pub unsafe fn draw_item(&self, item: &DrawItem, shader: &Shader, objects: &UniformRing) {
+        if let Some(model) = &self.entities[item.entity].model {
+            model.draw_mesh(item.mesh, &item.model_matrix, shader, objects);
+        }
     }
 
     pub fn update(&mut self) {
@@ -0,0 +1,314 @@
+use std::fs;
+
+use cgmath::{ vec2, vec3, Point3, Vector3 };
+use cgmath::prelude::*;
+
+use crate::graphics::camera::Camera;
+use crate::graphics::device::{ PixelFormat, RenderDevice, Sampler, TextureData, WrapMode };
+use crate::graphics::hdr::{ Exposure, Tonemapper };
+use crate::graphics::material::{ BlendMode, Material };
+use crate::graphics::mesh::{ Mesh, Texture, Vertex };
+use crate::graphics::model::Model;
+use crate::graphics::shader::Shader;
+use crate::world::entity::Entity;
+use crate::world::light::{ DirectionalLight, PointLight, SpotLight };
+use crate::world::scene::Scene;
+use crate::world::skybox::SkyBox;
+use crate::world::transform::Transform;
+
+// A scene described in a text file with one directive per line, `#` starts a comment. Colors are sRGB
+// in [0, 1], angles in degrees and paths relative to the working directory.
+//
+//   size <width> <height>
+//   frames <count>
+//   tolerance <channel difference> <fraction of pixels>
+//   camera <x y z> <yaw> <pitch> [zoom]
+//   hdr off | hdr <reinhard|aces|agx> [exposure]
+//   sun <dx dy dz> <r g b> <intensity> <ambient>
+//   point <x y z> <r g b> <intensity> <radius>
+//   spot <x y z> <dx dy dz> <r g b> <intensity> <radius> [inner outer]
+//   skybox <r g b> | skybox <+x> <-x> <+y> <-y> <+z> <-z>
+//   model <path> <x y z> [rx ry rz] [sx sy sz]
+//   cube <r g b a> <x y z> [rx ry rz] [sx sy sz] [opaque|cutout|alpha|premultiplied|additive]
+//   quad <r g b a | texture path> <x y z> [rx ry rz] [sx sy sz] [blend mode]
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub width: usize,
+    pub height: usize,
+    // frames rendered before the image is kept, so temporal effects settle
+    pub frames: usize,
+    // largest per-channel difference and fraction of differing pixels accepted when compared with a reference
+    pub tolerance: Option<(u8, f32)>
+}
+
+enum SkyboxSource {
+    COLOR([f32; 3]),
+    FILES(Vec<String>)
+}
+
+impl SceneFile {
+    pub fn load<D: RenderDevice>(device: &mut D, path: &str, skybox_shader: &Shader) -> Result<SceneFile, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        SceneFile::parse(device, &source, skybox_shader).map_err(|e| format!("{}:{}", path, e))
+    }
+
+    pub fn parse<D: RenderDevice>(device: &mut D, source: &str, skybox_shader: &Shader) -> Result<SceneFile, String> {
+        let mut lines = Vec::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if !line.is_empty() {
+                lines.push((number + 1, Tokens { tokens: line.split_whitespace().collect(), next: 1 }));
+            }
+        }
+
+        // the scene is created around its skybox, so that directive is read first
+        let mut skybox = SkyboxSource::COLOR([0.1, 0.1, 0.1]);
+        for (number, tokens) in lines.iter_mut().filter(|(_, tokens)| tokens.tokens[0] == "skybox") {
+            skybox = tokens.skybox().map_err(|e| format!("{}: {}", number, e))?;
+        }
+        let skybox = match skybox {
+            SkyboxSource::COLOR(color) => {
+                let pixels = srgb_bytes(&color);
+                let face = TextureData { width: 1, height: 1, format: PixelFormat::SRGB8, pixels: &pixels };
+                SkyBox::with_device(device, &[face; 6], skybox_shader)
+            }
+            SkyboxSource::FILES(faces) => {
+                let faces: Vec<&str> = faces.iter().map(|face| face.as_str()).collect();
+                SkyBox::from_files(device, &faces, skybox_shader)
+            }
+        };
+
+        let mut file = SceneFile {
+            scene: Scene::with_skybox(skybox),
+            camera: Camera::default(),
+            width: 320,
+            height: 240,
+            frames: 1,
+            tolerance: None
+        };
+        for (number, tokens) in lines.iter_mut() {
+            if tokens.tokens[0] != "skybox" {
+                file.directive(device, tokens).map_err(|e| format!("{}: {}", number, e))?;
+            }
+            if tokens.next < tokens.tokens.len() {
+                return Err(format!("{}: unexpected `{}`", number, tokens.tokens[tokens.next]));
+            }
+        }
+        Ok(file)
+    }
+
+    fn directive<D: RenderDevice>(&mut self, device: &mut D, tokens: &mut Tokens) -> Result<(), String> {
+        match tokens.tokens[0] {
+            "size" => {
+                self.width = tokens.float()?.max(1.0) as usize;
+                self.height = tokens.float()?.max(1.0) as usize;
+            }
+            "frames" => self.frames = tokens.float()?.max(1.0) as usize,
+            "tolerance" => self.tolerance = Some((tokens.float()?.clamp(0.0, 255.0) as u8, tokens.float()?)),
+            "camera" => {
+                let position = tokens.vector()?;
+                let (yaw, pitch) = (tokens.float()?, tokens.float()?);
+                let hdr = self.camera.hdr;
+                self.camera = Camera::new(Point3::new(position.x, position.y, position.z), yaw, pitch);
+                self.camera.hdr = hdr;
+                if tokens.has_float() {
+                    self.camera.zoom = tokens.float()?;
+                }
+            }
+            "hdr" => {
+                let tonemapper = match tokens.word()? {
+                    "off" => {
+                        self.camera.hdr.enabled = false;
+                        return Ok(());
+                    }
+                    "reinhard" => Tonemapper::REINHARD,
+                    "aces" => Tonemapper::ACES,
+                    "agx" => Tonemapper::AGX,
+                    other => return Err(format!("unknown tonemapper `{}`", other))
+                };
+                self.camera.hdr.enabled = true;
+                self.camera.hdr.tonemapper = tonemapper;
+                if tokens.has_float() {
+                    self.camera.hdr.exposure = Exposure::MANUAL(tokens.float()?);
+                }
+            }
+            "sun" => {
+                self.scene.directional_light = DirectionalLight {
+                    direction: tokens.vector()?.normalize(),
+                    color: tokens.vector()?,
+                    intensity: tokens.float()?,
+                    ambient: tokens.float()?
+                };
+            }
+            "point" => {
+                let (position, color) = (tokens.vector()?, tokens.vector()?);
+                let (intensity, radius) = (tokens.float()?, tokens.float()?);
+                self.scene.point_lights.push(PointLight::new(position, color, intensity, radius));
+            }
+            "spot" => {
+                let (position, direction, color) = (tokens.vector()?, tokens.vector()?, tokens.vector()?);
+                let (intensity, radius) = (tokens.float()?, tokens.float()?);
+                let mut light = SpotLight::new(position, direction, color, intensity, radius);
+                if tokens.has_float() {
+                    light.inner_angle = tokens.float()?;
+                    light.outer_angle = tokens.float()?;
+                }
+                self.scene.spot_lights.push(light);
+            }
+            "model" => {
+                let path = tokens.word()?;
+                let model = Model::with_device(device, path);
+                let transform = tokens.transform()?;
+                self.scene.entities.push(Entity::new(Some(model), transform));
+            }
+            "cube" | "quad" => {
+                let is_cube = tokens.tokens[0] == "cube";
+                let color = if tokens.has_float() {
+                    Some([tokens.float()?, tokens.float()?, tokens.float()?, tokens.float()?])
+                } else {
+                    None
+                };
+                let path = if color.is_none() { Some(tokens.word()?) } else { None };
+                let transform = tokens.transform()?;
+                let blend_mode = match tokens.optional_word() {
+                    None | Some("opaque") => BlendMode::OPAQUE,
+                    Some("cutout") => BlendMode::CUTOUT,
+                    Some("alpha") => BlendMode::ALPHA,
+                    Some("premultiplied") => BlendMode::PREMULTIPLIED,
+                    Some("additive") => BlendMode::ADDITIVE,
+                    Some(other) => return Err(format!("unknown blend mode `{}`", other))
+                };
+                let material = Material { blend_mode, ..Material::default() };
+
+                let model = match (color, path) {
+                    (None, Some(path)) if !is_cube => Model::textured_quad_with_device(device, path, material),
+                    (Some(color), _) => {
+                        let texture = solid_texture(device, color);
+                        let (vertices, indices) = if is_cube { cube() } else { quad() };
+                        let mut model = Model::from_meshes(vec![Mesh::with_device(device, vertices, indices, vec![texture.clone()], material)]);
+                        model.adopt_texture(device, texture);
+                        model
+                    }
+                    _ => return Err("cubes only take a color".into())
+                };
+                self.scene.entities.push(Entity::new(Some(model), transform));
+            }
+            other => return Err(format!("unknown directive `{}`", other))
+        }
+        Ok(())
+    }
+}
+
+struct Tokens<'a> {
+    tokens: Vec<&'a str>,
+    next: usize
+}
+
+impl<'a> Tokens<'a> {
+    fn word(&mut self) -> Result<&'a str, String> {
+        self.optional_word().ok_or_else(|| format!("missing arguments to `{}`", self.tokens[0]))
+    }
+
+    fn optional_word(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.next).copied();
+        if token.is_some() {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn has_float(&self) -> bool {
+        self.tokens.get(self.next).is_some_and(|token| token.parse::<f32>().is_ok())
+    }
+
+    fn float(&mut self) -> Result<f32, String> {
+        let token = self.word()?;
+        token.parse().map_err(|_| format!("expected a number, found `{}`", token))
+    }
+
+    fn vector(&mut self) -> Result<Vector3<f32>, String> {
+        Ok(vec3(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn skybox(&mut self) -> Result<SkyboxSource, String> {
+        if self.has_float() {
+            let color = self.vector()?;
+            Ok(SkyboxSource::COLOR([color.x, color.y, color.z]))
+        } else {
+            let faces: Result<Vec<String>, String> = (0..6).map(|_| self.word().map(String::from)).collect();
+            Ok(SkyboxSource::FILES(faces?))
+        }
+    }
+
+    // position, then optional rotation in degrees and scale
+    fn transform(&mut self) -> Result<Transform, String> {
+        let mut transform = Transform { position: self.vector()?, ..Transform::default() };
+        if self.has_float() {
+            let degrees = self.vector()?;
+            transform.rotation = vec3(degrees.x.to_radians(), degrees.y.to_radians(), degrees.z.to_radians());
+        }
+        if self.has_float() {
+            transform.scale = self.vector()?;
+        }
+        Ok(transform)
+    }
+}
+
+fn srgb_bytes(color: &[f32]) -> Vec<u8> {
+    color.iter().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect()
+}
+
+fn solid_texture<D: RenderDevice>(device: &mut D, color: [f32; 4]) -> Texture {
+    let pixels = srgb_bytes(&color);
+    let data = TextureData { width: 1, height: 1, format: PixelFormat::SRGB8_ALPHA8, pixels: &pixels };
+    Texture {
+        id: device.create_texture(&data, Sampler { wrap: WrapMode::REPEAT, mipmaps: false }),
+        type_: "texture_diffuse".into(),
+        path: String::new(),
+        has_alpha: color[3] < 1.0
+    }
+}
+
+// unit quad in the XY plane facing +Z
+fn quad() -> (Vec<Vertex>, Vec<u32>) {
+    let corners = [(-0.5, -0.5, 0.0, 0.0), (0.5, -0.5, 1.0, 0.0), (0.5, 0.5, 1.0, 1.0), (-0.5, 0.5, 0.0, 1.0)];
+    let vertices = corners.iter()
+        .map(|&(x, y, u, v)| Vertex {
+            position: vec3(x, y, 0.0),
+            normal: vec3(0.0, 0.0, 1.0),
+            tex_coords: vec2(u, v),
+            ..Vertex::default()
+        })
+        .collect();
+    (vertices, vec![0, 1, 2, 0, 2, 3])
+}
+
+// unit cube centered on the origin, four vertices per face for flat normals
+fn cube() -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for axis in 0..3 {
+        for &sign in &[1.0f32, -1.0] {
+            let mut normal = vec3(0.0, 0.0, 0.0);
+            normal[axis] = sign;
+            // two axes spanning the face, ordered so the triangles wind counter-clockwise seen from outside
+            let mut u = vec3(0.0, 0.0, 0.0);
+            let mut v = vec3(0.0, 0.0, 0.0);
+            u[(axis + 1) % 3] = sign;
+            v[(axis + 2) % 3] = 1.0;
+
+            let base = vertices.len() as u32;
+            for &(a, b) in &[(-0.5f32, -0.5f32), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+                vertices.push(Vertex {
+                    position: normal * 0.5 + u * a + v * b,
+                    normal,
+                    tex_coords: vec2(a + 0.5, b + 0.5),
+                    ..Vertex::default()
+                });
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+    (vertices, indices)
+}
@@ -1,25 +1,45 @@
-use std::ptr;
 use std::mem;
-use std::os::raw::c_void;
 use std::path::Path;
 use std::ffi::CStr;
 
-use cgmath::Matrix4;
-
 use image;
 use image::GenericImage;
 
-use crate::graphics::camera::Camera;
+use crate::graphics::device::{ as_bytes, BufferKind, BufferUsage, DepthFunc, GlDevice, Pipeline, PipelineState, PixelFormat, RenderDevice, TextureData, TextureTarget, UniformValue, VertexAttribute };
+use crate::graphics::resource::{ GpuHandle, ResourceKind };
 use crate::graphics::shader::Shader;
 
+// the cube and its cubemap are deleted with the skybox
 pub struct SkyBox {
-    vao: u32,
-    vbo: u32,
-    texture: u32
+    vao: GpuHandle,
+    _vbo: GpuHandle,
+    texture: GpuHandle
 }
 
 impl SkyBox {
+    // faces are loaded in the +X, -X, +Y, -Y, +Z, -Z order
     pub unsafe fn new(faces: &[&str], shader: &Shader) -> SkyBox {
+        SkyBox::from_files(&mut GlDevice::current(), faces, shader)
+    }
+
+    pub fn from_files<D: RenderDevice>(device: &mut D, faces: &[&str], shader: &Shader) -> SkyBox {
+        let images: Vec<_> = faces.iter()
+            .map(|face| image::open(&Path::new(face)).expect("Cubemap texture failed to load"))
+            .collect();
+        let pixels: Vec<Vec<u8>> = images.iter().map(|img| img.raw_pixels()).collect();
+        let faces: Vec<TextureData> = images.iter().zip(&pixels)
+            .map(|(img, pixels)| TextureData {
+                width: img.width() as i32,
+                height: img.height() as i32,
+                format: PixelFormat::SRGB8,
+                pixels
+            })
+            .collect();
+
+        SkyBox::with_device(device, &faces, shader)
+    }
+
+    pub fn with_device<D: RenderDevice>(device: &mut D, faces: &[TextureData], shader: &Shader) -> SkyBox {
         // Setup skybox VAO and VBO
         let skybox_vertices: [f32; 108] = [
             // positions
@@ -66,75 +86,36 @@ impl SkyBox {
              1.0, -1.0,  1.0
         ];
 
-        let (mut vao, mut vbo) = (0, 0);
-        gl::GenVertexArrays(1, &mut vao);
-        gl::GenBuffers(1, &mut vbo);
-        gl::BindVertexArray(vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(gl::ARRAY_BUFFER,
-                       (skybox_vertices.len() * mem::size_of::<f32>()) as isize,
-                       &skybox_vertices[0] as *const f32 as *const c_void,
-                       gl::STATIC_DRAW);
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * mem::size_of::<f32>() as i32, ptr::null());
+        let vbo = device.create_buffer(BufferKind::VERTEX, BufferUsage::STATIC, unsafe { as_bytes(&skybox_vertices) });
+        let attributes = [VertexAttribute { location: 0, components: 3, offset: 0 }];
+        let vao = device.create_vertex_array(vbo, None, 3 * mem::size_of::<f32>() as i32, &attributes);
 
         // Load cubemap texture
-        let texture = SkyBox::load_cubemap(faces);
+        let texture = device.create_cubemap(faces);
 
         // Set texture unit in the shader
-        shader.use_program();
-        shader.set_int(c_str!("skybox"), 0);
+        device.use_program(shader.id);
+        device.set_uniform(shader.id, unsafe { c_str!("skybox") }, UniformValue::INT(0));
 
-        SkyBox { vao, vbo, texture }
-    }
-
-    unsafe fn load_cubemap(faces: &[&str]) -> u32 {
-        let mut texture_id = 0;
-        gl::GenTextures(1, &mut texture_id);
-        gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture_id);
-
-        for (i, face) in faces.iter().enumerate() {
-            let img = image::open(&Path::new(face)).expect("Cubemap texture failed to load");
-            let data = img.raw_pixels();
-            gl::TexImage2D(
-                gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
-                0, gl::RGB as i32, img.width() as i32, img.height() as i32,
-                0, gl::RGB, gl::UNSIGNED_BYTE,
-                &data[0] as *const u8 as *const c_void);
+        SkyBox {
+            vao: GpuHandle::new(device, ResourceKind::VERTEX_ARRAY, vao, "skybox vertex array"),
+            _vbo: GpuHandle::new(device, ResourceKind::BUFFER, vbo, "skybox vertex buffer"),
+            texture: GpuHandle::new(device, ResourceKind::TEXTURE, texture, "skybox cubemap")
         }
-
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
-
-        texture_id
     }
 
-    pub unsafe fn draw(&self, projection: Matrix4<f32>, camera: &Camera, shader: &Shader) {
-        // Draw skybox
-        gl::DepthFunc(gl::LEQUAL);
-        shader.use_program();
-
-        let mut view = camera.get_view_matrix();
-        view.w[0] = 0.0;
-        view.w[1] = 0.0;
-        view.w[2] = 0.0;
-
-        shader.set_mat4(c_str!("view"), &view);
-        shader.set_mat4(c_str!("projection"), &projection);
-
-        gl::BindVertexArray(self.vao);
-        gl::ActiveTexture(gl::TEXTURE0);
-        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.texture);
-        gl::DrawArrays(gl::TRIANGLES, 0, 36);
-        gl::BindVertexArray(0);
-        gl::DepthFunc(gl::LESS);
+    pub fn texture(&self) -> u32 {
+        self.texture.id()
     }
 
-    pub unsafe fn cleanup(&self) {
-        gl::DeleteVertexArrays(1, &self.vao);
-        gl::DeleteBuffers(1, &self.vbo);
+    // the camera comes from the per-frame uniform buffer
+    pub fn draw<D: RenderDevice>(&self, device: &mut D, shader: &Shader) {
+        // Draw skybox, at the far plane so it only fills what the scene left empty
+        let state = PipelineState { depth_func: DepthFunc::LEQUAL, ..PipelineState::default() };
+        device.bind_pipeline(&Pipeline { program: shader.id, state });
+
+        device.bind_texture(0, TextureTarget::CUBE_MAP, self.texture.id());
+        device.draw_arrays(self.vao.id(), 0, 36);
+        device.set_state(&PipelineState::default());
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,7 @@
-use cgmath::Vector3;
+use cgmath::{ Matrix3, Matrix4, Quaternion, Rad, Vector3 };
+use cgmath::prelude::*;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Transform {
     pub position: Vector3<f32>,
     pub rotation: Vector3<f32>,
@@ -24,4 +26,33 @@ impl Transform {
             scale
         }
     }
+
+    // model matrix: scale, then rotate around x, y and z, then translate
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from_angle_x(Rad(self.rotation.x))
+            * Matrix4::from_angle_y(Rad(self.rotation.y))
+            * Matrix4::from_angle_z(Rad(self.rotation.z))
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // the rotation as a quaternion, in the same x, y, z order as matrix
+    pub fn orientation(&self) -> Quaternion<f32> {
+        Quaternion::from_angle_x(Rad(self.rotation.x))
+            * Quaternion::from_angle_y(Rad(self.rotation.y))
+            * Quaternion::from_angle_z(Rad(self.rotation.z))
+    }
+
+    // Euler angles of `orientation`. Looking straight along y the x and z rotations are the same
+    // axis, all of it goes to x.
+    pub fn set_orientation(&mut self, orientation: Quaternion<f32>) {
+        let m = Matrix3::from(orientation);
+        // rows of Rx * Ry * Rz, the matrix is column major
+        let sin_y = m.z.x.clamp(-1.0, 1.0);
+        self.rotation = if sin_y.abs() < 0.9999 {
+            Vector3::new((-m.z.y).atan2(m.z.z), sin_y.asin(), (-m.y.x).atan2(m.x.x))
+        } else {
+            Vector3::new(m.y.z.atan2(m.y.y), sin_y.asin(), 0.0)
+        };
+    }
 }
\ No newline at end of file
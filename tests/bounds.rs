@@ -0,0 +1,155 @@
+use std::f32::consts::FRAC_PI_2;
+use std::rc::Rc;
+
+use cgmath::{ vec3, Matrix4, Point3, Rad, Vector3 };
+use cgmath::prelude::*;
+
+use argus_engine::graphics::camera::Camera;
+use argus_engine::graphics::device::NullDevice;
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::{ Mesh, Vertex };
+use argus_engine::graphics::model::Model;
+use argus_engine::world::bounds::{ Aabb, BoundingSphere };
+use argus_engine::world::entity::Entity;
+use argus_engine::world::transform::Transform;
+
+mod common;
+
+use common::scene;
+
+fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a - b).magnitude() < 1e-4
+}
+
+fn mesh(device: &mut NullDevice, points: &[Vector3<f32>]) -> Mesh {
+    let vertices = points.iter().map(|&position| Vertex { position, ..Vertex::default() }).collect();
+    Mesh::with_device(device, vertices, vec![0, 1, 2], Vec::new(), Material::default())
+}
+
+#[test]
+fn boxes_grow_around_points_and_each_other() {
+    let aabb = Aabb::from_points(vec![vec3(1.0, -2.0, 0.0), vec3(-1.0, 2.0, 3.0), vec3(0.0, 0.0, 1.0)]);
+    assert_eq!(aabb, Aabb::new(vec3(-1.0, -2.0, 0.0), vec3(1.0, 2.0, 3.0)));
+    assert_eq!(aabb.center(), vec3(0.0, 0.0, 1.5));
+    assert_eq!(aabb.extents(), vec3(1.0, 2.0, 1.5));
+    assert!(aabb.contains_point(vec3(1.0, 2.0, 3.0)));
+    assert!(!aabb.contains_point(vec3(1.1, 0.0, 1.0)));
+
+    let empty = Aabb::from_points(Vec::new());
+    assert!(empty.is_empty());
+    assert_eq!(empty.union(&aabb), aabb);
+    assert_eq!(empty.transformed(&Matrix4::from_scale(2.0)), empty);
+
+    let other = Aabb::new(vec3(1.0, 0.0, 0.0), vec3(4.0, 1.0, 1.0));
+    assert!(aabb.intersects(&other));
+    assert!(!aabb.intersects(&Aabb::new(vec3(1.5, 0.0, 0.0), vec3(4.0, 1.0, 1.0))));
+    assert_eq!(aabb.union(&other), Aabb::new(vec3(-1.0, -2.0, 0.0), vec3(4.0, 2.0, 3.0)));
+}
+
+#[test]
+fn transformed_boxes_contain_the_transformed_corners() {
+    let aabb = Aabb::new(vec3(0.0, 0.0, 0.0), vec3(2.0, 1.0, 1.0));
+    // a quarter turn around y is exact
+    let turned = aabb.transformed(&Matrix4::from_angle_y(Rad(FRAC_PI_2)));
+    assert!(close(turned.min, vec3(0.0, 0.0, -2.0)));
+    assert!(close(turned.max, vec3(1.0, 1.0, 0.0)));
+
+    let transform = Transform::new(vec3(5.0, -1.0, 2.0), vec3(0.3, 1.1, -0.7), vec3(2.0, 0.5, 1.0));
+    let matrix = transform.matrix();
+    let world = aabb.transformed(&matrix);
+    for i in 0..8 {
+        let corner = vec3(
+            if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+            if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+            if i & 4 == 0 { aabb.min.z } else { aabb.max.z }
+        );
+        let moved = (matrix * corner.extend(1.0)).truncate();
+        let slack = Aabb::new(world.min - vec3(1e-4, 1e-4, 1e-4), world.max + vec3(1e-4, 1e-4, 1e-4));
+        assert!(slack.contains_point(moved), "corner {:?} outside of {:?}", moved, world);
+    }
+}
+
+#[test]
+fn spheres_cover_their_points_and_scale_with_the_largest_axis() {
+    let points = [vec3(-1.0, 0.0, 0.0), vec3(3.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0)];
+    let sphere = BoundingSphere::from_points(&points);
+    assert_eq!(sphere.center, vec3(1.0, 0.5, 0.0));
+    assert!(points.iter().all(|&p| sphere.contains_point(p + (sphere.center - p) * 1e-5)));
+
+    let transform = Transform::new(vec3(0.0, 10.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(1.0, 3.0, 2.0));
+    let moved = BoundingSphere::new(vec3(0.0, 0.0, 0.0), 1.0).transformed(&transform.matrix());
+    assert_eq!(moved, BoundingSphere::new(vec3(0.0, 10.0, 0.0), 3.0));
+
+    let a = BoundingSphere::new(vec3(0.0, 0.0, 0.0), 1.0);
+    assert!(a.intersects(&BoundingSphere::new(vec3(1.5, 0.0, 0.0), 0.5)));
+    assert!(!a.intersects(&BoundingSphere::new(vec3(1.6, 0.0, 0.0), 0.5)));
+    assert!(a.intersects_aabb(&Aabb::new(vec3(0.5, 0.5, -1.0), vec3(2.0, 2.0, 1.0))));
+    assert!(!a.intersects_aabb(&Aabb::new(vec3(0.8, 0.8, -1.0), vec3(2.0, 2.0, 1.0))));
+
+    let enclosing = BoundingSphere::enclosing(&[a, BoundingSphere::new(vec3(4.0, 0.0, 0.0), 1.0)]);
+    assert_eq!(enclosing, BoundingSphere::new(vec3(2.0, 0.0, 0.0), 3.0));
+}
+
+#[test]
+fn meshes_and_models_are_bounded_at_import() {
+    let mut device = NullDevice::new();
+    let small = mesh(&mut device, &[vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)]);
+    assert_eq!(small.bounds, Aabb::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0)));
+    assert_eq!(small.sphere.center, vec3(0.5, 0.5, 0.0));
+
+    let far = mesh(&mut device, &[vec3(4.0, 0.0, 0.0), vec3(5.0, 0.0, 0.0), vec3(4.0, 0.0, -3.0)]);
+    let model = Model::from_meshes(vec![small, far]);
+    assert_eq!(model.bounds, Aabb::new(vec3(0.0, 0.0, -3.0), vec3(5.0, 1.0, 0.0)));
+    for mesh in &model.meshes {
+        let inner = mesh.sphere;
+        assert!((inner.center - model.sphere.center).magnitude() + inner.radius <= model.sphere.radius + 1e-4);
+    }
+}
+
+#[test]
+fn entities_cache_their_world_bounds_until_moved() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    assert!(scene.bounds().is_none());
+
+    let model = Model::from_meshes(vec![mesh(&mut device, &[vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0), vec3(0.0, 0.0, 0.0)])]);
+    scene.entities.push(Entity::new(Some(model), Transform::new(vec3(10.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(2.0, 2.0, 2.0))));
+    scene.entities.push(Entity::new(None, Transform::default()));
+
+    let bounds = scene.entities[0].world_bounds().unwrap();
+    assert_eq!(bounds.aabb, Aabb::new(vec3(8.0, -2.0, -2.0), vec3(12.0, 2.0, 2.0)));
+    assert_eq!(bounds.sphere.center, vec3(10.0, 0.0, 0.0));
+    assert!((bounds.sphere.radius - 2.0 * 3f32.sqrt()).abs() < 1e-4);
+    assert_eq!(scene.entities[0].world_bounds(), Some(bounds));
+    assert!(scene.entities[1].world_bounds().is_none());
+
+    scene.entities[0].transform.position.y = 5.0;
+    let moved = scene.entities[0].world_bounds().unwrap();
+    assert_eq!(moved.aabb, Aabb::new(vec3(8.0, 3.0, -2.0), vec3(12.0, 7.0, 2.0)));
+    assert_eq!(scene.bounds().unwrap().aabb, moved.aabb);
+
+    // swapping the model for another one, even where the old one was freed, refreshes the bounds
+    let smaller = Model::from_meshes(vec![mesh(&mut device, &[vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5), vec3(0.0, 0.0, 0.0)])]);
+    scene.entities[0].model = None;
+    scene.entities[0].model = Some(Rc::new(smaller));
+    let swapped = scene.entities[0].world_bounds().unwrap();
+    assert_eq!(swapped.aabb, Aabb::new(vec3(9.0, 4.0, -1.0), vec3(11.0, 6.0, 1.0)));
+}
+
+#[test]
+fn focusing_fits_the_sphere_in_the_view() {
+    let sphere = BoundingSphere::new(vec3(3.0, 1.0, -4.0), 2.0);
+    for &aspect in &[0.5, 1.0, 16.0 / 9.0] {
+        let mut camera = Camera::new(Point3::new(0.0, 0.0, 0.0), -60.0, -20.0);
+        camera.focus(&sphere, aspect);
+
+        // the center straight ahead, every plane of the frustum at least a radius away from it
+        let view = (camera.get_view_matrix() * sphere.center.extend(1.0)).truncate();
+        assert!(view.x.abs() < 1e-3 && view.y.abs() < 1e-3 && view.z < 0.0);
+        let clip = camera.get_projection_matrix(aspect);
+        let (sx, sy) = (clip.x.x, clip.y.y);
+        let depth = -view.z;
+        assert!(depth * sy / (1.0 + sy * sy).sqrt() >= sphere.radius - 1e-3);
+        assert!(depth * sx / (1.0 + sx * sx).sqrt() >= sphere.radius - 1e-3);
+    }
+}
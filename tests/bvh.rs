@@ -0,0 +1,204 @@
+use cgmath::{ perspective, vec3, Deg, Point3 };
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+use argus_engine::graphics::camera::Camera;
+use argus_engine::graphics::device::NullDevice;
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::{ Mesh, Vertex };
+use argus_engine::graphics::model::Model;
+use argus_engine::world::bounds::{ Aabb, BoundingSphere, Ray };
+use argus_engine::world::bvh::Bvh;
+use argus_engine::world::entity::Entity;
+use argus_engine::world::frustum::Frustum;
+
+mod common;
+
+use common::{ at, scene };
+
+fn random_boxes(rng: &mut StdRng, count: usize) -> Vec<Aabb> {
+    (0..count).map(|_| {
+        let center = vec3(rng.gen_range(-50.0, 50.0), rng.gen_range(-50.0, 50.0), rng.gen_range(-50.0, 50.0));
+        let half = vec3(rng.gen_range(0.1, 2.0), rng.gen_range(0.1, 2.0), rng.gen_range(0.1, 2.0));
+        Aabb::new(center - half, center + half)
+    }).collect()
+}
+
+fn build(boxes: &[Aabb]) -> Bvh<usize> {
+    let mut bvh = Bvh::default();
+    for (index, aabb) in boxes.iter().enumerate() {
+        bvh.insert(*aabb, index);
+    }
+    bvh
+}
+
+fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+    indices.sort();
+    indices
+}
+
+fn matching<F: Fn(&Aabb) -> bool>(boxes: &[Aabb], test: F) -> Vec<usize> {
+    (0..boxes.len()).filter(|&i| test(&boxes[i])).collect()
+}
+
+#[test]
+fn queries_match_a_linear_scan() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let boxes = random_boxes(&mut rng, 500);
+    let bvh = build(&boxes);
+    bvh.validate();
+    assert_eq!(bvh.len(), 500);
+
+    for _ in 0..20 {
+        let region = random_boxes(&mut rng, 1)[0].expanded(8.0);
+        assert_eq!(sorted(bvh.query_aabb(&region)), matching(&boxes, |b| b.intersects(&region)));
+
+        let sphere = BoundingSphere::new(region.center(), rng.gen_range(1.0, 20.0));
+        assert_eq!(sorted(bvh.query_sphere(&sphere)), matching(&boxes, |b| sphere.intersects_aabb(b)));
+
+        let point = region.center();
+        let (nearest, distance) = bvh.nearest(point).unwrap();
+        let closest = boxes.iter().map(|b| b.distance_to_point(point)).fold(f32::INFINITY, f32::min);
+        assert_eq!(distance, closest);
+        assert_eq!(boxes[nearest].distance_to_point(point), closest);
+
+        let direction = vec3(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+        let ray = Ray::new(vec3(0.0, 0.0, 0.0) - direction * 80.0, direction);
+        let first = boxes.iter().filter_map(|b| b.intersect_ray(&ray)).fold(f32::INFINITY, f32::min);
+        match bvh.raycast(&ray, f32::INFINITY) {
+            Some((hit, distance)) => {
+                assert_eq!(distance, first);
+                assert_eq!(boxes[hit].intersect_ray(&ray), Some(first));
+            }
+            None => assert_eq!(first, f32::INFINITY)
+        }
+    }
+
+    let camera = Camera::new(Point3::new(0.0, 0.0, 60.0), -90.0, 0.0);
+    let frustum = Frustum::from_matrix(&(perspective(Deg(45.0), 1.5, 0.1, 80.0) * camera.get_view_matrix()));
+    let visible = sorted(bvh.query_frustum(&frustum));
+    assert_eq!(visible, matching(&boxes, |b| frustum.intersects_aabb(b)));
+    assert!(!visible.is_empty() && visible.len() < boxes.len());
+}
+
+#[test]
+fn raycasts_stop_at_the_closest_refined_hit() {
+    let boxes = vec![
+        Aabb::new(vec3(-1.0, -1.0, 4.0), vec3(1.0, 1.0, 6.0)),
+        Aabb::new(vec3(-1.0, -1.0, 9.0), vec3(1.0, 1.0, 11.0)),
+        Aabb::new(vec3(5.0, -1.0, 1.0), vec3(7.0, 1.0, 3.0))
+    ];
+    let bvh = build(&boxes);
+    let ray = Ray::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0));
+    assert_eq!(bvh.raycast(&ray, 100.0), Some((0, 4.0)));
+    assert_eq!(bvh.raycast(&ray, 3.0), None);
+    // the first box is hollow along the ray
+    assert_eq!(bvh.raycast_with(&ray, 100.0, |entity, distance| if entity == 0 { None } else { Some(distance + 0.5) }), Some((1, 9.5)));
+    // starting inside a box hits it at once
+    let inside = Ray::new(vec3(0.0, 0.0, 5.0), vec3(0.0, 0.0, 1.0));
+    assert_eq!(bvh.raycast(&inside, 100.0), Some((0, 0.0)));
+}
+
+#[test]
+fn moving_leaves_refits_only_when_they_leave_their_fat_box() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut boxes = random_boxes(&mut rng, 200);
+    let mut bvh = Bvh::new(0.5);
+    let proxies: Vec<_> = boxes.iter().enumerate().map(|(i, aabb)| bvh.insert(*aabb, i)).collect();
+
+    let nudge = vec3(0.2, 0.0, 0.0);
+    let moved = Aabb::new(boxes[0].min + nudge, boxes[0].max + nudge);
+    assert!(!bvh.update(proxies[0], moved));
+    assert_eq!(bvh.bounds(proxies[0]), moved);
+    boxes[0] = moved;
+
+    for _ in 0..5 {
+        for (i, proxy) in proxies.iter().enumerate() {
+            let offset = vec3(rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0));
+            boxes[i] = Aabb::new(boxes[i].min + offset, boxes[i].max + offset);
+            bvh.update(*proxy, boxes[i]);
+        }
+        bvh.validate();
+    }
+    let region = Aabb::new(vec3(-20.0, -20.0, -20.0), vec3(20.0, 20.0, 20.0));
+    assert_eq!(sorted(bvh.query_aabb(&region)), matching(&boxes, |b| b.intersects(&region)));
+    assert!(proxies.iter().enumerate().all(|(i, proxy)| bvh.data(*proxy) == i));
+
+    // removed leaves disappear, the other ids stay valid
+    for proxy in proxies.iter().step_by(2) {
+        bvh.remove(*proxy);
+    }
+    bvh.validate();
+    assert_eq!(bvh.len(), 100);
+    let everything = Aabb::new(vec3(-1000.0, -1000.0, -1000.0), vec3(1000.0, 1000.0, 1000.0));
+    assert_eq!(sorted(bvh.query_aabb(&everything)), (1..200).step_by(2).collect::<Vec<usize>>());
+    assert_eq!(bvh.data(proxies[1]), 1);
+
+    bvh.clear();
+    assert!(bvh.is_empty());
+    assert_eq!(bvh.raycast(&Ray::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)), 10.0), None);
+    assert_eq!(bvh.nearest(vec3(0.0, 0.0, 0.0)), None);
+}
+
+#[test]
+fn sorted_insertions_stay_balanced() {
+    let mut bvh = Bvh::default();
+    for i in 0..1024 {
+        let x = i as f32 * 2.0;
+        bvh.insert(Aabb::new(vec3(x, 0.0, 0.0), vec3(x + 1.0, 1.0, 1.0)), i);
+    }
+    bvh.validate();
+    // log2(1024) = 10 for a perfect tree
+    assert!(bvh.height() <= 20, "height {}", bvh.height());
+}
+
+fn cube_model(device: &mut NullDevice) -> Model {
+    let vertices = vec![
+        Vertex { position: vec3(-0.5, -0.5, -0.5), ..Vertex::default() },
+        Vertex { position: vec3(0.5, 0.5, 0.5), ..Vertex::default() },
+        Vertex { position: vec3(0.5, -0.5, 0.5), ..Vertex::default() }
+    ];
+    Model::from_meshes(vec![Mesh::with_device(device, vertices, vec![0, 1, 2], Vec::new(), Material::default())])
+}
+
+#[test]
+fn scene_keeps_its_bvh_in_step_with_the_entities() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    for x in 0..10 {
+        let model = cube_model(&mut device);
+        scene.entities.push(Entity::new(Some(model), at(vec3(x as f32 * 3.0, 0.0, 0.0))));
+    }
+    scene.entities.push(Entity::new(None, at(vec3(0.0, 0.0, 0.0))));
+    scene.update_bvh();
+    scene.bvh().validate();
+    assert_eq!(scene.bvh().len(), 10);
+    assert!(scene.proxy(10).is_none());
+
+    let ray = Ray::new(vec3(-10.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+    assert_eq!(scene.bvh().raycast(&ray, 100.0), Some((0, 9.5)));
+    assert_eq!(scene.bvh().nearest(vec3(13.0, 4.0, 0.0)).map(|(entity, _)| entity), Some(4));
+
+    // the first entity moves out of the way of the ray
+    scene.entities[0].transform.position.y = 5.0;
+    scene.update_bvh();
+    assert_eq!(scene.bvh().raycast(&ray, 100.0).map(|(entity, _)| entity), Some(1));
+
+    // entities without a model leave the tree, truncated ones too
+    scene.entities[1].model = None;
+    scene.entities.truncate(5);
+    scene.update_bvh();
+    scene.bvh().validate();
+    assert_eq!(sorted(scene.bvh().query_sphere(&BoundingSphere::new(vec3(0.0, 0.0, 0.0), 1000.0))), vec![0, 2, 3, 4]);
+
+    // culling through the tree agrees with testing every entity
+    let camera = Camera::new(Point3::new(6.0, 0.0, 10.0), -90.0, 0.0);
+    let frustum = Frustum::from_matrix(&(perspective(Deg(30.0), 1.0, 0.1, 100.0) * camera.get_view_matrix()));
+    let culling = scene.cull(&frustum);
+    let expected: Vec<bool> = scene.entities.iter()
+        .map(|entity| entity.world_bounds().is_none_or(|bounds| frustum.intersects_aabb(&bounds.aabb)))
+        .collect();
+    assert_eq!((0..5).map(|entity| culling.is_visible(entity)).collect::<Vec<bool>>(), expected);
+    assert_eq!(culling.stats.tested, 4);
+    assert!(culling.stats.culled() > 0);
+}
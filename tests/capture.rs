@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::Path;
+
+use argus_engine::graphics::capture::{ write_exr, CaptureFormat, CapturePixels, CapturedImage, Recorder };
+
+fn read_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn read_f32(bytes: &[u8], at: usize) -> f32 {
+    f32::from_bits(read_i32(bytes, at) as u32)
+}
+
+#[test]
+fn exr_scanlines_are_planar_and_indexed() {
+    // 2x2, rows top to bottom
+    let rgba = [
+        1.0, 2.0, 3.0, 1.0,   4.0, 5.0, 6.0, 1.0,
+        7.0, 8.0, 9.0, 0.5,   10.0, 11.0, 12.0, 0.5
+    ];
+    let mut bytes = Vec::new();
+    write_exr(&mut bytes, 2, 2, &rgba).unwrap();
+
+    assert_eq!(&bytes[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+    assert_eq!(read_i32(&bytes, 4), 2);
+
+    // header ends with an empty attribute name right before the offset table
+    let table = bytes.windows(18).position(|window| window == b"screenWindowWidth\0").unwrap() + 18 + 6 + 4 + 4 + 1;
+    let first = u64::from_le_bytes(bytes[table..table + 8].try_into().unwrap()) as usize;
+    let second = u64::from_le_bytes(bytes[table + 8..table + 16].try_into().unwrap()) as usize;
+    assert_eq!(first, table + 16);
+    assert_eq!(second - first, 8 + 2 * 4 * 4);
+    assert_eq!(bytes.len(), second + 8 + 2 * 4 * 4);
+
+    // second line: y, size, then A A B B G G R R
+    assert_eq!(read_i32(&bytes, second), 1);
+    assert_eq!(read_i32(&bytes, second + 4), 32);
+    let channels: Vec<f32> = (0..8).map(|i| read_f32(&bytes, second + 8 + i * 4)).collect();
+    assert_eq!(channels, vec![0.5, 0.5, 9.0, 12.0, 8.0, 11.0, 7.0, 10.0]);
+}
+
+#[test]
+fn saved_images_are_flipped_to_top_down() {
+    let directory = Path::new("target/capture_test");
+    let path = directory.join("flip.png");
+    // GL rows: bottom red, top green
+    let image = CapturedImage {
+        width: 1,
+        height: 2,
+        pixels: CapturePixels::LDR(vec![255, 0, 0, 255, 0, 255, 0, 255])
+    };
+    image.save(&path).unwrap();
+
+    let loaded = image::open(&path).unwrap().to_rgba();
+    assert_eq!(loaded.get_pixel(0, 0).data, [0, 255, 0, 255]);
+    assert_eq!(loaded.get_pixel(0, 1).data, [255, 0, 0, 255]);
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn recorder_numbers_frames_at_a_fixed_step() {
+    let mut recorder = Recorder::new(Path::new("recordings/take"), 30.0, CaptureFormat::EXR);
+    assert!((recorder.frame_time - 1.0 / 30.0).abs() < 1e-6);
+    assert_eq!(recorder.next_path(), Path::new("recordings/take/frame_00000.exr"));
+    assert_eq!(recorder.next_path(), Path::new("recordings/take/frame_00001.exr"));
+    assert_eq!(recorder.frame, 2);
+}
+
+#[test]
+fn format_follows_the_extension() {
+    assert_eq!(CaptureFormat::from_path(Path::new("shot.PNG")), Some(CaptureFormat::PNG));
+    assert_eq!(CaptureFormat::from_path(Path::new("shot.exr")), Some(CaptureFormat::EXR));
+    assert_eq!(CaptureFormat::from_path(Path::new("shot")), None);
+}
@@ -0,0 +1,104 @@
+use cgmath::{ perspective, vec3, vec4, Deg, Matrix4, Vector3 };
+
+use argus_engine::graphics::clustered::{ ClusterConfig, ClusterGrid, LightSphere };
+
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
+
+fn projection() -> Matrix4<f32> {
+    perspective(Deg(45.0), 16.0 / 9.0, NEAR, FAR)
+}
+
+fn grid(config: ClusterConfig) -> ClusterGrid {
+    ClusterGrid::new(config, &projection(), NEAR, FAR)
+}
+
+// cluster of a view-space point, computed the way model.fs does from the fragment position
+fn cluster_of(grid: &ClusterGrid, point: Vector3<f32>) -> usize {
+    let clip = projection() * vec4(point.x, point.y, point.z, 1.0);
+    let (ndc_x, ndc_y) = (clip.x / clip.w, clip.y / clip.w);
+    let config = &grid.config;
+    let x = (((ndc_x * 0.5 + 0.5) * config.tiles_x as f32) as usize).min(config.tiles_x - 1);
+    let y = (((ndc_y * 0.5 + 0.5) * config.tiles_y as f32) as usize).min(config.tiles_y - 1);
+    grid.cluster_index(x, y, grid.depth_slice(-point.z))
+}
+
+#[test]
+fn slices_span_near_to_far() {
+    let grid = grid(ClusterConfig::default());
+    let slices = grid.config.depth_slices;
+    assert!((grid.slice_depth(0) - NEAR).abs() < 1e-5);
+    assert!((grid.slice_depth(slices) - FAR).abs() < 1e-3);
+
+    for slice in 0..slices {
+        let middle = (grid.slice_depth(slice) + grid.slice_depth(slice + 1)) / 2.0;
+        assert_eq!(grid.depth_slice(middle), slice);
+    }
+    // depths outside of the frustum are clamped to the first and last slice
+    assert_eq!(grid.depth_slice(0.0), 0);
+    assert_eq!(grid.depth_slice(1000.0), slices - 1);
+}
+
+#[test]
+fn cluster_bounds_contain_their_points() {
+    let grid = grid(ClusterConfig::default());
+    for &point in &[vec3(0.0, 0.0, -1.0), vec3(0.5, -0.3, -2.0), vec3(-20.0, 10.0, -60.0), vec3(3.0, 1.5, -7.5)] {
+        let bounds = grid.bounds(cluster_of(&grid, point));
+        assert!(bounds.intersects_sphere(point, 1e-4), "{:?} is outside of {:?}", point, bounds);
+    }
+}
+
+#[test]
+fn light_is_assigned_to_its_own_cluster() {
+    let grid = grid(ClusterConfig::default());
+    let light = LightSphere { center: vec3(1.0, 0.5, -5.0), radius: 0.5 };
+    let assignment = grid.assign(&[light]);
+
+    assert_eq!(assignment.lights_in(cluster_of(&grid, light.center)), &[0]);
+    // the light only reaches the clusters around it
+    let reached = (0..grid.cluster_count()).filter(|&c| !assignment.lights_in(c).is_empty()).count();
+    assert!(reached > 0 && reached < 64, "light reached {} clusters", reached);
+    assert_eq!(assignment.dropped, 0);
+}
+
+#[test]
+fn lights_outside_of_the_frustum_are_culled() {
+    let grid = grid(ClusterConfig::default());
+    let lights = [
+        // behind the camera
+        LightSphere { center: vec3(0.0, 0.0, 5.0), radius: 1.0 },
+        // past the far plane
+        LightSphere { center: vec3(0.0, 0.0, -150.0), radius: 10.0 },
+        // far to the left
+        LightSphere { center: vec3(-50.0, 0.0, -5.0), radius: 1.0 }
+    ];
+    let assignment = grid.assign(&lights);
+    assert!(assignment.indices.is_empty());
+}
+
+#[test]
+fn lights_keep_their_order_in_every_cluster() {
+    let grid = grid(ClusterConfig::default());
+    let lights: Vec<LightSphere> = (0..8)
+        .map(|i| LightSphere { center: vec3(i as f32 * 0.1, 0.0, -4.0), radius: 2.0 })
+        .collect();
+    let assignment = grid.assign(&lights);
+
+    let cluster = cluster_of(&grid, vec3(0.3, 0.0, -4.0));
+    assert_eq!(assignment.lights_in(cluster), &[0, 1, 2, 3, 4, 5, 6, 7]);
+    for c in 0..grid.cluster_count() {
+        assert!(assignment.lights_in(c).windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
+
+#[test]
+fn full_clusters_drop_the_last_lights() {
+    let grid = grid(ClusterConfig { max_lights_per_cluster: 4, ..ClusterConfig::default() });
+    let lights = vec![LightSphere { center: vec3(0.0, 0.0, -3.0), radius: 0.2 }; 6];
+    let assignment = grid.assign(&lights);
+
+    let cluster = cluster_of(&grid, vec3(0.0, 0.0, -3.0));
+    assert_eq!(assignment.lights_in(cluster), &[0, 1, 2, 3]);
+    assert!(assignment.dropped >= 2);
+    assert!((0..grid.cluster_count()).all(|c| assignment.lights_in(c).len() <= 4));
+}
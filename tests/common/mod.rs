@@ -0,0 +1,52 @@
+// Fixtures shared by the integration tests. Every suite compiles its own copy of this module and uses
+// only some of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use cgmath::{ vec3, Vector3 };
+
+use argus_engine::graphics::device::{ NullDevice, PixelFormat, TextureData };
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::{ Mesh, Texture, Vertex };
+use argus_engine::graphics::shader::Shader;
+use argus_engine::world::scene::Scene;
+use argus_engine::world::skybox::SkyBox;
+use argus_engine::world::transform::Transform;
+
+// Write `files` under target/<suite>/<test>, a directory of its own per test as they run in parallel.
+// Names may contain folders. Returns the directory.
+pub fn write_files(suite: &str, test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = Path::new("target").join(suite).join(test);
+    fs::create_dir_all(&directory).unwrap();
+    for (name, source) in files {
+        let path = directory.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    directory
+}
+
+// an empty scene with a black 1x1 skybox
+pub fn scene(device: &mut NullDevice) -> Scene {
+    let shader = Shader::from_source(device, "", "").unwrap();
+    let pixels = [0u8; 3];
+    let face = TextureData { width: 1, height: 1, format: PixelFormat::SRGB8, pixels: &pixels };
+    Scene::with_skybox(SkyBox::with_device(device, &[face; 6], &shader))
+}
+
+// the triangle (0, 0, 0), (1, 0, 0), (0, 1, 0)
+pub fn triangle(device: &mut NullDevice, textures: Vec<Texture>, material: Material) -> Mesh {
+    let vertices = vec![
+        Vertex { position: vec3(0.0, 0.0, 0.0), ..Vertex::default() },
+        Vertex { position: vec3(1.0, 0.0, 0.0), ..Vertex::default() },
+        Vertex { position: vec3(0.0, 1.0, 0.0), ..Vertex::default() }
+    ];
+    Mesh::with_device(device, vertices, vec![0, 1, 2], textures, material)
+}
+
+// moved to `position`, neither rotated nor scaled
+pub fn at(position: Vector3<f32>) -> Transform {
+    Transform { position, ..Transform::default() }
+}
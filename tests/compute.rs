@@ -0,0 +1,161 @@
+use std::path::{ Path, PathBuf };
+
+use argus_engine::graphics::compute::ComputeShader;
+use argus_engine::graphics::device::{ Command, DeviceFeatures, ImageAccess, ImageFormat, NullDevice, ShaderStage };
+use argus_engine::graphics::shader::Shader;
+
+mod common;
+
+const VERTEX: &str = "#version 410 core\nlayout (location = 0) in vec3 aPos;\nvoid main() { gl_Position = vec4(aPos, 1.0); }\n";
+const TESS_CONTROL: &str = "#version 410 core\nlayout (vertices = 3) out;\nvoid main() {}\n";
+const TESS_EVALUATION: &str = "#version 410 core\nlayout (triangles) in;\nvoid main() {}\n";
+const GEOMETRY: &str = "#version 410 core\nlayout (triangles) in;\nlayout (line_strip, max_vertices = 4) out;\nvoid main() {}\n";
+const FRAGMENT: &str = "#version 410 core\nout vec4 color;\nvoid main() { color = vec4(1.0); }\n";
+const COMPUTE: &str = "#version 430 core
+#define GROUP_SIZE 16
+layout (local_size_x = GROUP_SIZE, local_size_y = 4) in;
+layout (std430, binding = 0) buffer Particles { vec4 positions[]; };
+layout (rgba16f, binding = 1) uniform image2D target;
+void main() {}
+";
+
+// the paths of `files` in the order given
+fn write_sources(test: &str, files: &[(&str, &str)]) -> Vec<PathBuf> {
+    let directory = common::write_files("compute_test", test, files);
+    files.iter().map(|(name, _)| directory.join(name)).collect()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn created_stages(device: &NullDevice) -> Vec<ShaderStage> {
+    device.commands.iter()
+        .filter_map(|c| match c {
+            Command::CREATE_SHADER { stage, .. } => Some(*stage),
+            _ => None
+        })
+        .collect()
+}
+
+#[test]
+fn features_follow_the_context_version() {
+    let baseline = DeviceFeatures::for_version(3, 3);
+    assert!(baseline.supports(ShaderStage::GEOMETRY));
+    assert!(!baseline.supports(ShaderStage::TESS_CONTROL));
+    assert!(!baseline.supports(ShaderStage::COMPUTE));
+    assert_eq!(DeviceFeatures::default(), baseline);
+
+    let tessellation = DeviceFeatures::for_version(4, 1);
+    assert!(tessellation.tessellation && !tessellation.compute);
+    assert!(DeviceFeatures::for_version(4, 6).supports(ShaderStage::COMPUTE));
+}
+
+#[test]
+fn builder_links_every_stage_in_pipeline_order() {
+    let files = write_sources("all_stages", &[
+        ("test.fs", FRAGMENT), ("test.gs", GEOMETRY), ("test.tes", TESS_EVALUATION), ("test.tcs", TESS_CONTROL), ("test.vs", VERTEX)
+    ]);
+    let mut device = NullDevice::new();
+    device.features = DeviceFeatures::for_version(4, 1);
+
+    let shader = Shader::builder()
+        .stage(ShaderStage::FRAGMENT, path(&files[0]))
+        .stage(ShaderStage::GEOMETRY, path(&files[1]))
+        .stage(ShaderStage::TESS_EVALUATION, path(&files[2]))
+        .stage(ShaderStage::TESS_CONTROL, path(&files[3]))
+        .stage(ShaderStage::VERTEX, path(&files[4]))
+        .build_with(&mut device)
+        .unwrap();
+
+    let order = vec![ShaderStage::VERTEX, ShaderStage::TESS_CONTROL, ShaderStage::TESS_EVALUATION, ShaderStage::GEOMETRY, ShaderStage::FRAGMENT];
+    assert_eq!(created_stages(&device), order);
+    assert_eq!(shader.stages, order);
+    assert_eq!(shader.sources[0], files[4]);
+    assert!(device.commands.iter().any(|c| matches!(c, Command::CREATE_PROGRAM { shaders, .. } if shaders.len() == 5)));
+}
+
+#[test]
+fn unsupported_stages_are_rejected_before_compiling() {
+    let files = write_sources("unsupported", &[("test.vs", VERTEX), ("test.tes", TESS_EVALUATION), ("test.fs", FRAGMENT)]);
+    let mut device = NullDevice::new();
+
+    let error = Shader::builder()
+        .stage(ShaderStage::VERTEX, path(&files[0]))
+        .stage(ShaderStage::TESS_EVALUATION, path(&files[1]))
+        .stage(ShaderStage::FRAGMENT, path(&files[2]))
+        .build_with(&mut device)
+        .err()
+        .unwrap();
+    assert!(error.contains("TESS_EVALUATION"), "{}", error);
+    assert!(device.commands.is_empty());
+}
+
+#[test]
+fn stages_must_form_a_program() {
+    let files = write_sources("invalid", &[("test.fs", FRAGMENT), ("test.comp", COMPUTE), ("test.vs", VERTEX)]);
+    let mut device = NullDevice::new();
+    device.features = DeviceFeatures::for_version(4, 6);
+
+    let fragment_only = Shader::builder().stage(ShaderStage::FRAGMENT, path(&files[0])).build_with(&mut device);
+    assert!(fragment_only.is_err());
+    let mixed = Shader::builder()
+        .stage(ShaderStage::VERTEX, path(&files[2]))
+        .stage(ShaderStage::COMPUTE, path(&files[1]))
+        .build_with(&mut device);
+    assert!(mixed.is_err());
+    assert!(device.commands.is_empty());
+}
+
+#[test]
+fn compute_shaders_fall_back_on_a_3_3_context() {
+    let files = write_sources("fallback", &[("test.comp", COMPUTE)]);
+    let mut device = NullDevice::new();
+
+    assert!(!ComputeShader::is_supported(&mut device));
+    let error = ComputeShader::with_device(&mut device, path(&files[0]), &[]).err().unwrap();
+    assert!(error.contains("4.3"), "{}", error);
+    assert!(device.commands.is_empty());
+}
+
+#[test]
+fn compute_dispatch_covers_the_work_items() {
+    let files = write_sources("dispatch", &[("test.comp", COMPUTE)]);
+    let mut device = NullDevice::new();
+    device.features = DeviceFeatures::for_version(4, 3);
+
+    let compute = ComputeShader::with_device(&mut device, path(&files[0]), &[]).unwrap();
+    assert_eq!(compute.local_size, [16, 4, 1]);
+    assert_eq!(compute.group_count([100, 8, 0]), [7, 2, 1]);
+
+    device.clear();
+    compute.bind_storage_buffer(&mut device, 0, 42);
+    compute.bind_image(&mut device, 1, 7, ImageAccess::WRITE_ONLY, ImageFormat::RGBA16F);
+    compute.run(&mut device, [32, 4, 1]);
+    assert_eq!(device.commands, vec![
+        Command::BIND_STORAGE_BUFFER { binding: 0, buffer: 42 },
+        Command::BIND_IMAGE { unit: 1, texture: 7, access: ImageAccess::WRITE_ONLY, format: ImageFormat::RGBA16F },
+        Command::USE_PROGRAM(compute.shader.id),
+        Command::DISPATCH_COMPUTE([2, 1, 1]),
+        Command::MEMORY_BARRIER
+    ]);
+}
+
+#[test]
+fn multi_stage_programs_reload_every_stage() {
+    let files = write_sources("reload", &[("test.vs", VERTEX), ("test.gs", GEOMETRY), ("test.fs", FRAGMENT)]);
+    let mut device = NullDevice::new();
+    let mut shader = Shader::builder()
+        .stage(ShaderStage::VERTEX, path(&files[0]))
+        .stage(ShaderStage::GEOMETRY, path(&files[1]))
+        .stage(ShaderStage::FRAGMENT, path(&files[2]))
+        .build_with(&mut device)
+        .unwrap();
+    let old = shader.id;
+
+    device.clear();
+    shader.reload_with(&mut device).unwrap();
+    assert_eq!(created_stages(&device), vec![ShaderStage::VERTEX, ShaderStage::GEOMETRY, ShaderStage::FRAGMENT]);
+    assert!(device.commands.contains(&Command::DELETE_PROGRAM(old)));
+    assert_eq!(shader.generation, 2);
+}
@@ -0,0 +1,140 @@
+use cgmath::{ ortho, perspective, vec3, Deg, Point3, Vector3 };
+
+use argus_engine::graphics::camera::Camera;
+use argus_engine::graphics::device::NullDevice;
+use argus_engine::graphics::instancing::InstanceBuffer;
+use argus_engine::graphics::material::{ BlendMode, Material, RenderQueue };
+use argus_engine::graphics::mesh::{ Mesh, Vertex };
+use argus_engine::graphics::model::Model;
+use argus_engine::graphics::profiler::Profiler;
+use argus_engine::graphics::shader::Shader;
+use argus_engine::world::bounds::{ Aabb, BoundingSphere };
+use argus_engine::world::entity::Entity;
+use argus_engine::world::frustum::{ Containment, Frustum };
+use argus_engine::world::scene::CullingStats;
+
+mod common;
+
+use common::{ at, scene };
+
+// at the origin looking down -z, 90 degrees vertically and horizontally, from 1 to 10
+fn frustum() -> Frustum {
+    let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), -90.0, 0.0);
+    Frustum::from_matrix(&(perspective(Deg(90.0), 1.0, 1.0, 10.0) * camera.get_view_matrix()))
+}
+
+fn cube(center: Vector3<f32>, half: f32) -> Aabb {
+    Aabb::new(center - vec3(half, half, half), center + vec3(half, half, half))
+}
+
+fn unit_model(device: &mut NullDevice, material: Material) -> Model {
+    let vertices = vec![
+        Vertex { position: vec3(-0.5, -0.5, 0.0), ..Vertex::default() },
+        Vertex { position: vec3(0.5, -0.5, 0.0), ..Vertex::default() },
+        Vertex { position: vec3(0.0, 0.5, 0.0), ..Vertex::default() }
+    ];
+    Model::from_meshes(vec![Mesh::with_device(device, vertices, vec![0, 1, 2], Vec::new(), material)])
+}
+
+#[test]
+fn planes_face_into_the_frustum_in_world_units() {
+    let frustum = frustum();
+    // near and far
+    assert!((frustum.planes[4].signed_distance(vec3(0.0, 0.0, -3.0)) - 2.0).abs() < 1e-4);
+    assert!((frustum.planes[5].signed_distance(vec3(0.0, 0.0, -3.0)) - 7.0).abs() < 1e-4);
+
+    assert!(frustum.contains_point(vec3(0.0, 0.0, -5.0)));
+    assert!(frustum.contains_point(vec3(4.9, -4.9, -5.0)));
+    assert!(!frustum.contains_point(vec3(5.1, 0.0, -5.0)));
+    assert!(!frustum.contains_point(vec3(0.0, 0.0, 5.0)));
+    assert!(!frustum.contains_point(vec3(0.0, 0.0, -0.5)));
+    assert!(!frustum.contains_point(vec3(0.0, 0.0, -10.5)));
+}
+
+#[test]
+fn spheres_and_boxes_are_tested_against_every_plane() {
+    let frustum = frustum();
+    assert!(frustum.intersects_sphere(&BoundingSphere::new(vec3(0.0, 0.0, -5.0), 1.0)));
+    // straddling the far plane and the left plane
+    assert!(frustum.intersects_sphere(&BoundingSphere::new(vec3(0.0, 0.0, -10.5), 1.0)));
+    assert!(frustum.intersects_sphere(&BoundingSphere::new(vec3(-5.5, 0.0, -5.0), 1.0)));
+    assert!(!frustum.intersects_sphere(&BoundingSphere::new(vec3(0.0, 0.0, 2.0), 1.0)));
+    assert!(!frustum.intersects_sphere(&BoundingSphere::new(vec3(0.0, 8.0, -5.0), 1.0)));
+
+    assert_eq!(frustum.classify_aabb(&cube(vec3(0.0, 0.0, -5.0), 1.0)), Containment::INSIDE);
+    assert_eq!(frustum.classify_aabb(&cube(vec3(0.0, 0.0, -10.0), 1.0)), Containment::INTERSECTS);
+    assert_eq!(frustum.classify_aabb(&cube(vec3(5.0, 0.0, -5.0), 0.5)), Containment::INTERSECTS);
+    assert_eq!(frustum.classify_aabb(&cube(vec3(0.0, 0.0, 3.0), 1.0)), Containment::OUTSIDE);
+    assert_eq!(frustum.classify_aabb(&cube(vec3(-9.0, 0.0, -5.0), 1.0)), Containment::OUTSIDE);
+    assert_eq!(frustum.classify_aabb(&Aabb::empty()), Containment::OUTSIDE);
+    // a box around the whole frustum
+    assert!(frustum.intersects_aabb(&cube(vec3(0.0, 0.0, 0.0), 100.0)));
+}
+
+#[test]
+fn orthographic_frustums_are_boxes() {
+    let frustum = Frustum::from_matrix(&ortho(-2.0, 2.0, -1.0, 1.0, 0.0, 5.0));
+    assert!(frustum.contains_point(vec3(1.9, 0.9, -4.9)));
+    assert!(!frustum.contains_point(vec3(2.1, 0.0, -1.0)));
+    assert!(!frustum.contains_point(vec3(0.0, 1.1, -1.0)));
+    assert!(!frustum.contains_point(vec3(0.0, 0.0, -5.1)));
+    assert!(frustum.intersects_aabb(&cube(vec3(2.4, 0.0, -1.0), 0.5)));
+    assert!(!frustum.intersects_aabb(&cube(vec3(2.6, 0.0, -1.0), 0.5)));
+}
+
+#[test]
+fn culled_entities_are_not_drawn() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let shader = Shader::from_source(&mut device, "", "").unwrap();
+    let instances = InstanceBuffer::with_device(&mut device, 64);
+    let glass = Material { blend_mode: BlendMode::ALPHA, opacity: 0.5, ..Material::default() };
+
+    // in front, behind, in front but transparent, off to the side, past the far plane
+    for (position, material) in [
+        (vec3(0.0, 0.0, -5.0), Material::default()),
+        (vec3(0.0, 0.0, 5.0), Material::default()),
+        (vec3(1.0, 0.0, -4.0), glass),
+        (vec3(20.0, 0.0, -5.0), Material::default()),
+        (vec3(0.0, 0.0, -30.0), glass)
+    ] {
+        let model = unit_model(&mut device, material);
+        scene.entities.push(Entity::new(Some(model), at(position)));
+    }
+    scene.entities.push(Entity::new(None, at(vec3(0.0, 0.0, 0.0))));
+
+    let culling = scene.cull(&frustum());
+    assert_eq!(culling.stats, CullingStats { tested: 5, visible: 2 });
+    assert_eq!(culling.stats.culled(), 3);
+    let visible: Vec<bool> = (0..7).map(|entity| culling.is_visible(entity)).collect();
+    assert_eq!(visible, vec![true, false, true, false, false, true, true]);
+
+    let opaque = scene.collect_visible_batches(&culling, |mesh| !mesh.material.is_transparent());
+    assert_eq!(opaque.iter().map(|batch| batch.entity).collect::<Vec<usize>>(), vec![0]);
+    let transparent = scene.collect_visible_queue(RenderQueue::TRANSPARENT, vec3(0.0, 0.0, 0.0), &culling);
+    assert_eq!(transparent.iter().map(|item| item.entity).collect::<Vec<usize>>(), vec![2]);
+
+    device.clear();
+    scene.render_visible_with(&mut device, &shader, &instances, &culling, |_| true);
+    assert_eq!(device.draws().len(), 2);
+    device.clear();
+    scene.render_filtered_with(&mut device, &shader, &instances, |_| true);
+    assert_eq!(device.draws().len(), 5);
+
+    // moving an entity into view is picked up on the next cull
+    scene.entities[1].transform.position = vec3(-1.0, 1.0, -3.0);
+    assert_eq!(scene.cull(&frustum()).stats.visible, 3);
+}
+
+#[test]
+fn profiler_reports_culling() {
+    let mut profiler = Profiler::new();
+    profiler.toggle();
+    profiler.record_culling(CullingStats { tested: 100, visible: 40 });
+    profiler.end_frame(1.0);
+    assert_eq!(profiler.last_culling().culled(), 60);
+    profiler.record_culling(CullingStats { tested: 10, visible: 10 });
+    profiler.end_frame(0.1);
+    assert_eq!(profiler.last_culling(), CullingStats { tested: 10, visible: 10 });
+    assert!(profiler.report().contains("entities 10 visible of 10"));
+}
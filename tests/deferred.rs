@@ -0,0 +1,27 @@
+use cgmath::{ vec3, Vector3 };
+use cgmath::prelude::*;
+
+use argus_engine::graphics::deferred::{ circumscribing_scale, unit_sphere };
+
+#[test]
+fn light_volumes_contain_the_light_range() {
+    for (segments, rings) in [(16, 12), (8, 6), (32, 32)] {
+        let (positions, indices) = unit_sphere(segments, rings);
+        let vertices: Vec<Vector3<f32>> = positions.chunks_exact(3).map(|p| vec3(p[0], p[1], p[2])).collect();
+        assert_eq!(vertices.len() as u32, (segments + 1) * (rings + 1));
+        assert!(vertices.iter().all(|v| v.magnitude() >= 1.0 - 1e-5), "a vertex is inside the unit sphere");
+        assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+
+        // once scaled every face is outside the unit sphere, the poles have degenerate triangles
+        let scale = circumscribing_scale(segments, rings);
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| vertices[triangle[k] as usize] * scale);
+            let normal = (b - a).cross(c - a);
+            if normal.magnitude2() < 1e-10 {
+                continue;
+            }
+            let distance = normal.normalize().dot(a).abs();
+            assert!(distance >= 1.0 - 1e-5, "{}x{}: a face is {} from the light", segments, rings, distance);
+        }
+    }
+}
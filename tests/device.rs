@@ -0,0 +1,104 @@
+use argus_engine::graphics::device::{ BufferKind, Command, DepthFunc, NullDevice, PipelineState, PixelFormat, TextureData, TextureTarget, UniformValue };
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::{ Texture, Vertex };
+use argus_engine::graphics::shader::Shader;
+use argus_engine::world::skybox::SkyBox;
+
+mod common;
+
+use common::triangle;
+
+fn texture(id: u32, type_: &str) -> Texture {
+    Texture { id, type_: type_.into(), path: String::new(), has_alpha: false }
+}
+
+#[test]
+fn shader_programs_link_both_stages_and_release_them() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, "vertex", "fragment").unwrap();
+
+    let linked = device.commands.iter().find_map(|c| match c {
+        Command::CREATE_PROGRAM { program, shaders } => Some((*program, shaders.clone())),
+        _ => None
+    });
+    let (program, shaders) = linked.unwrap();
+    assert_eq!(program, shader.id);
+    assert_eq!(shaders.len(), 2);
+    for stage in shaders {
+        assert!(device.commands.contains(&Command::DELETE_SHADER(stage)));
+    }
+}
+
+#[test]
+fn mesh_upload_creates_buffers_and_vertex_layout() {
+    let mut device = NullDevice::new();
+    let mesh = triangle(&mut device, Vec::new(), Material::default());
+
+    let sizes: Vec<(BufferKind, usize)> = device.commands.iter()
+        .filter_map(|c| match c {
+            Command::CREATE_BUFFER { kind, size, .. } => Some((*kind, *size)),
+            _ => None
+        })
+        .collect();
+    assert_eq!(sizes, [(BufferKind::VERTEX, 3 * std::mem::size_of::<Vertex>()), (BufferKind::INDEX, 3 * 4)]);
+
+    match device.commands.last().unwrap() {
+        Command::CREATE_VERTEX_ARRAY { vertex_array, stride, attributes, index_buffer, .. } => {
+            assert_eq!(*vertex_array, mesh.vao);
+            assert_eq!(*stride as usize, std::mem::size_of::<Vertex>());
+            assert!(index_buffer.is_some());
+            let locations: Vec<u32> = attributes.iter().map(|a| a.location).collect();
+            assert_eq!(locations, [0, 1, 2, 3, 4]);
+        }
+        command => panic!("unexpected {:?}", command)
+    }
+}
+
+#[test]
+fn mesh_draw_binds_numbered_samplers_then_draws_its_indices() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, "", "").unwrap();
+    let textures = vec![texture(7, "texture_diffuse"), texture(8, "texture_specular"), texture(9, "texture_diffuse")];
+    let mesh = triangle(&mut device, textures, Material::default());
+    device.clear();
+
+    mesh.draw_with(&mut device, &shader);
+
+    assert_eq!(device.uniform(shader.id, "texture_diffuse1"), Some(UniformValue::INT(0)));
+    assert_eq!(device.uniform(shader.id, "texture_specular1"), Some(UniformValue::INT(1)));
+    assert_eq!(device.uniform(shader.id, "texture_diffuse2"), Some(UniformValue::INT(2)));
+    assert!(device.commands.contains(&Command::BIND_TEXTURE { unit: 2, target: TextureTarget::TEXTURE_2D, texture: 9 }));
+    assert_eq!(device.uniform(shader.id, "material.alphaCutoff"), Some(UniformValue::FLOAT(0.0)));
+
+    assert_eq!(device.draws(), [&Command::DRAW_ELEMENTS { vertex_array: mesh.vao, count: 3 }]);
+    assert!(device.commands.last().unwrap().is_draw());
+}
+
+#[test]
+fn skybox_is_drawn_with_lequal_depth_and_restores_the_state() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, "", "").unwrap();
+    let pixels = [0u8; 3];
+    let face = TextureData { width: 1, height: 1, format: PixelFormat::SRGB8, pixels: &pixels };
+    let skybox = SkyBox::with_device(&mut device, &[face; 6], &shader);
+    assert!(device.commands.iter().any(|c| matches!(c, Command::CREATE_CUBEMAP { faces: 6, .. })));
+    device.clear();
+
+    skybox.draw(&mut device, &shader);
+
+    let states: Vec<&PipelineState> = device.commands.iter()
+        .filter_map(|c| match c {
+            Command::SET_STATE(state) => Some(state),
+            _ => None
+        })
+        .collect();
+    assert_eq!(states.len(), 2);
+    assert_eq!(states[0].depth_func, DepthFunc::LEQUAL);
+    assert_eq!(*states[1], PipelineState::default());
+
+    // the draw happens between the two state changes
+    let draw = device.commands.iter().position(|c| c.is_draw()).unwrap();
+    assert!(matches!(device.commands[draw], Command::DRAW_ARRAYS { first: 0, count: 36, .. }));
+    assert_eq!(device.commands.last(), Some(&Command::SET_STATE(PipelineState::default())));
+    assert_eq!(device.draws().len(), 1);
+}
@@ -0,0 +1,218 @@
+use cgmath::vec3;
+
+use argus_engine::graphics::device::{ Command, NullDevice, PipelineState, RenderDevice, TextureTarget, UniformValue };
+use argus_engine::graphics::draw_queue::{ DrawQueue, SortKey };
+use argus_engine::graphics::material::{ BlendMode, Material, RenderQueue };
+use argus_engine::graphics::mesh::{ Mesh, Texture };
+use argus_engine::graphics::model::Model;
+use argus_engine::graphics::profiler::Profiler;
+use argus_engine::graphics::shader::Shader;
+use argus_engine::graphics::state_tracker::{ StateCounts, StateStats, StateTracker };
+use argus_engine::graphics::uniform_buffer::UniformRing;
+use argus_engine::world::entity::Entity;
+use argus_engine::world::scene::{ Culling, Scene };
+
+mod common;
+
+use common::{ at, scene, triangle };
+
+const FRAGMENT: &str = "#version 330 core
+struct Material {
+    float specular;
+    float shininess;
+    float opacity;
+    float alphaCutoff;
+};
+uniform Material material;
+uniform sampler2D texture_diffuse1;
+out vec4 FragColor;
+";
+
+fn textured_triangle(device: &mut NullDevice, texture: u32, material: Material) -> Mesh {
+    let textures = vec![Texture { id: texture, type_: "texture_diffuse".to_string(), path: String::new(), has_alpha: false }];
+    triangle(device, textures, material)
+}
+
+// entities further and further away, alternating between two textures
+fn alternating_scene(device: &mut NullDevice, material: Material, count: usize) -> Scene {
+    let mut scene = scene(device);
+    for i in 0..count {
+        let mesh = textured_triangle(device, 100 + (i % 2) as u32, material);
+        scene.entities.push(Entity::new(Some(Model::from_meshes(vec![mesh])), at(vec3(i as f32 * 2.0, 0.0, 0.0))));
+    }
+    scene
+}
+
+fn texture_binds(device: &NullDevice) -> usize {
+    device.commands.iter().filter(|c| matches!(c, Command::BIND_TEXTURE { .. })).count()
+}
+
+#[test]
+fn sort_keys_order_by_queue_program_material_then_depth() {
+    let key = |queue, program, material, depth| SortKey::new(queue, program, material, depth);
+
+    assert!(key(RenderQueue::OPAQUE, 9, 9, 100.0) < key(RenderQueue::ALPHA_TEST, 1, 1, 1.0));
+    assert!(key(RenderQueue::ALPHA_TEST, 9, 9, 100.0) < key(RenderQueue::TRANSPARENT, 1, 1, 1.0));
+    assert!(key(RenderQueue::OPAQUE, 1, 9, 100.0) < key(RenderQueue::OPAQUE, 2, 1, 1.0));
+    assert!(key(RenderQueue::OPAQUE, 1, 1, 100.0) < key(RenderQueue::OPAQUE, 1, 2, 1.0));
+    // front to back
+    assert!(key(RenderQueue::OPAQUE, 1, 1, 1.0) < key(RenderQueue::OPAQUE, 1, 1, 2.0));
+    assert!(key(RenderQueue::OPAQUE, 1, 1, 0.0) < key(RenderQueue::OPAQUE, 1, 1, 0.01));
+
+    // blending needs back to front, whatever the program or material
+    assert!(key(RenderQueue::TRANSPARENT, 9, 9, 50.0) < key(RenderQueue::TRANSPARENT, 1, 1, 10.0));
+    assert!(key(RenderQueue::TRANSPARENT, 1, 1, 10.0) < key(RenderQueue::TRANSPARENT, 2, 1, 10.0));
+}
+
+#[test]
+fn draw_queue_groups_materials_and_keeps_transparent_back_to_front() {
+    let mut device = NullDevice::new();
+    let scene = alternating_scene(&mut device, Material::default(), 6);
+
+    let mut queue = DrawQueue::new();
+    queue.collect(&scene, RenderQueue::OPAQUE, 1, vec3(0.0, 0.0, 0.0), &Culling::none());
+    assert_eq!(queue.len(), 6);
+    queue.sort();
+    let textures: Vec<u32> = queue.items().map(|item| item.entity as u32 % 2).collect();
+    assert!(textures == vec![0, 0, 0, 1, 1, 1] || textures == vec![1, 1, 1, 0, 0, 0]);
+    // front to back inside a material
+    let entities: Vec<usize> = queue.items().map(|item| item.entity).collect();
+    assert!(entities[..3].windows(2).all(|pair| pair[0] < pair[1]));
+
+    let glass = Material { blend_mode: BlendMode::ALPHA, opacity: 0.5, ..Material::default() };
+    let scene = alternating_scene(&mut device, glass, 6);
+    let mut queue = DrawQueue::new();
+    queue.collect(&scene, RenderQueue::TRANSPARENT, 1, vec3(0.0, 0.0, 0.0), &Culling::none());
+    queue.sort();
+    let entities: Vec<usize> = queue.items().map(|item| item.entity).collect();
+    assert_eq!(entities, vec![5, 4, 3, 2, 1, 0]);
+
+    queue.clear();
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn state_tracker_drops_redundant_changes() {
+    let mut device = NullDevice::new();
+    let stats = {
+        let mut tracker = StateTracker::new(&mut device);
+        for _ in 0..3 {
+            tracker.use_program(1);
+            tracker.set_state(&PipelineState::default());
+            tracker.bind_texture(0, TextureTarget::TEXTURE_2D, 7);
+            tracker.set_uniform_location(1, 4, UniformValue::FLOAT(0.5));
+            tracker.bind_uniform_buffer(2, 9, 0, 64);
+            tracker.draw_arrays(3, 0, 3);
+        }
+        // any difference goes through
+        tracker.bind_texture(0, TextureTarget::TEXTURE_2D, 8);
+        tracker.bind_texture(1, TextureTarget::TEXTURE_2D, 8);
+        tracker.set_uniform_location(1, 4, UniformValue::FLOAT(0.25));
+        tracker.bind_uniform_buffer(2, 9, 64, 64);
+        tracker.use_program(2);
+        // uniforms belong to their program
+        tracker.use_program(1);
+        tracker.set_uniform_location(1, 4, UniformValue::FLOAT(0.25));
+        tracker.stats()
+    };
+
+    let requested = StateCounts { programs: 5, pipeline_states: 3, textures: 5, uniforms: 5, uniform_buffers: 4, draws: 3 };
+    let issued = StateCounts { programs: 3, pipeline_states: 1, textures: 3, uniforms: 2, uniform_buffers: 2, draws: 3 };
+    assert_eq!(stats, StateStats { requested, issued });
+    assert_eq!(texture_binds(&device), 3);
+    assert_eq!(device.draws().len(), 3);
+
+    // a new tracker knows nothing of what is bound
+    device.clear();
+    let mut tracker = StateTracker::new(&mut device);
+    tracker.bind_texture(0, TextureTarget::TEXTURE_2D, 8);
+    tracker.invalidate();
+    tracker.bind_texture(0, TextureTarget::TEXTURE_2D, 8);
+    assert_eq!(tracker.stats().issued.textures, 2);
+}
+
+#[test]
+fn sorting_by_material_cuts_texture_binds_and_uniforms() {
+    let mut device = NullDevice::new();
+    let scene = alternating_scene(&mut device, Material::default(), 8);
+    let shader = Shader::from_source(&mut device, "", FRAGMENT).unwrap();
+    let objects = UniformRing::with_device(&mut device, 64 * 1024);
+
+    let mut submit = |sort: bool| {
+        let mut queue = DrawQueue::new();
+        queue.collect(&scene, RenderQueue::OPAQUE, shader.id, vec3(0.0, 0.0, 0.0), &Culling::none());
+        if sort {
+            queue.sort();
+        }
+        device.clear();
+        let mut tracker = StateTracker::new(&mut device);
+        queue.submit(&mut tracker, &scene, &shader, &objects, false);
+        (tracker.stats(), texture_binds(&device))
+    };
+
+    let (unsorted, unsorted_binds) = submit(false);
+    let (sorted, sorted_binds) = submit(true);
+    assert_eq!(unsorted.requested, sorted.requested);
+    assert_eq!(unsorted.issued.draws, 8);
+    assert_eq!(sorted.issued.draws, 8);
+    assert_eq!(unsorted_binds, 8);
+    assert_eq!(sorted_binds, 2);
+    assert_eq!(sorted.issued.programs, 1);
+    // the material parameters are equal, only the first draw sets them
+    assert!(sorted.issued.uniforms < unsorted.requested.uniforms);
+    assert!(sorted.issued.changes() < unsorted.issued.changes());
+}
+
+#[test]
+fn blended_submission_sets_the_state_once_per_change() {
+    let mut device = NullDevice::new();
+    let glass = Material { blend_mode: BlendMode::ALPHA, opacity: 0.5, ..Material::default() };
+    let scene = alternating_scene(&mut device, glass, 4);
+    let shader = Shader::from_source(&mut device, "", FRAGMENT).unwrap();
+    let objects = UniformRing::with_device(&mut device, 64 * 1024);
+
+    let mut queue = DrawQueue::new();
+    queue.collect(&scene, RenderQueue::TRANSPARENT, shader.id, vec3(0.0, 0.0, 0.0), &Culling::none());
+    queue.sort();
+    device.clear();
+    let mut tracker = StateTracker::new(&mut device);
+    queue.submit(&mut tracker, &scene, &shader, &objects, true);
+    let stats = tracker.stats();
+    assert_eq!(stats.requested.pipeline_states, 5);
+    // the blend state, then back to the default
+    assert_eq!(stats.issued.pipeline_states, 2);
+
+    let states: Vec<PipelineState> = device.commands.iter()
+        .filter_map(|c| match c {
+            Command::SET_STATE(state) => Some(*state),
+            _ => None
+        })
+        .collect();
+    assert_eq!(states[0], PipelineState { depth_write: false, blend: BlendMode::ALPHA, ..PipelineState::default() });
+    assert_eq!(states[1], PipelineState::default());
+}
+
+#[test]
+fn profiler_adds_up_the_passes_of_a_frame() {
+    let mut profiler = Profiler::new();
+    let pass = StateStats {
+        requested: StateCounts { programs: 4, textures: 10, draws: 4, ..StateCounts::default() },
+        issued: StateCounts { programs: 1, textures: 2, draws: 4, ..StateCounts::default() }
+    };
+    profiler.record(pass);
+    profiler.record(pass);
+    profiler.end_frame(0.016);
+    let frame = profiler.last_frame();
+    assert_eq!(frame.requested.changes(), 28);
+    assert_eq!(frame.issued.changes(), 6);
+    assert_eq!(frame.issued.draws, 8);
+
+    // nothing recorded since
+    profiler.end_frame(0.016);
+    assert_eq!(profiler.last_frame(), StateStats::default());
+
+    assert!(profiler.toggle());
+    profiler.record(pass);
+    profiler.end_frame(0.5);
+    assert!(profiler.report().contains("textures 10 -> 2"));
+}
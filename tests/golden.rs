@@ -0,0 +1,187 @@
+// Golden image tests. Every tests/golden/<name>.scene is rendered and compared with tests/golden/<name>.png.
+//
+//   ARGUS_UPDATE_GOLDEN=1     rewrite the references from the current output instead of comparing
+//   ARGUS_GOLDEN_BACKEND=gl   render with the OpenGL renderer in a hidden window instead of the software rasterizer
+//
+// On failure the rendered image and a diff, with the differing pixels in red, are written to target/golden.
+
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use image::{ Rgba, RgbaImage };
+
+use argus_engine::graphics::shader::Shader;
+use argus_engine::graphics::software::{ SoftwareDevice, SoftwareRasterizer };
+use argus_engine::world::scene_file::SceneFile;
+
+const SCENES: &str = "tests/golden";
+const OUTPUT: &str = "target/golden";
+// largest per-channel difference and fraction of pixels allowed to exceed it, unless the scene sets its own
+const DEFAULT_TOLERANCE: (u8, f32) = (3, 0.001);
+const FRAME_TIME: f32 = 1.0 / 60.0;
+
+trait Backend {
+    // render the scene file and return its last frame
+    fn render(&mut self, path: &Path) -> Result<(RgbaImage, Option<(u8, f32)>), String>;
+}
+
+struct Software;
+
+impl Backend for Software {
+    fn render(&mut self, path: &Path) -> Result<(RgbaImage, Option<(u8, f32)>), String> {
+        let mut device = SoftwareDevice::new();
+        let skybox_shader = Shader::from_source(&mut device, "", "")?;
+        let mut file = SceneFile::load(&mut device, path.to_str().unwrap(), &skybox_shader)?;
+
+        let mut rasterizer = SoftwareRasterizer::new(file.width, file.height);
+        let mut image = None;
+        for _ in 0..file.frames {
+            file.scene.update();
+            image = Some(rasterizer.render(&device, &file.scene, &file.camera));
+        }
+        Ok((image.unwrap(), file.tolerance))
+    }
+}
+
+struct Gl;
+
+impl Backend for Gl {
+    fn render(&mut self, path: &Path) -> Result<(RgbaImage, Option<(u8, f32)>), String> {
+        use glfw::Context;
+        use argus_engine::graphics::device::GlDevice;
+        use argus_engine::graphics::renderer::Renderer;
+        use argus_engine::graphics::shader::ShaderType;
+
+        // the size is only known once the file is read, and files are cheap to parse twice
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let size = source.lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .find(|tokens| tokens.first() == Some(&"size") && tokens.len() == 3)
+            .map(|tokens| (tokens[1].parse().unwrap_or(320), tokens[2].parse().unwrap_or(240)))
+            .unwrap_or((320u32, 240u32));
+
+        let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).map_err(|e| format!("{:?}", e))?;
+        glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+        let (mut window, _events) = glfw.create_window(size.0, size.1, "golden", glfw::WindowMode::Windowed)
+            .ok_or("failed to create a GL context")?;
+        window.make_current();
+        gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+
+        let (width, height) = window.get_framebuffer_size();
+        let mut renderer = Renderer::new(width, height);
+        let mut device = unsafe { GlDevice::current() };
+        let mut file = SceneFile::load(&mut device, path.to_str().unwrap(), renderer.shader(ShaderType::SKYBOX))?;
+
+        for _ in 0..file.frames {
+            file.scene.update();
+            unsafe { renderer.render(&mut file.scene, &file.camera, FRAME_TIME) };
+        }
+
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        unsafe {
+            gl::Finish();
+            gl::ReadPixels(0, 0, width, height, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut _);
+        }
+        // GL rows go bottom to top
+        let mut image = RgbaImage::from_raw(width as u32, height as u32, pixels).unwrap();
+        image = image::imageops::flip_vertical(&image);
+        Ok((image, file.tolerance))
+    }
+}
+
+struct Comparison {
+    differing: usize,
+    max_difference: u8,
+    diff: RgbaImage
+}
+
+fn compare(actual: &RgbaImage, reference: &RgbaImage, channel_tolerance: u8) -> Comparison {
+    let mut diff = RgbaImage::new(reference.width(), reference.height());
+    let mut differing = 0;
+    let mut max_difference = 0;
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let got = actual.get_pixel(x, y);
+        let difference = (0..3).map(|c| (got.data[c] as i32 - expected.data[c] as i32).unsigned_abs() as u8).max().unwrap();
+        max_difference = max_difference.max(difference);
+        let pixel = if difference > channel_tolerance {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // the reference, faded so the differences stand out
+            let luminance = (expected.data[0] as u32 * 2 + expected.data[1] as u32 * 7 + expected.data[2] as u32) / 10;
+            let faded = (luminance / 3) as u8;
+            Rgba([faded, faded, faded, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+    Comparison { differing, max_difference, diff }
+}
+
+fn check(backend: &mut dyn Backend, scene: &Path, update: bool) -> Result<(), String> {
+    let name = scene.file_stem().unwrap().to_str().unwrap();
+    let reference_path = scene.with_extension("png");
+    let (actual, tolerance) = backend.render(scene)?;
+
+    if update {
+        actual.save(&reference_path).map_err(|e| e.to_string())?;
+        println!("updated {}", reference_path.display());
+        return Ok(());
+    }
+
+    let save_failure = |diff: Option<&RgbaImage>| {
+        fs::create_dir_all(OUTPUT).unwrap();
+        let output = PathBuf::from(OUTPUT);
+        actual.save(output.join(format!("{}.actual.png", name))).unwrap();
+        if let Some(diff) = diff {
+            diff.save(output.join(format!("{}.diff.png", name))).unwrap();
+        }
+    };
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.to_rgba(),
+        Err(_) => {
+            save_failure(None);
+            return Err(format!("{}: no reference, run with ARGUS_UPDATE_GOLDEN=1 to create it", name));
+        }
+    };
+    if reference.dimensions() != actual.dimensions() {
+        save_failure(None);
+        return Err(format!("{}: rendered {:?}, the reference is {:?}", name, actual.dimensions(), reference.dimensions()));
+    }
+
+    let (channel_tolerance, pixel_fraction) = tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    let comparison = compare(&actual, &reference, channel_tolerance);
+    let allowed = (pixel_fraction * (reference.width() * reference.height()) as f32) as usize;
+    if comparison.differing > allowed {
+        save_failure(Some(&comparison.diff));
+        return Err(format!(
+            "{}: {} pixels differ by more than {} (up to {}), {} allowed, see {}",
+            name, comparison.differing, channel_tolerance, comparison.max_difference, allowed, OUTPUT
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn golden_images() {
+    let update = env::var("ARGUS_UPDATE_GOLDEN").is_ok_and(|value| value != "0");
+    let mut backend: Box<dyn Backend> = match env::var("ARGUS_GOLDEN_BACKEND").as_deref() {
+        Ok("gl") => Box::new(Gl),
+        _ => Box::new(Software)
+    };
+
+    let mut scenes: Vec<PathBuf> = fs::read_dir(SCENES).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "scene"))
+        .collect();
+    scenes.sort();
+    assert!(!scenes.is_empty(), "no scene in {}", SCENES);
+
+    let failures: Vec<String> = scenes.iter()
+        .filter_map(|scene| check(backend.as_mut(), scene, update).err())
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
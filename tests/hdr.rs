@@ -0,0 +1,52 @@
+use argus_engine::graphics::hdr::{ adapt_exposure, target_exposure, LuminanceHistogram, EXPOSURE_KEY };
+
+#[test]
+fn histograms_bin_and_clamp_log_luminance() {
+    let mut histogram = LuminanceHistogram::new(4, -4.0, 4.0);
+    histogram.build(&[-3.5, -1.0, 0.0, 0.5, 3.9, -20.0, 20.0]);
+    assert_eq!(histogram.bins, vec![2, 1, 2, 2]);
+
+    // building again starts from empty bins
+    histogram.build(&[1.0]);
+    assert_eq!(histogram.bins, vec![0, 0, 1, 0]);
+}
+
+#[test]
+fn averages_skip_the_darkest_and_brightest_samples() {
+    let mut histogram = LuminanceHistogram::new(8, -8.0, 8.0);
+    // a single value averages to the center of its bin
+    histogram.build(&[1.0; 10]);
+    assert!((histogram.average_log2(0.0, 1.0) - 1.0).abs() < 1e-5);
+
+    // a few very bright pixels move the plain average but not one below the 90th percentile
+    let mut samples = vec![-3.0; 90];
+    samples.extend([7.0; 10]);
+    histogram.build(&samples);
+    assert!((histogram.average_log2(0.0, 1.0) - (-2.0)).abs() < 1e-4);
+    assert!((histogram.average_log2(0.0, 0.9) - (-3.0)).abs() < 1e-4);
+    // and the darkest ones are skipped the same way from below
+    assert!((histogram.average_log2(0.95, 1.0) - 7.0).abs() < 1e-4);
+
+    // without samples the middle of the range
+    histogram.build(&[]);
+    assert_eq!(histogram.average_log2(0.5, 0.95), 0.0);
+}
+
+#[test]
+fn exposure_targets_middle_grey_and_adapts_smoothly() {
+    assert!((target_exposure(EXPOSURE_KEY.log2(), 0.0) - 1.0).abs() < 1e-5);
+    assert!((target_exposure(2.0, 0.0) - EXPOSURE_KEY / 4.0).abs() < 1e-6);
+    // every stop of compensation doubles the exposure
+    assert!((target_exposure(2.0, 1.0) - EXPOSURE_KEY / 2.0).abs() < 1e-6);
+
+    assert_eq!(adapt_exposure(1.0, 4.0, 1.5, 0.0), 1.0);
+    assert!((adapt_exposure(1.0, 4.0, 1.5, 100.0) - 4.0).abs() < 1e-4);
+    // half way in stops after ln 2 / speed seconds, whichever way it goes
+    let half_life = std::f32::consts::LN_2 / 1.5;
+    assert!((adapt_exposure(1.0, 4.0, 1.5, half_life) - 2.0).abs() < 1e-4);
+    assert!((adapt_exposure(4.0, 1.0, 1.5, half_life) - 2.0).abs() < 1e-4);
+
+    // two short frames get as far as one long one
+    let two_frames = adapt_exposure(adapt_exposure(0.5, 8.0, 2.0, 0.02), 8.0, 2.0, 0.02);
+    assert!((two_frames - adapt_exposure(0.5, 8.0, 2.0, 0.04)).abs() < 1e-4);
+}
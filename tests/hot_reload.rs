@@ -0,0 +1,101 @@
+use std::fs::{ self, File };
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, SystemTime };
+
+use argus_engine::graphics::device::{ Command, NullDevice };
+use argus_engine::graphics::hot_reload::{ depends_on, ShaderWatcher };
+use argus_engine::graphics::shader::Shader;
+
+mod common;
+
+const VERTEX: &str = "#version 330 core\nvoid main() { gl_Position = vec4(0.0); }\n";
+const FRAGMENT: &str = "#version 330 core\nout vec4 color;\nvoid main() { color = vec4(1.0); }\n";
+
+fn shader_files(test: &str) -> (PathBuf, PathBuf) {
+    let directory = common::write_files("hot_reload_test", test, &[("test.vs", VERTEX), ("test.fs", FRAGMENT)]);
+    (directory.join("test.vs"), directory.join("test.fs"))
+}
+
+fn file_shader(vertex: &Path, fragment: &Path) -> Shader {
+    Shader { sources: vec![vertex.to_path_buf(), fragment.to_path_buf()], ..Shader::default() }
+}
+
+fn touch(path: &Path, seconds_later: u64) {
+    let time = SystemTime::now() + Duration::from_secs(seconds_later);
+    File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+}
+
+#[test]
+fn reload_swaps_the_program_and_deletes_the_old_one() {
+    let (vertex, fragment) = shader_files("swap");
+    let mut device = NullDevice::new();
+    let mut shader = file_shader(&vertex, &fragment);
+
+    shader.reload_with(&mut device).unwrap();
+    let first = shader.id;
+    assert_ne!(first, 0);
+    assert_eq!(shader.generation, 1);
+
+    shader.reload_with(&mut device).unwrap();
+    assert_ne!(shader.id, first);
+    assert_eq!(shader.generation, 2);
+    assert!(device.commands.contains(&Command::DELETE_PROGRAM(first)));
+}
+
+#[test]
+fn failed_reload_keeps_the_previous_program() {
+    let (vertex, fragment) = shader_files("failure");
+    let mut device = NullDevice::new();
+    let mut shader = file_shader(&vertex, &fragment);
+    shader.reload_with(&mut device).unwrap();
+    let working = shader.id;
+
+    fs::write(&fragment, "#version 330 core\n#error broken\n").unwrap();
+    let error = shader.reload_with(&mut device).unwrap_err();
+    assert!(error.contains("FRAGMENT"), "{}", error);
+    assert_eq!(shader.id, working);
+    assert_eq!(shader.generation, 1);
+    assert!(!device.commands.contains(&Command::DELETE_PROGRAM(working)));
+    // the vertex shader compiled before the failure is not leaked
+    let created = device.commands.iter().filter(|c| matches!(c, Command::CREATE_SHADER { .. })).count();
+    let deleted = device.commands.iter().filter(|c| matches!(c, Command::DELETE_SHADER(_))).count();
+    assert_eq!(created, deleted);
+}
+
+#[test]
+fn strings_cannot_be_reloaded() {
+    let mut device = NullDevice::new();
+    let mut shader = Shader::from_source(&mut device, VERTEX, FRAGMENT).unwrap();
+    let id = shader.id;
+    assert!(shader.reload_with(&mut device).is_err());
+    assert_eq!(shader.id, id);
+}
+
+#[test]
+fn watcher_reports_each_change_once() {
+    let (vertex, fragment) = shader_files("watcher");
+    let shader = file_shader(&vertex, &fragment);
+    let mut watcher = ShaderWatcher::new(Duration::from_secs(0));
+    watcher.watch_shader(&shader);
+    watcher.watch_shader(&shader);
+    assert_eq!(watcher.watched(), 2);
+    assert!(watcher.changed_files().is_empty());
+
+    touch(&fragment, 5);
+    let changed = watcher.changed_files();
+    assert_eq!(changed, vec![fragment.clone()]);
+    assert!(depends_on(&shader, &changed));
+    assert!(watcher.changed_files().is_empty());
+}
+
+#[test]
+fn watcher_polls_at_most_once_per_interval() {
+    let (vertex, _) = shader_files("interval");
+    let mut watcher = ShaderWatcher::new(Duration::from_secs(3600));
+    watcher.watch(&vertex);
+    assert!(watcher.poll().is_empty());
+
+    touch(&vertex, 5);
+    assert!(watcher.poll().is_empty());
+    assert_eq!(watcher.changed_files(), vec![vertex]);
+}
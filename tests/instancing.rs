@@ -0,0 +1,122 @@
+use std::mem::size_of;
+use std::rc::Rc;
+
+use cgmath::vec3;
+
+use argus_engine::graphics::device::{ Command, NullDevice };
+use argus_engine::graphics::instancing::{ InstanceBuffer, INSTANCE_LOCATION };
+use argus_engine::graphics::material::{ BlendMode, Material };
+use argus_engine::graphics::model::Model;
+use argus_engine::graphics::shader::Shader;
+use argus_engine::graphics::uniform_buffer::ObjectData;
+use argus_engine::world::asteroids::AsteroidBelt;
+use argus_engine::world::entity::Entity;
+
+mod common;
+
+use common::{ at, scene, triangle };
+
+fn instanced_draws(device: &NullDevice) -> Vec<(u32, i32)> {
+    device.commands.iter()
+        .filter_map(|c| match c {
+            Command::DRAW_ELEMENTS_INSTANCED { vertex_array, instances, .. } => Some((*vertex_array, *instances)),
+            _ => None
+        })
+        .collect()
+}
+
+#[test]
+fn entities_sharing_a_model_are_batched_per_mesh() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let cutout = Material { blend_mode: BlendMode::CUTOUT, ..Material::default() };
+    let shared = Rc::new(Model::from_meshes(vec![triangle(&mut device, Vec::new(), Material::default()), triangle(&mut device, Vec::new(), cutout)]));
+    for x in 0..3 {
+        scene.entities.push(Entity::with_shared_model(Some(shared.clone()), at(vec3(x as f32, 0.0, 0.0))));
+    }
+    // an equal model loaded on its own is not shared
+    scene.entities.push(Entity::new(Some(Model::from_meshes(vec![triangle(&mut device, Vec::new(), Material::default())])), at(vec3(5.0, 0.0, 0.0))));
+    scene.entities.push(Entity::new(None, at(vec3(6.0, 0.0, 0.0))));
+
+    let batches = scene.collect_batches(|_| true);
+    let sizes: Vec<(usize, usize, usize)> = batches.iter().map(|b| (b.entity, b.mesh, b.instances.len())).collect();
+    assert_eq!(sizes, vec![(0, 0, 3), (0, 1, 3), (3, 0, 1)]);
+    assert_eq!(batches[0].instances[2], ObjectData::new(&at(vec3(2.0, 0.0, 0.0)).matrix()));
+
+    let opaque = scene.collect_batches(|mesh| mesh.material.blend_mode == BlendMode::OPAQUE);
+    assert_eq!(opaque.len(), 2);
+}
+
+#[test]
+fn batches_stream_their_instances_and_draw_once_per_mesh() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let shader = Shader::from_source(&mut device, "", "").unwrap();
+    let instances = InstanceBuffer::with_device(&mut device, 64);
+    let shared = Rc::new(Model::from_meshes(vec![triangle(&mut device, Vec::new(), Material::default())]));
+    let vao = shared.meshes[0].vao;
+    for x in 0..10 {
+        scene.entities.push(Entity::with_shared_model(Some(shared.clone()), at(vec3(x as f32, 0.0, 0.0))));
+    }
+    device.clear();
+
+    scene.render_filtered_with(&mut device, &shader, &instances, |_| true);
+    assert_eq!(device.draws().len(), 1);
+    assert_eq!(instanced_draws(&device), vec![(vao, 10)]);
+    assert!(device.commands.contains(&Command::UPDATE_BUFFER { buffer: instances.buffer, offset: 0, size: 10 * size_of::<ObjectData>() }));
+
+    let attributes = device.commands.iter().find_map(|c| match c {
+        Command::SET_INSTANCE_BUFFER { vertex_array, buffer, stride, attributes, .. } if *vertex_array == vao && *buffer == instances.buffer => {
+            assert_eq!(*stride as usize, size_of::<ObjectData>());
+            Some(attributes.clone())
+        }
+        _ => None
+    }).unwrap();
+    let locations: Vec<u32> = attributes.iter().map(|a| a.location).collect();
+    assert_eq!(locations, (INSTANCE_LOCATION..INSTANCE_LOCATION + 8).collect::<Vec<u32>>());
+    assert!(attributes.iter().all(|a| a.components == 4));
+}
+
+#[test]
+fn instance_buffer_wraps_and_splits_large_batches() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, "", "").unwrap();
+    let mesh = triangle(&mut device, Vec::new(), Material::default());
+    let instances = InstanceBuffer::with_device(&mut device, 8);
+    let object = ObjectData::new(&at(vec3(0.0, 0.0, 0.0)).matrix());
+
+    let offsets: Vec<usize> = (0..3).map(|_| instances.push(&mut device, &[object; 3])).collect();
+    assert_eq!(offsets, vec![0, 3 * size_of::<ObjectData>(), 0]);
+
+    device.clear();
+    instances.draw(&mut device, &mesh, &shader, &[object; 20]);
+    let counts: Vec<i32> = instanced_draws(&device).iter().map(|&(_, instances)| instances).collect();
+    assert_eq!(counts, vec![8, 8, 4]);
+}
+
+#[test]
+fn asteroid_belt_is_a_single_draw_per_rock_mesh() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let shader = Shader::from_source(&mut device, "", "").unwrap();
+    let instances = InstanceBuffer::with_device(&mut device, 16 * 1024);
+    let planet = Model::from_meshes(vec![triangle(&mut device, Vec::new(), Material::default())]);
+    let rock = Rc::new(Model::from_meshes(vec![triangle(&mut device, Vec::new(), Material::default())]));
+
+    let mut belt = AsteroidBelt::with_models(&mut scene, planet, rock, 10_000, &mut rand::thread_rng());
+    assert_eq!(belt.rocks(), 10_000);
+    assert_eq!(scene.entities.len(), 10_001);
+    device.clear();
+
+    scene.render_filtered_with(&mut device, &shader, &instances, |_| true);
+    let counts: Vec<i32> = instanced_draws(&device).iter().map(|&(_, instances)| instances).collect();
+    assert_eq!(counts, vec![1, 10_000]);
+
+    // rocks stay in the belt as they orbit
+    let before = scene.entities[1].transform.position;
+    belt.update(&mut scene, 1.0);
+    let after = scene.entities[1].transform.position;
+    assert_ne!(before, after);
+    let distance = (after.x * after.x + after.z * after.z).sqrt();
+    assert!((distance - AsteroidBelt::RADIUS).abs() <= AsteroidBelt::SPREAD);
+}
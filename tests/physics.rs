@@ -0,0 +1,332 @@
+use cgmath::{ vec3, Quaternion, Rad, Vector3 };
+use cgmath::prelude::*;
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+use argus_engine::graphics::device::NullDevice;
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::{ Mesh, Vertex };
+use argus_engine::graphics::model::Model;
+use argus_engine::physics::collider::{ Collider, ConvexHull };
+use argus_engine::physics::narrow_phase::collide;
+use argus_engine::physics::rigid_body::RigidBody;
+use argus_engine::physics::world::PhysicsWorld;
+use argus_engine::world::entity::Entity;
+use argus_engine::world::scene::Scene;
+use argus_engine::world::transform::Transform;
+
+mod common;
+
+use common::{ at, scene };
+
+// a static slab whose top is at y = 0
+fn add_ground(scene: &mut Scene) {
+    let mut ground = Entity::new(None, at(vec3(0.0, -0.5, 0.0)));
+    ground.collider = Some(Collider::cuboid(vec3(20.0, 0.5, 20.0)));
+    scene.entities.push(ground);
+}
+
+fn add_body(scene: &mut Scene, transform: Transform, collider: Collider, mass: f32) -> usize {
+    let mut entity = Entity::new(None, transform);
+    entity.rigid_body = Some(RigidBody::dynamic(mass));
+    entity.collider = Some(collider);
+    scene.entities.push(entity);
+    scene.entities.len() - 1
+}
+
+fn velocity(scene: &Scene, entity: usize) -> f32 {
+    let body = scene.entities[entity].rigid_body.unwrap();
+    body.linear_velocity.magnitude() + body.angular_velocity.magnitude()
+}
+
+fn run(physics: &mut PhysicsWorld, scene: &mut Scene, steps: usize) {
+    for _ in 0..steps {
+        physics.step(scene);
+    }
+}
+
+#[test]
+fn euler_angles_round_trip_through_quaternions() {
+    let mut transform = Transform::new(vec3(0.0, 0.0, 0.0), vec3(0.3, -1.1, 2.0), vec3(1.0, 1.0, 1.0));
+    let orientation = transform.orientation();
+    let matrix = transform.matrix();
+    transform.set_orientation(orientation);
+    assert!((transform.rotation - vec3(0.3, -1.1, 2.0)).magnitude() < 1e-4);
+    assert!((0..4).all(|column| (transform.matrix()[column] - matrix[column]).magnitude() < 1e-5));
+
+    // straight along y the z rotation folds into x
+    let turned = Quaternion::from_angle_y(Rad(std::f32::consts::FRAC_PI_2)) * Quaternion::from_angle_z(Rad(0.4));
+    transform.set_orientation(turned);
+    assert!((transform.orientation().dot(turned).abs() - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn hulls_merge_coplanar_triangles_into_faces() {
+    let mut points = Vec::new();
+    for i in 0..27 {
+        // the corners, edge midpoints, face centers and center of a cube
+        points.push(vec3((i % 3) as f32 - 1.0, ((i / 3) % 3) as f32 - 1.0, (i / 9) as f32 - 1.0));
+    }
+    let hull = ConvexHull::new(&points).unwrap();
+    assert_eq!(hull.faces.len(), 6);
+    for face in &hull.faces {
+        assert!((face.distance - 1.0).abs() < 1e-5);
+        // counter-clockwise around the normal
+        let corner = |k: usize| hull.vertices[face.vertices[k % face.vertices.len()]];
+        let area = (0..face.vertices.len()).fold(Vector3::zero(), |sum, k| sum + corner(k).cross(corner(k + 1)));
+        assert!(area.dot(face.normal) > 0.0);
+    }
+    assert!(hull.vertices.iter().all(|v| v.x.abs() == 1.0 || v.y.abs() == 1.0 || v.z.abs() == 1.0));
+    assert_eq!(ConvexHull::cuboid(vec3(1.0, 1.0, 1.0)).edges.len(), 12);
+    assert!(ConvexHull::new(&[vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(1.0, 1.0, 0.0)]).is_none());
+}
+
+#[test]
+fn narrow_phase_finds_manifolds_for_every_pair_of_shapes() {
+    let none = Quaternion::one();
+    let cube = Collider::cuboid(vec3(0.5, 0.5, 0.5));
+
+    // a box resting on a box touches at the four corners of its bottom face
+    let contacts = collide(&cube, vec3(0.0, 0.0, 0.0), none, &cube, vec3(0.2, 0.99, 0.0), none);
+    assert_eq!(contacts.len(), 4);
+    assert!(contacts.iter().all(|c| (c.normal - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-5 && (c.separation + 0.01).abs() < 1e-5));
+    assert!(collide(&cube, vec3(0.0, 0.0, 0.0), none, &cube, vec3(0.0, 1.1, 0.0), none).is_empty());
+
+    // edge on edge, the top box turned 45 degrees around two axes
+    let turned = Quaternion::from_angle_x(Rad(std::f32::consts::FRAC_PI_4)) * Quaternion::from_angle_z(Rad(std::f32::consts::FRAC_PI_4));
+    let contacts = collide(&cube, vec3(0.0, 0.0, 0.0), none, &cube, vec3(0.0, 1.2, 0.0), turned);
+    assert!(!contacts.is_empty() && contacts.iter().all(|c| c.normal.y > 0.5));
+
+    let sphere = Collider::sphere(0.5);
+    let contacts = collide(&sphere, vec3(0.3, 0.95, 0.0), none, &cube, vec3(0.0, 0.0, 0.0), none);
+    assert_eq!(contacts.len(), 1);
+    assert!((contacts[0].normal - vec3(0.0, -1.0, 0.0)).magnitude() < 1e-4);
+    assert!((contacts[0].separation + 0.05).abs() < 1e-4);
+    // the center inside the box still pushes out through the nearest face
+    let contacts = collide(&sphere, vec3(0.0, 0.0, 0.4), none, &cube, vec3(0.0, 0.0, 0.0), none);
+    assert!((contacts[0].normal - vec3(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+    assert!((contacts[0].separation + 0.6).abs() < 1e-4);
+
+    // a capsule lying on a box rests on both ends
+    let lying = Quaternion::from_angle_z(Rad(std::f32::consts::FRAC_PI_2));
+    let contacts = collide(&Collider::capsule(0.25, 0.4), vec3(0.0, 0.74, 0.0), lying, &cube, vec3(0.0, 0.0, 0.0), none);
+    assert_eq!(contacts.len(), 2);
+    assert!(contacts.iter().all(|c| (c.separation + 0.01).abs() < 1e-4));
+
+    let contacts = collide(&sphere, vec3(0.0, 0.0, 0.0), none, &sphere, vec3(0.0, 0.0, 0.9), none);
+    assert!((contacts[0].normal - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5 && (contacts[0].separation + 0.1).abs() < 1e-5);
+
+    // an upright capsule crossed by a lying one is pushed out sideways, across both of them
+    let capsule = Collider::capsule(0.25, 0.5);
+    let contacts = collide(&capsule, vec3(0.0, 0.0, 0.0), none, &capsule, vec3(0.0, 0.2, 0.0), lying);
+    assert_eq!(contacts.len(), 1);
+    assert!((contacts[0].normal.z.abs() - 1.0).abs() < 1e-5 && (contacts[0].separation + 0.5).abs() < 1e-5);
+    let contacts = collide(&capsule, vec3(0.0, 0.0, 0.0), none, &capsule, vec3(0.0, 0.2, 0.1), lying);
+    assert!((contacts[0].normal - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5 && (contacts[0].separation + 0.4).abs() < 1e-5);
+    // spheres on the same spot still separate
+    let contacts = collide(&sphere, vec3(1.0, 0.0, 0.0), none, &sphere, vec3(1.0, 0.0, 0.0), none);
+    assert!((contacts[0].normal.magnitude() - 1.0).abs() < 1e-5 && (contacts[0].separation + 1.0).abs() < 1e-5);
+}
+
+fn floor_model(device: &mut NullDevice) -> Model {
+    // two triangles facing up, 20 by 20 at y = 0
+    let corner = |x: f32, z: f32| Vertex { position: vec3(x, 0.0, z), normal: vec3(0.0, 1.0, 0.0), ..Vertex::default() };
+    let vertices = vec![corner(-10.0, -10.0), corner(-10.0, 10.0), corner(10.0, 10.0), corner(10.0, -10.0)];
+    Model::from_meshes(vec![Mesh::with_device(device, vertices, vec![0, 1, 2, 0, 2, 3], Vec::new(), Material::default())])
+}
+
+#[test]
+fn bodies_come_to_rest_on_a_triangle_mesh() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let floor = floor_model(&mut device);
+    let mut ground = Entity::new(None, at(vec3(0.0, 0.0, 0.0)));
+    ground.collider = Some(Collider::triangle_mesh(&floor, vec3(1.0, 1.0, 1.0)));
+    scene.entities.push(ground);
+
+    let sphere = add_body(&mut scene, at(vec3(-2.0, 2.0, 0.0)), Collider::sphere(0.5), 1.0);
+    let cube = add_body(&mut scene, at(vec3(2.0, 3.0, 0.0)), Collider::cuboid(vec3(0.5, 0.5, 0.5)), 1.0);
+    let points = [vec3(-0.5, 0.0, -0.5), vec3(0.5, 0.0, -0.5), vec3(0.0, 0.0, 0.5), vec3(0.0, 0.8, 0.0)];
+    let pyramid = add_body(&mut scene, at(vec3(0.0, 1.0, 3.0)), Collider::convex_hull(&points).unwrap(), 1.0);
+
+    let mut physics = PhysicsWorld::new();
+    run(&mut physics, &mut scene, 240);
+    let height = |entity: usize| scene.entities[entity].transform.position.y;
+    assert!((height(sphere) - 0.5).abs() < 0.02, "sphere at {}", height(sphere));
+    assert!((height(cube) - 0.5).abs() < 0.02, "box at {}", height(cube));
+    assert!(height(pyramid).abs() < 0.02, "pyramid at {}", height(pyramid));
+    assert!([sphere, cube, pyramid].iter().all(|&entity| velocity(&scene, entity) < 0.05));
+    // the ground entity itself never moves
+    assert_eq!(scene.entities[0].transform, at(vec3(0.0, 0.0, 0.0)));
+}
+
+#[test]
+fn stacked_boxes_stay_stacked() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    add_ground(&mut scene);
+    let boxes: Vec<usize> = (0..6)
+        .map(|level| add_body(&mut scene, at(vec3(0.0, 0.5 + level as f32, 0.0)), Collider::cuboid(vec3(0.5, 0.5, 0.5)), 1.0))
+        .collect();
+
+    let mut physics = PhysicsWorld::new();
+    run(&mut physics, &mut scene, 600);
+    for (level, &entity) in boxes.iter().enumerate() {
+        let transform = scene.entities[entity].transform;
+        let expected = vec3(0.0, 0.5 + level as f32, 0.0);
+        assert!((transform.position - expected).magnitude() < 0.05, "box {} at {:?}", level, transform.position);
+        assert!(transform.rotation.magnitude() < 0.01, "box {} turned {:?}", level, transform.rotation);
+        assert!(velocity(&scene, entity) < 0.05);
+    }
+    // every box rests on the one below through a face
+    assert_eq!(physics.manifolds().count(), 6);
+    assert!(physics.manifolds().all(|manifold| manifold.points.len() == 4));
+}
+
+#[test]
+fn spheres_roll_off_a_capsule_lying_on_its_side() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    add_ground(&mut scene);
+    let lying = Transform::new(vec3(0.0, 0.25, 0.0), vec3(0.0, 0.0, std::f32::consts::FRAC_PI_2), vec3(1.0, 1.0, 1.0));
+    let capsule = add_body(&mut scene, lying, Collider::capsule(0.25, 1.0), 2.0);
+    let sphere = add_body(&mut scene, at(vec3(0.3, 1.5, 0.1)), Collider::sphere(0.5), 1.0);
+
+    let mut physics = PhysicsWorld::new();
+    run(&mut physics, &mut scene, 240);
+    let position = |entity: usize| scene.entities[entity].transform.position;
+    assert!((position(capsule).y - 0.25).abs() < 0.02, "capsule at {:?}", position(capsule));
+    // the capsule stays on its side
+    assert!((scene.entities[capsule].transform.rotation.z.abs() - std::f32::consts::FRAC_PI_2).abs() < 0.05);
+    // the sphere lands on the capsule off center and rolls down on the side it landed
+    assert!((position(sphere).y - 0.5).abs() < 0.02, "sphere at {:?}", position(sphere));
+    assert!(position(sphere).z > 0.5);
+}
+
+#[test]
+fn body_types_move_as_they_should() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    add_ground(&mut scene);
+
+    // a kinematic paddle sweeping into a box pushes it without slowing down
+    let mut paddle = Entity::new(None, at(vec3(-3.0, 0.5, 0.0)));
+    let mut kinematic = RigidBody::kinematic();
+    kinematic.linear_velocity = vec3(2.0, 0.0, 0.0);
+    paddle.rigid_body = Some(kinematic);
+    paddle.collider = Some(Collider::cuboid(vec3(0.25, 0.5, 2.0)));
+    scene.entities.push(paddle);
+    let paddle = scene.entities.len() - 1;
+    let pushed = add_body(&mut scene, at(vec3(0.0, 0.5, 0.0)), Collider::cuboid(vec3(0.5, 0.5, 0.5)), 1.0);
+
+    // a static body with a RigidBody stays in place
+    let mut post = Entity::new(None, at(vec3(0.0, 0.5, 6.0)));
+    post.rigid_body = Some(RigidBody::fixed());
+    post.collider = Some(Collider::cuboid(vec3(0.5, 0.5, 0.5)));
+    scene.entities.push(post);
+    let post = scene.entities.len() - 1;
+    // a bouncy ball dropped on it
+    let ball = add_body(&mut scene, at(vec3(0.0, 3.0, 6.0)), Collider { restitution: 0.8, ..Collider::sphere(0.25) }, 1.0);
+    // and a floating one
+    let floating = add_body(&mut scene, at(vec3(5.0, 3.0, 5.0)), Collider::sphere(0.25), 1.0);
+    scene.entities[floating].rigid_body.as_mut().unwrap().gravity_scale = 0.0;
+
+    let mut physics = PhysicsWorld::new();
+    let mut highest_after_bounce = 0.0f32;
+    let mut bounced = false;
+    for _ in 0..120 {
+        physics.step(&mut scene);
+        let ball_body = scene.entities[ball].rigid_body.unwrap();
+        bounced |= ball_body.linear_velocity.y > 1.0;
+        if bounced {
+            highest_after_bounce = highest_after_bounce.max(scene.entities[ball].transform.position.y);
+        }
+    }
+    let paddle = &scene.entities[paddle];
+    assert!((paddle.transform.position.x - 1.0).abs() < 1e-3);
+    assert_eq!(paddle.rigid_body.unwrap().linear_velocity, vec3(2.0, 0.0, 0.0));
+    // the box is in front of the paddle, moving with it
+    assert!(scene.entities[pushed].transform.position.x > 1.2);
+    assert_eq!(scene.entities[post].transform.position, vec3(0.0, 0.5, 6.0));
+    // the ball fell 2 units onto the post and came back up most of the way
+    assert!(bounced && highest_after_bounce > 2.0, "bounced to {}", highest_after_bounce);
+    assert_eq!(scene.entities[floating].transform.position, vec3(5.0, 3.0, 5.0));
+}
+
+#[test]
+fn forces_and_the_fixed_time_step() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let body = add_body(&mut scene, at(vec3(0.0, 0.0, 0.0)), Collider::sphere(0.5), 2.0);
+    let mut physics = PhysicsWorld::new();
+    physics.gravity = Vector3::zero();
+
+    // time left over from a frame carries over to the next
+    assert_eq!(physics.update(&mut scene, 1.5 / 60.0), 1);
+    assert_eq!(physics.update(&mut scene, 0.6 / 60.0), 1);
+    assert_eq!(physics.update(&mut scene, 0.0), 0);
+    // a long frame catches up a few steps at most
+    assert_eq!(physics.update(&mut scene, 10.0), 8);
+
+    let rigid_body = scene.entities[body].rigid_body.as_mut().unwrap();
+    rigid_body.linear_damping = 0.0;
+    rigid_body.apply_force(vec3(120.0, 0.0, 0.0));
+    physics.step(&mut scene);
+    // F / m * dt
+    let rigid_body = scene.entities[body].rigid_body.unwrap();
+    assert!((rigid_body.linear_velocity.x - 1.0).abs() < 1e-5);
+    physics.step(&mut scene);
+    assert!((scene.entities[body].rigid_body.unwrap().linear_velocity.x - 1.0).abs() < 1e-5);
+
+    // moving an entity from outside is picked up
+    scene.entities[body].transform.rotation = vec3(0.0, 1.0, 0.0);
+    scene.entities[body].transform.position = vec3(10.0, 0.0, 0.0);
+    physics.step(&mut scene);
+    assert!((scene.entities[body].transform.rotation.y - 1.0).abs() < 1e-5);
+    assert!((scene.entities[body].transform.position.x - (10.0 + 1.0 / 60.0)).abs() < 1e-4);
+}
+
+// a heap of random shapes dropped onto the ground
+fn drop_pile(device: &mut NullDevice, seed: u64) -> Scene {
+    let mut scene = scene(device);
+    add_ground(&mut scene);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let hull_points: Vec<Vector3<f32>> = (0..12)
+        .map(|_| vec3(rng.gen_range(-0.4, 0.4), rng.gen_range(-0.4, 0.4), rng.gen_range(-0.4, 0.4)))
+        .collect();
+    for i in 0..30 {
+        let position = vec3(rng.gen_range(-1.5, 1.5), 1.0 + i as f32 * 0.6, rng.gen_range(-1.5, 1.5));
+        let rotation = vec3(rng.gen_range(0.0, 3.0), rng.gen_range(0.0, 3.0), rng.gen_range(0.0, 3.0));
+        let collider = match i % 4 {
+            0 => Collider::cuboid(vec3(0.3, 0.2, 0.4)),
+            1 => Collider::sphere(0.3),
+            2 => Collider::capsule(0.2, 0.3),
+            _ => Collider::convex_hull(&hull_points).unwrap()
+        };
+        add_body(&mut scene, Transform::new(position, rotation, vec3(1.0, 1.0, 1.0)), collider, rng.gen_range(0.5, 2.0));
+    }
+    scene
+}
+
+#[test]
+fn simulations_are_deterministic() {
+    let mut device = NullDevice::new();
+    let mut first = drop_pile(&mut device, 11);
+    let mut second = drop_pile(&mut device, 11);
+    let mut first_world = PhysicsWorld::new();
+    let mut second_world = PhysicsWorld::new();
+    // the second world starts out of step in time, only the steps taken count
+    run(&mut first_world, &mut first, 300);
+    for _ in 0..300 {
+        second_world.update(&mut second, 1.0 / 60.0 + 1e-6);
+    }
+
+    let transforms = |scene: &Scene| scene.entities.iter().map(|entity| entity.transform).collect::<Vec<Transform>>();
+    assert_eq!(transforms(&first), transforms(&second));
+    // everything landed and nothing fell through the ground
+    assert!(first.entities.iter().skip(1).all(|entity| entity.transform.position.y > 0.0 && entity.transform.position.y < 4.0));
+
+    let mut third = drop_pile(&mut device, 12);
+    run(&mut PhysicsWorld::new(), &mut third, 300);
+    assert_ne!(transforms(&first), transforms(&third));
+}
@@ -0,0 +1,126 @@
+use cgmath::{ vec3, Point3, Vector3 };
+use cgmath::prelude::*;
+
+use argus_engine::graphics::camera::Camera;
+use argus_engine::graphics::device::NullDevice;
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::{ Mesh, Vertex };
+use argus_engine::graphics::model::Model;
+use argus_engine::world::bounds::Ray;
+use argus_engine::world::entity::Entity;
+use argus_engine::world::picking::intersect_triangle;
+use argus_engine::world::transform::Transform;
+
+mod common;
+
+use common::{ at, scene };
+
+// a unit square in the xy plane facing +z, as two triangles, and a second mesh one unit behind it
+fn panel_model(device: &mut NullDevice) -> Model {
+    let corner = |x: f32, y: f32, z: f32| Vertex { position: vec3(x, y, z), normal: vec3(0.0, 0.0, 1.0), ..Vertex::default() };
+    let front = vec![corner(-0.5, -0.5, 0.0), corner(0.5, -0.5, 0.0), corner(0.5, 0.5, 0.0), corner(-0.5, 0.5, 0.0)];
+    let back = vec![corner(-0.5, -0.5, -1.0), corner(0.5, -0.5, -1.0), corner(0.5, 0.5, -1.0), corner(-0.5, 0.5, -1.0)];
+    Model::from_meshes(vec![
+        Mesh::with_device(device, back, vec![0, 1, 2, 0, 2, 3], Vec::new(), Material::default()),
+        Mesh::with_device(device, front, vec![0, 1, 2, 0, 2, 3], Vec::new(), Material::default())
+    ])
+}
+
+fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a - b).magnitude() < 1e-4
+}
+
+#[test]
+fn triangles_are_hit_from_both_sides_with_barycentrics() {
+    let (a, b, c) = (vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+    let (distance, u, v) = intersect_triangle(&Ray::new(vec3(0.25, 0.5, 2.0), vec3(0.0, 0.0, -1.0)), a, b, c).unwrap();
+    assert!((distance - 2.0).abs() < 1e-6 && (u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+    assert!(intersect_triangle(&Ray::new(vec3(0.25, 0.25, -3.0), vec3(0.0, 0.0, 1.0)), a, b, c).is_some());
+
+    // outside the edges, behind the origin, along the plane
+    assert_eq!(intersect_triangle(&Ray::new(vec3(0.6, 0.6, 1.0), vec3(0.0, 0.0, -1.0)), a, b, c), None);
+    assert_eq!(intersect_triangle(&Ray::new(vec3(0.25, 0.25, 1.0), vec3(0.0, 0.0, 1.0)), a, b, c), None);
+    assert_eq!(intersect_triangle(&Ray::new(vec3(-1.0, 0.25, 0.0), vec3(1.0, 0.0, 0.0)), a, b, c), None);
+}
+
+#[test]
+fn raycasts_return_the_closest_triangle_of_any_entity() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    for z in [-6.0, -3.0] {
+        let model = panel_model(&mut device);
+        scene.entities.push(Entity::new(Some(model), at(vec3(0.0, 0.0, z))));
+    }
+    scene.entities.push(Entity::new(None, at(vec3(0.0, 0.0, -1.0))));
+    scene.update_bvh();
+
+    let ray = Ray::new(vec3(0.25, 0.1, 0.0), vec3(0.0, 0.0, -1.0));
+    let hit = scene.raycast(&ray).unwrap();
+    assert_eq!((hit.entity, hit.mesh, hit.triangle), (1, 1, 0));
+    assert!((hit.distance - 3.0).abs() < 1e-5);
+    assert!(close(hit.position, vec3(0.25, 0.1, -3.0)));
+    assert!(close(hit.normal, vec3(0.0, 0.0, 1.0)));
+    assert!((hit.barycentrics.x + hit.barycentrics.y + hit.barycentrics.z - 1.0).abs() < 1e-6);
+    // the corners weighted by the barycentrics give back the hit
+    let mesh = &scene.entities[1].model.as_ref().unwrap().meshes[1];
+    let corner = |i: usize| mesh.vertices[mesh.indices[3 * hit.triangle + i] as usize].position;
+    let local = corner(0) * hit.barycentrics.x + corner(1) * hit.barycentrics.y + corner(2) * hit.barycentrics.z;
+    assert!(close(local + vec3(0.0, 0.0, -3.0), hit.position));
+
+    // the upper left half is the second triangle, seen from behind the normal faces the ray
+    let hit = scene.raycast(&Ray::new(vec3(-0.25, 0.25, -10.0), vec3(0.0, 0.0, 1.0))).unwrap();
+    assert_eq!((hit.entity, hit.mesh, hit.triangle), (0, 0, 1));
+    assert!((hit.distance - 3.0).abs() < 1e-5);
+    assert!(close(hit.normal, vec3(0.0, 0.0, -1.0)));
+
+    assert_eq!(scene.raycast(&Ray::new(vec3(2.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0))), None);
+    assert_eq!(scene.raycast(&Ray::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0))), None);
+}
+
+#[test]
+fn raycasts_follow_transforms_the_bvh_has_not_seen() {
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let model = panel_model(&mut device);
+    scene.entities.push(Entity::new(Some(model), at(vec3(0.0, 0.0, -5.0))));
+    scene.update_bvh();
+
+    // scaled up and turned to face +x, without updating the tree
+    scene.entities[0].transform = Transform::new(vec3(4.0, 0.0, 0.0), vec3(0.0, std::f32::consts::FRAC_PI_2, 0.0), vec3(4.0, 4.0, 4.0));
+    let hit = scene.raycast(&Ray::new(vec3(10.0, 1.5, 0.5), vec3(-1.0, 0.0, 0.0))).unwrap();
+    assert_eq!((hit.entity, hit.mesh), (0, 1));
+    assert!((hit.distance - 6.0).abs() < 1e-4);
+    assert!(close(hit.position, vec3(4.0, 1.5, 0.5)));
+    assert!(close(hit.normal, vec3(1.0, 0.0, 0.0)));
+
+    // an entity added since is found too
+    let model = panel_model(&mut device);
+    scene.entities.push(Entity::new(Some(model), at(vec3(8.0, 1.5, 0.5))));
+    assert_eq!(scene.raycast(&Ray::new(vec3(10.0, 1.5, 0.5), vec3(-1.0, 0.0, 0.0))).map(|hit| hit.entity), Some(0));
+    assert_eq!(scene.raycast(&Ray::new(vec3(8.0, 1.5, 5.0), vec3(0.0, 0.0, -1.0))).map(|hit| hit.entity), Some(1));
+}
+
+#[test]
+fn screen_rays_go_through_the_cursor() {
+    let camera = Camera::new(Point3::new(1.0, 2.0, 3.0), -60.0, 20.0);
+    let center = camera.screen_ray(400.0, 300.0, 800.0, 600.0).unwrap();
+    assert!(close(center.direction, camera.front));
+    assert!(close(center.origin, Point3::new(1.0, 2.0, 3.0).to_vec() + camera.front * camera.near));
+
+    // the top left corner is up and to the left of the view direction
+    let corner = camera.screen_ray(0.0, 0.0, 800.0, 600.0).unwrap();
+    assert!(corner.direction.dot(camera.up) > 0.0 && corner.direction.dot(camera.right) < 0.0);
+    let vertical = corner.direction.dot(camera.up) / corner.direction.dot(camera.front);
+    assert!((vertical - (camera.zoom * 0.5).to_radians().tan()).abs() < 1e-4);
+    // a minimized window has no rays
+    assert!(camera.screen_ray(0.0, 0.0, 800.0, 0.0).is_none());
+
+    // clicking an object in front of the camera picks it
+    let mut device = NullDevice::new();
+    let mut scene = scene(&mut device);
+    let model = panel_model(&mut device);
+    scene.entities.push(Entity::new(Some(model), at(vec3(0.0, 0.0, -4.0))));
+    let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), -90.0, 0.0);
+    assert_eq!(scene.raycast(&camera.screen_ray(400.0, 300.0, 800.0, 600.0).unwrap()).map(|hit| hit.entity), Some(0));
+    assert_eq!(scene.raycast(&camera.screen_ray(10.0, 10.0, 800.0, 600.0).unwrap()), None);
+}
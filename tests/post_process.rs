@@ -0,0 +1,50 @@
+use argus_engine::graphics::post_process::CubeLut;
+
+// a 2x2x2 identity LUT over `domain`, the data written before or after the DOMAIN lines
+fn identity_cube(domain: &str, domain_first: bool) -> String {
+    let mut data = String::new();
+    for i in 0..8 {
+        let scale = if domain.is_empty() { 1.0 } else { 2.0 };
+        let (r, g, b) = ((i % 2) as f32 * scale, ((i / 2) % 2) as f32 * scale, (i / 4) as f32 * scale);
+        data += &format!("{} {} {}\n", r, g, b);
+    }
+    if domain_first {
+        format!("TITLE \"identity\"\nLUT_3D_SIZE 2\n{}{}", domain, data)
+    } else {
+        format!("TITLE \"identity\"\nLUT_3D_SIZE 2\n{}{}", data, domain)
+    }
+}
+
+#[test]
+fn the_domain_maps_input_colours_onto_the_cube() {
+    let lut = CubeLut::parse(&identity_cube("", true)).unwrap();
+    assert_eq!(lut.size, 2);
+    assert_eq!((lut.domain_min, lut.domain_max), ([0.0; 3], [1.0; 3]));
+    assert_eq!(lut.data[1], [1.0, 0.0, 0.0]);
+    assert_eq!(lut.data[6], [0.0, 1.0, 1.0]);
+
+    // the domain applies to the input wherever it is declared, the entries stay as written
+    let domain = "DOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n";
+    for domain_first in [true, false] {
+        let lut = CubeLut::parse(&identity_cube(domain, domain_first)).unwrap();
+        assert_eq!(lut.domain_max, [2.0, 2.0, 2.0]);
+        assert_eq!(lut.data[7], [2.0, 2.0, 2.0]);
+        assert_eq!(lut.data[2], [0.0, 2.0, 0.0]);
+
+        assert_eq!(lut.coordinates([1.0, 0.5, 2.0]), [0.5, 0.25, 1.0]);
+        // an identity over its domain grades every colour in it to itself, the rest to the closest edge
+        let close = |a: [f32; 3], b: [f32; 3]| (0..3).all(|c| (a[c] - b[c]).abs() < 1e-5);
+        assert!(close(lut.lookup([1.5, 0.5, 2.0]), [1.5, 0.5, 2.0]));
+        assert!(close(lut.lookup([3.0, -1.0, 1.0]), [2.0, 0.0, 1.0]));
+    }
+}
+
+#[test]
+fn malformed_luts_are_rejected() {
+    let error = CubeLut::parse(&identity_cube("DOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 0 2\n", false)).err().unwrap();
+    assert!(error.contains("domain"), "{}", error);
+    assert!(CubeLut::parse(&identity_cube("DOMAIN_MIN 1 1 1\n", true)).is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    assert!(CubeLut::parse("LUT_1D_SIZE 4\n").is_err());
+    assert!(CubeLut::parse("0 0 0\n").is_err());
+}
@@ -0,0 +1,138 @@
+use std::fs;
+
+use argus_engine::graphics::device::{ Command, NullDevice };
+use argus_engine::graphics::preprocessor::{ ShaderPreprocessor, SHADER_DIRECTORY };
+use argus_engine::graphics::shader::{ Shader, ShaderVariants };
+
+mod common;
+
+fn keywords(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn includes_are_pasted_after_the_version_and_defines() {
+    let directory = common::write_files("preprocessor_test", "includes", &[
+        ("main.fs", "#version 330 core\n#include \"common.glsl\"\nvoid main() {}\n"),
+        ("common.glsl", "#include <lib/math.glsl>\nuniform float shared;\n"),
+        ("lib/math.glsl", "float square(float x) { return x * x; }\n")
+    ]);
+    let mut preprocessor = ShaderPreprocessor::new(&[directory.to_str().unwrap()]);
+    preprocessor.define("MAX_LIGHTS", "16");
+    let processed = preprocessor.process_file(&directory.join("main.fs"), &keywords(&["NORMAL_MAP"])).unwrap();
+
+    assert_eq!(processed.code, "#version 330 core\n#define MAX_LIGHTS 16\n#define NORMAL_MAP 1\n\
+        float square(float x) { return x * x; }\nuniform float shared;\nvoid main() {}\n");
+    assert_eq!(processed.files, vec![
+        directory.join("main.fs"),
+        directory.join("common.glsl"),
+        directory.join("lib/math.glsl")
+    ]);
+}
+
+#[test]
+fn lines_map_back_to_their_file() {
+    let directory = common::write_files("preprocessor_test", "lines", &[
+        ("main.fs", "#version 330 core\n\n#include \"common.glsl\"\nvoid main() {\n    broken;\n}\n"),
+        ("common.glsl", "// shared\nuniform float shared;\n")
+    ]);
+    let processed = ShaderPreprocessor::new(&[])
+        .process_file(&directory.join("main.fs"), &keywords(&["SKINNED"]))
+        .unwrap();
+    let main = directory.join("main.fs");
+    let common = directory.join("common.glsl");
+
+    assert_eq!(processed.source_location(1), Some((main.as_path(), 1)));
+    assert_eq!(processed.source_location(2), None);
+    assert_eq!(processed.source_location(5), Some((common.as_path(), 2)));
+    assert_eq!(processed.source_location(7), Some((main.as_path(), 5)));
+
+    let log = "0:7(5): error: `broken' undeclared\n0(5) : error C0000: syntax error\nERROR: 0:2: 'x' : redefinition";
+    let expected = format!(
+        "{}:5(5): error: `broken' undeclared\n{}:2 : error C0000: syntax error\nERROR: <defines>:2: 'x' : redefinition",
+        main.display(), common.display()
+    );
+    assert_eq!(processed.map_log(log), expected);
+}
+
+#[test]
+fn pragma_once_and_cycles() {
+    let directory = common::write_files("preprocessor_test", "once", &[
+        ("main.fs", "#include \"a.glsl\"\n#include \"a.glsl\"\n#include \"b.glsl\"\n"),
+        ("a.glsl", "#pragma once\nint a;\n"),
+        ("b.glsl", "#include \"a.glsl\"\nint b;\n"),
+        ("cycle.fs", "#include \"c.glsl\"\n"),
+        ("c.glsl", "#include \"d.glsl\"\n"),
+        ("d.glsl", "#include \"c.glsl\"\n")
+    ]);
+    let preprocessor = ShaderPreprocessor::new(&[]);
+    let processed = preprocessor.process_file(&directory.join("main.fs"), &[]).unwrap();
+    assert_eq!(processed.code, "int a;\nint b;\n");
+
+    let error = preprocessor.process_file(&directory.join("cycle.fs"), &[]).err().unwrap();
+    assert!(error.contains("d.glsl:1") && error.contains("includes itself"), "{}", error);
+}
+
+#[test]
+fn missing_includes_name_the_including_line() {
+    let directory = common::write_files("preprocessor_test", "missing", &[("main.fs", "#version 330 core\n#include \"nowhere.glsl\"\n")]);
+    let error = ShaderPreprocessor::new(&[])
+        .process_file(&directory.join("main.fs"), &[])
+        .err()
+        .unwrap();
+    assert!(error.contains("main.fs:2") && error.contains("nowhere.glsl"), "{}", error);
+}
+
+#[test]
+fn engine_shaders_preprocess() {
+    let preprocessor = ShaderPreprocessor::default();
+    for entry in fs::read_dir(SHADER_DIRECTORY).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "vs" || extension == "fs") {
+            let processed = preprocessor.process_file(&path, &[]).unwrap();
+            assert!(!processed.code.contains("#include"), "{}", path.display());
+            assert!(processed.code.starts_with("#version"), "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn variants_are_cached_by_keyword_set() {
+    let directory = common::write_files("preprocessor_test", "variants", &[
+        ("test.vs", "#version 330 core\nvoid main() {}\n"),
+        ("test.fs", "#version 330 core\n#include \"common.glsl\"\nvoid main() {}\n"),
+        ("common.glsl", "uniform float shared;\n")
+    ]);
+    let (vertex, fragment) = (directory.join("test.vs"), directory.join("test.fs"));
+    let mut device = NullDevice::new();
+    let mut variants = ShaderVariants::new(vertex.to_str().unwrap(), fragment.to_str().unwrap());
+
+    let skinned = variants.variant_with(&mut device, &["SKINNED", "NORMAL_MAP"]).id;
+    assert_eq!(variants.variant_with(&mut device, &["NORMAL_MAP", "SKINNED", "SKINNED"]).id, skinned);
+    let plain = variants.variant_with(&mut device, &[]).id;
+    assert_ne!(plain, skinned);
+    assert_eq!(variants.len(), 2);
+    let programs = device.commands.iter().filter(|c| matches!(c, Command::CREATE_PROGRAM { .. })).count();
+    assert_eq!(programs, 2);
+
+    let variant = variants.get(&["SKINNED", "NORMAL_MAP"]).unwrap();
+    assert_eq!(variant.keywords, vec!["NORMAL_MAP".to_string(), "SKINNED".to_string()]);
+    assert_eq!(variant.includes, vec![directory.join("common.glsl")]);
+    assert!(variants.get(&["SKINNED"]).is_none());
+}
+
+#[test]
+fn compile_errors_point_at_the_include() {
+    let directory = common::write_files("preprocessor_test", "errors", &[
+        ("test.vs", "#version 330 core\nvoid main() {}\n"),
+        ("test.fs", "#version 330 core\n#include \"broken.glsl\"\nvoid main() {}\n"),
+        ("broken.glsl", "// fine\n#error not fine\n")
+    ]);
+    let mut device = NullDevice::new();
+    let shader = Shader::with_device(&mut device, directory.join("test.vs").to_str().unwrap(), directory.join("test.fs").to_str().unwrap(), &[]);
+    assert_eq!(shader.id, 0);
+
+    let mut shader = Shader { sources: shader.sources, ..Shader::default() };
+    let error = shader.reload_with(&mut device).unwrap_err();
+    assert!(error.contains(&format!("{}:2", directory.join("broken.glsl").display())), "{}", error);
+}
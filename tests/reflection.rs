@@ -0,0 +1,144 @@
+use std::ffi::CString;
+
+use argus_engine::graphics::device::{ Command, NullDevice, UniformValue };
+use argus_engine::graphics::reflection::{ ProgramReflection, UniformType };
+use argus_engine::graphics::shader::Shader;
+
+const VERTEX: &str = "#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 2) in vec2 aTexCoords;
+
+layout (std140, binding = 3) uniform Frame {
+    mat4 viewProjection;
+    vec4 time;
+};
+
+uniform mat4 model;
+";
+
+const FRAGMENT: &str = "#version 330 core
+#define MAX_LIGHTS 4
+struct Light {
+    vec3 position;
+    float intensity;
+};
+
+uniform Light lights[MAX_LIGHTS];
+uniform sampler2D texture_diffuse1; // diffuse map
+uniform float opacity, alphaCutoff;
+/* uniform vec3 commented; */
+in vec2 TexCoords;
+out vec4 FragColor;
+
+void main() {
+    FragColor = texture(texture_diffuse1, TexCoords) * opacity;
+}
+";
+
+fn uniform_commands(device: &NullDevice) -> Vec<Command> {
+    device.commands.iter()
+        .filter(|c| matches!(c, Command::SET_UNIFORM { .. } | Command::SET_UNIFORM_LOCATION { .. }))
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn sources_are_reflected_from_their_declarations() {
+    let reflection = ProgramReflection::from_sources(&[VERTEX, FRAGMENT]);
+
+    assert_eq!(reflection.uniform("model").unwrap().type_, UniformType::MAT4);
+    assert_eq!(reflection.uniform("texture_diffuse1").unwrap().type_, UniformType::SAMPLER);
+    assert_eq!(reflection.uniform("alphaCutoff").unwrap().type_, UniformType::FLOAT);
+    assert!(reflection.uniform("commented").is_none());
+    // block members are not plain uniforms
+    assert!(reflection.uniform("viewProjection").is_none());
+
+    assert_eq!(reflection.uniform("lights[3].intensity").unwrap().type_, UniformType::FLOAT);
+    assert_eq!(reflection.uniform("lights[0].position").unwrap().type_, UniformType::VEC3);
+    assert!(reflection.uniform("lights[4].position").is_none());
+
+    let frame = reflection.block("Frame").unwrap();
+    assert_eq!(frame.binding, 3);
+
+    assert_eq!(reflection.attribute("aTexCoords").unwrap().location, 2);
+    assert_eq!(reflection.attribute("aPos").unwrap().type_, UniformType::VEC3);
+    // inputs of the fragment stage are not attributes
+    assert!(reflection.attribute("TexCoords").is_none());
+}
+
+#[test]
+fn arrays_are_listed_by_name_and_by_element() {
+    let mut reflection = ProgramReflection::default();
+    reflection.add_uniform("weights[0]", UniformType::FLOAT, 3, |name| match name {
+        "weights[0]" => 10,
+        "weights[1]" => 11,
+        "weights[2]" => 12,
+        _ => -1
+    });
+
+    let weights = reflection.uniform("weights").unwrap();
+    assert_eq!((weights.location, weights.size), (10, 3));
+    assert_eq!(reflection.uniform("weights[2]").unwrap().location, 12);
+}
+
+#[test]
+fn uniforms_are_set_through_their_cached_location() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, VERTEX, FRAGMENT).unwrap();
+    let location = shader.uniforms.reflection.as_ref().unwrap().uniform("opacity").unwrap().location;
+
+    let name = CString::new("opacity").unwrap();
+    shader.set_with(&mut device, &name, UniformValue::FLOAT(0.5));
+
+    assert_eq!(uniform_commands(&device), vec![Command::SET_UNIFORM_LOCATION { program: shader.id, location, value: UniformValue::FLOAT(0.5) }]);
+    assert_eq!(device.uniform(shader.id, "opacity"), Some(UniformValue::FLOAT(0.5)));
+}
+
+#[test]
+fn values_of_the_wrong_type_are_not_set() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, VERTEX, FRAGMENT).unwrap();
+
+    let name = CString::new("model").unwrap();
+    shader.set_with(&mut device, &name, UniformValue::VEC3([1.0, 2.0, 3.0]));
+    assert!(uniform_commands(&device).is_empty());
+
+    // samplers take texture units
+    let name = CString::new("texture_diffuse1").unwrap();
+    shader.set_with(&mut device, &name, UniformValue::INT(2));
+    assert_eq!(device.uniform(shader.id, "texture_diffuse1"), Some(UniformValue::INT(2)));
+}
+
+#[test]
+fn unknown_uniforms_are_still_set_by_name() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, VERTEX, FRAGMENT).unwrap();
+
+    let name = CString::new("missing").unwrap();
+    shader.set_with(&mut device, &name, UniformValue::INT(1));
+    shader.set_with(&mut device, &name, UniformValue::INT(2));
+
+    assert_eq!(uniform_commands(&device).len(), 2);
+    assert_eq!(device.uniform(shader.id, "missing"), Some(UniformValue::INT(2)));
+}
+
+#[test]
+fn reflection_follows_reloads() {
+    let mut device = NullDevice::new();
+    let mut shader = Shader::from_source(&mut device, VERTEX, FRAGMENT).unwrap();
+    assert!(shader.uniforms.reflection.as_ref().unwrap().uniform("model").is_some());
+
+    shader = Shader::from_source(&mut device, "#version 330 core\nuniform vec4 color;\n", "").unwrap();
+    let reflection = shader.uniforms.reflection.as_ref().unwrap();
+    assert!(reflection.uniform("model").is_none());
+    assert_eq!(reflection.uniform("color").unwrap().type_, UniformType::VEC4);
+}
+
+#[test]
+fn uniform_types_accept_matching_values() {
+    assert!(UniformType::MAT4.accepts(&UniformValue::MAT4([[0.0; 4]; 4])));
+    assert!(UniformType::SAMPLER.accepts(&UniformValue::INT(0)));
+    assert!(UniformType::BOOL.accepts(&UniformValue::INT(1)));
+    assert!(!UniformType::VEC3.accepts(&UniformValue::FLOAT(1.0)));
+    assert!(!UniformType::FLOAT.accepts(&UniformValue::INT(1)));
+}
@@ -0,0 +1,167 @@
+use argus_engine::graphics::framebuffer::TextureFormat;
+use argus_engine::graphics::render_graph::{ LoadOp, RenderGraph, TextureDesc };
+
+type Graph = RenderGraph<Vec<&'static str>>;
+
+const DESC: TextureDesc = TextureDesc { width: 64, height: 64, format: TextureFormat::RGBA8 };
+
+fn names(graph: &Graph, passes: &[usize]) -> Vec<String> {
+    passes.iter().map(|&p| graph.pass_name(p).to_string()).collect()
+}
+
+#[test]
+fn readers_run_after_writers_whatever_the_declaration_order() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+    let shadows = graph.external("shadows");
+
+    graph.add_pass("lighting", |pass| {
+        pass.read(shadows);
+        pass.color_attachment(backbuffer, LoadOp::CLEAR);
+    }, |_, _| {});
+    graph.add_pass("shadow_map", |pass| { pass.write(shadows); }, |_, _| {});
+
+    let compiled = graph.compile().unwrap();
+    assert_eq!(names(&graph, &compiled.order), ["shadow_map", "lighting"]);
+}
+
+#[test]
+fn writers_of_a_resource_keep_their_order() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+    let color = graph.external("color");
+
+    graph.add_pass("present", |pass| {
+        pass.read(color);
+        pass.color_attachment(backbuffer, LoadOp::CLEAR);
+    }, |_, _| {});
+    graph.add_pass("opaque", |pass| { pass.write(color); }, |_, _| {});
+    graph.add_pass("skybox", |pass| { pass.write(color); }, |_, _| {});
+    graph.add_pass("transparent", |pass| { pass.write(color); }, |_, _| {});
+
+    let compiled = graph.compile().unwrap();
+    assert_eq!(names(&graph, &compiled.order), ["opaque", "skybox", "transparent", "present"]);
+}
+
+#[test]
+fn unused_passes_are_culled() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+    let debug = graph.external("debug");
+    let stats = graph.external("stats");
+
+    graph.add_pass("main", |pass| { pass.color_attachment(backbuffer, LoadOp::CLEAR); }, |_, _| {});
+    graph.add_pass("debug_view", |pass| { pass.write(debug); }, |_, _| {});
+    graph.add_pass("gpu_stats", |pass| {
+        pass.write(stats);
+        pass.side_effect();
+    }, |_, _| {});
+
+    let compiled = graph.compile().unwrap();
+    assert_eq!(names(&graph, &compiled.order), ["main", "gpu_stats"]);
+    assert_eq!(names(&graph, &compiled.culled), ["debug_view"]);
+}
+
+#[test]
+fn clearing_a_target_culls_its_earlier_writers() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+
+    graph.add_pass("overwritten", |pass| { pass.color_attachment(backbuffer, LoadOp::LOAD); }, |_, _| {});
+    graph.add_pass("clear", |pass| { pass.color_attachment(backbuffer, LoadOp::CLEAR); }, |_, _| {});
+    graph.add_pass("overlay", |pass| { pass.color_attachment(backbuffer, LoadOp::LOAD); }, |_, _| {});
+
+    let compiled = graph.compile().unwrap();
+    assert_eq!(names(&graph, &compiled.order), ["clear", "overlay"]);
+}
+
+#[test]
+fn transients_with_disjoint_lifetimes_share_storage() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+
+    // a chain of full-screen effects, only two targets are alive at any time
+    let mut input = graph.add_pass("source", |pass| {
+        let output = pass.create_texture("source", DESC);
+        pass.color_attachment(output, LoadOp::CLEAR)
+    }, |_, _| {});
+    for name in &["effect_a", "effect_b", "effect_c", "effect_d"] {
+        let source = input;
+        input = graph.add_pass(name, |pass| {
+            pass.read(source);
+            let output = pass.create_texture(name, DESC);
+            pass.color_attachment(output, LoadOp::CLEAR)
+        }, |_, _| {});
+    }
+    let last = input;
+    graph.add_pass("present", |pass| {
+        pass.read(last);
+        pass.color_attachment(backbuffer, LoadOp::CLEAR);
+    }, |_, _| {});
+
+    let compiled = graph.compile().unwrap();
+    assert_eq!(compiled.order.len(), 6);
+    assert_eq!(compiled.physical.len(), 2);
+}
+
+#[test]
+fn transients_alive_at_the_same_time_or_of_another_format_are_not_aliased() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+    let depth_desc = TextureDesc { format: TextureFormat::DEPTH24, ..DESC };
+
+    let (color, depth) = graph.add_pass("gbuffer", |pass| {
+        let color = pass.create_texture("color", DESC);
+        let depth = pass.create_texture("depth", depth_desc);
+        (pass.color_attachment(color, LoadOp::CLEAR), pass.depth_attachment(depth, LoadOp::CLEAR))
+    }, |_, _| {});
+    let blurred = graph.add_pass("blur", |pass| {
+        pass.read(color);
+        let output = pass.create_texture("blurred", DESC);
+        pass.color_attachment(output, LoadOp::CLEAR)
+    }, |_, _| {});
+    graph.add_pass("compose", |pass| {
+        pass.read(blurred);
+        pass.read(depth);
+        pass.color_attachment(backbuffer, LoadOp::CLEAR);
+    }, |_, _| {});
+
+    let compiled = graph.compile().unwrap();
+    // color and blurred overlap in the blur pass, depth has another format
+    assert_eq!(compiled.physical.len(), 3);
+    assert_ne!(compiled.aliases[color.index()], compiled.aliases[blurred.index()]);
+}
+
+#[test]
+fn cycles_are_reported() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+    let a = graph.external("a");
+    let b = graph.external("b");
+
+    graph.add_pass("first", |pass| {
+        pass.read(b);
+        pass.write(a);
+    }, |_, _| {});
+    graph.add_pass("second", |pass| {
+        pass.read(a);
+        pass.write(b);
+        pass.color_attachment(backbuffer, LoadOp::CLEAR);
+    }, |_, _| {});
+
+    assert!(graph.compile().is_err());
+}
+
+#[test]
+fn reading_an_unwritten_transient_is_an_error() {
+    let mut graph = Graph::new();
+    let backbuffer = graph.backbuffer(64, 64);
+
+    let texture = graph.add_pass("declare", |pass| pass.create_texture("never_written", DESC), |_, _| {});
+    graph.add_pass("use", |pass| {
+        pass.read(texture);
+        pass.color_attachment(backbuffer, LoadOp::CLEAR);
+    }, |_, _| {});
+
+    assert!(graph.compile().is_err());
+}
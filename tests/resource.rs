@@ -0,0 +1,127 @@
+use argus_engine::graphics::device::{ BufferKind, BufferUsage, Command, NullDevice, PixelFormat, RenderDevice, TextureData };
+use argus_engine::graphics::material::Material;
+use argus_engine::graphics::mesh::Texture;
+use argus_engine::graphics::model::Model;
+use argus_engine::graphics::resource::{ self, GpuHandle, ResourceKind };
+use argus_engine::graphics::shader::Shader;
+use argus_engine::world::skybox::SkyBox;
+
+mod common;
+
+use common::triangle;
+
+fn deletions(device: &NullDevice) -> Vec<&Command> {
+    device.commands.iter()
+        .filter(|c| matches!(c, Command::DELETE_BUFFER(_) | Command::DELETE_TEXTURE(_) | Command::DELETE_VERTEX_ARRAY(_) | Command::DELETE_PROGRAM(_)
+            | Command::DELETE_FRAMEBUFFER(_)))
+        .collect()
+}
+
+#[test]
+fn dropped_meshes_are_deleted_on_the_next_flush() {
+    let mut device = NullDevice::new();
+    let mesh = triangle(&mut device, Vec::new(), Material::default());
+    let vao = mesh.vao;
+    assert_eq!(resource::live_resources(device.device_id()).len(), 3);
+
+    // a null device is never the GL thread, everything waits for the flush
+    drop(mesh);
+    assert_eq!(resource::pending_deletions(device.device_id()), 3);
+    assert!(resource::live_resources(device.device_id()).is_empty());
+    device.clear();
+
+    assert_eq!(resource::flush_deletions(&mut device), 3);
+    let deleted = deletions(&device);
+    assert_eq!(deleted.len(), 3);
+    assert!(deleted.contains(&&Command::DELETE_VERTEX_ARRAY(vao)));
+    assert_eq!(deleted.iter().filter(|c| matches!(c, Command::DELETE_BUFFER(_))).count(), 2);
+    assert_eq!(resource::pending_deletions(device.device_id()), 0);
+}
+
+#[test]
+fn flushing_a_device_leaves_the_handles_of_others() {
+    let mut first = NullDevice::new();
+    let mut second = NullDevice::new();
+    drop(triangle(&mut first, Vec::new(), Material::default()));
+    drop(triangle(&mut second, Vec::new(), Material::default()));
+
+    assert_eq!(resource::flush_deletions(&mut first), 3);
+    assert_eq!(resource::pending_deletions(second.device_id()), 3);
+    assert_eq!(resource::flush_deletions(&mut second), 3);
+}
+
+#[test]
+fn released_handles_are_deleted_right_away() {
+    let mut device = NullDevice::new();
+    let buffer = device.create_buffer(BufferKind::VERTEX, BufferUsage::STATIC, &[0; 4]);
+    let handle = GpuHandle::new(&device, ResourceKind::BUFFER, buffer, "test buffer");
+    assert_eq!(resource::live_resources(device.device_id()), vec![(ResourceKind::BUFFER, buffer, "test buffer".to_string())]);
+    device.clear();
+
+    handle.release_with(&mut device);
+    assert_eq!(device.commands, vec![Command::DELETE_BUFFER(buffer)]);
+    assert_eq!(resource::pending_deletions(device.device_id()), 0);
+    assert!(resource::live_resources(device.device_id()).is_empty());
+}
+
+#[test]
+fn framebuffer_handles_delete_their_framebuffer() {
+    let mut device = NullDevice::new();
+    drop(GpuHandle::new(&device, ResourceKind::FRAMEBUFFER, 3, "test framebuffer"));
+    assert_eq!(resource::pending_deletions(device.device_id()), 1);
+
+    assert_eq!(resource::flush_deletions(&mut device), 1);
+    assert_eq!(device.commands, vec![Command::DELETE_FRAMEBUFFER(3)]);
+}
+
+#[test]
+fn raw_handles_are_no_longer_owned() {
+    let device = NullDevice::new();
+    let handle = GpuHandle::new(&device, ResourceKind::TEXTURE, 7, "test texture");
+    assert_eq!(handle.into_raw(), 7);
+    assert_eq!(resource::pending_deletions(device.device_id()), 0);
+    assert!(resource::live_resources(device.device_id()).is_empty());
+}
+
+#[test]
+fn dropped_shaders_delete_their_program() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, "vertex", "fragment").unwrap();
+    let program = shader.id;
+    drop(shader);
+    device.clear();
+
+    resource::flush_deletions(&mut device);
+    assert_eq!(device.commands, vec![Command::DELETE_PROGRAM(program)]);
+}
+
+#[test]
+fn skybox_releases_its_cubemap() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, "vertex", "fragment").unwrap();
+    let pixels = [0u8; 3];
+    let face = TextureData { width: 1, height: 1, format: PixelFormat::SRGB8, pixels: &pixels };
+    let skybox = SkyBox::with_device(&mut device, &[face; 6], &shader);
+    let cubemap = skybox.texture();
+    drop(skybox);
+    device.clear();
+
+    resource::flush_deletions(&mut device);
+    assert!(device.commands.contains(&Command::DELETE_TEXTURE(cubemap)));
+    assert_eq!(deletions(&device).len(), 3);
+}
+
+#[test]
+fn models_own_the_textures_they_adopt() {
+    let mut device = NullDevice::new();
+    let texture = Texture { id: 42, type_: "texture_diffuse".into(), path: "solid".into(), has_alpha: false };
+    let mut model = Model::from_meshes(vec![triangle(&mut device, vec![texture.clone()], Material::default())]);
+    model.adopt_texture(&device, texture);
+    assert_eq!(model.texture_loaded.len(), 1);
+    drop(model);
+    device.clear();
+
+    resource::flush_deletions(&mut device);
+    assert!(device.commands.contains(&Command::DELETE_TEXTURE(42)));
+    assert_eq!(deletions(&device).len(), 4);
+}
@@ -0,0 +1,90 @@
+use cgmath::{ vec3, vec4, Deg, Matrix3, Matrix4, Vector3 };
+use cgmath::prelude::*;
+
+use argus_engine::graphics::shadow::{ compute_split_distances, fit_cascade };
+
+const RESOLUTION: i32 = 1024;
+
+// the corners of a box around `center`, in the order frustum_corners gives them
+fn box_corners(center: Vector3<f32>, half_size: Vector3<f32>) -> [Vector3<f32>; 8] {
+    let mut corners = [Vector3::zero(); 8];
+    let mut i = 0;
+    for &x in &[-1.0, 1.0] {
+        for &y in &[-1.0, 1.0] {
+            for &z in &[-1.0, 1.0] {
+                corners[i] = center + vec3(x * half_size.x, y * half_size.y, z * half_size.z);
+                i += 1;
+            }
+        }
+    }
+    corners
+}
+
+fn clip(matrix: &Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+    let clip = matrix * vec4(point.x, point.y, point.z, 1.0);
+    clip.truncate() / clip.w
+}
+
+#[test]
+fn splits_blend_uniform_and_logarithmic() {
+    let (near, far) = (0.1, 100.0);
+    let uniform = compute_split_distances(near, far, 4, 0.0);
+    let logarithmic = compute_split_distances(near, far, 4, 1.0);
+    for i in 0..4 {
+        let p = (i + 1) as f32 / 4.0;
+        assert!((uniform[i] - (near + (far - near) * p)).abs() < 1e-4);
+        assert!((logarithmic[i] - near * (far / near).powf(p)).abs() < 1e-3);
+    }
+
+    let practical = compute_split_distances(near, far, 4, 0.75);
+    assert_eq!(practical.len(), 4);
+    // every cascade ends further than the last and the last one at the far plane
+    assert!(practical.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!((practical[3] - far).abs() < 1e-3);
+    for i in 0..4 {
+        let expected = 0.75 * logarithmic[i] + 0.25 * uniform[i];
+        assert!((practical[i] - expected).abs() < 1e-3);
+    }
+    // lambda is clamped to the two schemes
+    assert_eq!(compute_split_distances(near, far, 4, 2.0), logarithmic);
+    assert_eq!(compute_split_distances(near, far, 4, -1.0), uniform);
+    assert_eq!(compute_split_distances(near, far, 1, 0.5), vec![far]);
+}
+
+#[test]
+fn cascades_enclose_their_slice_and_the_casters_in_front() {
+    let corners = box_corners(vec3(3.0, 1.0, -8.0), vec3(2.0, 1.5, 4.0));
+    let light = vec3(-0.3, -1.0, -0.5);
+    let matrix = fit_cascade(&corners, light, RESOLUTION, 20.0);
+    for &corner in &corners {
+        let point = clip(&matrix, corner);
+        assert!(point.x.abs() <= 1.0 && point.y.abs() <= 1.0 && point.z.abs() <= 1.0, "{:?} maps to {:?}", corner, point);
+    }
+
+    // a caster up to the margin towards the light still lands in the depth range, one past it does not
+    let center = corners.iter().fold(Vector3::zero(), |sum, c| sum + c) / 8.0;
+    let towards_light = -light.normalize();
+    let radius = corners.iter().map(|c| (c - center).magnitude()).fold(0.0f32, f32::max);
+    assert!(clip(&matrix, center + towards_light * (radius + 19.0)).z >= -1.0);
+    assert!(clip(&matrix, center + towards_light * (radius + 21.0)).z < -1.0);
+}
+
+#[test]
+fn cascades_keep_their_size_and_snap_to_texels() {
+    let corners = box_corners(vec3(0.0, 0.0, -10.0), vec3(3.0, 2.0, 5.0));
+    let light = vec3(0.2, -1.0, 0.4);
+    let matrix = fit_cascade(&corners, light, RESOLUTION, 10.0);
+
+    // the same slice seen from a turned camera covers exactly as many world units per texel
+    let turn = Matrix3::from_angle_y(Deg(37.0));
+    let center = vec3(0.0, 0.0, -10.0);
+    let turned = fit_cascade(&corners.map(|c| center + turn * (c - center)), light, RESOLUTION, 10.0);
+    assert!((matrix.x.truncate().magnitude() - turned.x.truncate().magnitude()).abs() < 1e-6);
+
+    // the world origin lands on a texel corner whatever the slice
+    let half = RESOLUTION as f32 / 2.0;
+    for matrix in [matrix, turned] {
+        let origin = clip(&matrix, Vector3::zero()) * half;
+        assert!((origin.x - origin.x.round()).abs() < 1e-2 && (origin.y - origin.y.round()).abs() < 1e-2, "{:?}", origin);
+    }
+}
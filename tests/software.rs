@@ -0,0 +1,213 @@
+use cgmath::{ vec2, vec3, vec4, Vector3 };
+
+use argus_engine::graphics::camera::Camera;
+use argus_engine::graphics::device::{ PixelFormat, RenderDevice, Sampler, TextureData, WrapMode };
+use argus_engine::graphics::material::{ BlendMode, Material };
+use argus_engine::graphics::mesh::{ Mesh, Texture, Vertex };
+use argus_engine::graphics::model::Model;
+use argus_engine::graphics::shader::Shader;
+use argus_engine::graphics::software::{ SoftwareDevice, SoftwareRasterizer, SoftwareTexture };
+use argus_engine::world::entity::Entity;
+use argus_engine::world::scene::Scene;
+use argus_engine::world::skybox::SkyBox;
+use argus_engine::world::transform::Transform;
+
+const SIZE: usize = 64;
+
+// one color per cubemap face, in the +X, -X, +Y, -Y, +Z, -Z order
+const FACES: [[u8; 3]; 6] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 0], [255, 0, 255], [0, 255, 255]];
+
+fn scene(device: &mut SoftwareDevice) -> Scene {
+    let shader = Shader::from_source(device, "", "").unwrap();
+    let faces: Vec<TextureData> = FACES.iter()
+        .map(|pixels| TextureData { width: 1, height: 1, format: PixelFormat::SRGB8, pixels })
+        .collect();
+    let mut scene = Scene::with_skybox(SkyBox::with_device(device, &faces, &shader));
+
+    // unlit: the output is the albedo
+    scene.directional_light.intensity = 0.0;
+    scene.directional_light.ambient = 1.0;
+    scene
+}
+
+fn camera() -> Camera {
+    let mut camera = Camera::default();
+    camera.hdr.enabled = false;
+    camera
+}
+
+fn texture(device: &mut SoftwareDevice, width: i32, height: i32, pixels: &[u8], mipmaps: bool) -> Texture {
+    let data = TextureData { width, height, format: PixelFormat::SRGB8_ALPHA8, pixels };
+    let id = device.create_texture(&data, Sampler { wrap: WrapMode::CLAMP_TO_EDGE, mipmaps });
+    Texture { id, type_: "texture_diffuse".into(), path: String::new(), has_alpha: pixels.chunks(4).any(|t| t[3] < 255) }
+}
+
+// a quad from its four corners, with the texture coordinates (0,0), (1,0), (1,1), (0,1)
+fn quad(device: &mut SoftwareDevice, corners: [Vector3<f32>; 4], texture: Texture, material: Material) -> Model {
+    let uvs = [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)];
+    let vertices = corners.iter().zip(uvs.iter())
+        .map(|(&position, &tex_coords)| Vertex { position, normal: vec3(0.0, 0.0, 1.0), tex_coords, ..Vertex::default() })
+        .collect();
+    Model::from_meshes(vec![Mesh::with_device(device, vertices, vec![0, 1, 2, 0, 2, 3], vec![texture], material)])
+}
+
+// a square facing the camera at depth -z
+fn facing_quad(device: &mut SoftwareDevice, z: f32, half_size: f32, color: [u8; 4], material: Material) -> Model {
+    let texture = texture(device, 1, 1, &color, false);
+    let s = half_size;
+    quad(device, [vec3(-s, -s, z), vec3(s, -s, z), vec3(s, s, z), vec3(-s, s, z)], texture, material)
+}
+
+fn add(scene: &mut Scene, model: Model) {
+    scene.entities.push(Entity::new(Some(model), Transform::default()));
+}
+
+fn pixel(image: &image::RgbaImage, x: usize, y: usize) -> [u8; 3] {
+    let p = image.get_pixel(x as u32, y as u32).data;
+    [p[0], p[1], p[2]]
+}
+
+fn close(a: [u8; 3], b: [u8; 3]) -> bool {
+    a.iter().zip(b.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 3)
+}
+
+#[test]
+fn empty_scene_shows_the_skybox_face_in_front_of_the_camera() {
+    let mut device = SoftwareDevice::new();
+    let scene = scene(&mut device);
+    let mut rasterizer = SoftwareRasterizer::new(SIZE, SIZE);
+
+    let mut camera = camera();
+    let image = rasterizer.render(&device, &scene, &camera);
+    assert!(close(pixel(&image, SIZE / 2, SIZE / 2), FACES[5]));
+
+    // turn to look down +X
+    camera.process_mouse_movement(900.0, 0.0, true);
+    let image = rasterizer.render(&device, &scene, &camera);
+    assert!(close(pixel(&image, SIZE / 2, SIZE / 2), FACES[0]));
+    assert!(rasterizer.depth().iter().all(|&d| d == 1.0));
+}
+
+#[test]
+fn geometry_writes_the_projected_depth() {
+    let mut device = SoftwareDevice::new();
+    let mut scene = scene(&mut device);
+    let model = facing_quad(&mut device, -5.0, 1.0, [255, 255, 255, 255], Material::default());
+    add(&mut scene, model);
+
+    let camera = camera();
+    let mut rasterizer = SoftwareRasterizer::new(SIZE, SIZE);
+    let image = rasterizer.render(&device, &scene, &camera);
+
+    assert!(close(pixel(&image, SIZE / 2, SIZE / 2), [255, 255, 255]));
+    assert!(close(pixel(&image, 0, 0), FACES[5]));
+
+    let clip = camera.get_projection_matrix(1.0) * vec4(0.0, 0.0, -5.0, 1.0);
+    let expected = clip.z / clip.w * 0.5 + 0.5;
+    let depth = rasterizer.depth()[SIZE / 2 * SIZE + SIZE / 2];
+    assert!((depth - expected).abs() < 1e-4, "{} != {}", depth, expected);
+}
+
+#[test]
+fn nearest_surface_wins_whatever_the_draw_order() {
+    for &near_first in &[true, false] {
+        let mut device = SoftwareDevice::new();
+        let mut scene = scene(&mut device);
+        let near = facing_quad(&mut device, -3.0, 1.0, [255, 0, 0, 255], Material::default());
+        let far = facing_quad(&mut device, -4.0, 2.0, [0, 0, 255, 255], Material::default());
+        if near_first {
+            add(&mut scene, near);
+            add(&mut scene, far);
+        } else {
+            add(&mut scene, far);
+            add(&mut scene, near);
+        }
+
+        let image = SoftwareRasterizer::new(SIZE, SIZE).render(&device, &scene, &camera());
+        assert!(close(pixel(&image, SIZE / 2, SIZE / 2), [255, 0, 0]));
+    }
+}
+
+#[test]
+fn texture_coordinates_are_perspective_correct() {
+    // a floor going away from the camera, red on its near half and blue on its far half
+    let mut pixels = Vec::new();
+    for row in 0..64 {
+        pixels.extend_from_slice(if row < 32 { &[255, 0, 0, 255] } else { &[0, 0, 255, 255] });
+    }
+    let mut device = SoftwareDevice::new();
+    let mut scene = scene(&mut device);
+    let texture = texture(&mut device, 1, 64, &pixels, false);
+    let corners = [vec3(-10.0, -1.0, -1.0), vec3(10.0, -1.0, -1.0), vec3(10.0, -1.0, -21.0), vec3(-10.0, -1.0, -21.0)];
+    let floor = quad(&mut device, corners, texture, Material::default());
+    add(&mut scene, floor);
+
+    let camera = camera();
+    let image = SoftwareRasterizer::new(SIZE, SIZE).render(&device, &scene, &camera);
+
+    // the color changes at the row where the middle of the floor projects to
+    let clip = camera.get_projection_matrix(1.0) * vec4(0.0, -1.0, -11.0, 1.0);
+    let row = ((0.5 - clip.y / clip.w * 0.5) * SIZE as f32) as usize;
+    assert!(close(pixel(&image, SIZE / 2, row + 2), [255, 0, 0]));
+    assert!(close(pixel(&image, SIZE / 2, row - 2), [0, 0, 255]));
+}
+
+#[test]
+fn triangles_crossing_the_near_plane_are_clipped() {
+    let mut device = SoftwareDevice::new();
+    let mut scene = scene(&mut device);
+    let texture = texture(&mut device, 1, 1, &[0, 255, 0, 255], false);
+    // a floor running from behind the camera into the distance
+    let corners = [vec3(-5.0, -1.0, 5.0), vec3(5.0, -1.0, 5.0), vec3(5.0, -1.0, -20.0), vec3(-5.0, -1.0, -20.0)];
+    let floor = quad(&mut device, corners, texture, Material::default());
+    add(&mut scene, floor);
+
+    let image = SoftwareRasterizer::new(SIZE, SIZE).render(&device, &scene, &camera());
+    assert!(close(pixel(&image, SIZE / 2, SIZE - 1), [0, 255, 0]));
+    assert!(close(pixel(&image, SIZE / 2, 0), FACES[5]));
+}
+
+#[test]
+fn cutout_texels_are_discarded_and_transparent_meshes_blend() {
+    let mut device = SoftwareDevice::new();
+    let mut scene = scene(&mut device);
+    let cutout = Material { blend_mode: BlendMode::CUTOUT, ..Material::default() };
+    let hole = facing_quad(&mut device, -3.0, 1.0, [255, 255, 255, 0], cutout);
+    add(&mut scene, hole);
+    let glass = Material { blend_mode: BlendMode::ADDITIVE, ..Material::default() };
+    let tint = facing_quad(&mut device, -2.0, 0.25, [255, 0, 0, 255], glass);
+    add(&mut scene, tint);
+
+    let image = SoftwareRasterizer::new(SIZE, SIZE).render(&device, &scene, &camera());
+    // the skybox shows through the cutout and the additive quad adds red to it
+    assert!(close(pixel(&image, SIZE / 2, SIZE / 2), [255, 255, 255]));
+    assert!(close(pixel(&image, SIZE / 2, SIZE / 8), FACES[5]));
+}
+
+#[test]
+fn mip_levels_average_the_texels() {
+    // 4x4 black and white checkerboard
+    let mut pixels = Vec::new();
+    for y in 0..4 {
+        for x in 0..4 {
+            let c = if (x + y) % 2 == 0 { 255 } else { 0 };
+            pixels.extend_from_slice(&[c, c, c, 255]);
+        }
+    }
+    let texture = SoftwareTexture::new(
+        &TextureData { width: 4, height: 4, format: PixelFormat::RGBA8, pixels: &pixels },
+        Sampler { wrap: WrapMode::REPEAT, mipmaps: true }
+    );
+    assert_eq!(texture.level_count(), 3);
+
+    let one_texel = vec2(0.25, 0.0);
+    assert!(texture.lod(one_texel, one_texel).abs() < 1e-4);
+    let whole_texture = vec2(1.0, 0.0);
+    assert!((texture.lod(whole_texture, whole_texture) - 2.0).abs() < 1e-4);
+
+    let grey = texture.sample(vec2(0.3, 0.7), 2.0);
+    assert!((grey.x - 0.5).abs() < 1e-4 && (grey.w - 1.0).abs() < 1e-4);
+    let texel = texture.sample(vec2(0.125, 0.125), 0.0);
+    assert!((texel.x - 1.0).abs() < 1e-4);
+}
+
@@ -0,0 +1,33 @@
+use cgmath::Vector3;
+use cgmath::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use argus_engine::graphics::ssao::{ generate_kernel, MAX_KERNEL_SIZE };
+
+#[test]
+fn kernels_fill_the_hemisphere_around_the_normal() {
+    let kernel = generate_kernel(MAX_KERNEL_SIZE, &mut StdRng::seed_from_u64(5));
+    assert_eq!(kernel.len(), MAX_KERNEL_SIZE);
+    // in front of the surface and inside the unit sphere
+    assert!(kernel.iter().all(|sample| sample.z >= 0.0 && sample.magnitude() <= 1.0), "{:?}", kernel);
+    // spread around the normal rather than along one side of it
+    let sum = kernel.iter().fold(Vector3::zero(), |sum, sample| sum + sample);
+    assert!(sum.x.abs() < sum.z && sum.y.abs() < sum.z, "{:?}", sum);
+}
+
+#[test]
+fn kernels_gather_towards_the_origin() {
+    let count = 32;
+    let kernel = generate_kernel(count, &mut StdRng::seed_from_u64(9));
+    // the i-th sample is scaled down to at most 0.1 + 0.9 (i / count)^2
+    for (i, sample) in kernel.iter().enumerate() {
+        let t = i as f32 / count as f32;
+        assert!(sample.magnitude() <= 0.1 + 0.9 * t * t + 1e-6, "sample {} is {:?}", i, sample);
+    }
+    let average = |samples: &[Vector3<f32>]| samples.iter().map(|s| s.magnitude()).sum::<f32>() / samples.len() as f32;
+    assert!(average(&kernel[..count / 2]) < average(&kernel[count / 2..]));
+
+    // the same seed gives the same kernel
+    assert_eq!(generate_kernel(count, &mut StdRng::seed_from_u64(9)), kernel);
+}
@@ -0,0 +1,104 @@
+use std::mem::size_of;
+
+use cgmath::{ vec3, Matrix4, SquareMatrix };
+
+use argus_engine::graphics::device::{ Command, NullDevice };
+use argus_engine::graphics::shader::Shader;
+use argus_engine::graphics::uniform_buffer::{ FrameData, ObjectData, UniformBuffer, UniformRing, FRAME_BINDING, OBJECT_BINDING };
+
+const VERTEX: &str = "#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (std140) uniform ObjectData {
+    mat4 model;
+    mat4 normalMatrix;
+};
+layout (std140) uniform FrameData {
+    mat4 view;
+    mat4 projection;
+};
+layout (std140) uniform Custom {
+    vec4 tint;
+};
+";
+
+fn bound_ranges(device: &NullDevice) -> Vec<(u32, usize, usize)> {
+    device.commands.iter()
+        .filter_map(|c| match c {
+            Command::BIND_UNIFORM_BUFFER { binding, offset, size, .. } => Some((*binding, *offset, *size)),
+            _ => None
+        })
+        .collect()
+}
+
+#[test]
+fn blocks_match_their_std140_layout() {
+    // six matrices and three vec4
+    assert_eq!(size_of::<FrameData>(), 6 * 64 + 3 * 16);
+    assert_eq!(size_of::<ObjectData>(), 2 * 64);
+}
+
+#[test]
+fn frame_data_holds_the_derived_matrices() {
+    let projection = cgmath::perspective(cgmath::Deg(45.0), 2.0, 0.1, 100.0);
+    let view = Matrix4::from_translation(vec3(0.0, 0.0, -5.0));
+    let frame = FrameData::new(&projection, &view, vec3(0.0, 0.0, 5.0), 3.0, 0.5, 800, 400);
+
+    let inverse_view: Matrix4<f32> = frame.inverse_view.into();
+    assert_eq!(inverse_view, Matrix4::from_translation(vec3(0.0, 0.0, 5.0)));
+    let view_projection: Matrix4<f32> = frame.view_projection.into();
+    assert_eq!(view_projection, projection * view);
+    assert_eq!(frame.camera_position, [0.0, 0.0, 5.0, 1.0]);
+    assert_eq!(frame.time, [3.0, 0.5, 0.0, 0.0]);
+    assert_eq!(frame.resolution, [800.0, 400.0, 1.0 / 800.0, 1.0 / 400.0]);
+}
+
+#[test]
+fn normal_matrix_undoes_non_uniform_scale() {
+    let object = ObjectData::new(&Matrix4::from_nonuniform_scale(2.0, 1.0, 1.0));
+    assert_eq!(object.normal_matrix[0][0], 0.5);
+    assert_eq!(object.normal_matrix[1][1], 1.0);
+}
+
+#[test]
+fn engine_blocks_are_assigned_their_binding_points_on_link() {
+    let mut device = NullDevice::new();
+    let shader = Shader::from_source(&mut device, VERTEX, "").unwrap();
+    let reflection = shader.uniforms.reflection.as_ref().unwrap();
+    let object = reflection.block("ObjectData").unwrap().index;
+    let frame = reflection.block("FrameData").unwrap().index;
+
+    let bindings: Vec<&Command> = device.commands.iter().filter(|c| matches!(c, Command::BIND_UNIFORM_BLOCK { .. })).collect();
+    assert_eq!(bindings, vec![
+        &Command::BIND_UNIFORM_BLOCK { program: shader.id, block: object, binding: OBJECT_BINDING },
+        &Command::BIND_UNIFORM_BLOCK { program: shader.id, block: frame, binding: FRAME_BINDING }
+    ]);
+}
+
+#[test]
+fn uniform_buffer_is_rewritten_whole() {
+    let mut device = NullDevice::new();
+    let frame = FrameData::new(&Matrix4::identity(), &Matrix4::identity(), vec3(0.0, 0.0, 0.0), 0.0, 0.0, 1, 1);
+    let buffer = UniformBuffer::with_device(&mut device, size_of::<FrameData>());
+    device.clear();
+
+    buffer.update(&mut device, FRAME_BINDING, &frame);
+    assert_eq!(device.commands, vec![
+        Command::UPDATE_BUFFER { buffer: buffer.buffer, offset: 0, size: size_of::<FrameData>() },
+        Command::BIND_UNIFORM_BUFFER { binding: FRAME_BINDING, buffer: buffer.buffer, offset: 0, size: size_of::<FrameData>() }
+    ]);
+}
+
+#[test]
+fn ring_streams_aligned_ranges_and_wraps() {
+    let mut device = NullDevice::new();
+    // NullDevice aligns to 256 bytes, room for three objects
+    let ring = UniformRing::with_device(&mut device, 3 * 256);
+    device.clear();
+
+    let offsets: Vec<usize> = (0..4).map(|_| ring.push_object(&mut device, &Matrix4::identity())).collect();
+    assert_eq!(offsets, vec![0, 256, 512, 0]);
+
+    let ranges = bound_ranges(&device);
+    assert_eq!(ranges.len(), 4);
+    assert!(ranges.iter().all(|&(binding, _, size)| binding == OBJECT_BINDING && size == size_of::<ObjectData>()));
+}